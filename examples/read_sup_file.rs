@@ -1,6 +1,6 @@
 use log::{debug, error};
 
-use pgs_parse::{Error, PgsFile, PgsOdsSegment, PgsPcsSegment, PgsPdsSegment, PgsReader, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsWdsSegment, Result};
+use pgs_parse::{Error, PgsFile, PgsReader, PgsSegment, PgsSegmentHeader, PgsSegmentType, Result};
 
 mod helpers;
 use crate::helpers::init_logging;
@@ -14,27 +14,10 @@ fn read_segment(file: &mut PgsFile) -> Result<PgsSegment> {
         return Err(Error::ReadInvalidSegment);
     }
 
-    let mut buffer = Vec::with_capacity(header.segment_length as usize);
-    buffer.resize(header.segment_length as usize, 0);
-    file.read_bytes(&mut buffer.as_mut_slice())?;
+    let mut buffer = vec![0; header.segment_length as usize];
+    file.read_bytes(buffer.as_mut_slice())?;
 
-    let segment = match header.segment_type {
-        PgsSegmentType::PCS => {            
-            PgsSegment::Pcs(PgsPcsSegment::from_data(header, &buffer)?)
-        },
-        PgsSegmentType::WDS => {
-            PgsSegment::Wds(PgsWdsSegment::from_data(header, &buffer)?)
-        },
-        PgsSegmentType::PDS => {
-            PgsSegment::Pds(PgsPdsSegment::from_data(header, &buffer)?)
-        },
-        PgsSegmentType::ODS => {
-            PgsSegment::Ods(PgsOdsSegment::from_data(header, &buffer)?)
-        },
-        _ => PgsSegment::End
-    };
-
-    Ok(segment)
+    PgsSegment::from_data(header, &buffer)
 }
 
 pub fn main() {
@@ -0,0 +1,55 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{export::{contact_sheet as render_contact_sheet, PgsContactSheetOptions}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::{write_image, ImageFormat, OutputArgs}};
+
+#[derive(Args, Debug)]
+pub struct ContactSheetArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Output image file.
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Image format of the sheet.
+    #[clap(long, value_enum, default_value_t = ImageFormat::Png)]
+    pub format: ImageFormat,
+
+    /// Draw every Nth subtitle event.
+    #[clap(long, default_value_t = 1)]
+    pub every: usize,
+
+    /// Number of thumbnails per row.
+    #[clap(long, default_value_t = 4)]
+    pub columns: usize,
+
+    /// Width of a thumbnail in pixels.
+    #[clap(long, default_value_t = 384)]
+    pub width: usize,
+}
+
+/// Renders every Nth subtitle event as a thumbnail of the video frame with its start time and tiles them into one
+/// image for a quick visual check of the whole track.
+pub fn contact_sheet(args: &ContactSheetArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let options = PgsContactSheetOptions { every: args.every, columns: args.columns, thumbnail_width: args.width, ..Default::default() };
+    let sheet = render_contact_sheet(parser.get_display_sets(), &options)?;
+    if sheet.events.is_empty() {
+        return Err(format!("{} has no subtitle events", args.input).into());
+    }
+    write_image(&args.output, &sheet.pixels, false, args.format)?;
+
+    let (width, height) = (sheet.pixels[0].len(), sheet.pixels.len());
+    output.print(json!({
+        "output": args.output,
+        "thumbnails": sheet.events.len(),
+        "width": width,
+        "height": height
+    }), || format!("{} thumbnails written to {} ({width}x{height})", sheet.events.len(), args.output.display()))?;
+    Ok(ExitCode::SUCCESS)
+}
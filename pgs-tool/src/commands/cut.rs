@@ -0,0 +1,40 @@
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{transform::trim, PgsParser};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::{format_timestamp, parse_time, OutputArgs}};
+
+#[derive(Args, Debug)]
+pub struct CutArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Start of the time range, e.g. `00:10:00`.
+    #[clap(long, value_parser = parse_time)]
+    pub from: u32,
+
+    /// End of the time range, e.g. `00:20:00`.
+    #[clap(long, value_parser = parse_time)]
+    pub to: u32,
+
+    /// Rebase the timestamps so the clip starts at zero.
+    #[clap(long)]
+    pub rebase: bool,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Keeps only the display sets shown within the time range and writes them as a new stream.
+pub fn cut(args: &CutArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    trim(&mut stream, args.from, args.to, args.rebase)?;
+    write_stream(&stream, &args.output, output, json!({
+        "from": format_timestamp(args.from),
+        "to": format_timestamp(args.to),
+        "rebase": args.rebase
+    }))
+}
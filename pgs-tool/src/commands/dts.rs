@@ -0,0 +1,55 @@
+use std::process::ExitCode;
+
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{analysis::{analyze_decoding_timestamps, PGS_MAX_DECODE_DELAY}, PgsParser, PgsWriter};
+
+use crate::{commands::CommandResult, helpers::OutputArgs};
+
+#[derive(Args, Debug)]
+pub struct DtsArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Write a copy with decoding timestamps regenerated from the decoder model.
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+/// Reports how decoding timestamps relate to presentation timestamps, and optionally writes the stream with
+/// regenerated decoding timestamps.
+pub fn dts(args: &DtsArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let report = analyze_decoding_timestamps(parser.get_segments());
+
+    if let Some(path) = &args.output {
+        let mut writer = PgsWriter::create(path)?;
+        writer.set_regenerate_dts(true);
+        writer.write_segments(parser.get_segments())?;
+        writer.flush()?;
+    }
+
+    let deltas: Vec<i64> = report.deltas.iter().flatten().copied().collect();
+    output.print(json!({
+        "file": args.input,
+        "segments": report.deltas.len(),
+        "zeroed": report.zeroed,
+        "bogus": report.bogus,
+        "min_delta": deltas.iter().min(),
+        "max_delta": deltas.iter().max(),
+        "deltas": report.deltas,
+        "output": args.output
+    }), || {
+        let mut text = format!("{} segments: {} without DTS, {} decoded after presentation or more than {} ticks before",
+            report.deltas.len(), report.zeroed, report.bogus, PGS_MAX_DECODE_DELAY);
+        if let (Some(min), Some(max)) = (deltas.iter().min(), deltas.iter().max()) {
+            text += &format!("\nPTS - DTS ranges from {min} to {max} ticks");
+        }
+        if let Some(path) = &args.output {
+            text += &format!("\nregenerated decoding timestamps written to {path}");
+        }
+        text
+    })?;
+    Ok(ExitCode::SUCCESS)
+}
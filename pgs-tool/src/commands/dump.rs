@@ -0,0 +1,164 @@
+use std::{fs, process::ExitCode};
+
+use clap::Args;
+use serde_json::{json, Value};
+
+use pgs_parse::{PgsDebugDump, PgsParseEvent, PgsParserOptions, PgsPushParser, PgsSegment};
+
+use crate::{commands::CommandResult, helpers::{format_timestamp, OutputArgs}};
+
+#[derive(Args, Debug)]
+pub struct DumpArgs {
+    /// Input SUP file.
+    pub input: String,
+    /// Print every segment as an annotated hexdump below its line.
+    #[arg(long)]
+    pub hex: bool,
+}
+
+/// Returns the bytes of the segment starting at `offset`, as far as its header gives its length and the data
+/// holds them.
+fn segment_bytes(data: &[u8], offset: usize) -> &[u8] {
+    let end = data.get(offset + 11..offset + 13).map_or(data.len(), |length| offset + 13 + u16::from_be_bytes([length[0], length[1]]) as usize);
+    data.get(offset..end.min(data.len())).unwrap_or_default()
+}
+
+/// Returns the lines of the annotated hexdump of the raw bytes of a segment.
+fn hexdump(raw: &[u8], offset: usize) -> Vec<String> {
+    format!("{:?}", PgsDebugDump::from_raw(raw).with_offset(offset)).lines().map(str::to_string).collect()
+}
+
+/// Returns the segment specific fields worth showing when debugging a stream.
+fn segment_fields(segment: &PgsSegment) -> Value {
+    match segment {
+        PgsSegment::Pcs(pcs) => json!({
+            "width": pcs.width,
+            "height": pcs.height,
+            "frame_rate": pcs.frame_rate,
+            "composition_number": pcs.composition_number,
+            "composition_state": format!("{:?}", pcs.composition_state),
+            "palette_update_flag": pcs.palette_update_flag,
+            "palette_id": pcs.palette_id,
+            "composition_objects": pcs.composition_objects.iter().map(|obj| json!({
+                "object_id": obj.object_id,
+                "window_id": obj.window_id,
+                "x": obj.object_horizontal_position,
+                "y": obj.object_vertical_position,
+                "cropped": format!("{:?}", obj.object_cropped_flag),
+                "forced": obj.object_forced_flag
+            })).collect::<Vec<_>>()
+        }),
+        PgsSegment::Wds(wds) => json!({
+            "windows": wds.windows.iter().map(|win| json!({
+                "window_id": win.window_id,
+                "x": win.window_horizontal_position,
+                "y": win.window_vertical_position,
+                "width": win.window_width,
+                "height": win.window_height
+            })).collect::<Vec<_>>()
+        }),
+        PgsSegment::Pds(pds) => json!({
+            "palette_id": pds.palette_id,
+            "palette_version_number": pds.palette_version_number,
+            "palette_entries": pds.palette_entries.len()
+        }),
+        PgsSegment::Ods(ods) => json!({
+            "object_id": ods.object_id,
+            "object_version_number": ods.object_version_number,
+            "sequence_flag": format!("{:?}", ods.last_in_sequence_flag),
+            "width": ods.width,
+            "height": ods.height,
+            "object_data_length": ods.object_data_length
+        }),
+        PgsSegment::End(end) if !end.data.is_empty() => json!({ "data_length": end.data.len() }),
+        PgsSegment::End(_) => json!({}),
+        PgsSegment::Unknown(unknown) => json!({
+            "segment_type": unknown.segment_type,
+            "data_length": unknown.data.len()
+        })
+    }
+}
+
+/// Prints the annotated hexdump of the raw bytes of a segment, indented below its line, and an empty line.
+fn print_hexdump(raw: &[u8], offset: usize) {
+    for line in hexdump(raw, offset) {
+        println!("    {line}");
+    }
+    println!();
+}
+
+/// Renders the segment fields as a single line of `key=value` pairs.
+fn fields_to_text(fields: &Value) -> String {
+    let Some(map) = fields.as_object() else {
+        return String::new();
+    };
+    map.iter().map(|(key, value)| match value {
+        Value::String(text) => format!("{key}={text}"),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(|item| format!("{{{}}}", fields_to_text(item))).collect();
+            format!("{key}=[{}]", items.join(", "))
+        },
+        other => format!("{key}={other}")
+    }).collect::<Vec<_>>().join(" ")
+}
+
+/// Lists every segment of the file, including segments of unknown type. Parsing stops at the first malformed
+/// segment, which is reported together with its offset, and the command exits with a failure code. With `--hex`,
+/// the bytes of every segment, the malformed one included, are shown as an annotated hexdump.
+pub fn dump(args: &DumpArgs, output: &OutputArgs) -> CommandResult {
+    let data = fs::read(&args.input)?;
+    let mut parser = PgsPushParser::new(PgsParserOptions { keep_unknown_segments: true, keep_raw_segments: true, ..Default::default() });
+    let mut events = parser.push_bytes(&data);
+    events.extend(parser.finish());
+
+    let mut segments: Vec<Value> = Vec::new();
+    let mut failure: Option<(usize, Value)> = None;
+    for event in events {
+        match event {
+            PgsParseEvent::Segment { offset, segment, raw } => {
+                let header = segment.header();
+                let raw = raw.unwrap_or_default();
+                let fields = segment_fields(&segment);
+                if output.json {
+                    segments.push(json!({
+                        "offset": offset,
+                        "type": format!("{:?}", header.segment_type),
+                        "pts": header.presentation_timestamp,
+                        "dts": header.decoding_timestamp,
+                        "size": header.segment_length,
+                        "fields": fields,
+                        "hexdump": args.hex.then(|| hexdump(&raw, offset))
+                    }));
+                } else {
+                    println!("{:#010x}  {:?}  pts={} dts={} size={}  {}",
+                        offset, header.segment_type, format_timestamp(header.presentation_timestamp),
+                        format_timestamp(header.decoding_timestamp), header.segment_length, fields_to_text(&fields));
+                    if args.hex {
+                        print_hexdump(&raw, offset);
+                    }
+                }
+            },
+            PgsParseEvent::Error(err) => {
+                let offset = err.offset().unwrap_or(parser.offset());
+                let raw = segment_bytes(&data, offset);
+                failure = Some((offset, json!({ "offset": offset, "error": err.to_string(), "hexdump": args.hex.then(|| hexdump(raw, offset)) })));
+            },
+            _ => {}
+        }
+    }
+
+    if output.json {
+        println!("{}", serde_json::to_string_pretty(&json!({
+            "file": args.input,
+            "segments": segments,
+            "error": failure.as_ref().map(|(_, failure)| failure)
+        }))?);
+    } else if let Some((offset, failure)) = &failure {
+        println!("{:#010x}  error: {}", offset, failure["error"].as_str().unwrap_or_default());
+        if args.hex {
+            print_hexdump(segment_bytes(&data, *offset), *offset);
+        }
+    }
+
+    Ok(if failure.is_some() { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
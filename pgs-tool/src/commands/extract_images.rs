@@ -0,0 +1,69 @@
+use std::{fs::{self, File}, io::{self, BufWriter, Write}, path::PathBuf, process::ExitCode};
+
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{export::{batch, export_events, PgsBatchOptions, PgsExportEvent}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::{format_timestamp, write_image, ImageFormat, OutputArgs, ProgressArgs}};
+
+/// Name of the sidecar file listing the timing and placement of every exported image.
+const TIMING_FILE_NAME: &str = "timing.csv";
+
+#[derive(Args, Debug)]
+pub struct ExtractImagesArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Output directory, created if it does not exist.
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Image format of the exported files.
+    #[clap(long, value_enum, default_value_t = ImageFormat::Png)]
+    pub format: ImageFormat,
+
+    /// Export grayscale images instead of full color.
+    #[clap(long)]
+    pub gray: bool,
+
+    #[clap(flatten)]
+    pub progress: ProgressArgs,
+}
+
+/// Renders every complete display set to a numbered image file and writes a `timing.csv` sidecar with the start
+/// and end time and the screen position of each image.
+///
+/// The end time of an image is the presentation time of the following display set, which either clears or
+/// replaces it; it is left empty for the last display set of the stream.
+pub fn extract_images(args: &ExtractImagesArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let display_sets = parser.get_display_sets();
+
+    fs::create_dir_all(&args.output)?;
+    let mut timing = BufWriter::new(File::create(args.output.join(TIMING_FILE_NAME))?);
+    writeln!(timing, "index,file,start,end,x,y,width,height")?;
+
+    let events = export_events(display_sets).len();
+    let options = PgsBatchOptions { gray: args.gray, ..Default::default() };
+    let mut done = 0;
+    batch(display_sets, |event: &PgsExportEvent, pixels: Vec<Vec<u32>>| {
+        let file_name = event.image_file_name(args.format.extension());
+        write_image(&args.output.join(&file_name), &pixels, args.gray, args.format)
+            .map_err(|err| io::Error::other(err.to_string()))?;
+
+        writeln!(timing, "{},{},{},{},{},{},{},{}", event.display_set, file_name, format_timestamp(event.start),
+            event.end.map_or(String::new(), format_timestamp), event.x, event.y, event.width, event.height)?;
+        done += 1;
+        args.progress.report(output, "extract-images", done, events);
+        Ok(())
+    }, &options)?;
+    timing.flush()?;
+
+    output.print(json!({
+        "output": args.output,
+        "images": events,
+        "timing": args.output.join(TIMING_FILE_NAME)
+    }), || format!("{} images written to {}", events, args.output.display()))?;
+    Ok(ExitCode::SUCCESS)
+}
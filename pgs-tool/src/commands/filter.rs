@@ -0,0 +1,32 @@
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{transform::{filter as filter_stream, forced_only, non_forced_only}, PgsParser};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::OutputArgs};
+
+#[derive(Args, Debug)]
+pub struct FilterArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Keep only forced subtitles, e.g. for a "foreign parts only" track.
+    #[clap(long, conflicts_with = "non_forced", required_unless_present = "non_forced")]
+    pub forced: bool,
+
+    /// Keep only subtitles that are not forced.
+    #[clap(long)]
+    pub non_forced: bool,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Keeps only forced or only non-forced subtitles and writes the reduced stream.
+pub fn filter(args: &FilterArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let removed = filter_stream(&mut stream, if args.forced { forced_only() } else { non_forced_only() });
+    write_stream(&stream, &args.output, output, json!({ "display_sets_removed": removed }))
+}
@@ -0,0 +1,90 @@
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{transform::merge_events, PgsParser, PgsStream};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::{parse_offset, OutputArgs}};
+
+#[derive(Args, Debug)]
+pub struct MergeArgs {
+    /// Input SUP files.
+    #[clap(required = true, num_args = 2..)]
+    pub inputs: Vec<String>,
+
+    /// Offset added to the timestamps of each input, in input order, e.g. `--offset 0 --offset -2s`.
+    #[clap(long, allow_hyphen_values = true, value_parser = parse_offset)]
+    pub offset: Vec<i64>,
+
+    /// Show subtitles of different inputs that overlap in time together, re-encoding them as composite display sets.
+    #[clap(long)]
+    pub composite: bool,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ConcatArgs {
+    /// Input SUP files, in playback order.
+    #[clap(required = true, num_args = 2..)]
+    pub inputs: Vec<String>,
+
+    /// Start time of each input, in input order, e.g. `--offset 0 --offset 00:21:32.500`. By default each input
+    /// starts at the last timestamp of the previous one.
+    #[clap(long, allow_hyphen_values = true, value_parser = parse_offset)]
+    pub offset: Vec<i64>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Checks that one offset is given per input, if any, and parses every input into a stream.
+fn parse_inputs(inputs: &[String], offsets: &[i64]) -> Result<Vec<PgsStream>, Box<dyn std::error::Error>> {
+    if !offsets.is_empty() && offsets.len() != inputs.len() {
+        return Err(format!("{} offsets given for {} inputs", offsets.len(), inputs.len()).into());
+    }
+    inputs.iter().map(|input| Ok(PgsParser::parse(input)?.into_stream())).collect()
+}
+
+/// Writes the combined stream and reports the clamped timestamps.
+fn write_output(stream: &PgsStream, path: &str, output: &OutputArgs, inputs: &[String], clamped: usize) -> CommandResult {
+    if clamped > 0 {
+        output.warn(&format!("{clamped} timestamps were clamped to the valid range"));
+    }
+    write_stream(stream, path, output, json!({ "inputs": inputs, "clamped_timestamps": clamped }))
+}
+
+/// Offsets every input and interleaves their display sets by presentation time into a single stream.
+pub fn merge(args: &MergeArgs, output: &OutputArgs) -> CommandResult {
+    let mut streams = parse_inputs(&args.inputs, &args.offset)?;
+
+    let mut clamped = 0;
+    for (stream, offset) in streams.iter_mut().zip(&args.offset) {
+        clamped += stream.shift_timestamps(*offset);
+    }
+    if !args.composite {
+        return write_output(&PgsStream::merge(streams), &args.output, output, &args.inputs, clamped);
+    }
+
+    let (stream, composited) = merge_events(&streams)?;
+    if !output.json {
+        println!("{composited} display sets combine overlapping subtitles");
+    }
+    write_output(&stream, &args.output, output, &args.inputs, clamped)
+}
+
+/// Appends the inputs one after another, shifting each to its start time.
+pub fn concat(args: &ConcatArgs, output: &OutputArgs) -> CommandResult {
+    let mut streams = parse_inputs(&args.inputs, &args.offset)?;
+
+    let mut clamped = 0;
+    let mut start = 0;
+    for (index, stream) in streams.iter_mut().enumerate() {
+        let offset = args.offset.get(index).copied().unwrap_or(start);
+        clamped += stream.shift_timestamps(offset);
+        start = stream.end_timestamp().map_or(start, |end| end as i64);
+    }
+    write_output(&PgsStream::concat(streams), &args.output, output, &args.inputs, clamped)
+}
@@ -0,0 +1,66 @@
+use std::process::ExitCode;
+
+use serde_json::{json, Value};
+
+use pgs_parse::PgsStream;
+
+use crate::helpers::OutputArgs;
+
+mod contact_sheet;
+mod cut;
+mod dts;
+mod dump;
+mod extract_images;
+mod filter;
+mod merge;
+mod normalize;
+mod optimize;
+mod palette;
+mod preview;
+mod quality;
+mod scale;
+mod shift;
+mod shot_changes;
+mod stats;
+mod to_bdn;
+mod to_srt;
+mod validate;
+mod watermark;
+
+pub use contact_sheet::*;
+pub use cut::*;
+pub use dts::*;
+pub use dump::*;
+pub use extract_images::*;
+pub use filter::*;
+pub use merge::*;
+pub use normalize::*;
+pub use optimize::*;
+pub use palette::*;
+pub use preview::*;
+pub use quality::*;
+pub use scale::*;
+pub use shift::*;
+pub use shot_changes::*;
+pub use stats::*;
+pub use to_bdn::*;
+pub use to_srt::*;
+pub use validate::*;
+pub use watermark::*;
+
+/// Result returned by every subcommand: the process exit code on success, or the error to report.
+pub type CommandResult = Result<ExitCode, Box<dyn std::error::Error>>;
+
+/// Writes an edited stream and prints a summary. `result` is a JSON object with command specific fields, to which
+/// the output path and the number of written segments are added.
+fn write_stream(stream: &PgsStream, path: &str, output: &OutputArgs, mut result: Value) -> CommandResult {
+    stream.write(path)?;
+
+    let segments = stream.get_segments().len();
+    if let Some(fields) = result.as_object_mut() {
+        fields.insert("output".to_string(), json!(path));
+        fields.insert("segments".to_string(), json!(segments));
+    }
+    output.print(result, || format!("{segments} segments written to {path}"))?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,34 @@
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{transform::normalize_compositions, PgsParser};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::OutputArgs};
+
+#[derive(Args, Debug)]
+pub struct NormalizeArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Renumbers the compositions, repairs invalid composition states and writes the result.
+pub fn normalize(args: &NormalizeArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let report = normalize_compositions(&mut stream);
+    if !output.json {
+        println!("{} compositions renumbered", report.compositions_renumbered);
+        println!("{} epoch starts added, {} acquisition points downgraded, {} palette update flags cleared",
+            report.epoch_starts_added, report.acquisition_points_downgraded, report.palette_update_flags_cleared);
+    }
+    write_stream(&stream, &args.output, output, json!({
+        "compositions_renumbered": report.compositions_renumbered,
+        "epoch_starts_added": report.epoch_starts_added,
+        "acquisition_points_downgraded": report.acquisition_points_downgraded,
+        "palette_update_flags_cleared": report.palette_update_flags_cleared
+    }))
+}
@@ -0,0 +1,79 @@
+use std::process::ExitCode;
+
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{analysis::PGS_DEFAULT_STRAY_PIXELS, transform::{collapse_duplicate_display_sets, deduplicate_objects, optimize_with_options, recompress, strip_blank_display_sets, PgsOptimizeOptions}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::OutputArgs};
+
+#[derive(Args, Debug)]
+pub struct OptimizeArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+
+    /// Also remove display sets that show the same pixels as the display set before them.
+    #[clap(long)]
+    pub collapse_duplicates: bool,
+
+    /// Also turn display sets showing no more than `--max-stray-pixels` visible pixels into clear frames.
+    #[clap(long)]
+    pub strip_blank: bool,
+
+    /// Number of visible pixels, e.g. encoder artifacts, up to which `--strip-blank` treats a display set as blank.
+    #[clap(long, default_value_t = PGS_DEFAULT_STRAY_PIXELS)]
+    pub max_stray_pixels: usize,
+
+    /// Also remove acquisition points that change nothing. Seeking in players becomes less precise.
+    #[clap(long)]
+    pub strip_acquisition_points: bool,
+
+    /// Also re-encode object bitmaps with the shortest RLE codes.
+    #[clap(long)]
+    pub recompress: bool,
+
+    /// Also send identical object bitmaps only once per epoch, reusing the object already in the buffer.
+    #[clap(long)]
+    pub share_objects: bool,
+}
+
+/// Removes duplicate display sets, repeated objects and no-op palette updates, optionally clears blank display
+/// sets and recompresses the object bitmaps, and reports the size savings.
+pub fn optimize(args: &OptimizeArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let blank_stripped = if args.strip_blank { strip_blank_display_sets(&mut stream, args.max_stray_pixels)? } else { 0 };
+    let options = PgsOptimizeOptions { strip_acquisition_points: args.strip_acquisition_points };
+    let mut report = optimize_with_options(&mut stream, options)?;
+    let duplicates_removed = if args.collapse_duplicates { collapse_duplicate_display_sets(&mut stream)? } else { 0 };
+    report.display_sets_removed += duplicates_removed;
+    let objects_shared = if args.share_objects { deduplicate_objects(&mut stream)? } else { 0 };
+    report.objects_removed += objects_shared;
+    let objects_recompressed = if args.recompress { recompress(&mut stream)?.objects_recompressed } else { 0 };
+
+    let mut data: Vec<u8> = Vec::new();
+    stream.write_to(&mut data)?;
+    report.size_after = data.len();
+    std::fs::write(&args.output, data)?;
+
+    output.print(json!({
+        "output": args.output,
+        "display_sets_removed": report.display_sets_removed,
+        "duplicates_removed": duplicates_removed,
+        "blank_stripped": blank_stripped,
+        "acquisition_points_removed": report.acquisition_points_removed,
+        "objects_shared": objects_shared,
+        "objects_recompressed": objects_recompressed,
+        "objects_removed": report.objects_removed,
+        "palettes_removed": report.palettes_removed,
+        "size_before": report.size_before,
+        "size_after": report.size_after
+    }), || format!("removed {} display sets, {} objects, {} palettes, cleared {} blank display sets, recompressed {} objects\n{} -> {} bytes, saved {} bytes ({:.1}%)",
+        report.display_sets_removed, report.objects_removed, report.palettes_removed, blank_stripped, objects_recompressed, report.size_before,
+        report.size_after, report.saved(), report.saved() as f64 * 100.0 / report.size_before.max(1) as f64))?;
+    Ok(ExitCode::SUCCESS)
+}
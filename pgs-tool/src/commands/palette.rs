@@ -0,0 +1,99 @@
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{transform::{adjust_luminance, recolor as recolor_stream, reduce_palettes, scale_transparency, PgsColorScheme, PgsLuminanceCurve}, PgsParser};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::OutputArgs};
+
+#[derive(Args, Debug)]
+pub struct PaletteArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Luminance factor, e.g. `0.75` to dim bright white subtitles.
+    #[clap(long, default_value_t = 1.0)]
+    pub brightness: f64,
+
+    /// Contrast factor around mid grey.
+    #[clap(long, default_value_t = 1.0)]
+    pub contrast: f64,
+
+    /// Gamma correction; values above one brighten mid tones.
+    #[clap(long, default_value_t = 1.0)]
+    pub gamma: f64,
+
+    /// Factor applied to every alpha value, e.g. `0.6` for semi-transparent subtitles.
+    #[clap(long, default_value_t = 1.0)]
+    pub opacity: f64,
+
+    /// Requantize the colors of every subtitle to this many palette entries, e.g. `16` for limited decoders. This
+    /// re-encodes the bitmaps.
+    #[clap(long, value_parser = clap::value_parser!(u16).range(2..=256))]
+    pub colors: Option<u16>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Applies a luminance curve and an opacity factor to every palette, optionally reduces the number of colors, and
+/// writes the result. Bitmaps are left untouched unless colors are reduced.
+pub fn palette(args: &PaletteArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let curve = PgsLuminanceCurve { brightness: args.brightness, contrast: args.contrast, gamma: args.gamma };
+    let luminance_modified = adjust_luminance(&mut stream, curve)?;
+    let transparency_modified = scale_transparency(&mut stream, args.opacity)?;
+    let colors_reduced = match args.colors {
+        Some(colors) => reduce_palettes(&mut stream, colors as usize)?,
+        None => 0
+    };
+    if !output.json && args.colors.is_some() {
+        println!("{colors_reduced} display sets reduced to {} colors", args.colors.unwrap_or_default());
+    }
+    write_stream(&stream, &args.output, output, json!({
+        "luminance_modified": luminance_modified,
+        "transparency_modified": transparency_modified,
+        "colors_reduced": colors_reduced
+    }))
+}
+
+#[derive(Args, Debug)]
+pub struct RecolorArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Color of the letters, as RGB hex, e.g. `ffff00`.
+    #[clap(long, value_parser = parse_color, default_value = "ffff00")]
+    pub fill: (u8, u8, u8),
+
+    /// Color of the outline, as RGB hex.
+    #[clap(long, value_parser = parse_color, default_value = "000000")]
+    pub outline: (u8, u8, u8),
+
+    /// Color of translucent shadows, as RGB hex. Shadows are treated as outline if not given.
+    #[clap(long, value_parser = parse_color)]
+    pub shadow: Option<(u8, u8, u8)>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Parses an RGB hex color, with or without a leading `#`.
+fn parse_color(value: &str) -> Result<(u8, u8, u8), String> {
+    let hex = value.trim().trim_start_matches('#');
+    let channel = |index: usize| hex.get(index..index + 2).and_then(|channel| u8::from_str_radix(channel, 16).ok());
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(red), Some(green), Some(blue)) => Ok((red, green, blue)),
+        _ => Err(format!("invalid color `{value}`, expected RGB hex such as ffff00"))
+    }
+}
+
+/// Maps every palette onto the fill, outline and shadow colors and writes the result.
+pub fn recolor(args: &RecolorArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let modified = recolor_stream(&mut stream, PgsColorScheme { fill: args.fill, outline: args.outline, shadow: args.shadow });
+    write_stream(&stream, &args.output, output, json!({ "palettes_modified": modified }))
+}
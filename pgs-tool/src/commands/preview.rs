@@ -0,0 +1,87 @@
+use std::{io, process::ExitCode};
+
+use clap::{Args, ValueEnum};
+use serde_json::json;
+
+use pgs_parse::{export::export_events, preview::{render_preview, PgsPreviewProtocol}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::{format_timestamp, parse_time, OutputArgs}};
+
+/// Terminal graphics protocols that can be selected on the command line.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum PreviewProtocol {
+    /// Detect the protocol from the terminal environment.
+    Auto,
+    /// Colored Unicode half-blocks, supported by any modern terminal.
+    Blocks,
+    Sixel,
+    Kitty,
+}
+
+#[derive(Args, Debug)]
+pub struct PreviewArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Index of the display set to show. Defaults to the first subtitle.
+    #[clap(long, conflicts_with = "at")]
+    pub index: Option<usize>,
+
+    /// Show the subtitle on screen at this time, e.g. `00:01:23.500`.
+    #[clap(long, value_parser = parse_time)]
+    pub at: Option<u32>,
+
+    /// Terminal graphics protocol.
+    #[clap(long, value_enum, default_value_t = PreviewProtocol::Auto)]
+    pub protocol: PreviewProtocol,
+
+    /// Maximum width in pixels (text columns for `blocks`). Defaults to the terminal width for `blocks` and to
+    /// the bitmap width otherwise.
+    #[clap(long)]
+    pub width: Option<usize>,
+}
+
+/// Renders one subtitle bitmap to the terminal, preceded by its timing and placement. In JSON mode only the
+/// timing and placement are printed.
+pub fn preview(args: &PreviewArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let display_sets = parser.get_display_sets();
+    let events = export_events(display_sets);
+
+    let event = match (args.index, args.at) {
+        (Some(index), _) => events.iter().find(|event| event.display_set == index)
+            .ok_or(format!("display set {index} has no subtitle bitmap"))?,
+        (_, Some(at)) => events.iter().find(|event| event.start <= at && at < event.end_or_default())
+            .ok_or(format!("no subtitle is shown at {}", format_timestamp(at)))?,
+        _ => events.first().ok_or("the stream has no subtitle bitmaps")?
+    };
+
+    let protocol = match args.protocol {
+        PreviewProtocol::Auto => PgsPreviewProtocol::detect(),
+        PreviewProtocol::Blocks => PgsPreviewProtocol::HalfBlocks,
+        PreviewProtocol::Sixel => PgsPreviewProtocol::Sixel,
+        PreviewProtocol::Kitty => PgsPreviewProtocol::Kitty,
+    };
+    let width = args.width.unwrap_or(match protocol {
+        PgsPreviewProtocol::HalfBlocks => std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()).unwrap_or(120),
+        _ => 0
+    });
+
+    output.print(json!({
+        "display_set": event.display_set,
+        "start": event.start,
+        "end": event.end,
+        "x": event.x,
+        "y": event.y,
+        "width": event.width,
+        "height": event.height
+    }), || format!("display set {}  {} --> {}  {}x{} at ({}, {})", event.display_set, format_timestamp(event.start),
+        event.end.map_or("-".to_string(), format_timestamp), event.width, event.height, event.x, event.y))?;
+    if output.json {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let pixels = display_sets[event.display_set].get_decoded_image(false)?;
+    render_preview(io::stdout().lock(), &pixels, protocol, width)?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,62 @@
+use std::process::ExitCode;
+
+use clap::{Args, ValueEnum};
+use serde_json::{json, Value};
+
+use pgs_parse::{analysis::{check_quality, PgsQualityLevel, PgsQualityProfile}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::OutputArgs};
+
+/// Quality profiles that can be selected on the command line.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum QualityProfile {
+    /// Blu-ray authoring: specification violations and decoding timestamps fail the track.
+    BdRomStrict,
+    /// Soft subtitles for software players: only broken references fail the track.
+    SoftSubLenient,
+}
+
+#[derive(Args, Debug)]
+pub struct QualityArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Profile grading the findings.
+    #[clap(long, value_enum, default_value_t = QualityProfile::BdRomStrict)]
+    pub profile: QualityProfile,
+}
+
+/// Runs the validator and the event analyses and prints the findings graded by the profile. The command exits
+/// with a failure code when the track does not pass the profile.
+pub fn quality(args: &QualityArgs, output: &OutputArgs) -> CommandResult {
+    let stream = PgsParser::parse(&args.input)?.into_stream();
+    let profile = match args.profile {
+        QualityProfile::BdRomStrict => PgsQualityProfile::BdRomStrict,
+        QualityProfile::SoftSubLenient => PgsQualityProfile::SoftSubLenient,
+    };
+    let report = check_quality(&stream, profile)?;
+
+    let (errors, warnings, infos) = (report.count(PgsQualityLevel::Error), report.count(PgsQualityLevel::Warning), report.count(PgsQualityLevel::Info));
+    output.print(json!({
+        "file": args.input,
+        "profile": report.profile.to_string(),
+        "passed": report.passed(),
+        "errors": errors,
+        "warnings": warnings,
+        "infos": infos,
+        "findings": report.findings.iter().map(|finding| json!({
+            "level": format!("{:?}", finding.level),
+            "check": format!("{:?}", finding.check),
+            "display_set": finding.display_set,
+            "pts": finding.presentation_timestamp,
+            "message": finding.message
+        })).collect::<Vec<Value>>()
+    }), || {
+        let mut lines: Vec<String> = report.findings.iter().map(|finding| finding.to_string()).collect();
+        let verdict = if report.passed() { "passed" } else { "failed" };
+        lines.push(format!("{}: {verdict} {}, {errors} errors, {warnings} warnings, {infos} infos", args.input, report.profile));
+        lines.join("\n")
+    })?;
+
+    Ok(if report.passed() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
@@ -0,0 +1,187 @@
+use clap::{Args, ValueEnum};
+use serde_json::json;
+
+use pgs_parse::{transform::{enforce_safe_area, flatten_objects, reposition as reposition_stream, resize_canvas, rescale_with_filter, PgsScaleFilter}, PgsParser};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::OutputArgs};
+
+/// Bitmap resampling filters that can be selected on the command line.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ScaleFilter {
+    /// Nearest neighbour, keeping hard pixel edges.
+    Nearest,
+    /// Bilinear, requantized to the object's palette for smoother edges.
+    Bilinear,
+}
+
+#[derive(Args, Debug)]
+pub struct ScaleArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Resolution the stream was authored for, e.g. `1920x1080`.
+    #[clap(long, value_parser = parse_resolution)]
+    pub from: (u16, u16),
+
+    /// Target resolution, e.g. `3840x2160`.
+    #[clap(long, value_parser = parse_resolution)]
+    pub to: (u16, u16),
+
+    /// Filter used to resample the bitmaps.
+    #[clap(long, value_enum, default_value_t = ScaleFilter::Nearest)]
+    pub filter: ScaleFilter,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Parses a `<width>x<height>` resolution.
+fn parse_resolution(value: &str) -> Result<(u16, u16), String> {
+    let parse = |part: &str| part.trim().parse::<u16>().ok().filter(|&size| size > 0);
+    match value.split_once(['x', 'X']) {
+        Some((width, height)) => parse(width).zip(parse(height)),
+        None => None
+    }.ok_or(format!("invalid resolution `{value}`, expected e.g. 1920x1080"))
+}
+
+/// Rescales positions, windows and bitmaps from one video resolution to another and writes the result.
+pub fn scale(args: &ScaleArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let filter = match args.filter {
+        ScaleFilter::Nearest => PgsScaleFilter::Nearest,
+        ScaleFilter::Bilinear => PgsScaleFilter::Bilinear,
+    };
+    rescale_with_filter(&mut stream, args.from, args.to, filter)?;
+    write_stream(&stream, &args.output, output, json!({
+        "from": format!("{}x{}", args.from.0, args.from.1),
+        "to": format!("{}x{}", args.to.0, args.to.1)
+    }))
+}
+
+#[derive(Args, Debug)]
+pub struct RepositionArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Horizontal offset in pixels, negative to move left.
+    #[clap(long, allow_hyphen_values = true, default_value_t = 0)]
+    pub dx: i32,
+
+    /// Vertical offset in pixels, negative to move up.
+    #[clap(long, allow_hyphen_values = true, default_value_t = 0)]
+    pub dy: i32,
+
+    /// Only move windows whose top edge is at or below this line, e.g. `540` for bottom subtitles in 1080p.
+    #[clap(long, default_value_t = 0)]
+    pub below: u16,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Moves subtitle windows and their objects on screen, clamped to the video size, and writes the result.
+pub fn reposition(args: &RepositionArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let moved = reposition_stream(&mut stream, args.below, args.dx, args.dy);
+    write_stream(&stream, &args.output, output, json!({ "moved_windows": moved }))
+}
+
+#[derive(Args, Debug)]
+pub struct CanvasArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// New video size, e.g. `1920x800`.
+    #[clap(long, value_parser = parse_resolution)]
+    pub to: (u16, u16),
+
+    /// Horizontal offset of the old canvas on the new one. Subtitles are recentred if no offset is given.
+    #[clap(long, allow_hyphen_values = true, requires = "dy")]
+    pub dx: Option<i32>,
+
+    /// Vertical offset of the old canvas on the new one.
+    #[clap(long, allow_hyphen_values = true, requires = "dx")]
+    pub dy: Option<i32>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Changes the video size without scaling the bitmaps, moving the windows onto the new canvas, and writes the
+/// result.
+pub fn canvas(args: &CanvasArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let moved = resize_canvas(&mut stream, args.to, args.dx.zip(args.dy))?;
+    write_stream(&stream, &args.output, output, json!({
+        "to": format!("{}x{}", args.to.0, args.to.1),
+        "moved_windows": moved
+    }))
+}
+
+#[derive(Args, Debug)]
+pub struct SafeAreaArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Margin on every side, in percent of the video width or height.
+    #[clap(long, default_value_t = 5.0)]
+    pub margin: f64,
+
+    /// Scale down the objects of windows larger than the safe area instead of cropping them.
+    #[clap(long)]
+    pub scale_objects: bool,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Moves or shrinks windows into the title-safe area, prints every adjusted window and writes the result.
+pub fn safe_area(args: &SafeAreaArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let adjustments = enforce_safe_area(&mut stream, args.margin / 100.0, args.scale_objects)?;
+    let rectangle = |(x, y, width, height): (u16, u16, u16, u16)| format!("{width}x{height} at ({x}, {y})");
+    if !output.json {
+        for adjustment in &adjustments {
+            println!("display set {} window {}: {} -> {}{}", adjustment.display_set, adjustment.window_id, rectangle(adjustment.old_window),
+                rectangle(adjustment.new_window), if adjustment.scaled { " (scaled)" } else { "" });
+        }
+    }
+    let adjustments: Vec<_> = adjustments.iter().map(|adjustment| json!({
+        "display_set": adjustment.display_set,
+        "pts": adjustment.presentation_timestamp,
+        "window_id": adjustment.window_id,
+        "old_window": rectangle(adjustment.old_window),
+        "new_window": rectangle(adjustment.new_window),
+        "scaled": adjustment.scaled
+    })).collect();
+    write_stream(&stream, &args.output, output, json!({ "adjustments": adjustments }))
+}
+
+#[derive(Args, Debug)]
+pub struct FlattenArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Composites the objects of every display set into a single object and writes the result.
+pub fn flatten(args: &FlattenArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let flattened = flatten_objects(&mut stream)?;
+    if !output.json {
+        println!("{flattened} display sets flattened to one object");
+    }
+    write_stream(&stream, &args.output, output, json!({ "flattened_display_sets": flattened }))
+}
@@ -0,0 +1,249 @@
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{retime::{self, PgsChapterOffset, PgsRetimeFactor, PgsTimingRules}, PgsParser};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::{format_timestamp, parse_offset, parse_time, OutputArgs}};
+
+#[derive(Args, Debug)]
+pub struct ShiftArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Offset added to every timestamp, e.g. `-1500ms`, `2s` or `-00:00:01.250`.
+    #[clap(long, allow_hyphen_values = true, value_parser = parse_offset)]
+    pub by: i64,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Offsets the PTS and DTS of every segment and writes the retimed stream.
+pub fn shift(args: &ShiftArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let clamped = retime::shift(&mut stream, args.by);
+    if clamped > 0 {
+        output.warn(&format!("{clamped} timestamps were clamped to the valid range"));
+    }
+    write_stream(&stream, &args.output, output, json!({ "clamped_timestamps": clamped }))
+}
+
+#[derive(Args, Debug)]
+pub struct StretchArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Frame rate the subtitles are timed for, e.g. `25`.
+    #[clap(long)]
+    pub from_fps: f64,
+
+    /// Frame rate of the target video, e.g. `23.976`.
+    #[clap(long)]
+    pub to_fps: f64,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Scales every timestamp for another frame rate and writes the retimed stream.
+pub fn stretch(args: &StretchArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let factor = PgsRetimeFactor::from_frame_rates(args.from_fps, args.to_fps)?;
+    let clamped = retime::stretch(&mut stream, factor)?;
+    if clamped > 0 {
+        output.warn(&format!("{clamped} timestamps were clamped to the valid range"));
+    }
+    write_stream(&stream, &args.output, output, json!({
+        "factor": format!("{}/{}", factor.numerator, factor.denominator),
+        "clamped_timestamps": clamped
+    }))
+}
+
+#[derive(Args, Debug)]
+pub struct SnapArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Frame rate of the video, e.g. `23.976`.
+    #[clap(long)]
+    pub fps: f64,
+
+    /// Minimum number of frames between the end of a subtitle and the start of the next one.
+    #[clap(long, default_value_t = 2)]
+    pub min_gap: u32,
+
+    /// Clear subtitles that are never cleared before the next epoch after this time on screen, e.g. `10s`.
+    #[clap(long, value_parser = parse_time)]
+    pub max_duration: Option<u32>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Inserts missing clears, moves every display set to a video frame boundary, keeping a minimum gap between
+/// subtitles, and writes the retimed stream.
+pub fn snap(args: &SnapArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let inserted = match args.max_duration {
+        Some(max_duration) => retime::insert_missing_clears(&mut stream, max_duration)?,
+        None => Vec::new()
+    };
+    let moved = retime::snap_to_frames(&mut stream, args.fps, args.min_gap)?;
+    if !output.json {
+        for timestamp in &inserted {
+            println!("inserted clear at {}", format_timestamp(*timestamp));
+        }
+    }
+    write_stream(&stream, &args.output, output, json!({ "inserted_clears": inserted, "moved_display_sets": moved }))
+}
+
+#[derive(Args, Debug)]
+pub struct FixTimingArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Minimum time a subtitle stays on screen, e.g. `1s` or `800ms`.
+    #[clap(long, value_parser = parse_time, default_value = "0")]
+    pub min_duration: u32,
+
+    /// Minimum time between two subtitles, e.g. `84ms` (two frames at 23.976 fps).
+    #[clap(long, value_parser = parse_time, default_value = "0")]
+    pub min_gap: u32,
+
+    /// Clear subtitles that are never cleared before the next epoch after this time on screen, e.g. `10s`.
+    #[clap(long, value_parser = parse_time)]
+    pub max_duration: Option<u32>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Inserts missing clears, extends too short subtitles, trims overlaps and enforces a minimum gap, prints every
+/// adjustment and writes the corrected stream.
+pub fn fix_timing(args: &FixTimingArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let inserted = match args.max_duration {
+        Some(max_duration) => retime::insert_missing_clears(&mut stream, max_duration)?,
+        None => Vec::new()
+    };
+    let adjustments = retime::enforce_timing(&mut stream, PgsTimingRules { min_duration: args.min_duration, min_gap: args.min_gap });
+    if !output.json {
+        for timestamp in &inserted {
+            println!("inserted clear at {}", format_timestamp(*timestamp));
+        }
+        for adjustment in &adjustments {
+            println!("display set {}: {} -> {} ({:?})", adjustment.display_set, format_timestamp(adjustment.old_timestamp),
+                format_timestamp(adjustment.new_timestamp), adjustment.reason);
+        }
+    }
+    let adjustments: Vec<_> = adjustments.iter().map(|adjustment| json!({
+        "display_set": adjustment.display_set,
+        "old_pts": adjustment.old_timestamp,
+        "new_pts": adjustment.new_timestamp,
+        "reason": format!("{:?}", adjustment.reason)
+    })).collect();
+    write_stream(&stream, &args.output, output, json!({ "inserted_clears": inserted, "adjustments": adjustments }))
+}
+
+#[derive(Args, Debug)]
+pub struct RemapArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Anchor mapping an old time to its new time, e.g. `00:20:00=00:20:01.500`. Repeat for every anchor.
+    #[clap(long = "anchor", value_parser = parse_anchor, required = true)]
+    pub anchors: Vec<(u32, u32)>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Parses an `<old>=<new>` anchor.
+fn parse_anchor(value: &str) -> Result<(u32, u32), String> {
+    let (old, new) = value.split_once('=').ok_or(format!("invalid anchor `{value}`, expected e.g. 00:20:00=00:20:01.500"))?;
+    Ok((parse_time(old.trim())?, parse_time(new.trim())?))
+}
+
+/// Retimes the stream piecewise-linearly through the anchors and writes the retimed stream.
+pub fn remap(args: &RemapArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let mut anchors = args.anchors.clone();
+    anchors.sort_unstable();
+    let clamped = retime::remap(&mut stream, &anchors)?;
+    if clamped > 0 {
+        output.warn(&format!("{clamped} timestamps were clamped to the valid range"));
+    }
+    write_stream(&stream, &args.output, output, json!({ "anchors": anchors.len(), "clamped_timestamps": clamped }))
+}
+
+#[derive(Args, Debug)]
+pub struct ChaptersArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Chapter start and the offset of its subtitles, e.g. `00:22:10=-1500ms`. Repeat for every chapter.
+    #[clap(long = "chapter", allow_hyphen_values = true, value_parser = parse_chapter, required = true)]
+    pub chapters: Vec<PgsChapterOffset>,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Parses a `<start>=<offset>` chapter.
+fn parse_chapter(value: &str) -> Result<PgsChapterOffset, String> {
+    let (start, offset) = value.split_once('=').ok_or(format!("invalid chapter `{value}`, expected e.g. 00:22:10=-1500ms"))?;
+    Ok(PgsChapterOffset { start: parse_time(start.trim())?, offset: parse_offset(offset.trim())? })
+}
+
+/// Shifts the subtitles of every chapter by its own offset and writes the retimed stream.
+pub fn chapters(args: &ChaptersArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let mut chapters = args.chapters.clone();
+    chapters.sort_unstable_by_key(|chapter| chapter.start);
+    let moved = retime::shift_chapters(&mut stream, &chapters)?;
+    write_stream(&stream, &args.output, output, json!({ "chapters": chapters.len(), "moved_display_sets": moved }))
+}
+
+#[derive(Args, Debug)]
+pub struct FrameRateArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// New frame rate: 23.976, 24, 25, 29.97, 50 or 59.94.
+    #[clap(long)]
+    pub fps: f64,
+
+    /// Also move every display set to the nearest frame of the new frame rate.
+    #[clap(long)]
+    pub snap: bool,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Rewrites the frame rate code of every PCS, optionally snapping to the new frames, and writes the stream.
+pub fn frame_rate(args: &FrameRateArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+
+    let report = retime::set_frame_rate(&mut stream, args.fps, args.snap)?;
+    if !output.json {
+        println!("{} compositions changed, {} display sets snapped to frames", report.compositions_changed, report.moved_display_sets);
+    }
+    write_stream(&stream, &args.output, output, json!({
+        "compositions_changed": report.compositions_changed,
+        "moved_display_sets": report.moved_display_sets
+    }))
+}
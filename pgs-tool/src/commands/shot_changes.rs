@@ -0,0 +1,76 @@
+use std::{fs, path::PathBuf, process::ExitCode};
+
+use clap::Args;
+use serde_json::{json, Value};
+
+use pgs_parse::{analysis::{check_shot_changes, PgsEventEdge, PgsShotChangeOptions}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::{format_timestamp, parse_time, OutputArgs}};
+
+#[derive(Args, Debug)]
+pub struct ShotChangesArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Text file with the time of one shot change per line, e.g. `00:01:02.500`; empty lines and lines starting
+    /// with `#` are skipped.
+    #[clap(long)]
+    pub shots: PathBuf,
+
+    /// Frame rate of the video.
+    #[clap(long, default_value_t = 23.976)]
+    pub fps: f64,
+
+    /// In-times closer than this many frames to a shot change should start on it.
+    #[clap(long, default_value_t = 12)]
+    pub in_frames: u32,
+
+    /// Out-times closer than this many frames to a shot change should end `--gap` frames before it.
+    #[clap(long, default_value_t = 12)]
+    pub out_frames: u32,
+
+    /// Frames between a snapped out-time and the shot change.
+    #[clap(long, default_value_t = 2)]
+    pub gap: u32,
+}
+
+/// Reads the shot changes, one time per line.
+fn read_shot_changes(path: &PathBuf) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)?;
+    let lines = text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+    Ok(lines.map(parse_time).collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Reports subtitle in- and out-times too close to a shot change, with the times they should be snapped to. The
+/// command exits with a failure code when any violation is found.
+pub fn shot_changes(args: &ShotChangesArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let shot_changes = read_shot_changes(&args.shots)?;
+    let options = PgsShotChangeOptions { frame_rate: args.fps, in_frames: args.in_frames, out_frames: args.out_frames, out_gap_frames: args.gap };
+    let violations = check_shot_changes(parser.get_display_sets(), &shot_changes, &options)?;
+
+    let edge = |edge: PgsEventEdge| match edge {
+        PgsEventEdge::Start => "in",
+        PgsEventEdge::End => "out"
+    };
+    output.print(json!({
+        "file": args.input,
+        "shot_changes": shot_changes.len(),
+        "violations": violations.iter().map(|violation| json!({
+            "display_set": violation.display_set,
+            "edge": edge(violation.edge),
+            "pts": violation.timestamp,
+            "shot_change": violation.shot_change,
+            "frames": violation.frames,
+            "suggested": violation.suggested
+        })).collect::<Vec<Value>>()
+    }), || {
+        let mut lines: Vec<String> = violations.iter().map(|violation| format!("{} {}-time {:+} frames from the shot change at {}, snap to {}",
+            format_timestamp(violation.timestamp), edge(violation.edge), violation.frames,
+            format_timestamp(violation.shot_change), format_timestamp(violation.suggested))).collect();
+        lines.push(format!("{}: {} violations at {} shot changes", args.input, violations.len(), shot_changes.len()));
+        lines.join("\n")
+    })?;
+
+    Ok(if violations.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
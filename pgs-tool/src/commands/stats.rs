@@ -0,0 +1,261 @@
+use std::{fs::File, io::{BufWriter, Write}, path::PathBuf, process::ExitCode};
+
+use clap::Args;
+use serde_json::{json, Value};
+
+use pgs_parse::{analysis::{classify_positions, color_usage, coverage, detect_letterbox, event_sizes, find_duplicate_display_sets, find_window_overlaps, statistics, PgsColorReport, PgsColorUsage, PgsCoverage, PgsDuplicateDisplaySet, PgsEventSize, PgsLetterboxReport, PgsPositionSummary, PgsStatistics, PgsTimeSummary, PgsWindowOverlap}, export::{export_events, group_animated_events}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::{format_timestamp, parse_time, OutputArgs}};
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Runtime of the video (e.g. `01:52:30`) the coverage refers to; defaults to the end of the last subtitle.
+    #[clap(long, value_parser = parse_time)]
+    pub runtime: Option<u32>,
+
+    /// Also report color usage: colors per event, dominant fill and outline colors and alpha distribution.
+    #[clap(long)]
+    pub colors: bool,
+
+    /// Also report bitmap sizes: visible pixels, screen coverage and oversized events such as full-screen credits.
+    #[clap(long)]
+    pub sizes: bool,
+
+    /// Also write the statistics as a `section,key,value` CSV file.
+    #[clap(long)]
+    pub csv: Option<PathBuf>,
+}
+
+/// Formats a color as `#rrggbb`.
+fn format_color(usage: &PgsColorUsage) -> String {
+    let (red, green, blue) = usage.color;
+    format!("#{red:02x}{green:02x}{blue:02x}")
+}
+
+/// Quotes a CSV field holding commas, quotes or line breaks, doubling the quotes inside it.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string()
+    }
+}
+
+/// The analyses shown by the statistics report.
+struct Report {
+    stats: PgsStatistics,
+    /// Number of animated events.
+    animations: usize,
+    window_overlaps: Vec<PgsWindowOverlap>,
+    duplicates: Vec<PgsDuplicateDisplaySet>,
+    positions: PgsPositionSummary,
+    letterbox: Option<PgsLetterboxReport>,
+    coverage: PgsCoverage,
+    colors: Option<PgsColorReport>,
+    sizes: Option<Vec<PgsEventSize>>
+}
+
+/// Returns the rows of the statistics report as `(section, key, value)`; times are formatted as `hh:mm:ss.mmm`.
+fn report_rows(report: &Report) -> Vec<(&'static str, String, String)> {
+    let Report { stats, animations, window_overlaps, duplicates, positions, letterbox, coverage, colors, sizes } = report;
+    let summary = |section: &'static str, summary: &PgsTimeSummary| vec![
+        (section, "count".to_string(), summary.count.to_string()),
+        (section, "min".to_string(), format_timestamp(summary.min)),
+        (section, "max".to_string(), format_timestamp(summary.max)),
+        (section, "mean".to_string(), format_timestamp(summary.mean)),
+        (section, "total".to_string(), format_timestamp(summary.total.min(u32::MAX as u64) as u32))
+    ];
+
+    let mut rows = vec![
+        ("events", "count".to_string(), stats.events.to_string()),
+        ("events", "forced".to_string(), stats.forced_events.to_string()),
+        ("events", "overlaps".to_string(), stats.overlaps.len().to_string()),
+        ("events", "window_overlaps".to_string(), window_overlaps.len().to_string()),
+        ("events", "duplicates".to_string(), duplicates.len().to_string()),
+        ("events", "animations".to_string(), animations.to_string())
+    ];
+    rows.extend(summary("durations", &stats.durations));
+    rows.extend(summary("gaps", &stats.gaps));
+    let last_bucket = stats.duration_histogram.len() - 1;
+    rows.extend(stats.duration_histogram.iter().enumerate().map(|(seconds, count)| {
+        let bucket = if seconds == last_bucket { format!("{seconds}s+") } else { format!("{seconds}-{}s", seconds + 1) };
+        ("duration_histogram", bucket, count.to_string())
+    }));
+    rows.extend([
+        ("positions", "bottom".to_string(), positions.bottom.to_string()),
+        ("positions", "top".to_string(), positions.top.to_string()),
+        ("positions", "center".to_string(), positions.center.to_string()),
+        ("positions", "summary".to_string(), positions.to_string())
+    ]);
+    if let Some(letterbox) = letterbox {
+        let (x, y, width, height) = letterbox.active_picture;
+        rows.extend([
+            ("picture", "aspect_ratio".to_string(), format!("{:.2}:1", letterbox.aspect_ratio)),
+            ("picture", "active".to_string(), format!("{width}x{height}+{x}+{y}")),
+            ("picture", "letterboxed".to_string(), letterbox.is_letterboxed().to_string()),
+            ("picture", "events_inside".to_string(), format!("{}/{}", letterbox.events_inside, letterbox.events))
+        ]);
+    }
+    rows.extend(stats.per_minute.iter().enumerate().map(|(minute, count)| ("per_minute", minute.to_string(), count.to_string())));
+    rows.extend([
+        ("coverage", "runtime".to_string(), format_timestamp(coverage.runtime)),
+        ("coverage", "covered".to_string(), format_timestamp(coverage.covered.min(u32::MAX as u64) as u32)),
+        ("coverage", "percent".to_string(), format!("{:.1}", coverage.percentage()))
+    ]);
+    if let Some((start, end)) = coverage.longest_gap {
+        rows.push(("coverage", "longest_gap".to_string(), format!("{} - {}", format_timestamp(start), format_timestamp(end))));
+    }
+    rows.extend(coverage.per_minute.iter().enumerate().map(|(minute, coverage)| ("coverage_per_minute", minute.to_string(), format!("{:.1}", coverage.percentage()))));
+    if let Some(colors) = colors {
+        let alpha = |range: std::ops::RangeInclusive<usize>| colors.alpha_histogram[range].iter().sum::<u64>().to_string();
+        rows.extend([
+            ("colors", "max_per_event".to_string(), colors.max_colors().to_string()),
+            ("colors", "events_over_3".to_string(), colors.events_exceeding(3).to_string()),
+            ("colors", "fill".to_string(), colors.fill.map_or("-".to_string(), |fill| format_color(&fill))),
+            ("colors", "outline".to_string(), colors.outline.map_or("-".to_string(), |outline| format_color(&outline))),
+            ("alpha", "transparent".to_string(), alpha(0..=0)),
+            ("alpha", "translucent".to_string(), alpha(1..=254)),
+            ("alpha", "opaque".to_string(), alpha(255..=255))
+        ]);
+    }
+    if let Some(sizes) = sizes {
+        let max_percentage = sizes.iter().map(|size| size.screen_percentage).fold(0.0, f64::max);
+        rows.extend([
+            ("sizes", "max_screen".to_string(), format!("{max_percentage:.1}%")),
+            ("sizes", "max_visible".to_string(), sizes.iter().map(|size| size.visible_pixels).max().unwrap_or(0).to_string()),
+            ("sizes", "oversized".to_string(), sizes.iter().filter(|size| size.oversized).count().to_string())
+        ]);
+        rows.extend(sizes.iter().filter(|size| size.oversized).map(|size| {
+            ("oversized", format_timestamp(size.start), format!("display set {} {}x{} ({:.1}%)", size.display_set, size.width, size.height, size.screen_percentage))
+        }));
+    }
+    rows
+}
+
+/// Prints the timing statistics of the stream: durations, gaps, overlaps (in time and in windows not cleared
+/// between display sets), duplicate display sets, animations, screen positions, the active picture of letterboxed
+/// tracks, per-minute density, coverage of the runtime and forced counts, and optionally the color usage and bitmap
+/// sizes.
+pub fn stats(args: &StatsArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let events = export_events(parser.get_display_sets());
+    let stats = statistics(&events);
+    let coverage = coverage(&events, args.runtime);
+    let window_overlaps = find_window_overlaps(parser.get_display_sets());
+    let duplicates = find_duplicate_display_sets(parser.get_segments())?;
+    let positions = PgsPositionSummary::from_positions(&classify_positions(parser.get_display_sets()));
+    let letterbox = detect_letterbox(parser.get_display_sets());
+    let colors = match args.colors {
+        true => Some(color_usage(parser.get_display_sets())?),
+        false => None
+    };
+    let sizes = match args.sizes {
+        true => Some(event_sizes(parser.get_display_sets())?),
+        false => None
+    };
+    let animations = group_animated_events(parser.get_display_sets()).iter().filter(|event| event.animated).count();
+    let report = Report { stats, animations, window_overlaps, duplicates, positions, letterbox, coverage, colors, sizes };
+    let rows = report_rows(&report);
+    let Report { stats, animations, window_overlaps, duplicates, positions, letterbox, coverage, colors, sizes } = &report;
+
+    if let Some(path) = &args.csv {
+        let mut csv = BufWriter::new(File::create(path)?);
+        writeln!(csv, "section,key,value")?;
+        for (section, key, value) in &rows {
+            writeln!(csv, "{section},{},{}", csv_field(key), csv_field(value))?;
+        }
+        csv.flush()?;
+    }
+
+    let summary = |summary: &PgsTimeSummary| json!({
+        "count": summary.count,
+        "min": summary.min,
+        "max": summary.max,
+        "mean": summary.mean,
+        "total": summary.total
+    });
+    output.print(json!({
+        "file": args.input,
+        "events": stats.events,
+        "forced_events": stats.forced_events,
+        "animations": animations,
+        "durations": summary(&stats.durations),
+        "duration_histogram": stats.duration_histogram,
+        "gaps": summary(&stats.gaps),
+        "overlaps": stats.overlaps.iter().map(|(first, second)| json!([first, second])).collect::<Vec<Value>>(),
+        "window_overlaps": window_overlaps.iter().map(|overlap| json!({
+            "first": overlap.first,
+            "second": overlap.second,
+            "window_id": overlap.window_id,
+            "pts": overlap.presentation_timestamp
+        })).collect::<Vec<Value>>(),
+        "duplicates": duplicates.iter().map(|duplicate| json!({
+            "original": duplicate.original,
+            "duplicate": duplicate.duplicate,
+            "pts": duplicate.presentation_timestamp
+        })).collect::<Vec<Value>>(),
+        "positions": {
+            "bottom": positions.bottom,
+            "top": positions.top,
+            "center": positions.center,
+            "summary": positions.to_string()
+        },
+        "picture": letterbox.as_ref().map(|letterbox| json!({
+            "aspect_ratio": letterbox.aspect_ratio,
+            "active": letterbox.active_picture,
+            "letterboxed": letterbox.is_letterboxed(),
+            "events": letterbox.events,
+            "events_inside": letterbox.events_inside
+        })),
+        "per_minute": stats.per_minute,
+        "coverage": {
+            "runtime": coverage.runtime,
+            "covered": coverage.covered,
+            "percent": coverage.percentage(),
+            "longest_gap": coverage.longest_gap.map(|(start, end)| json!([start, end])),
+            "per_minute": coverage.per_minute.iter().map(|minute| minute.covered).collect::<Vec<_>>()
+        },
+        "colors": colors.as_ref().map(|colors| json!({
+            "per_event": colors.events.iter().map(|event| event.colors).collect::<Vec<_>>(),
+            "fill": colors.fill.as_ref().map(format_color),
+            "outline": colors.outline.as_ref().map(format_color),
+            "alpha_histogram": colors.alpha_histogram
+        })),
+        "sizes": sizes.as_ref().map(|sizes| sizes.iter().map(|size| json!({
+            "display_set": size.display_set,
+            "pts": size.start,
+            "width": size.width,
+            "height": size.height,
+            "visible_pixels": size.visible_pixels,
+            "bounds": size.bounds.map(|(x, y, width, height)| json!([x, y, width, height])),
+            "screen_percent": size.screen_percentage,
+            "oversized": size.oversized
+        })).collect::<Vec<Value>>())
+    }), || {
+        let mut lines: Vec<String> = Vec::new();
+        let mut section = "";
+        for (row_section, key, value) in &rows {
+            if *row_section != section {
+                section = row_section;
+                lines.push(format!("{section}:"));
+            }
+            lines.push(format!("  {key:<15} {value}"));
+        }
+        lines.join("\n")
+    })?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("00:00:01.250"), "00:00:01.250");
+        assert_eq!(csv_field("75% bottom, 25% top"), "\"75% bottom, 25% top\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fs::{self, File}, io::BufWriter, path::{Path, PathBuf}, process::ExitCode};
+
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{export::{export_events, write_bdn, PgsBdnOptions}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::{write_image, ImageFormat, OutputArgs, ProgressArgs}};
+
+#[derive(Args, Debug)]
+pub struct ToBdnArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Output project directory, created if it does not exist.
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Video frame rate used for the timecodes (defaults to the frame rate of the stream, or 23.976 if it is unknown).
+    #[clap(long)]
+    pub fps: Option<f64>,
+
+    /// ISO 639-2 language code written into the BDN description.
+    #[clap(long, default_value = "eng")]
+    pub language: String,
+
+    /// Title written into the BDN description (defaults to the input file name). The XML file is always named after
+    /// the input file.
+    #[clap(long)]
+    pub title: Option<String>,
+
+    #[clap(flatten)]
+    pub progress: ProgressArgs,
+}
+
+/// Exports the stream as a BDN project: one PNG per subtitle event plus an XML file describing timing and placement.
+pub fn to_bdn(args: &ToBdnArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let display_sets = parser.get_display_sets();
+    let events = export_events(display_sets);
+
+    let stem = Path::new(&args.input).file_stem().map_or("subtitle".to_string(), |stem| stem.to_string_lossy().to_string());
+    let first_pcs = display_sets.iter().find_map(|ds| ds.pcs.as_ref());
+    let options = PgsBdnOptions {
+        title: args.title.clone().unwrap_or_else(|| stem.clone()),
+        language: args.language.clone(),
+        frame_rate: args.fps.or_else(|| first_pcs.and_then(|pcs| pcs.frames_per_second())).unwrap_or(PgsBdnOptions::default().frame_rate),
+        video_height: first_pcs.map_or(1080, |pcs| pcs.height),
+        image_extension: ImageFormat::Png.extension().to_string()
+    };
+
+    fs::create_dir_all(&args.output)?;
+    for (done, event) in events.iter().enumerate() {
+        let pixels = display_sets[event.display_set].get_decoded_image(false)?;
+        write_image(&args.output.join(event.image_file_name(&options.image_extension)), &pixels, false, ImageFormat::Png)?;
+        args.progress.report(output, "to-bdn", done + 1, events.len());
+    }
+
+    let xml_path = args.output.join(format!("{stem}.xml"));
+    write_bdn(BufWriter::new(File::create(&xml_path)?), &events, &options)?;
+
+    output.print(json!({
+        "output": args.output,
+        "xml": xml_path,
+        "events": events.len()
+    }), || format!("{} events written to {}", events.len(), xml_path.display()))?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,71 @@
+use std::{fs::File, io::BufWriter, process::ExitCode};
+
+use clap::{Args, ValueEnum};
+use serde_json::json;
+
+use pgs_parse::{export::{export_events, write_srt, PgsTextEvent}, ocr::{extract_text, PgsOcrEngine, PgsTextExtractOptions}, PgsParser};
+
+use crate::{commands::CommandResult, helpers::OutputArgs};
+
+/// OCR engines that can be selected on the command line.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OcrEngineKind {
+    Tesseract,
+}
+
+#[derive(Args, Debug)]
+pub struct ToSrtArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// Output SRT file.
+    #[clap(short, long)]
+    pub output: String,
+
+    /// Recognize the subtitle text with an OCR engine (requires the `ocr` feature).
+    #[clap(long, value_enum)]
+    pub ocr: Option<OcrEngineKind>,
+
+    /// OCR language code.
+    #[clap(long, default_value = "eng")]
+    pub lang: String,
+}
+
+#[cfg(feature = "ocr")]
+fn create_engine(kind: OcrEngineKind, language: &str) -> Result<Box<dyn PgsOcrEngine>, Box<dyn std::error::Error>> {
+    match kind {
+        OcrEngineKind::Tesseract => Ok(Box::new(pgs_parse::ocr::PgsTesseractEngine::new(language)))
+    }
+}
+
+#[cfg(not(feature = "ocr"))]
+fn create_engine(_kind: OcrEngineKind, _language: &str) -> Result<Box<dyn PgsOcrEngine>, Box<dyn std::error::Error>> {
+    Err("pgs-tool was built without the `ocr` feature".into())
+}
+
+/// Writes an SRT file with one entry per subtitle event. With OCR the recognized text of all objects on screen
+/// forms an entry, and consecutive display sets with the same text are merged; without OCR each entry carries a
+/// `[NNNNN]` placeholder naming the display set, matching the image names written by `extract-images`.
+pub fn to_srt(args: &ToSrtArgs, output: &OutputArgs) -> CommandResult {
+    let mut engine = args.ocr.map(|kind| create_engine(kind, &args.lang)).transpose()?;
+
+    let text_events = match engine.as_mut() {
+        Some(engine) => extract_text(&args.input, &mut **engine, &PgsTextExtractOptions::default())?,
+        None => {
+            let parser = PgsParser::parse(&args.input)?;
+            let events = export_events(parser.get_display_sets());
+            events.iter().map(|event| {
+                PgsTextEvent { start: event.start, end: event.end_or_default(), text: format!("[{:05}]", event.display_set) }
+            }).collect()
+        }
+    };
+
+    write_srt(BufWriter::new(File::create(&args.output)?), &text_events)?;
+
+    output.print(json!({
+        "output": args.output,
+        "events": text_events.len(),
+        "ocr": args.ocr.is_some()
+    }), || format!("{} events written to {}", text_events.len(), args.output))?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,44 @@
+use std::process::ExitCode;
+
+use clap::Args;
+use serde_json::json;
+
+use pgs_parse::{validate as validate_stream, PgsParser};
+
+use crate::{commands::CommandResult, helpers::OutputArgs};
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Input SUP file.
+    pub input: String,
+}
+
+/// Runs the spec-conformance checks and prints the report. The command exits with a failure code when any
+/// error-level violation is found; warnings alone do not fail validation.
+pub fn validate(args: &ValidateArgs, output: &OutputArgs) -> CommandResult {
+    let parser = PgsParser::parse(&args.input)?;
+    let report = validate_stream(&parser);
+
+    let issues: Vec<_> = report.issues.iter().map(|issue| json!({
+        "severity": format!("{:?}", issue.severity),
+        "category": format!("{:?}", issue.category),
+        "display_set": issue.display_set,
+        "composition_number": issue.composition_number,
+        "pts": issue.presentation_timestamp,
+        "message": issue.message
+    })).collect();
+    output.print(json!({
+        "file": args.input,
+        "valid": report.is_valid(),
+        "errors": report.errors().count(),
+        "warnings": report.warnings().count(),
+        "object_buffer_peaks": report.object_buffer_peaks,
+        "issues": issues
+    }), || {
+        let mut lines: Vec<String> = report.issues.iter().map(|issue| issue.to_string()).collect();
+        lines.push(format!("{}: {} errors, {} warnings", args.input, report.errors().count(), report.warnings().count()));
+        lines.join("\n")
+    })?;
+
+    Ok(if report.is_valid() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use clap::{Args, ValueEnum};
+use serde_json::json;
+
+use pgs_parse::{transform::{stamp_overlay, PgsOverlay, PgsOverlayAnchor}, PgsParser};
+
+use crate::{commands::{write_stream, CommandResult}, helpers::{read_png_rgba, OutputArgs}};
+
+/// Position of the watermark on each subtitle bitmap.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl From<Anchor> for PgsOverlayAnchor {
+    fn from(anchor: Anchor) -> Self {
+        match anchor {
+            Anchor::TopLeft => PgsOverlayAnchor::TopLeft,
+            Anchor::TopRight => PgsOverlayAnchor::TopRight,
+            Anchor::BottomLeft => PgsOverlayAnchor::BottomLeft,
+            Anchor::BottomRight => PgsOverlayAnchor::BottomRight,
+            Anchor::Center => PgsOverlayAnchor::Center,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct WatermarkArgs {
+    /// Input SUP file.
+    pub input: String,
+
+    /// PNG image to draw onto every subtitle, blended by its alpha channel.
+    #[clap(long)]
+    pub image: String,
+
+    /// Position of the image on each subtitle bitmap.
+    #[clap(long, value_enum, default_value_t = Anchor::BottomRight)]
+    pub anchor: Anchor,
+
+    /// Output SUP file.
+    #[clap(short, long)]
+    pub output: String,
+}
+
+/// Draws a PNG image onto every subtitle bitmap and writes the re-encoded stream.
+pub fn watermark(args: &WatermarkArgs, output: &OutputArgs) -> CommandResult {
+    let mut stream = PgsParser::parse(&args.input)?.into_stream();
+    let (width, height, rgba) = read_png_rgba(Path::new(&args.image))?;
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(format!("watermark image {width}x{height} is too large").into());
+    }
+
+    let overlay = PgsOverlay { width: width as u16, height: height as u16, rgba };
+    let stamped = stamp_overlay(&mut stream, &overlay, args.anchor.into())?;
+    if !output.json {
+        println!("{stamped} subtitle bitmaps watermarked");
+    }
+    write_stream(&stream, &args.output, output, json!({ "image": args.image, "objects_stamped": stamped }))
+}
@@ -0,0 +1,82 @@
+use std::{error::Error, fs::File, io::{BufReader, BufWriter}, path::Path};
+
+use clap::ValueEnum;
+use tiff::encoder::{colortype, TiffEncoder};
+
+/// Image file formats supported when exporting decoded subtitle bitmaps.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Tiff,
+}
+
+impl ImageFormat {
+    /// File extension used for images written in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Tiff => "tiff",
+        }
+    }
+}
+
+/// Converts decoded pixels into a flat sample buffer: RGBA for ARGB pixels, a single luma sample for gray pixels.
+fn to_samples(pixels: &[Vec<u32>], gray: bool) -> Vec<u8> {
+    pixels.iter().flatten().flat_map(|&pixel| {
+        if gray {
+            vec![(pixel & 0xFF) as u8]
+        } else {
+            vec![(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, (pixel >> 24) as u8]
+        }
+    }).collect()
+}
+
+/// Writes decoded pixels (as returned by `PgsDisplaySet::get_decoded_image`) to an image file.
+pub fn write_image(path: &Path, pixels: &[Vec<u32>], gray: bool, format: ImageFormat) -> Result<(), Box<dyn Error>> {
+    let height = pixels.len() as u32;
+    let width = pixels.first().map_or(0, |row| row.len()) as u32;
+    let samples = to_samples(pixels, gray);
+    let file = BufWriter::new(File::create(path)?);
+
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(file, width, height);
+            encoder.set_color(if gray { png::ColorType::Grayscale } else { png::ColorType::Rgba });
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&samples)?;
+        },
+        ImageFormat::Tiff => {
+            let mut encoder = TiffEncoder::new(file)?;
+            if gray {
+                encoder.write_image::<colortype::Gray8>(width, height, &samples)?;
+            } else {
+                encoder.write_image::<colortype::RGBA8>(width, height, &samples)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a PNG image as 8-bit RGBA samples, expanding palette, grayscale and RGB images.
+///
+/// # Returns
+/// The width, height and samples of the image.
+pub fn read_png_rgba(path: &Path) -> Result<(u32, u32, Vec<u8>), Box<dyn Error>> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let samples = &buffer[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => samples.to_vec(),
+        png::ColorType::Rgb => samples.chunks_exact(3).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => samples.chunks_exact(2).flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]]).collect(),
+        png::ColorType::Grayscale => samples.iter().flat_map(|&gray| [gray, gray, gray, 255]).collect(),
+        png::ColorType::Indexed => return Err("unsupported indexed PNG".into())
+    };
+    Ok((info.width, info.height, rgba))
+}
@@ -0,0 +1,7 @@
+mod image;
+mod output;
+mod time;
+
+pub use image::*;
+pub use output::*;
+pub use time::*;
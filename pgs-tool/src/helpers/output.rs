@@ -0,0 +1,63 @@
+use std::io::{self, Write};
+
+use clap::Args;
+use serde_json::{json, Value};
+
+/// Output options shared by every subcommand.
+#[derive(Args, Debug, Clone, Copy)]
+pub struct OutputArgs {
+    /// Print results as a JSON document on stdout instead of human-readable text.
+    #[clap(long, global = true)]
+    pub json: bool,
+}
+
+impl OutputArgs {
+    /// Prints the result of a command: `value` as a pretty JSON document in JSON mode, otherwise `text`.
+    pub fn print(&self, value: Value, text: impl FnOnce() -> String) -> serde_json::Result<()> {
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&value)?);
+        } else {
+            println!("{}", text());
+        }
+        Ok(())
+    }
+
+    /// Prints an error, as a JSON document on stdout in JSON mode and on stderr otherwise.
+    pub fn error(&self, message: &str) {
+        if self.json {
+            println!("{}", json!({ "error": message }));
+        } else {
+            eprintln!("error: {message}");
+        }
+    }
+
+    /// Prints a warning on stderr.
+    pub fn warn(&self, message: &str) {
+        eprintln!("warning: {message}");
+    }
+}
+
+/// Progress option of the subcommands running long operations item by item.
+#[derive(Args, Debug, Clone, Copy)]
+pub struct ProgressArgs {
+    /// Report the progress on stderr (as JSON lines together with `--json`).
+    #[clap(long)]
+    pub progress: bool,
+}
+
+impl ProgressArgs {
+    /// Reports that `done` of `total` items of a long operation are finished, in the output mode of `output`.
+    pub fn report(&self, output: &OutputArgs, task: &str, done: usize, total: usize) {
+        if !self.progress {
+            return;
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = if output.json {
+            writeln!(stderr, "{}", json!({ "task": task, "done": done, "total": total }))
+        } else if done >= total {
+            writeln!(stderr, "\r{task}: {done}/{total}")
+        } else {
+            write!(stderr, "\r{task}: {done}/{total}").and_then(|_| stderr.flush())
+        };
+    }
+}
@@ -0,0 +1,100 @@
+/// Number of PGS clock ticks per millisecond (timestamps use a 90 kHz clock).
+pub const TICKS_PER_MS: u64 = 90;
+
+/// Formats a 90 kHz timestamp as `hh:mm:ss.mmm`.
+pub fn format_timestamp(ticks: u32) -> String {
+    let ms = ticks as u64 / TICKS_PER_MS;
+    format!("{:02}:{:02}:{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+/// Parses an unsigned time given as `hh:mm:ss[.mmm]`, `mm:ss[.mmm]`, `<n>ms`, `<n>s` or plain milliseconds,
+/// returning the number of milliseconds.
+fn parse_millis(text: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid time '{text}'");
+    // `f64` parsing also accepts signs, `inf` and `nan`, which are no unsigned time
+    let number = |part: &str| match part.starts_with(['+', '-']) {
+        true => Err(invalid()),
+        false => part.parse::<f64>().ok().filter(|value| value.is_finite()).ok_or_else(invalid)
+    };
+    if let Some(ms) = text.strip_suffix("ms") {
+        return number(ms);
+    }
+    if let Some(seconds) = text.strip_suffix('s') {
+        return number(seconds).map(|seconds| seconds * 1000.0);
+    }
+    if text.contains(':') {
+        let mut seconds = 0.0;
+        for part in text.split(':') {
+            seconds = seconds * 60.0 + number(part)?;
+        }
+        return Ok(seconds * 1000.0);
+    }
+    number(text)
+}
+
+/// Parses a point in time such as `00:10:00`, `90s` or `1500ms` into 90 kHz ticks.
+pub fn parse_time(text: &str) -> Result<u32, String> {
+    let ms = parse_millis(text)?;
+    let ticks = (ms * TICKS_PER_MS as f64).round();
+    if ticks > u32::MAX as f64 {
+        return Err(format!("time '{text}' is out of range"));
+    }
+    Ok(ticks as u32)
+}
+
+/// Parses a signed time offset such as `-1500ms`, `+2s`, `0.5s` or `-00:00:01.250` into 90 kHz ticks.
+pub fn parse_offset(text: &str) -> Result<i64, String> {
+    let (sign, value) = match text.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, text.strip_prefix('+').unwrap_or(text))
+    };
+    let ticks = (parse_millis(value)? * TICKS_PER_MS as f64).round();
+    // An offset moving a timestamp past the whole 32-bit range cannot be meant
+    if ticks > u32::MAX as f64 {
+        return Err(format!("time '{text}' is out of range"));
+    }
+    Ok(sign * ticks as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_millis() {
+        assert_eq!(parse_millis("1500ms"), Ok(1500.0));
+        assert_eq!(parse_millis("2s"), Ok(2000.0));
+        assert_eq!(parse_millis("00:00:01.250"), Ok(1250.0));
+        assert_eq!(parse_millis("01:30"), Ok(90_000.0));
+        assert_eq!(parse_millis("250"), Ok(250.0));
+        for text in ["-1500ms", "+2s", "-0", "nan", "inf", "-inf", "00:-01:00", "1.5x", ""] {
+            assert!(parse_millis(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("00:00:01.250"), Ok(112_500));
+        assert_eq!(parse_time("1500ms"), Ok(135_000));
+        assert_eq!(parse_time("0"), Ok(0));
+        for text in ["-1500ms", "+2s", "-0", "nan", "inf"] {
+            assert_eq!(parse_time(text), Err(format!("invalid time '{text}'")));
+        }
+        // The largest 32-bit timestamp is about 13:15:21
+        assert_eq!(parse_time("13:15:00"), Ok(4_293_000_000));
+        assert_eq!(parse_time("13:16:00"), Err("time '13:16:00' is out of range".to_string()));
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("-1500ms"), Ok(-135_000));
+        assert_eq!(parse_offset("+2s"), Ok(180_000));
+        assert_eq!(parse_offset("0.5s"), Ok(45_000));
+        assert_eq!(parse_offset("-00:00:01.250"), Ok(-112_500));
+        assert_eq!(parse_offset("-0"), Ok(0));
+        for text in ["nan", "-inf", "+-2s", "--1s"] {
+            assert!(parse_offset(text).is_err(), "{text}");
+        }
+        assert_eq!(parse_offset("-13:16:00"), Err("time '-13:16:00' is out of range".to_string()));
+    }
+}
@@ -0,0 +1,135 @@
+//! # pgs-tool
+//!
+//! Command line tool for inspecting and editing PGS (Presentation Graphic Stream) subtitle files.
+
+mod commands;
+mod helpers;
+
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+#[derive(Parser, Debug)]
+#[clap(version, author = "Milan Bolaric", about = "Inspect and edit PGS (SUP) subtitle files", name = "pgs-tool")]
+struct Cli {
+    #[clap(flatten)]
+    output: helpers::OutputArgs,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List every segment with its offset, type, timestamps, size and key fields.
+    Dump(commands::DumpArgs),
+    /// Render every complete display set to numbered image files with a sidecar timing file.
+    ExtractImages(commands::ExtractImagesArgs),
+    /// Tile thumbnails of every Nth subtitle event with their start times into one image for visual checks.
+    ContactSheet(commands::ContactSheetArgs),
+    /// Extract the display sets shown within a time range.
+    Cut(commands::CutArgs),
+    /// Merge several streams with per-file offsets, interleaving their display sets by time.
+    Merge(commands::MergeArgs),
+    /// Concatenate episode parts into a single stream.
+    Concat(commands::ConcatArgs),
+    /// Offset all timestamps and write the retimed stream.
+    Shift(commands::ShiftArgs),
+    /// Scale all timestamps from one frame rate to another, e.g. to undo a PAL speed-up.
+    Stretch(commands::StretchArgs),
+    /// Move display sets to video frame boundaries with a minimum gap between subtitles.
+    Snap(commands::SnapArgs),
+    /// Rewrite the frame rate code of every composition, e.g. when moving from a 24p to a 25p master.
+    FrameRate(commands::FrameRateArgs),
+    /// Retime piecewise-linearly between anchor points, e.g. when reels drift by different amounts.
+    Remap(commands::RemapArgs),
+    /// Shift the subtitles of every chapter by its own offset, e.g. to follow an edit decision list.
+    Chapters(commands::ChaptersArgs),
+    /// Extend too short subtitles, trim overlaps and enforce a minimum gap between subtitles.
+    FixTiming(commands::FixTimingArgs),
+    /// Remove duplicate display sets, repeated objects and no-op palette updates.
+    Optimize(commands::OptimizeArgs),
+    /// Keep only forced or only non-forced subtitles.
+    Filter(commands::FilterArgs),
+    /// Renumber compositions and repair composition states, e.g. after merging or cutting streams.
+    Normalize(commands::NormalizeArgs),
+    /// Render a subtitle bitmap to the terminal (kitty, sixel or ANSI half-blocks).
+    Preview(commands::PreviewArgs),
+    /// Rescale positions, windows and bitmaps to another video resolution.
+    Scale(commands::ScaleArgs),
+    /// Move subtitles on screen, e.g. bottom subtitles up and away from a logo.
+    Reposition(commands::RepositionArgs),
+    /// Change the video size without scaling bitmaps, e.g. to remove letterboxing.
+    Canvas(commands::CanvasArgs),
+    /// Keep all windows inside the title-safe area, moving or shrinking them.
+    SafeArea(commands::SafeAreaArgs),
+    /// Composite all objects of every display set into one object, for decoders supporting a single object.
+    Flatten(commands::FlattenArgs),
+    /// Adjust palette brightness, contrast, gamma and opacity without touching the bitmaps.
+    Palette(commands::PaletteArgs),
+    /// Recolor subtitles to a fill, outline and shadow color scheme, e.g. yellow with a black outline.
+    Recolor(commands::RecolorArgs),
+    /// Draw a PNG image, e.g. a screener watermark, onto every subtitle bitmap.
+    Watermark(commands::WatermarkArgs),
+    /// Report timing statistics: durations, gaps, overlaps, screen positions, per-minute density, coverage and forced counts.
+    Stats(commands::StatsArgs),
+    /// Report decoding timestamps and optionally regenerate them from the decoder model.
+    Dts(commands::DtsArgs),
+    /// Report in- and out-times too close to shot changes, with the times to snap them to.
+    ShotChanges(commands::ShotChangesArgs),
+    /// Run the spec-conformance checks and exit with a failure code on violations.
+    Validate(commands::ValidateArgs),
+    /// Grade validation and analysis findings against a delivery profile and report pass or fail.
+    Quality(commands::QualityArgs),
+    /// Export the stream as BDN XML plus PNG images.
+    ToBdn(commands::ToBdnArgs),
+    /// Export the stream as SRT, with timing only or with OCR'd text.
+    ToSrt(commands::ToSrtArgs),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Dump(args) => commands::dump(&args, &cli.output),
+        Command::ExtractImages(args) => commands::extract_images(&args, &cli.output),
+        Command::ContactSheet(args) => commands::contact_sheet(&args, &cli.output),
+        Command::Cut(args) => commands::cut(&args, &cli.output),
+        Command::Merge(args) => commands::merge(&args, &cli.output),
+        Command::Concat(args) => commands::concat(&args, &cli.output),
+        Command::Shift(args) => commands::shift(&args, &cli.output),
+        Command::Stretch(args) => commands::stretch(&args, &cli.output),
+        Command::Snap(args) => commands::snap(&args, &cli.output),
+        Command::FrameRate(args) => commands::frame_rate(&args, &cli.output),
+        Command::Remap(args) => commands::remap(&args, &cli.output),
+        Command::Chapters(args) => commands::chapters(&args, &cli.output),
+        Command::FixTiming(args) => commands::fix_timing(&args, &cli.output),
+        Command::Optimize(args) => commands::optimize(&args, &cli.output),
+        Command::Filter(args) => commands::filter(&args, &cli.output),
+        Command::Normalize(args) => commands::normalize(&args, &cli.output),
+        Command::Preview(args) => commands::preview(&args, &cli.output),
+        Command::Scale(args) => commands::scale(&args, &cli.output),
+        Command::Reposition(args) => commands::reposition(&args, &cli.output),
+        Command::Canvas(args) => commands::canvas(&args, &cli.output),
+        Command::SafeArea(args) => commands::safe_area(&args, &cli.output),
+        Command::Flatten(args) => commands::flatten(&args, &cli.output),
+        Command::Palette(args) => commands::palette(&args, &cli.output),
+        Command::Recolor(args) => commands::recolor(&args, &cli.output),
+        Command::Watermark(args) => commands::watermark(&args, &cli.output),
+        Command::Stats(args) => commands::stats(&args, &cli.output),
+        Command::Dts(args) => commands::dts(&args, &cli.output),
+        Command::ShotChanges(args) => commands::shot_changes(&args, &cli.output),
+        Command::Validate(args) => commands::validate(&args, &cli.output),
+        Command::Quality(args) => commands::quality(&args, &cli.output),
+        Command::ToBdn(args) => commands::to_bdn(&args, &cli.output),
+        Command::ToSrt(args) => commands::to_srt(&args, &cli.output),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            cli.output.error(&err.to_string());
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Runs every subcommand with `--json` on a small SUP file and checks the fields of the printed JSON document.
+
+use std::{fs, path::{Path, PathBuf}, process::{self, Command}};
+
+use serde_json::Value;
+
+/// A directory in the temporary directory holding the input SUP file and the command outputs, removed when
+/// dropped.
+struct Fixture(PathBuf);
+
+impl Fixture {
+    /// Creates the directory, named after `name` and the process id, with `input.sup` holding two events.
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("pgs-tool-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("input.sup"), [event(90_000, 0), clear(270_000, 1), event(450_000, 2), clear(630_000, 3)].concat()).unwrap();
+        Fixture(dir)
+    }
+
+    /// Returns the path of `name` in the directory.
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_string()
+    }
+
+    fn input(&self) -> String {
+        self.path("input.sup")
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The bytes of a segment of `segment_type` presented at `pts`.
+fn segment(segment_type: u8, pts: u32, payload: &[u8]) -> Vec<u8> {
+    let mut data = vec![0x50, 0x47];
+    data.extend(pts.to_be_bytes());
+    data.extend([0, 0, 0, 0, segment_type]);
+    data.extend((payload.len() as u16).to_be_bytes());
+    data.extend(payload);
+    data
+}
+
+/// An Epoch Start display set of a 1920x1080 video at 23.976 fps showing a white 4x2 object in window 0.
+fn event(pts: u32, composition_number: u8) -> Vec<u8> {
+    let rle = [0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00];
+    let mut ods = vec![0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, rle.len() as u8 + 4, 0x00, 0x04, 0x00, 0x02];
+    ods.extend(rle);
+    [
+        segment(0x16, pts, &[0x07, 0x80, 0x04, 0x38, 0x10, 0x00, composition_number, 0x80, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x03, 0xC0, 0x03, 0xE8]),
+        segment(0x17, pts, &[0x01, 0x00, 0x03, 0xC0, 0x03, 0xE8, 0x00, 0x04, 0x00, 0x02]),
+        segment(0x14, pts, &[0x00, 0x00, 0x00, 0x10, 0x80, 0x80, 0x00, 0x01, 0xEB, 0x80, 0x80, 0xFF]),
+        segment(0x15, pts, &ods),
+        segment(0x80, pts, &[])
+    ].concat()
+}
+
+/// A Normal display set clearing window 0.
+fn clear(pts: u32, composition_number: u8) -> Vec<u8> {
+    [
+        segment(0x16, pts, &[0x07, 0x80, 0x04, 0x38, 0x10, 0x00, composition_number, 0x00, 0x00, 0x00, 0x00]),
+        segment(0x17, pts, &[0x01, 0x00, 0x03, 0xC0, 0x03, 0xE8, 0x00, 0x04, 0x00, 0x02]),
+        segment(0x80, pts, &[])
+    ].concat()
+}
+
+/// Runs `pgs-tool --json` with `args` and returns the JSON document printed on stdout.
+fn run(args: &[&str]) -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_pgs-tool")).arg("--json").args(args).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    serde_json::from_str(&stdout).unwrap_or_else(|error| panic!("{args:?} printed no JSON document ({error}): {stdout}"))
+}
+
+/// Asserts that `value` is an object with exactly the fields `keys`, in that order.
+#[track_caller]
+fn assert_keys(value: &Value, keys: &[&str]) {
+    let fields: Vec<&str> = value.as_object().unwrap_or_else(|| panic!("not an object: {value}")).keys().map(String::as_str).collect();
+    assert_eq!(fields, keys);
+}
+
+/// Asserts that `value` is the summary of a written stream: the command specific fields `keys`, followed by the
+/// output path and the number of written segments.
+#[track_caller]
+fn assert_written(value: &Value, keys: &[&str]) {
+    assert_keys(value, &[keys, &["output", "segments"]].concat());
+    assert!(value["segments"].as_u64().is_some_and(|segments| segments > 0), "{value}");
+}
+
+#[test]
+fn test_dump() {
+    let fixture = Fixture::new("dump");
+    let value = run(&["dump", &fixture.input()]);
+    assert_keys(&value, &["file", "segments", "error"]);
+    assert_eq!(value["segments"].as_array().unwrap().len(), 16);
+    assert_keys(&value["segments"][0], &["offset", "type", "pts", "dts", "size", "fields", "hexdump"]);
+    assert_keys(&value["segments"][0]["fields"], &["width", "height", "frame_rate", "composition_number", "composition_state",
+        "palette_update_flag", "palette_id", "composition_objects"]);
+}
+
+#[test]
+fn test_error() {
+    let fixture = Fixture::new("error");
+    assert_keys(&run(&["dump", &fixture.path("missing.sup")]), &["error"]);
+}
+
+#[test]
+fn test_extract_images() {
+    let fixture = Fixture::new("extract-images");
+    let value = run(&["extract-images", &fixture.input(), "-o", &fixture.path("images")]);
+    assert_keys(&value, &["output", "images", "timing"]);
+    assert_eq!(value["images"], 2);
+}
+
+#[test]
+fn test_contact_sheet() {
+    let fixture = Fixture::new("contact-sheet");
+    let value = run(&["contact-sheet", &fixture.input(), "-o", &fixture.path("sheet.png")]);
+    assert_keys(&value, &["output", "thumbnails", "width", "height"]);
+    assert_eq!(value["thumbnails"], 2);
+}
+
+#[test]
+fn test_cut() {
+    let fixture = Fixture::new("cut");
+    assert_written(&run(&["cut", &fixture.input(), "--from", "0", "--to", "4s", "-o", &fixture.path("output.sup")]), &["from", "to", "rebase"]);
+}
+
+#[test]
+fn test_merge() {
+    let fixture = Fixture::new("merge");
+    let (input, output) = (fixture.input(), fixture.path("output.sup"));
+    assert_written(&run(&["merge", &input, &input, "-o", &output]), &["inputs", "clamped_timestamps"]);
+    assert_written(&run(&["concat", &input, &input, "-o", &output]), &["inputs", "clamped_timestamps"]);
+}
+
+#[test]
+fn test_retime() {
+    let fixture = Fixture::new("retime");
+    let (input, output) = (fixture.input(), fixture.path("output.sup"));
+    assert_written(&run(&["shift", &input, "--by", "-500ms", "-o", &output]), &["clamped_timestamps"]);
+    assert_written(&run(&["stretch", &input, "--from-fps", "25", "--to-fps", "23.976", "-o", &output]), &["factor", "clamped_timestamps"]);
+    assert_written(&run(&["snap", &input, "--fps", "25", "-o", &output]), &["inserted_clears", "moved_display_sets"]);
+    assert_written(&run(&["frame-rate", &input, "--fps", "25", "--snap", "-o", &output]), &["compositions_changed", "moved_display_sets"]);
+    assert_written(&run(&["remap", &input, "--anchor", "0=0", "--anchor", "10s=11s", "-o", &output]), &["anchors", "clamped_timestamps"]);
+    assert_written(&run(&["chapters", &input, "--chapter", "0=1s", "-o", &output]), &["chapters", "moved_display_sets"]);
+    assert_written(&run(&["fix-timing", &input, "--min-duration", "1s", "-o", &output]), &["inserted_clears", "adjustments"]);
+}
+
+#[test]
+fn test_optimize() {
+    let fixture = Fixture::new("optimize");
+    let value = run(&["optimize", &fixture.input(), "-o", &fixture.path("output.sup")]);
+    assert_keys(&value, &["output", "display_sets_removed", "duplicates_removed", "blank_stripped", "acquisition_points_removed",
+        "objects_shared", "objects_recompressed", "objects_removed", "palettes_removed", "size_before", "size_after"]);
+}
+
+#[test]
+fn test_filter() {
+    let fixture = Fixture::new("filter");
+    let (input, output) = (fixture.input(), fixture.path("output.sup"));
+    assert_written(&run(&["filter", &input, "--non-forced", "-o", &output]), &["display_sets_removed"]);
+    assert_written(&run(&["normalize", &input, "-o", &output]), &["compositions_renumbered", "epoch_starts_added",
+        "acquisition_points_downgraded", "palette_update_flags_cleared"]);
+}
+
+#[test]
+fn test_preview() {
+    let fixture = Fixture::new("preview");
+    let value = run(&["preview", &fixture.input(), "--index", "0"]);
+    assert_keys(&value, &["display_set", "start", "end", "x", "y", "width", "height"]);
+    assert_eq!((&value["start"], &value["end"]), (&Value::from(90_000), &Value::from(270_000)));
+}
+
+#[test]
+fn test_geometry() {
+    let fixture = Fixture::new("geometry");
+    let (input, output) = (fixture.input(), fixture.path("output.sup"));
+    assert_written(&run(&["scale", &input, "--from", "1920x1080", "--to", "1280x720", "-o", &output]), &["from", "to"]);
+    assert_written(&run(&["reposition", &input, "--dy", "-10", "-o", &output]), &["moved_windows"]);
+    assert_written(&run(&["canvas", &input, "--to", "1920x800", "-o", &output]), &["to", "moved_windows"]);
+    assert_written(&run(&["safe-area", &input, "-o", &output]), &["adjustments"]);
+    assert_written(&run(&["flatten", &input, "-o", &output]), &["flattened_display_sets"]);
+}
+
+#[test]
+fn test_colors() {
+    let fixture = Fixture::new("colors");
+    let (input, output) = (fixture.input(), fixture.path("output.sup"));
+    assert_written(&run(&["palette", &input, "--brightness", "0.9", "-o", &output]), &["luminance_modified", "transparency_modified", "colors_reduced"]);
+    assert_written(&run(&["recolor", &input, "-o", &output]), &["palettes_modified"]);
+
+    let image = fixture.path("watermark.png");
+    let mut encoder = png::Encoder::new(fs::File::create(&image).unwrap(), 2, 1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(&[255, 0, 0, 255, 0, 0, 255, 128]).unwrap();
+    assert_written(&run(&["watermark", &input, "--image", &image, "-o", &output]), &["image", "objects_stamped"]);
+}
+
+#[test]
+fn test_stats() {
+    let fixture = Fixture::new("stats");
+    let value = run(&["stats", &fixture.input(), "--colors", "--sizes"]);
+    assert_keys(&value, &["file", "events", "forced_events", "animations", "durations", "duration_histogram", "gaps", "overlaps",
+        "window_overlaps", "duplicates", "positions", "picture", "per_minute", "coverage", "colors", "sizes"]);
+    assert_keys(&value["durations"], &["count", "min", "max", "mean", "total"]);
+    assert_keys(&value["positions"], &["bottom", "top", "center", "summary"]);
+    assert_keys(&value["coverage"], &["runtime", "covered", "percent", "longest_gap", "per_minute"]);
+    assert_keys(&value["colors"], &["per_event", "fill", "outline", "alpha_histogram"]);
+    assert_keys(&value["sizes"][0], &["display_set", "pts", "width", "height", "visible_pixels", "bounds", "screen_percent", "oversized"]);
+}
+
+#[test]
+fn test_dts() {
+    let fixture = Fixture::new("dts");
+    let value = run(&["dts", &fixture.input(), "-o", &fixture.path("output.sup")]);
+    assert_keys(&value, &["file", "segments", "zeroed", "bogus", "min_delta", "max_delta", "deltas", "output"]);
+    assert_eq!(value["zeroed"], 16);
+}
+
+#[test]
+fn test_shot_changes() {
+    let fixture = Fixture::new("shot-changes");
+    let shots = fixture.path("shots.txt");
+    fs::write(&shots, "10\n500\n").unwrap();
+    let value = run(&["shot-changes", &fixture.input(), "--shots", &shots]);
+    assert_keys(&value, &["file", "shot_changes", "violations"]);
+    assert_eq!(value["shot_changes"], 2);
+}
+
+#[test]
+fn test_validate() {
+    let fixture = Fixture::new("validate");
+    let value = run(&["validate", &fixture.input()]);
+    assert_keys(&value, &["file", "valid", "errors", "warnings", "object_buffer_peaks", "issues"]);
+    assert_eq!(value["valid"], true);
+
+    let value = run(&["quality", &fixture.input()]);
+    assert_keys(&value, &["file", "profile", "passed", "errors", "warnings", "infos", "findings"]);
+    assert_keys(&value["findings"][0], &["level", "check", "display_set", "pts", "message"]);
+}
+
+#[test]
+fn test_export() {
+    let fixture = Fixture::new("export");
+    let value = run(&["to-bdn", &fixture.input(), "-o", &fixture.path("bdn")]);
+    assert_keys(&value, &["output", "xml", "events"]);
+    assert!(Path::new(value["xml"].as_str().unwrap()).ends_with("input.xml"));
+
+    let value = run(&["to-srt", &fixture.input(), "-o", &fixture.path("output.srt")]);
+    assert_keys(&value, &["output", "events", "ocr"]);
+    assert_eq!(value["events"], 2);
+}
@@ -4,6 +4,12 @@
 //! commonly used in Blu-ray media. It defines various modules and components needed for reading,
 //! decoding, and processing PGS files.
 //!
+//! ### Untrusted Input
+//! Parsing and decoding never panic on malformed data: any byte sequence passed to `PgsParser`, `PgsPushParser`,
+//! `PgsSegmentHeader::from_data`, `PgsSegment::from_data` or the RLE decoder either succeeds or returns an
+//! `Error`. Decoded bitmaps are limited to the area of a 4096x4096 object, so untrusted files cannot make the
+//! decoder allocate unbounded memory.
+//!
 //! ### Example Usage
 //! ```rust
 //! use pgs_parse::PgsParser;
@@ -21,35 +27,81 @@
 //! ```
 
 mod pgs_error;
+mod pgs_cancel;
 mod pgs_decode_rle;
+mod pgs_encode_rle;
 mod pgs_read;
 mod pgs_memory_buffer;
 mod pgs_const;
 mod pgs_file;
 mod pgs_segment_type;
 mod pgs_segment_header;
+mod pgs_timestamp;
 mod pgs_segment;
 mod pgs_pcs_segment;
 mod pgs_wds_segment;
 mod pgs_pds_segment;
 mod pgs_ods_segment;
+mod pgs_end_segment;
+mod pgs_unknown_segment;
+mod pgs_rectangle;
 mod pgs_display_set;
+mod pgs_decode_cache;
 mod pgs_reader;
+mod pgs_push_parser;
 mod pgs_parser;
+mod pgs_trace;
+mod pgs_visitor;
+mod pgs_index;
+mod pgs_stream;
+mod pgs_arena;
+mod pgs_writer;
+mod pgs_debug_dump;
+mod pgs_validate;
+mod pgs_export;
+mod pgs_batch;
+mod pgs_animation;
+mod pgs_contact_sheet;
+mod pgs_statistics;
+mod pgs_dts;
+mod pgs_overlap;
+mod pgs_duplicates;
+mod pgs_blank;
+mod pgs_lines;
+mod pgs_fingerprint;
+mod pgs_align;
+mod pgs_position;
+mod pgs_letterbox;
+mod pgs_colors;
+mod pgs_sizes;
+mod pgs_shot_changes;
+mod pgs_quality;
+mod pgs_transform;
+mod pgs_palette;
+mod pgs_safe_area;
+mod pgs_composite;
+mod pgs_retime;
+mod pgs_pipeline;
+mod pgs_preview;
+mod pgs_ocr;
+mod pgs_text;
+#[cfg(test)]
+mod pgs_test_support;
 
 pub use pgs_read::{
     PgsSeek,
     PgsRead
 };
 pub use pgs_memory_buffer::{
-    BigEndian, LittleEndian, ReadBytes, ByteOrder,
+    BigEndian, LittleEndian, ReadBytes, WriteBytes, ByteOrder,
     PgsMemoryBuffer
 };
 pub use pgs_segment_type::PgsSegmentType;
 pub use pgs_file::PgsFile;
 pub use pgs_segment_header::PgsSegmentHeader;
-pub use pgs_segment::PgsSegment;
-pub use pgs_pcs_segment::{PgsPcsSegment, PgsPcsCompositionState, PgsPcsObjectCroppedFlag};
+pub use pgs_timestamp::{is_timestamp_wrap, PgsTimestampUnwrapper, PGS_TIMESTAMP_WRAP};
+pub use pgs_segment::{PgsSegment, PgsRc};
+pub use pgs_pcs_segment::{PGS_FRAME_RATE_CODES, PgsPcsSegment, PgsPcsSegmentCompositionObjects, PgsPcsCompositionState, PgsPcsObjectCroppedFlag};
 pub use pgs_wds_segment::{
     PgsWdsSegment,
     PgsWdsSegmentWindowDefinition
@@ -58,14 +110,88 @@ pub use pgs_pds_segment::{
     PgsPdsSegment,
     PgsPdsSegmentPaletteEntry
 };
-pub use pgs_display_set::{PgsDisplaySet, PgsDisplaySetState};
+pub use pgs_rectangle::PgsRectangle;
+pub use pgs_display_set::{PgsDisplaySet, PgsDisplaySetState, PgsResolvedObject};
+pub use pgs_decode_cache::PgsDecodeCache;
 pub use pgs_ods_segment::{
     PgsOdsSegment,
     PgsOdsSequenceFlag
 };
+pub use pgs_end_segment::PgsEndSegment;
+pub use pgs_unknown_segment::PgsUnknownSegment;
 pub use pgs_reader::PgsReader;
-pub use pgs_parser::PgsParser;
+pub use pgs_parser::{
+    PGS_DEFAULT_SLURP_LIMIT, PgsParser, PgsParserOptions, PgsSkippedRegion,
+    PgsParseResult, PgsParseIssue, PgsParseSeverity, PgsParseMetrics
+};
+pub use pgs_push_parser::{PgsPushParser, PgsParseEvent};
+pub use pgs_visitor::{PgsVisitor, PgsVisitFlow};
+pub use pgs_index::{PgsIndexedParser, PgsIndexEntry};
+pub use pgs_stream::PgsStream;
+pub use pgs_arena::{PgsSegmentArena, PgsArenaEntry};
+pub use pgs_writer::PgsWriter;
+pub use pgs_debug_dump::PgsDebugDump;
+pub use pgs_validate::{
+    validate, validate_segments, check_references, PGS_MAX_OBJECT_SIZE, PGS_DECODED_OBJECT_BUFFER_SIZE,
+    PgsValidationReport, PgsValidationIssue, PgsValidationSeverity, PgsValidationCategory, PgsDanglingReferences
+};
+pub use pgs_cancel::PgsCancelToken;
 pub use pgs_error::{
-    Error, 
+    Error, PgsErrorCategory,
     Result
-};
\ No newline at end of file
+};
+
+/// Conversion of display sets into timed subtitle events and interchange formats.
+pub mod export {
+    pub use crate::pgs_export::*;
+    pub use crate::pgs_batch::*;
+    pub use crate::pgs_animation::*;
+    pub use crate::pgs_contact_sheet::*;
+}
+
+/// Statistics and analyses of subtitle tracks.
+pub mod analysis {
+    pub use crate::pgs_statistics::*;
+    pub use crate::pgs_overlap::*;
+    pub use crate::pgs_dts::{analyze_decoding_timestamps, PgsDtsReport, PGS_MAX_DECODE_DELAY};
+    pub use crate::pgs_duplicates::{find_duplicate_display_sets, PgsDuplicateDisplaySet};
+    pub use crate::pgs_blank::{find_blank_display_sets, PgsBlankDisplaySet, PGS_DEFAULT_STRAY_PIXELS};
+    pub use crate::pgs_lines::*;
+    pub use crate::pgs_fingerprint::{fingerprint_events, PgsFingerprint};
+    pub use crate::pgs_align::*;
+    pub use crate::pgs_position::*;
+    pub use crate::pgs_letterbox::*;
+    pub use crate::pgs_colors::*;
+    pub use crate::pgs_sizes::*;
+    pub use crate::pgs_shot_changes::*;
+    pub use crate::pgs_quality::*;
+}
+
+/// Editing passes applied to a `PgsStream` before it is written back.
+pub mod transform {
+    pub use crate::pgs_transform::*;
+    pub use crate::pgs_palette::*;
+    pub use crate::pgs_safe_area::*;
+    pub use crate::pgs_rectangle::PgsRectangle;
+    pub use crate::pgs_composite::*;
+    pub use crate::pgs_duplicates::collapse_duplicate_display_sets;
+    pub use crate::pgs_blank::strip_blank_display_sets;
+    pub use crate::pgs_dts::regenerate_decoding_timestamps;
+    pub use crate::pgs_pipeline::*;
+}
+
+/// Timing changes applied to a `PgsStream`, such as shifting it to match its video.
+pub mod retime {
+    pub use crate::pgs_retime::*;
+}
+
+/// Rendering of decoded subtitle bitmaps to a terminal.
+pub mod preview {
+    pub use crate::pgs_preview::*;
+}
+
+/// Interface between decoded subtitle bitmaps and OCR engines, and text extraction from subtitle tracks.
+pub mod ocr {
+    pub use crate::pgs_ocr::*;
+    pub use crate::pgs_text::*;
+}
@@ -0,0 +1,204 @@
+//! # PGS Track Alignment
+//!
+//! This module estimates the timing difference between two subtitle tracks of the same movie, e.g. from
+//! different releases. Events are paired by their perceptual fingerprints, and a constant offset, optionally with
+//! a linear drift, is fitted to the presentation times of the pairs. The result can be applied to the second track
+//! to synchronize it with the first.
+
+use crate::{pgs_export::export_events, pgs_fingerprint::{fingerprint_events, PgsFingerprint}, pgs_retime::{shift, stretch, PgsRetimeFactor}, Error, PgsDisplaySet, PgsStream, Result};
+
+/// Options controlling `align_with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsAlignOptions {
+    /// Largest fingerprint distance of events showing the same subtitle.
+    pub max_distance: u32,
+    /// Largest difference in 90 kHz ticks between the aligned start times of paired events.
+    pub tolerance: u32,
+    /// Estimate a linear drift, e.g. from a frame-rate conversion, in addition to the offset.
+    pub drift: bool
+}
+
+impl Default for PgsAlignOptions {
+    fn default() -> Self {
+        PgsAlignOptions { max_distance: 6, tolerance: 45000, drift: true }
+    }
+}
+
+/// The timing difference between two subtitle tracks.
+///
+/// A timestamp `t` of the second track corresponds to `factor * t + offset` in the first track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsAlignmentReport {
+    /// Factor by which the second track is stretched, 1/1 without drift.
+    pub factor: PgsRetimeFactor,
+    /// Offset in 90 kHz ticks added to the stretched timestamps of the second track.
+    pub offset: i64,
+    /// Number of event pairs the estimate is based on.
+    pub matched: usize,
+    /// Mean difference in 90 kHz ticks between the start times of the paired events after alignment.
+    pub residual: u32
+}
+
+impl PgsAlignmentReport {
+    /// Retimes a stream of the second track to match the first one (see `retime::stretch` and `retime::shift`).
+    ///
+    /// # Returns
+    /// The number of timestamps that had to be clamped.
+    pub fn apply(&self, stream: &mut PgsStream) -> Result<usize> {
+        let clamped = match self.factor.numerator == self.factor.denominator {
+            true => 0,
+            false => stretch(stream, self.factor)?
+        };
+        Ok(clamped + shift(stream, self.offset))
+    }
+}
+
+/// Returns the start times `(b, a)` of the events showing the same subtitle in both tracks. Only events matching
+/// exactly one event of the other track are paired, as repeated subtitles cannot be paired reliably.
+fn pair_events(a: &[PgsDisplaySet], b: &[PgsDisplaySet], max_distance: u32) -> Result<Vec<(u32, u32)>> {
+    let (fingerprints_a, fingerprints_b) = (fingerprint_events(a)?, fingerprint_events(b)?);
+    let (events_a, events_b) = (export_events(a), export_events(b));
+    let matching = |fingerprint: &PgsFingerprint, others: &[PgsFingerprint]| {
+        others.iter().enumerate().filter(|(_, other)| fingerprint.distance(other) <= max_distance).map(|(index, _)| index).collect::<Vec<_>>()
+    };
+
+    let mut pairs: Vec<(u32, u32)> = Vec::new();
+    for (index_b, fingerprint) in fingerprints_b.iter().enumerate() {
+        if let [index_a] = matching(fingerprint, &fingerprints_a)[..] {
+            if matching(&fingerprints_a[index_a], &fingerprints_b).len() == 1 {
+                pairs.push((events_b[index_b].start, events_a[index_a].start));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Fits `a = slope * b + intercept` to the pairs by least squares.
+fn fit_line(pairs: &[(u32, u32)]) -> (f64, f64) {
+    let count = pairs.len() as f64;
+    let (mean_b, mean_a) = pairs.iter().fold((0.0, 0.0), |(b, a), &(tb, ta)| (b + tb as f64 / count, a + ta as f64 / count));
+    let (covariance, variance) = pairs.iter().fold((0.0, 0.0), |(covariance, variance), &(tb, ta)| {
+        let (db, da) = (tb as f64 - mean_b, ta as f64 - mean_a);
+        (covariance + db * da, variance + db * db)
+    });
+    let slope = if variance > 0.0 { covariance / variance } else { 1.0 };
+    (slope, mean_a - slope * mean_b)
+}
+
+/// Returns the differences between the start times in `a` and the stretched start times in `b` of the pairs.
+fn differences(pairs: &[(u32, u32)], factor: PgsRetimeFactor) -> Vec<i64> {
+    pairs.iter().map(|&(tb, ta)| ta as i64 - factor.apply(tb).unwrap_or(u32::MAX) as i64).collect()
+}
+
+/// Returns the median of the values, which must not be empty.
+fn median(mut values: Vec<i64>) -> i64 {
+    values.sort_unstable();
+    values[values.len() / 2]
+}
+
+/// Returns the retiming factor for a fitted slope: the conversion between two common frame rates if one is within
+/// 0.01% of the slope, the slope rounded to a millionth otherwise.
+fn slope_factor(slope: f64) -> PgsRetimeFactor {
+    const FRAME_RATES: [f64; 5] = [23.976, 24.0, 25.0, 29.97, 30.0];
+    let value = |factor: &PgsRetimeFactor| factor.numerator as f64 / factor.denominator as f64;
+    FRAME_RATES.iter()
+        .flat_map(|&from| FRAME_RATES.iter().filter_map(move |&to| PgsRetimeFactor::from_frame_rates(from, to).ok()))
+        .filter(|factor| (value(factor) - slope).abs() < slope * 0.0001)
+        .min_by(|x, y| (value(x) - slope).abs().total_cmp(&(value(y) - slope).abs()))
+        .unwrap_or_else(|| PgsRetimeFactor::new((slope * 1_000_000.0).round() as u64, 1_000_000))
+}
+
+/// Estimates the timing difference between two tracks with the default options.
+///
+/// See `align_with_options`.
+pub fn align(a: &[PgsDisplaySet], b: &[PgsDisplaySet]) -> Result<PgsAlignmentReport> {
+    align_with_options(a, b, &PgsAlignOptions::default())
+}
+
+/// Estimates the timing difference between two tracks of the same movie.
+///
+/// Events are paired by their fingerprints (see `analysis::fingerprint_events`). The offset is the median
+/// difference of the paired start times; with `drift` enabled, a line is fitted to the start times instead, pairs
+/// deviating more than `tolerance` from it are dropped one by one, and the slope is kept if it moves the last event
+/// by more than `tolerance`. Slopes close to the ratio of two common frame rates are snapped to it.
+///
+/// # Parameters
+/// - `a`: The display sets of the reference track, in stream order.
+/// - `b`: The display sets of the track to synchronize, in stream order.
+/// - `options`: Pairing and fitting options.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if no events can be paired, or `Error::InvalidRleData` if an object cannot be
+/// decoded.
+///
+/// # Returns
+/// The factor and offset mapping `b` to `a`, with the pairs they are based on.
+pub fn align_with_options(a: &[PgsDisplaySet], b: &[PgsDisplaySet], options: &PgsAlignOptions) -> Result<PgsAlignmentReport> {
+    let mut pairs = pair_events(a, b, options.max_distance)?;
+    if pairs.is_empty() {
+        return Err(Error::InvalidArgument("the tracks have no events in common".to_string()));
+    }
+
+    let mut factor = PgsRetimeFactor::new(1, 1);
+    if options.drift && pairs.len() > 1 {
+        loop {
+            let (slope, intercept) = fit_line(&pairs);
+            let deviation = |&(tb, ta): &(u32, u32)| (ta as f64 - (slope * tb as f64 + intercept)).abs();
+            let worst = pairs.iter().enumerate().max_by(|(_, x), (_, y)| deviation(x).total_cmp(&deviation(y)));
+            match worst {
+                Some((index, pair)) if deviation(pair) > options.tolerance as f64 && pairs.len() > 2 => {
+                    pairs.remove(index);
+                },
+                _ => break
+            }
+        }
+        let (slope, _) = fit_line(&pairs);
+        let last = pairs.iter().map(|&(tb, _)| tb).max().unwrap_or(0) as f64;
+        if slope > 0.0 && ((slope - 1.0) * last).abs() > options.tolerance as f64 {
+            factor = slope_factor(slope);
+        }
+    }
+
+    let offset = median(differences(&pairs, factor));
+    if !options.drift {
+        pairs.retain(|&(tb, ta)| (ta as i64 - tb as i64 - offset).unsigned_abs() <= options.tolerance as u64);
+    }
+    let residuals = differences(&pairs, factor).iter().map(|difference| (difference - offset).unsigned_abs()).collect::<Vec<_>>();
+    let residual = (residuals.iter().sum::<u64>() / residuals.len() as u64) as u32;
+    Ok(PgsAlignmentReport { factor, offset, matched: pairs.len(), residual })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{bitmap_display_set, black_and_white};
+
+    use super::*;
+
+    /// A display set showing a vertical bar at one of six positions of a 60x20 bitmap.
+    fn bar(pts: u32, index: usize) -> PgsDisplaySet {
+        bitmap_display_set(pts, black_and_white(), &vec![(0..60).map(|x| (x / 10 == index) as u8).collect(); 20])
+    }
+
+    #[test]
+    fn test_align() {
+        let starts: Vec<u32> = (0..6).map(|index| 90000 * (30 + 600 * index)).collect();
+        let track = |time: fn(u32) -> u32| starts.iter().enumerate().map(|(index, &start)| bar(time(start), index)).collect::<Vec<_>>();
+        let reference = track(|start| start);
+
+        // Two seconds late, with the first event missing and a repeated event that cannot be paired
+        let mut late = track(|start| start + 180000);
+        late.remove(0);
+        late.push(bar(90000 * 4000, 5));
+        let report = align(&reference, &late).unwrap();
+        assert_eq!((report.factor, report.offset, report.matched, report.residual), (PgsRetimeFactor::new(1, 1), -180000, 4, 0));
+        let constant = align_with_options(&reference, &late, &PgsAlignOptions { drift: false, ..Default::default() }).unwrap();
+        assert_eq!(constant, report);
+
+        // Sped up from 24 to 25 frames per second
+        let report = align(&reference, &track(|start| start / 25 * 24)).unwrap();
+        assert_eq!((report.factor, report.matched), (PgsRetimeFactor::new(25, 24), 6));
+        assert!(report.offset.abs() <= 1 && report.residual <= 1);
+
+        assert!(align(&reference, &[]).is_err());
+    }
+}
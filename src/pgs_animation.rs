@@ -0,0 +1,185 @@
+//! # PGS Animations
+//!
+//! This module groups subtitle events into logical events. Karaoke wipes and fades are authored as many display
+//! sets updating the same window in quick succession, either with new bitmaps or with palette-only updates;
+//! listing every one of them as a separate event buries the subtitle they animate. Such runs are detected by the
+//! rate of their updates and reported as one animated event.
+
+use std::ops::Range;
+
+use crate::{pgs_export::{export_events, PgsExportEvent}, PgsDisplaySet};
+
+/// Options controlling `group_animated_events_with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsAnimationOptions {
+    /// Largest mean time in 90 kHz ticks between the updates of an animation.
+    pub max_interval: u32,
+    /// Smallest number of display sets, including palette-only updates, forming an animation.
+    pub min_updates: usize
+}
+
+impl Default for PgsAnimationOptions {
+    fn default() -> Self {
+        PgsAnimationOptions { max_interval: 22500, min_updates: 4 }
+    }
+}
+
+/// A logical subtitle event: a single bitmap, or an animation of bitmaps and palette updates in the same windows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsAnimatedEvent {
+    /// Indexes of the display sets showing the event.
+    pub display_sets: Range<usize>,
+    /// Presentation timestamp (90 kHz) at which the event appears.
+    pub start: u32,
+    /// Presentation timestamp (90 kHz) at which the event is cleared or replaced, if any.
+    pub end: Option<u32>,
+    /// Bounding box of the bitmaps of the event.
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    /// The event holds a forced object.
+    pub forced: bool,
+    /// Number of display sets updating the screen during the event, including palette-only updates.
+    pub updates: usize,
+    /// The updates are frequent enough for the event to be an animation.
+    pub animated: bool
+}
+
+/// Returns the ids of the windows a display set composes into.
+fn window_ids(ds: &PgsDisplaySet) -> Vec<u8> {
+    let mut window_ids: Vec<u8> = ds.pcs.iter().flat_map(|pcs| pcs.composition_objects.iter().map(|obj| obj.window_id)).collect();
+    window_ids.sort_unstable();
+    window_ids.dedup();
+    window_ids
+}
+
+/// Builds the logical event of consecutive events, counting the display sets that update the screen until the
+/// last event ends.
+fn animated_event(display_sets: &[PgsDisplaySet], events: &[PgsExportEvent], options: &PgsAnimationOptions) -> PgsAnimatedEvent {
+    let (first, last) = (&events[0], &events[events.len() - 1]);
+    let end = last.end_or_default();
+    let shown = display_sets[first.display_set..].iter()
+        .take_while(|ds| ds.pcs.as_ref().is_none_or(|pcs| pcs.header.presentation_timestamp < end))
+        .count();
+    let updates: Vec<u32> = display_sets[first.display_set..first.display_set + shown].iter()
+        .filter_map(|ds| ds.pcs.as_ref())
+        .filter(|pcs| !pcs.composition_objects.is_empty())
+        .map(|pcs| pcs.header.presentation_timestamp)
+        .collect();
+    let interval = match updates.len() {
+        0 | 1 => u32::MAX,
+        count => (updates[count - 1] - updates[0]) / (count as u32 - 1)
+    };
+
+    let left = events.iter().map(|event| event.x).min().unwrap_or(0);
+    let top = events.iter().map(|event| event.y).min().unwrap_or(0);
+    let right = events.iter().map(|event| event.x as u32 + event.width as u32).max().unwrap_or(0);
+    let bottom = events.iter().map(|event| event.y as u32 + event.height as u32).max().unwrap_or(0);
+    PgsAnimatedEvent {
+        display_sets: first.display_set..first.display_set + shown.max(1),
+        start: first.start,
+        end: last.end,
+        x: left,
+        y: top,
+        width: (right - left as u32).min(u16::MAX as u32) as u16,
+        height: (bottom - top as u32).min(u16::MAX as u32) as u16,
+        forced: events.iter().any(|event| event.forced),
+        updates: updates.len(),
+        animated: updates.len() >= options.min_updates && interval <= options.max_interval
+    }
+}
+
+/// Groups the subtitle events of the display sets into logical events with the default options.
+///
+/// See `group_animated_events_with_options`.
+pub fn group_animated_events(display_sets: &[PgsDisplaySet]) -> Vec<PgsAnimatedEvent> {
+    group_animated_events_with_options(display_sets, &PgsAnimationOptions::default())
+}
+
+/// Groups the subtitle events of the display sets into logical events.
+///
+/// Events are built with `export_events`. Consecutive events replacing each other in the same windows within
+/// `max_interval` are candidates for one animation; they form an animated event if at least `min_updates` display
+/// sets, counting palette-only updates, update the screen at a mean interval of at most `max_interval`. A single
+/// event faded in or out by palette updates is animated the same way. All other events are kept as they are.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+/// - `options`: Update rate and count of animations.
+///
+/// # Returns
+/// The logical events in stream order.
+pub fn group_animated_events_with_options(display_sets: &[PgsDisplaySet], options: &PgsAnimationOptions) -> Vec<PgsAnimatedEvent> {
+    let events = export_events(display_sets);
+
+    // Runs of events (first, end) replacing each other quickly in the same windows
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (index, event) in events.iter().enumerate() {
+        match runs.last_mut() {
+            Some((_, end)) if {
+                let previous = &events[*end - 1];
+                previous.end == Some(event.start)
+                    && event.start - previous.start <= options.max_interval
+                    && window_ids(&display_sets[previous.display_set]) == window_ids(&display_sets[event.display_set])
+            } => *end = index + 1,
+            _ => runs.push((index, index + 1))
+        }
+    }
+
+    let mut grouped: Vec<PgsAnimatedEvent> = Vec::new();
+    for (first, end) in runs {
+        let run = animated_event(display_sets, &events[first..end], options);
+        match run.animated || end - first == 1 {
+            true => grouped.push(run),
+            false => grouped.extend((first..end).map(|index| animated_event(display_sets, &events[index..index + 1], options)))
+        }
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{composition_object, display_set_of, ods, pcs, pds, wds}, PgsPcsCompositionState, PgsPcsSegment};
+
+    use super::*;
+
+    /// A display set showing a bitmap of `width` pixels in window 0, only updating the palette, or clearing the
+    /// screen.
+    fn display_set(pts: u32, kind: &str, width: u16) -> PgsDisplaySet {
+        let objects = if kind == "clear" { Vec::new() } else { vec![composition_object(0, 0, 100, 900)] };
+        display_set_of(
+            PgsPcsSegment { palette_update_flag: if kind == "palette" { 0x80 } else { 0 }, ..pcs(pts, PgsPcsCompositionState::Normal, objects) },
+            (kind != "palette").then(|| wds(pts, Vec::new())),
+            (kind != "clear").then(|| pds(pts, 0, Vec::new())),
+            (kind == "show").then(|| ods(pts, 0, 0, width, 1, &[vec![0; width as usize]]))
+        )
+    }
+
+    #[test]
+    fn test_group_animated_events() {
+        let mut display_sets = vec![display_set(90000, "show", 10), display_set(270000, "clear", 0)];
+        // A karaoke wipe of 5 bitmaps, 100 ms apart
+        display_sets.extend((0..5).map(|index| display_set(450000 + 9000 * index, "show", 10 + 10 * index as u16)));
+        display_sets.push(display_set(600000, "clear", 0));
+        // A fade in by palette updates, and two bitmaps replacing each other slowly
+        display_sets.push(display_set(900000, "show", 10));
+        display_sets.extend((1..4).map(|index| display_set(900000 + 4500 * index, "palette", 0)));
+        display_sets.push(display_set(1_000_000, "show", 10));
+        display_sets.push(display_set(1_010_000, "show", 20));
+        display_sets.push(display_set(1_200_000, "clear", 0));
+
+        let events = group_animated_events(&display_sets);
+        assert_eq!(events.iter().map(|event| (event.display_sets.clone(), event.updates, event.animated)).collect::<Vec<_>>(), [
+            (0..1, 1, false),
+            (2..7, 5, true),
+            (8..12, 4, true),
+            (12..13, 1, false),
+            (13..14, 1, false)
+        ]);
+        assert_eq!((events[1].start, events[1].end, events[1].width), (450000, Some(600000), 50));
+
+        let options = PgsAnimationOptions { min_updates: 6, ..Default::default() };
+        assert_eq!(group_animated_events_with_options(&display_sets, &options).len(), 9);
+    }
+}
@@ -0,0 +1,135 @@
+//! # PGS Segment Arena
+//!
+//! This module defines `PgsSegmentArena`, a compact store for the segments of a whole stream. The payloads of all
+//! segments are kept back to back in a single buffer next to a table of headers, instead of one heap allocation per
+//! segment and field, so analyses over huge streams use a fraction of the memory of a `PgsStream` and free
+//! everything at once when the arena is dropped. Segments are decoded on demand.
+
+use crate::{Error, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsStream, PgsUnknownSegment, Result};
+
+/// A segment stored in a `PgsSegmentArena`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PgsArenaEntry {
+    /// The segment header, with `segment_length` matching the stored payload.
+    pub header: PgsSegmentHeader,
+    /// The segment type byte, which differs from `header.segment_type` for unknown segments.
+    pub segment_type: u8,
+    /// Offset of the payload in the arena buffer.
+    start: usize
+}
+
+/// Segments stored as serialized payloads in one buffer.
+///
+/// # Example
+/// ```rust
+/// use pgs_parse::{PgsParser, PgsParserOptions, PgsSegmentType};
+///
+/// if let Ok(arena) = PgsParser::parse_into_arena("subtitle.sup", PgsParserOptions::default()) {
+///     let objects = arena.headers().filter(|header| header.segment_type == PgsSegmentType::ODS).count();
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PgsSegmentArena {
+    data: Vec<u8>,
+    entries: Vec<PgsArenaEntry>
+}
+
+impl PgsSegmentArena {
+    /// Creates an empty arena.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serializes a segment into the arena.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidSegmentDataLength` if the serialized payload does not fit the 16-bit segment length.
+    pub fn push(&mut self, segment: &PgsSegment) -> Result<()> {
+        let payload = segment.to_data()?;
+        if payload.len() > u16::MAX as usize {
+            return Err(Error::InvalidSegmentDataLength { length: payload.len(), expected: u16::MAX as usize });
+        }
+        let header = PgsSegmentHeader { segment_length: payload.len() as u16, ..*segment.header() };
+        self.entries.push(PgsArenaEntry { header, segment_type: segment.segment_type_byte(), start: self.data.len() });
+        self.data.extend_from_slice(&payload);
+        Ok(())
+    }
+
+    /// Returns the number of stored segments.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no segment is stored.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the stored segments without their payloads, in stream order.
+    pub fn entries(&self) -> &[PgsArenaEntry] {
+        &self.entries
+    }
+
+    /// Returns the headers of the stored segments, in stream order.
+    pub fn headers(&self) -> impl Iterator<Item = &PgsSegmentHeader> + '_ {
+        self.entries.iter().map(|entry| &entry.header)
+    }
+
+    /// Returns the serialized payload of the segment at `index`, or `None` if there is no such segment.
+    pub fn payload(&self, index: usize) -> Option<&[u8]> {
+        let entry = self.entries.get(index)?;
+        Some(&self.data[entry.start..entry.start + entry.header.segment_length as usize])
+    }
+
+    /// Decodes the segment at `index`.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidArgument` if there is no such segment.
+    pub fn segment(&self, index: usize) -> Result<PgsSegment> {
+        let (Some(entry), Some(payload)) = (self.entries.get(index), self.payload(index)) else {
+            return Err(Error::InvalidArgument(format!("segment {index} is not in the arena of {} segments", self.len())));
+        };
+        if entry.header.segment_type == PgsSegmentType::ERR {
+            return Ok(PgsSegment::Unknown(PgsUnknownSegment::from_data(entry.header, entry.segment_type, payload)?));
+        }
+        PgsSegment::from_data(entry.header, payload)
+    }
+
+    /// Decodes the stored segments one by one, in stream order.
+    pub fn iter(&self) -> impl Iterator<Item = Result<PgsSegment>> + '_ {
+        (0..self.len()).map(|index| self.segment(index))
+    }
+
+    /// Decodes all stored segments into an editable `PgsStream`.
+    pub fn to_stream(&self) -> Result<PgsStream> {
+        Ok(PgsStream::new(self.iter().collect::<Result<Vec<_>>>()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::TempSupFile, PgsParser, PgsParserOptions, PgsWriter};
+
+    use super::*;
+
+    #[test]
+    fn test_arena() {
+        let mut data = vec![0x50, 0x47, 0, 0, 0, 1, 0, 0, 0, 0, 0x16, 0, 11, 0x07, 0x80, 0x04, 0x38, 0x10, 0, 0, 0x80, 0, 0, 0];
+        data.extend([0x50, 0x47, 0, 0, 0, 1, 0, 0, 0, 0, 0x99, 0, 2, 0xAB, 0xCD]);
+        data.extend([0x50, 0x47, 0, 0, 0, 1, 0, 0, 0, 0, 0x14, 0, 7, 0, 0, 1, 0xEB, 0x80, 0x80, 0xFF]);
+        data.extend([0x50, 0x47, 0, 0, 0, 1, 0, 0, 0, 0, 0x80, 0, 0]);
+        let file = TempSupFile::new("arena", &data);
+        let options = PgsParserOptions { keep_unknown_segments: true, ..Default::default() };
+        let arena = PgsParser::parse_into_arena(file.path(), options).unwrap();
+
+        assert_eq!(arena.len(), 4);
+        assert_eq!(arena.headers().map(|header| header.segment_length).collect::<Vec<_>>(), [11, 2, 7, 0]);
+        assert_eq!(arena.payload(1), Some([0xAB, 0xCD].as_slice()));
+        assert!(matches!(arena.segment(2), Ok(PgsSegment::Pds(pds)) if pds.palette_entries.len() == 1));
+        assert!(arena.segment(4).is_err());
+
+        let mut writer = PgsWriter::new(Vec::new());
+        writer.write_segments(arena.to_stream().unwrap().get_segments()).unwrap();
+        assert_eq!(writer.into_inner(), data);
+    }
+}
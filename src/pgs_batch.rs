@@ -0,0 +1,158 @@
+//! # PGS Batch Export
+//!
+//! This module decodes the bitmaps of all subtitle events on a pool of worker threads and hands them to a sink in
+//! stream order, so the sink can encode and write image N while the workers already decode the images after it.
+//! At most a bounded number of decoded images wait for the sink at any time.
+
+use std::{collections::BTreeMap, sync::{mpsc::{self, Receiver, Sender}, Mutex}, thread};
+
+use crate::{pgs_decode_rle::decode_rle_entries, pgs_export::{export_events, PgsExportEvent}, Error, PgsCancelToken, PgsDisplaySet, PgsOdsSegment, PgsPdsSegmentPaletteEntry, Result};
+
+/// Receives the decoded bitmaps of a batch export, on the thread that started the export.
+///
+/// Closures taking a `&PgsExportEvent` and the decoded pixels and returning a `Result<()>` implement this trait.
+pub trait PgsImageSink {
+    /// Encodes or stores the decoded pixels of an event, as returned by `PgsDisplaySet::get_decoded_image`.
+    ///
+    /// # Errors
+    /// Any error stops the export and is returned by `batch`.
+    fn write(&mut self, event: &PgsExportEvent, pixels: Vec<Vec<u32>>) -> Result<()>;
+}
+
+impl<F: FnMut(&PgsExportEvent, Vec<Vec<u32>>) -> Result<()>> PgsImageSink for F {
+    fn write(&mut self, event: &PgsExportEvent, pixels: Vec<Vec<u32>>) -> Result<()> {
+        self(event, pixels)
+    }
+}
+
+/// Options controlling a batch export.
+#[derive(Debug, Clone, Default)]
+pub struct PgsBatchOptions {
+    /// Number of decoding threads; 0 uses one thread per available CPU.
+    pub threads: usize,
+    /// Maximum number of images being decoded or waiting for the sink; 0 uses twice the number of threads.
+    pub queue: usize,
+    /// Decode the images in grayscale instead of full color.
+    pub gray: bool,
+    /// Token checked before every image and after every decoded row; the export fails with `Error::Cancelled` once
+    /// it is cancelled.
+    pub cancel: Option<PgsCancelToken>
+}
+
+/// An image to decode, detached from the shared segments so it can be sent to a worker thread.
+struct PgsBatchJob {
+    index: usize,
+    ods: PgsOdsSegment,
+    entries: Vec<PgsPdsSegmentPaletteEntry>
+}
+
+/// Decodes the bitmap of every event of `export_events(display_sets)` and passes it to the sink in event order.
+///
+/// Decoding runs on `options.threads` worker threads while the sink runs on the calling thread.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+/// - `sink`: Receives every event with its decoded pixels.
+/// - `options`: Number of threads, queue length, color mode and cancellation.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded, `Error::Cancelled` if the export was cancelled,
+/// or the first error of the sink. Images decoded before the error have been passed to the sink.
+///
+/// # Returns
+/// The number of images passed to the sink.
+pub fn batch<S: PgsImageSink>(display_sets: &[PgsDisplaySet], mut sink: S, options: &PgsBatchOptions) -> Result<usize> {
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads
+    };
+    let queue = match options.queue {
+        0 => 2 * threads,
+        queue => queue
+    };
+    let (job_sender, job_receiver) = mpsc::channel::<PgsBatchJob>();
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let (job_receiver, result_sender) = (&job_receiver, result_sender.clone());
+            scope.spawn(move || loop {
+                let Ok(job) = job_receiver.lock().unwrap().recv() else {
+                    break;
+                };
+                let pixels = decode_rle_entries(&job.entries, &job.ods, options.gray, options.cancel.as_ref());
+                if result_sender.send((job.index, pixels)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_sender);
+        // The job sender is dropped when `feed` returns, which stops the workers
+        feed(display_sets, &mut sink, options, queue, job_sender, result_receiver)
+    })
+}
+
+/// Sends the images to the workers, keeping at most `queue` in flight, and passes the decoded pixels to the sink
+/// in event order.
+fn feed<S: PgsImageSink>(display_sets: &[PgsDisplaySet], sink: &mut S, options: &PgsBatchOptions, queue: usize,
+    jobs: Sender<PgsBatchJob>, results: Receiver<(usize, Result<Vec<Vec<u32>>>)>) -> Result<usize> {
+    let events = export_events(display_sets);
+    let mut decoded: BTreeMap<usize, Result<Vec<Vec<u32>>>> = BTreeMap::new();
+    let (mut sent, mut written) = (0, 0);
+    while written < events.len() {
+        while sent < events.len() && sent - written < queue.max(1) {
+            if let Some(cancel) = &options.cancel {
+                cancel.check()?;
+            }
+            let display_set = &display_sets[events[sent].display_set];
+            let (Some(ods), Some(pds)) = (&display_set.ods, &display_set.pds) else {
+                return Err(Error::IncompleteDisplaySet);
+            };
+            let job = PgsBatchJob { index: sent, ods: ods.as_ref().clone(), entries: pds.palette_entries.to_vec() };
+            if jobs.send(job).is_err() {
+                break;
+            }
+            sent += 1;
+        }
+        // Fails only if every worker panicked, which the thread scope propagates
+        let Ok((index, pixels)) = results.recv() else {
+            break;
+        };
+        decoded.insert(index, pixels);
+        while let Some(pixels) = decoded.remove(&written) {
+            sink.write(&events[written], pixels?)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{bitmap_display_set, black_and_white};
+
+    use super::*;
+
+    #[test]
+    fn test_batch() {
+        // Single rows of palette entry 1, one pixel wider every second
+        let display_sets: Vec<PgsDisplaySet> = (1..=12).map(|width| bitmap_display_set(width as u32 * 90000, black_and_white(), &[vec![1; width]])).collect();
+        let mut images: Vec<(usize, Vec<Vec<u32>>)> = Vec::new();
+        let options = PgsBatchOptions { threads: 3, queue: 2, ..Default::default() };
+        let written = batch(&display_sets, |event: &PgsExportEvent, pixels| {
+            images.push((event.display_set, pixels));
+            Ok(())
+        }, &options).unwrap();
+
+        assert_eq!(written, 12);
+        let expected: Vec<(usize, Vec<Vec<u32>>)> = display_sets.iter().enumerate()
+            .map(|(index, ds)| (index, ds.get_decoded_image(false).unwrap())).collect();
+        assert_eq!(images, expected);
+
+        let cancel = PgsCancelToken::new();
+        cancel.cancel();
+        let options = PgsBatchOptions { cancel: Some(cancel), ..Default::default() };
+        assert!(matches!(batch(&display_sets, |_: &PgsExportEvent, _| Ok(()), &options), Err(Error::Cancelled)));
+    }
+}
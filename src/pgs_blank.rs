@@ -0,0 +1,129 @@
+//! # PGS Blank Display Sets
+//!
+//! This module finds display sets that compose objects but show nothing: every pixel is transparent, or only a
+//! handful of stray pixels left behind by an encoder are visible. Players show them as an empty subtitle that
+//! still ends the previous one, so they can be turned into plain clear frames, which `optimize` then removes where
+//! the screen is already clear.
+
+use crate::{pgs_duplicates::PgsPixelState, PgsPcsCompositionState, PgsRc, PgsSegment, PgsStream, Result};
+
+/// Number of visible pixels up to which a display set is usually an encoder artifact rather than a subtitle.
+pub const PGS_DEFAULT_STRAY_PIXELS: usize = 8;
+
+/// A display set showing no more than a few visible pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsBlankDisplaySet {
+    /// Index of the display set, as returned by `PgsStream::get_display_set_segments`.
+    pub display_set: usize,
+    /// Presentation timestamp (90 kHz) of the display set.
+    pub presentation_timestamp: u32,
+    /// Number of visible pixels over all shown objects.
+    pub visible_pixels: usize
+}
+
+/// Finds display sets whose shown objects are entirely transparent or have at most `max_stray_pixels` visible
+/// pixels.
+///
+/// Pixels are visible if their palette entry is defined and not fully transparent. Display sets referring to
+/// objects that are not in the buffer are skipped.
+///
+/// # Parameters
+/// - `segments`: The segments in stream order, for example from `PgsParser::get_segments`.
+/// - `max_stray_pixels`: The number of visible pixels up to which a display set counts as blank, `0` for only
+///   fully transparent ones.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The blank display sets in stream order.
+pub fn find_blank_display_sets(segments: &[PgsSegment], max_stray_pixels: usize) -> Result<Vec<PgsBlankDisplaySet>> {
+    let mut state = PgsPixelState::default();
+    let mut blank: Vec<PgsBlankDisplaySet> = Vec::new();
+    for (index, display_set) in segments.split_inclusive(|segment| matches!(segment, PgsSegment::End(_))).enumerate() {
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            continue;
+        };
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            state = PgsPixelState::default();
+        }
+        state.apply(display_set)?;
+        let shown = state.shown_objects(pcs);
+        // Objects missing from the buffer cannot be judged
+        if shown.is_empty() || shown.iter().any(Option::is_none) {
+            continue;
+        }
+
+        let visible_pixels = shown.iter().flatten()
+            .flat_map(|obj| obj.pixels.iter().flatten())
+            .filter(|color| color.is_some_and(|(_, _, _, alpha)| alpha > 0))
+            .count();
+        if visible_pixels <= max_stray_pixels {
+            blank.push(PgsBlankDisplaySet { display_set: index, presentation_timestamp: pcs.header.presentation_timestamp, visible_pixels });
+        }
+    }
+    Ok(blank)
+}
+
+/// Turns the display sets found by `find_blank_display_sets` into clear frames by removing their composition
+/// objects, so they still end the previous subtitle but show nothing.
+///
+/// The palettes and objects they define stay in the stream, as later display sets of the epoch may use them. Run
+/// `optimize` afterwards to remove clear frames following another clear frame.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `max_stray_pixels`: The number of visible pixels up to which a display set counts as blank.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The number of display sets turned into clear frames.
+pub fn strip_blank_display_sets(stream: &mut PgsStream, max_stray_pixels: usize) -> Result<usize> {
+    let blank = find_blank_display_sets(stream.get_segments(), max_stray_pixels)?;
+    let mut index = 0;
+    for segment in stream.get_segments_mut() {
+        match segment {
+            PgsSegment::Pcs(pcs) if blank.iter().any(|blank| blank.display_set == index) => {
+                let pcs = PgsRc::make_mut(pcs);
+                pcs.composition_objects.clear();
+                pcs.number_of_composition_objects = 0;
+                pcs.palette_update_flag = 0;
+            },
+            PgsSegment::End(_) => index += 1,
+            _ => {}
+        }
+    }
+    Ok(blank.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{black_and_white, composition_object, display_set_of, ods, pcs, pds, segments};
+
+    use super::*;
+
+    /// An Epoch Start showing a 10x10 object with `visible` opaque pixels.
+    fn display_set(pts: u32, visible: usize) -> Vec<PgsSegment> {
+        let bitmap: Vec<Vec<u8>> = (0..10).map(|y| (0..10).map(|x| (10 * y + x < visible) as u8).collect()).collect();
+        let pcs = pcs(pts, PgsPcsCompositionState::EpochStart, vec![composition_object(0, 0, 10, 20)]);
+        segments(&display_set_of(pcs, None, Some(pds(pts, 0, black_and_white())), Some(ods(pts, 0, 0, 10, 10, &bitmap))))
+    }
+
+    #[test]
+    fn test_blank_display_sets() {
+        let mut stream = PgsStream::new([display_set(0, 50), display_set(90000, 0), display_set(180000, 3)].concat());
+
+        let blank = find_blank_display_sets(stream.get_segments(), 4).unwrap();
+        assert_eq!(blank, [
+            PgsBlankDisplaySet { display_set: 1, presentation_timestamp: 90000, visible_pixels: 0 },
+            PgsBlankDisplaySet { display_set: 2, presentation_timestamp: 180000, visible_pixels: 3 }
+        ]);
+        assert_eq!(find_blank_display_sets(stream.get_segments(), 0).unwrap().len(), 1);
+
+        assert_eq!(strip_blank_display_sets(&mut stream, 0).unwrap(), 1);
+        let objects: Vec<usize> = stream.get_display_sets().iter().map(|ds| ds.pcs.as_ref().unwrap().composition_objects.len()).collect();
+        assert_eq!(objects, [1, 0, 1]);
+    }
+}
@@ -0,0 +1,77 @@
+//! # PGS Cancellation
+//!
+//! This module defines `PgsCancelToken`, a flag shared between a long running parse or decode and the code that may
+//! want to interrupt it, e.g. a user interface thread. The work checks the flag regularly and ends with
+//! `Error::Cancelled` once it is set.
+
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+use crate::{Error, Result};
+
+/// A cloneable flag requesting cancellation of the work it is passed to.
+///
+/// All clones share the same flag, so a clone can be moved to another thread and cancel the work from there.
+///
+/// # Example
+/// ```rust
+/// use pgs_parse::{PgsCancelToken, PgsParser, PgsParserOptions};
+///
+/// let cancel = PgsCancelToken::new();
+/// let options = PgsParserOptions { cancel: Some(cancel.clone()), ..Default::default() };
+/// cancel.cancel();
+/// assert!(PgsParser::parse_with_options("subtitle.sup", options).is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PgsCancelToken {
+    cancelled: Arc<AtomicBool>
+}
+
+impl PgsCancelToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation of the work using this token or any of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if cancellation was requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Checks the token from inside a loop.
+    ///
+    /// # Errors
+    /// Returns `Error::Cancelled` if cancellation was requested.
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(Error::Cancelled),
+            false => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_decode_rle::decode_rle_cancellable, pgs_test_support::{ods, pds}, PgsParseEvent, PgsParserOptions, PgsPushParser, PgsRc};
+
+    use super::*;
+
+    #[test]
+    fn test_cancel() {
+        let cancel = PgsCancelToken::new();
+        let ods = PgsRc::new(ods(0, 0, 0, 2, 2, &[vec![1, 0], vec![0, 1]]));
+        let pds = PgsRc::new(pds(0, 0, Vec::new()));
+        assert_eq!(decode_rle_cancellable(pds.clone(), ods.clone(), false, &cancel).unwrap().len(), 2);
+
+        let mut parser = PgsPushParser::new(PgsParserOptions { cancel: Some(cancel.clone()), ..Default::default() });
+        cancel.clone().cancel();
+        assert!(cancel.is_cancelled());
+        assert!(matches!(decode_rle_cancellable(pds, ods, false, &cancel), Err(Error::Cancelled)));
+        let events = parser.push_bytes(&[0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0, 0]);
+        assert!(matches!(events.as_slice(), [PgsParseEvent::Error(Error::Cancelled)]));
+    }
+}
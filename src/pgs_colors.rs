@@ -0,0 +1,166 @@
+//! # PGS Color Usage
+//!
+//! This module reports which colors the bitmaps of a subtitle track use: the number of visible colors of every
+//! event, the dominant fill and outline colors of the track and how the alpha of the visible pixels is
+//! distributed. Recoloring and conversion to formats with small palettes, such as VobSub with three visible
+//! colors, depend on these numbers.
+
+use std::collections::HashMap;
+
+use crate::{pgs_decode_rle::{calc_blue, calc_green, calc_red, decode_rle_indices}, pgs_export::export_events, PgsDisplaySet, Result};
+
+/// Pixels per RGB color.
+type PgsColorCounts = HashMap<(u8, u8, u8), u64>;
+
+/// An RGB color with the number of pixels using it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsColorUsage {
+    pub color: (u8, u8, u8),
+    pub pixels: u64
+}
+
+/// The colors used by the bitmap of a subtitle event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsEventColors {
+    /// Index of the display set holding the bitmap.
+    pub display_set: usize,
+    /// Presentation timestamp (90 kHz) at which the bitmap appears.
+    pub start: u32,
+    /// Number of distinct visible colors, counting color and alpha; the transparent background is not counted.
+    pub colors: usize,
+    /// Most used of the bright colors, the letters of a typical subtitle.
+    pub fill: Option<PgsColorUsage>,
+    /// Most used of the dark colors, the border around the letters of a typical subtitle.
+    pub outline: Option<PgsColorUsage>
+}
+
+/// Color usage of a subtitle track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsColorReport {
+    /// The colors of every event, in stream order.
+    pub events: Vec<PgsEventColors>,
+    /// Fill color using the most pixels over all events.
+    pub fill: Option<PgsColorUsage>,
+    /// Outline color using the most pixels over all events.
+    pub outline: Option<PgsColorUsage>,
+    /// Number of visible pixels per alpha value, from 1 to 255; index 0 counts the transparent pixels.
+    pub alpha_histogram: Vec<u64>
+}
+
+impl PgsColorReport {
+    /// Returns the largest number of visible colors used by an event.
+    pub fn max_colors(&self) -> usize {
+        self.events.iter().map(|event| event.colors).max().unwrap_or(0)
+    }
+
+    /// Returns the number of events using more than `colors` visible colors, e.g. `3` for the events that lose
+    /// colors when converted to VobSub.
+    pub fn events_exceeding(&self, colors: usize) -> usize {
+        self.events.iter().filter(|event| event.colors > colors).count()
+    }
+}
+
+/// Returns the most used color of a map, preferring the brighter one of equally used colors.
+fn dominant(pixels: &PgsColorCounts) -> Option<PgsColorUsage> {
+    pixels.iter()
+        .max_by_key(|&(color, pixels)| (*pixels, *color))
+        .map(|(&color, &pixels)| PgsColorUsage { color, pixels })
+}
+
+/// Reports the colors used by the bitmaps of every subtitle event.
+///
+/// Events are built with `export::export_events`. The visible colors of each bitmap are classified by luminance
+/// like `transform::recolor` does: colors in the brighter half between the darkest and brightest visible color
+/// are fill, the others outline. Pixels of colors missing from the palette are transparent, as when decoding.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The `PgsColorReport` of the events.
+pub fn color_usage(display_sets: &[PgsDisplaySet]) -> Result<PgsColorReport> {
+    let mut alpha_histogram = vec![0u64; 256];
+    let (mut fill, mut outline): (PgsColorCounts, PgsColorCounts) = (HashMap::new(), HashMap::new());
+    let mut events: Vec<PgsEventColors> = Vec::new();
+    for event in export_events(display_sets) {
+        let ds = &display_sets[event.display_set];
+        let (Some(ods), Some(pds)) = (&ds.ods, &ds.pds) else {
+            continue;
+        };
+        let mut counts = [0u64; 256];
+        for row in decode_rle_indices(ods)? {
+            for index in row {
+                counts[index as usize] += 1;
+            }
+        }
+
+        // Pixels per visible color (Y, Cr, Cb, alpha)
+        let mut used: HashMap<(u8, u8, u8, u8), u64> = HashMap::new();
+        for (index, &count) in counts.iter().enumerate().filter(|(_, &count)| count > 0) {
+            match pds.palette_entries.get(index).filter(|entry| entry.transparency > 0) {
+                Some(entry) => {
+                    *used.entry((entry.luminance, entry.color_difference_red, entry.color_difference_blue, entry.transparency)).or_default() += count;
+                    alpha_histogram[entry.transparency as usize] += count;
+                },
+                None => alpha_histogram[0] += count
+            }
+        }
+
+        let (darkest, brightest) = used.keys().fold((u8::MAX, 0), |(darkest, brightest), &(y, ..)| (darkest.min(y), brightest.max(y)));
+        let (mut event_fill, mut event_outline): (PgsColorCounts, PgsColorCounts) = (HashMap::new(), HashMap::new());
+        for (&(y, cr, cb, _), &count) in &used {
+            let rgb = (calc_red(y, cr), calc_green(y, cb, cr), calc_blue(y, cb));
+            let bright = brightest == darkest || 2 * (y - darkest) as u16 >= (brightest - darkest) as u16;
+            *if bright { event_fill.entry(rgb) } else { event_outline.entry(rgb) }.or_default() += count;
+        }
+        for (rgb, count) in &event_fill {
+            *fill.entry(*rgb).or_default() += count;
+        }
+        for (rgb, count) in &event_outline {
+            *outline.entry(*rgb).or_default() += count;
+        }
+
+        events.push(PgsEventColors {
+            display_set: event.display_set,
+            start: event.start,
+            colors: used.len(),
+            fill: dominant(&event_fill),
+            outline: dominant(&event_outline)
+        });
+    }
+    Ok(PgsColorReport { events, fill: dominant(&fill), outline: dominant(&outline), alpha_histogram })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{bitmap_display_set, palette_entry};
+
+    use super::*;
+
+    /// A display set showing a single row of palette entries, with a palette of the given luminance and alpha.
+    fn row(pts: u32, palette: &[(u8, u8)], row: &[u8]) -> PgsDisplaySet {
+        let entries = palette.iter().enumerate().map(|(id, &(luminance, transparency))| palette_entry(id as u8, luminance, transparency)).collect();
+        bitmap_display_set(pts, entries, &[row.to_vec()])
+    }
+
+    #[test]
+    fn test_color_usage() {
+        let palette = [(16, 0), (235, 255), (16, 255), (126, 255), (16, 128)];
+        let display_sets = [
+            row(90000, &palette, &[0, 0, 1, 1, 1, 2, 2, 3, 4, 9]),
+            row(180000, &palette[..3], &[1, 2, 2, 2, 2])
+        ];
+        let report = color_usage(&display_sets).unwrap();
+
+        let white = PgsColorUsage { color: (235, 235, 235), pixels: 3 };
+        assert_eq!(report.events[0], PgsEventColors { display_set: 0, start: 90000, colors: 4, fill: Some(white), outline: Some(PgsColorUsage { color: (16, 16, 16), pixels: 3 }) });
+        assert_eq!((report.events[1].colors, report.events[1].fill.map(|fill| fill.pixels)), (2, Some(1)));
+        assert_eq!(report.fill, Some(PgsColorUsage { color: (235, 235, 235), pixels: 4 }));
+        assert_eq!(report.outline, Some(PgsColorUsage { color: (16, 16, 16), pixels: 7 }));
+        assert_eq!((report.alpha_histogram[0], report.alpha_histogram[128], report.alpha_histogram[255]), (3, 1, 11));
+        assert_eq!((report.max_colors(), report.events_exceeding(3)), (4, 1));
+    }
+}
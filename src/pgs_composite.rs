@@ -0,0 +1,559 @@
+//! # PGS Compositing
+//!
+//! This module rebuilds streams from what they show on screen. The screen content of every display set is
+//! decoded to colored pixels, combined, and encoded again as self-contained Epoch Start display sets with a
+//! merged palette. This allows combining tracks that show subtitles at the same time, reducing display sets to a
+//! single object or a few colors for formats and devices that support only that, and drawing onto the subtitles.
+
+use std::collections::HashMap;
+
+use crate::{pgs_error::Error, pgs_palette::rgb_to_ycrcb, pgs_duplicates::{PgsColor, PgsPixelState, PgsShownObject}, pgs_encode_rle::encode_rle, pgs_dts::regenerate_decoding_timestamps, PgsEndSegment, PgsOdsSegment, PgsPcsCompositionState, PgsPcsObjectCroppedFlag, PgsPcsSegment, PgsPcsSegmentCompositionObjects, PgsPdsSegment, PgsPdsSegmentPaletteEntry, PgsRc, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsStream, PgsWdsSegment, PgsWdsSegmentWindowDefinition, Result};
+
+/// Largest number of objects and windows a composition may use.
+const MAX_COMPOSITION_OBJECTS: usize = 2;
+
+/// Palette entry id used for transparent pixels, which has the shortest RLE codes.
+const TRANSPARENT_ENTRY: u8 = 0;
+
+/// Largest number of palette entries, including the transparent one.
+const MAX_PALETTE_ENTRIES: usize = 256;
+
+/// Limits of the compositions written by the compositor.
+#[derive(Clone, Copy)]
+struct PgsCompositeLimits {
+    objects: usize,
+    colors: usize
+}
+
+/// Number of written display sets combining several streams, flattening objects and reducing colors.
+#[derive(Default)]
+struct PgsCompositeCounts {
+    combined: usize,
+    flattened: usize,
+    quantized: usize
+}
+
+/// Screen content of a stream from a presentation timestamp until the next one.
+pub(crate) struct PgsScreen {
+    pub(crate) presentation_timestamp: u32,
+    pub(crate) objects: PgsRc<Vec<PgsShownObject>>
+}
+
+/// Video size and frame rate code of a stream, taken from its first PCS.
+#[derive(Clone, Copy)]
+struct PgsVideoFormat {
+    width: u16,
+    height: u16,
+    frame_rate: u8
+}
+
+/// Decodes the screen content shown by every display set of a stream. Objects that are not in the buffer are
+/// left out.
+pub(crate) fn screens(stream: &PgsStream) -> Result<Vec<PgsScreen>> {
+    let mut state = PgsPixelState::default();
+    let mut screens: Vec<PgsScreen> = Vec::new();
+    for display_set in stream.get_display_set_segments() {
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            continue;
+        };
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            state = PgsPixelState::default();
+        }
+        state.apply(display_set)?;
+        let objects = state.shown_objects(pcs).into_iter().flatten().collect();
+        screens.push(PgsScreen { presentation_timestamp: pcs.header.presentation_timestamp, objects: PgsRc::new(objects) });
+    }
+    Ok(screens)
+}
+
+/// Returns the bounding rectangle (left, top, right, bottom) of an object.
+fn bounds(obj: &PgsShownObject) -> (u32, u32, u32, u32) {
+    let (width, height) = (obj.pixels.first().map_or(0, |row| row.len()), obj.pixels.len());
+    (obj.x as u32, obj.y as u32, obj.x as u32 + width as u32, obj.y as u32 + height as u32)
+}
+
+/// Paints objects onto a single object covering all of them. Later objects are drawn over earlier ones, and the
+/// object is forced only if all objects are.
+fn flatten(objects: &[&PgsShownObject]) -> PgsShownObject {
+    let (left, top, right, bottom) = objects.iter().map(|obj| bounds(obj))
+        .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+        .unwrap_or_default();
+    let mut pixels: Vec<Vec<Option<PgsColor>>> = vec![vec![None; (right - left) as usize]; (bottom - top) as usize];
+    for obj in objects {
+        for (y, row) in obj.pixels.iter().enumerate() {
+            let target = &mut pixels[obj.y as usize - top as usize + y];
+            for (x, &color) in row.iter().enumerate() {
+                if color.is_some_and(|color| color.3 > 0) {
+                    target[obj.x as usize - left as usize + x] = color;
+                }
+            }
+        }
+    }
+    PgsShownObject { x: left as u16, y: top as u16, forced: objects.iter().all(|obj| obj.forced), pixels }
+}
+
+/// Reduces objects to at most `max_objects` objects with non-overlapping bounds, flattening them into one object
+/// otherwise.
+fn arrange(objects: &[&PgsShownObject], max_objects: usize) -> Vec<PgsShownObject> {
+    let overlap = |a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)| a.0 < b.2 && b.0 < a.2 && a.1 < b.3 && b.1 < a.3;
+    let separate = objects.len() <= max_objects.min(MAX_COMPOSITION_OBJECTS)
+        && objects.iter().enumerate().all(|(index, a)| objects[index + 1..].iter().all(|b| !overlap(bounds(a), bounds(b))));
+    match separate {
+        true => objects.iter().map(|obj| flatten(&[obj])).collect(),
+        false => vec![flatten(objects)]
+    }
+}
+
+/// Reduces colors to at most `count` colors by median cut: the box of colors with the widest channel range is
+/// split at its weighted median until there are `count` boxes, and every box is replaced by its weighted average.
+fn quantize(colors: &[(PgsColor, usize)], count: usize) -> Vec<PgsColor> {
+    let channels = |color: PgsColor| [color.0, color.1, color.2, color.3];
+    let mut boxes: Vec<Vec<(PgsColor, usize)>> = vec![colors.to_vec()];
+    while boxes.len() < count {
+        // Widest channel range (range, channel) of every box
+        let widest = boxes.iter().enumerate().filter(|(_, colors)| colors.len() > 1).map(|(index, colors)| {
+            let range = (0..4).map(|channel| {
+                let values = colors.iter().map(|&(color, _)| channels(color)[channel]);
+                (values.clone().max().unwrap_or(0) - values.min().unwrap_or(0), channel)
+            }).max().unwrap_or_default();
+            (range, index)
+        }).max();
+        let Some(((_, channel), index)) = widest else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|&(color, _)| channels(color)[channel]);
+        let total: usize = colors.iter().map(|&(_, weight)| weight).sum();
+        let mut sum = 0;
+        let median = colors.iter().position(|&(_, weight)| { sum += weight; sum * 2 >= total }).unwrap_or(0);
+        let upper = colors.split_off((median + 1).min(colors.len() - 1));
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+    let mut palette: Vec<PgsColor> = boxes.iter().map(|colors| {
+        let total: usize = colors.iter().map(|&(_, weight)| weight).sum();
+        let average = |channel: usize| ((colors.iter().map(|&(color, weight)| channels(color)[channel] as usize * weight).sum::<usize>() + total / 2) / total) as u8;
+        (average(0), average(1), average(2), average(3))
+    }).collect();
+    palette.sort_unstable();
+    palette
+}
+
+/// Builds a palette of at most `max_entries` entries holding the colors of the objects, quantizing the colors if
+/// there are more and mapping every color to the closest palette color.
+///
+/// # Returns
+/// The palette entries, the palette entry id of every color, and whether colors were quantized.
+fn build_palette(objects: &[PgsShownObject], max_entries: usize) -> (Vec<PgsPdsSegmentPaletteEntry>, HashMap<PgsColor, u8>, bool) {
+    let mut counts: HashMap<PgsColor, usize> = HashMap::new();
+    for color in objects.iter().flat_map(|obj| obj.pixels.iter().flatten()).flatten().filter(|color| color.3 > 0) {
+        *counts.entry(*color).or_default() += 1;
+    }
+    let mut colors: Vec<(PgsColor, usize)> = counts.into_iter().collect();
+    colors.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let quantized = colors.len() >= max_entries;
+    let kept: Vec<PgsColor> = match quantized {
+        true => quantize(&colors, max_entries - 1),
+        false => colors.iter().map(|&(color, _)| color).collect()
+    };
+    let mut entries = vec![PgsPdsSegmentPaletteEntry { palette_entry_id: TRANSPARENT_ENTRY, luminance: 16, color_difference_red: 128, color_difference_blue: 128, transparency: 0 }];
+    let mut indices: HashMap<PgsColor, u8> = HashMap::new();
+    for (index, &color) in kept.iter().enumerate() {
+        let palette_entry_id = index as u8 + 1;
+        entries.push(PgsPdsSegmentPaletteEntry { palette_entry_id, luminance: color.0, color_difference_red: color.1, color_difference_blue: color.2, transparency: color.3 });
+        indices.insert(color, palette_entry_id);
+    }
+    let distance = |a: PgsColor, b: PgsColor| [(a.0, b.0), (a.1, b.1), (a.2, b.2), (a.3, b.3)].iter()
+        .map(|&(a, b)| (a as i32 - b as i32).pow(2)).sum::<i32>();
+    for &(color, _) in &colors {
+        if indices.contains_key(&color) {
+            continue;
+        }
+        let closest = (0..kept.len()).min_by_key(|&index| distance(color, kept[index])).map_or(TRANSPARENT_ENTRY, |index| index as u8 + 1);
+        indices.insert(color, closest);
+    }
+    (entries, indices, quantized)
+}
+
+/// Encodes screen content as an Epoch Start display set, or as a display set clearing the screen if it is empty.
+/// `windows` holds the windows currently on screen, which a clearing display set repeats so decoders erase them.
+fn encode_screen(objects: &[&PgsShownObject], presentation_timestamp: u32, format: PgsVideoFormat, limits: PgsCompositeLimits,
+    windows: &mut Vec<PgsWdsSegmentWindowDefinition>, counts: &mut PgsCompositeCounts) -> Vec<PgsSegment> {
+    let header = |segment_type| PgsSegmentHeader { segment_type, segment_length: 0, presentation_timestamp, decoding_timestamp: presentation_timestamp };
+    let shown = objects.len();
+    let objects = if objects.is_empty() { Vec::new() } else { arrange(objects, limits.objects) };
+    counts.flattened += (objects.len() < shown) as usize;
+    let composition_objects: Vec<PgsPcsSegmentCompositionObjects> = objects.iter().enumerate().map(|(index, obj)| PgsPcsSegmentCompositionObjects {
+        object_id: index as u16,
+        window_id: index as u8,
+        object_cropped_flag: PgsPcsObjectCroppedFlag::Off,
+        object_forced_flag: obj.forced,
+        object_horizontal_position: obj.x,
+        object_vertical_position: obj.y,
+        object_cropping_horizontal_position: 0,
+        object_cropping_vertical_position: 0,
+        object_cropping_width: 0,
+        object_cropping_height_position: 0
+    }).collect();
+    let pcs = PgsPcsSegment {
+        header: header(PgsSegmentType::PCS),
+        width: format.width,
+        height: format.height,
+        frame_rate: format.frame_rate,
+        composition_state: if objects.is_empty() { PgsPcsCompositionState::Normal } else { PgsPcsCompositionState::EpochStart },
+        number_of_composition_objects: composition_objects.len() as u8,
+        composition_objects,
+        ..Default::default()
+    };
+    let mut segments = vec![PgsSegment::Pcs(PgsRc::new(pcs))];
+
+    if !objects.is_empty() {
+        *windows = objects.iter().enumerate().map(|(index, obj)| {
+            let (left, top, right, bottom) = bounds(obj);
+            PgsWdsSegmentWindowDefinition { window_id: index as u8, window_horizontal_position: left as u16, window_vertical_position: top as u16,
+                window_width: (right - left) as u16, window_height: (bottom - top) as u16 }
+        }).collect();
+    }
+    segments.push(PgsSegment::Wds(PgsRc::new(PgsWdsSegment { header: header(PgsSegmentType::WDS), number_of_windows: windows.len() as u8, windows: windows.clone() })));
+
+    if !objects.is_empty() {
+        let (palette_entries, indices, quantized) = build_palette(&objects, limits.colors);
+        counts.quantized += quantized as usize;
+        segments.push(PgsSegment::Pds(PgsRc::new(PgsPdsSegment { header: header(PgsSegmentType::PDS), palette_id: 0, palette_version_number: 0, palette_entries: PgsRc::new(palette_entries) })));
+        for (index, obj) in objects.iter().enumerate() {
+            let bitmap: Vec<Vec<u8>> = obj.pixels.iter()
+                .map(|row| row.iter().map(|color| color.and_then(|color| indices.get(&color).copied()).unwrap_or(TRANSPARENT_ENTRY)).collect())
+                .collect();
+            let (width, height) = (bitmap.first().map_or(0, |row| row.len()) as u16, bitmap.len() as u16);
+            segments.extend(PgsOdsSegment::from_object(header(PgsSegmentType::ODS), index as u16, 0, width, height, &encode_rle(&bitmap))
+                .into_iter().map(|ods| PgsSegment::Ods(PgsRc::new(ods))));
+        }
+    }
+    segments.push(PgsSegment::End(PgsRc::new(PgsEndSegment { header: header(PgsSegmentType::END), data: Vec::new() })));
+    segments
+}
+
+/// Returns the video format of the first PCS of the streams.
+fn video_format(streams: &[&PgsStream]) -> Option<PgsVideoFormat> {
+    streams.iter().flat_map(|stream| stream.get_segments()).find_map(|segment| match segment {
+        PgsSegment::Pcs(pcs) => Some(PgsVideoFormat { width: pcs.width, height: pcs.height, frame_rate: pcs.frame_rate }),
+        _ => None
+    })
+}
+
+/// Encodes the combined screen content of several streams as a new stream, with a display set wherever the
+/// content changes.
+fn composite(streams: &[&PgsStream], limits: PgsCompositeLimits) -> Result<(PgsStream, PgsCompositeCounts)> {
+    let Some(format) = video_format(streams) else {
+        return Ok((PgsStream::default(), PgsCompositeCounts::default()));
+    };
+    let timelines = streams.iter().map(|stream| screens(stream)).collect::<Result<Vec<_>>>()?;
+    Ok(composite_screens(&timelines, format, limits))
+}
+
+/// Encodes the combined screen content of several timelines, as returned by `screens`, as a new stream.
+fn composite_screens(timelines: &[Vec<PgsScreen>], format: PgsVideoFormat, limits: PgsCompositeLimits) -> (PgsStream, PgsCompositeCounts) {
+    let mut timestamps: Vec<u32> = timelines.iter().flatten().map(|screen| screen.presentation_timestamp).collect();
+    timestamps.sort_unstable();
+    timestamps.dedup();
+
+    let mut segments: Vec<PgsSegment> = Vec::new();
+    let mut counts = PgsCompositeCounts::default();
+    let mut windows: Vec<PgsWdsSegmentWindowDefinition> = Vec::new();
+    let mut positions = vec![0; timelines.len()];
+    let mut previous: Vec<*const Vec<PgsShownObject>> = Vec::new();
+    for timestamp in timestamps {
+        // Content of every stream at this time
+        let mut current: Vec<&PgsRc<Vec<PgsShownObject>>> = Vec::new();
+        for (timeline, position) in timelines.iter().zip(positions.iter_mut()) {
+            while timeline.get(*position + 1).is_some_and(|next| next.presentation_timestamp <= timestamp) {
+                *position += 1;
+            }
+            if let Some(screen) = timeline.get(*position).filter(|screen| screen.presentation_timestamp <= timestamp) {
+                current.push(&screen.objects);
+            }
+        }
+        let identity: Vec<*const Vec<PgsShownObject>> = current.iter().map(|objects| PgsRc::as_ptr(objects)).collect();
+        if identity == previous {
+            continue;
+        }
+        previous = identity;
+
+        let objects: Vec<&PgsShownObject> = current.iter().flat_map(|objects| objects.iter()).collect();
+        if segments.is_empty() && objects.is_empty() {
+            continue;
+        }
+        counts.combined += (current.iter().filter(|objects| !objects.is_empty()).count() > 1) as usize;
+        segments.extend(encode_screen(&objects, timestamp, format, limits, &mut windows, &mut counts));
+    }
+
+    let mut stream = PgsStream::new(segments);
+    stream.renumber_compositions();
+    regenerate_decoding_timestamps(&mut stream);
+    (stream, counts)
+}
+
+/// Merges streams into one like `PgsStream::merge`, but shows subtitles of different streams that are on screen
+/// at the same time together instead of letting them replace each other.
+///
+/// Whenever the content of any stream changes, a display set is written showing the content of all streams:
+/// objects of different streams become separate composition objects if there are at most two and they do not
+/// overlap, and are painted into a single object otherwise. Every display set starts a new epoch with a palette
+/// merged from the colors on screen; above 255 colors, the colors are quantized like `reduce_palettes` does.
+///
+/// # Parameters
+/// - `streams`: The streams to merge, drawn over each other in order.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The merged stream and the number of its display sets showing subtitles of several streams.
+pub fn merge_events(streams: &[PgsStream]) -> Result<(PgsStream, usize)> {
+    let limits = PgsCompositeLimits { objects: MAX_COMPOSITION_OBJECTS, colors: MAX_PALETTE_ENTRIES };
+    let (stream, counts) = composite(&streams.iter().collect::<Vec<_>>(), limits)?;
+    Ok((stream, counts.combined))
+}
+
+/// Composites all objects of every display set into a single object and window, for formats and decoders that
+/// support only one object per composition.
+///
+/// The stream is re-encoded from its screen content like `merge_events` does, so every display set showing
+/// subtitles starts a new epoch. Flattened objects cover the bounding box of the original objects; objects drawn
+/// later are painted over earlier ones, and the object is forced only if all original objects were.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The number of display sets whose objects were flattened.
+pub fn flatten_objects(stream: &mut PgsStream) -> Result<usize> {
+    let (flattened, counts) = composite(&[stream], PgsCompositeLimits { objects: 1, colors: MAX_PALETTE_ENTRIES })?;
+    *stream = flattened;
+    Ok(counts.flattened)
+}
+
+/// Requantizes the colors shown by every display set to a palette of at most `colors` entries, for decoders with
+/// limited palettes or for export to formats like VobSub.
+///
+/// The stream is re-encoded from its screen content like `merge_events` does, so every display set showing
+/// subtitles starts a new epoch with its own palette. The transparent entry counts as one of the `colors`; the
+/// other colors are chosen by median cut over luminance, color difference and transparency, weighted by how many
+/// pixels use each color.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `colors`: The number of palette entries, from 2 to 256.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if `colors` is out of range, or `Error::InvalidRleData` if an object cannot be
+/// decoded.
+///
+/// # Returns
+/// The number of display sets whose colors were reduced.
+pub fn reduce_palettes(stream: &mut PgsStream, colors: usize) -> Result<usize> {
+    if !(2..=MAX_PALETTE_ENTRIES).contains(&colors) {
+        return Err(Error::InvalidArgument(format!("palette size {colors} is not between 2 and {MAX_PALETTE_ENTRIES}")));
+    }
+    let (reduced, counts) = composite(&[stream], PgsCompositeLimits { objects: MAX_COMPOSITION_OBJECTS, colors })?;
+    *stream = reduced;
+    Ok(counts.quantized)
+}
+
+/// An RGBA image drawn onto subtitles by `stamp_overlay`, for example a watermark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgsOverlay {
+    pub width: u16,
+    pub height: u16,
+    /// Red, green, blue and alpha of every pixel, row by row.
+    pub rgba: Vec<u8>
+}
+
+/// Where `stamp_overlay` places the overlay on an object.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PgsOverlayAnchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center
+}
+
+/// Alpha-blends the overlay onto an object at the anchor, clipped to the object's bounds.
+fn blend_overlay(obj: &mut PgsShownObject, overlay: &PgsOverlay, colors: &[PgsColor], anchor: PgsOverlayAnchor) {
+    let (width, height) = (obj.pixels.first().map_or(0, |row| row.len()) as i64, obj.pixels.len() as i64);
+    let (overlay_width, overlay_height) = (overlay.width as i64, overlay.height as i64);
+    let (left, top) = match anchor {
+        PgsOverlayAnchor::TopLeft => (0, 0),
+        PgsOverlayAnchor::TopRight => (width - overlay_width, 0),
+        PgsOverlayAnchor::BottomLeft => (0, height - overlay_height),
+        PgsOverlayAnchor::BottomRight => (width - overlay_width, height - overlay_height),
+        PgsOverlayAnchor::Center => ((width - overlay_width) / 2, (height - overlay_height) / 2)
+    };
+    for (index, &(y, cr, cb, alpha)) in colors.iter().enumerate() {
+        let (x, row) = (left + index as i64 % overlay_width, top + index as i64 / overlay_width);
+        if alpha == 0 || !(0..width).contains(&x) || !(0..height).contains(&row) {
+            continue;
+        }
+        let pixel = &mut obj.pixels[row as usize][x as usize];
+        let (below, below_alpha) = match *pixel {
+            Some((y, cr, cb, alpha)) => ([y, cr, cb], alpha as u32),
+            None => ([0, 128, 128], 0)
+        };
+        // Source over, with the alpha of the result scaled to 255
+        let out_alpha = alpha as u32 * 255 + below_alpha * (255 - alpha as u32);
+        let channel = |top: u8, below: u8| ((top as u32 * alpha as u32 * 255 + below as u32 * below_alpha * (255 - alpha as u32) + out_alpha / 2) / out_alpha) as u8;
+        *pixel = Some((channel(y, below[0]), channel(cr, below[1]), channel(cb, below[2]), out_alpha.div_ceil(255) as u8));
+    }
+}
+
+/// Draws an RGBA overlay onto every object shown by the stream, for example to watermark review copies.
+///
+/// The overlay is placed on each object at the anchor and clipped to the object; objects smaller than the
+/// overlay show only part of it. The stream is re-encoded from its screen content like `merge_events` does, with
+/// the overlay colors added to the palettes and quantized if more than 255 colors are shown.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `overlay`: The image to draw.
+/// - `anchor`: Where to place the overlay on each object.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the overlay is empty or its pixel data does not match its size, or
+/// `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The number of stamped objects.
+pub fn stamp_overlay(stream: &mut PgsStream, overlay: &PgsOverlay, anchor: PgsOverlayAnchor) -> Result<usize> {
+    if overlay.width == 0 || overlay.height == 0 || overlay.rgba.len() != overlay.width as usize * overlay.height as usize * 4 {
+        return Err(Error::InvalidArgument(format!("overlay data does not match its {}x{} size", overlay.width, overlay.height)));
+    }
+    let Some(format) = video_format(&[stream]) else {
+        return Ok(0);
+    };
+    let colors: Vec<PgsColor> = overlay.rgba.chunks_exact(4).map(|pixel| {
+        let [y, cr, cb] = rgb_to_ycrcb((pixel[0], pixel[1], pixel[2])).map(|value| value.round().clamp(0.0, 255.0) as u8);
+        (y, cr, cb, pixel[3])
+    }).collect();
+
+    let mut timeline = screens(stream)?;
+    let mut stamped = 0;
+    for screen in &mut timeline {
+        for obj in PgsRc::make_mut(&mut screen.objects) {
+            blend_overlay(obj, overlay, &colors, anchor);
+            stamped += 1;
+        }
+    }
+    let (stamped_stream, _) = composite_screens(&[timeline], format, PgsCompositeLimits { objects: MAX_COMPOSITION_OBJECTS, colors: MAX_PALETTE_ENTRIES });
+    *stream = stamped_stream;
+    Ok(stamped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_decode_rle::decode_rle_indices, PgsDisplaySetState};
+
+    use super::*;
+
+    /// A stream showing a filled `width`x`height` object of the given luminance at (x, y) from `start` to `end`.
+    fn event(start: u32, end: u32, (x, y): (u16, u16), (width, height): (u16, u16), luminance: u8) -> PgsStream {
+        let object = PgsShownObject { x, y, forced: false, pixels: vec![vec![Some((luminance, 128, 128, 255)); width as usize]; height as usize] };
+        let format = PgsVideoFormat { width: 1920, height: 1080, frame_rate: 0x10 };
+        let (limits, mut windows, mut counts) = (PgsCompositeLimits { objects: 1, colors: MAX_PALETTE_ENTRIES }, Vec::new(), PgsCompositeCounts::default());
+        PgsStream::new([
+            encode_screen(&[&object], start, format, limits, &mut windows, &mut counts),
+            encode_screen(&[], end, format, limits, &mut windows, &mut counts)
+        ].concat())
+    }
+
+    /// Presentation timestamp and composition object positions of every display set.
+    fn compositions(stream: &PgsStream) -> Vec<(u32, Vec<(u16, u16)>)> {
+        stream.get_display_sets().iter().map(|ds| {
+            let pcs = ds.pcs.as_ref().unwrap();
+            (pcs.header.presentation_timestamp, pcs.composition_objects.iter().map(|obj| (obj.object_horizontal_position, obj.object_vertical_position)).collect())
+        }).collect()
+    }
+
+    #[test]
+    fn test_merge_events() {
+        let top = event(0, 180000, (100, 100), (10, 5), 235);
+        let bottom = event(90000, 270000, (100, 900), (10, 5), 16);
+        let (merged, composited) = merge_events(&[top.clone(), bottom]).unwrap();
+        assert_eq!(composited, 1);
+        assert_eq!(compositions(&merged), vec![
+            (0, vec![(100, 100)]),
+            (90000, vec![(100, 100), (100, 900)]),
+            (180000, vec![(100, 900)]),
+            (270000, vec![])
+        ]);
+        assert_eq!(merged.get_display_sets()[3].state(), PgsDisplaySetState::EmptyFrame);
+
+        // Overlapping objects are painted into one object
+        let covering = event(90000, 180000, (105, 100), (10, 5), 16);
+        let (merged, _) = merge_events(&[top, covering]).unwrap();
+        let display_set = &merged.get_display_sets()[1];
+        assert_eq!(compositions(&merged)[1], (90000, vec![(100, 100)]));
+        let bitmap = decode_rle_indices(display_set.ods.as_ref().unwrap()).unwrap();
+        assert_eq!((bitmap[0].len(), bitmap.len()), (15, 5));
+        let palette = &display_set.pds.as_ref().unwrap().palette_entries;
+        assert_eq!(palette[bitmap[0][0] as usize].luminance, 235);
+        assert_eq!(palette[bitmap[0][14] as usize].luminance, 16);
+    }
+
+    #[test]
+    fn test_flatten_objects() {
+        let (mut stream, _) = merge_events(&[event(0, 90000, (100, 100), (10, 5), 235), event(0, 90000, (100, 900), (10, 5), 16)]).unwrap();
+        assert_eq!(compositions(&stream)[0], (0, vec![(100, 100), (100, 900)]));
+
+        assert_eq!(flatten_objects(&mut stream).unwrap(), 1);
+        assert_eq!(compositions(&stream), vec![(0, vec![(100, 100)]), (90000, vec![])]);
+        let display_set = &stream.get_display_sets()[0];
+        let window = &display_set.wds.as_ref().unwrap().windows;
+        assert_eq!((window.len(), window[0].window_width, window[0].window_height), (1, 10, 805));
+        assert_eq!(flatten_objects(&mut stream).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_reduce_palettes() {
+        // A horizontal gradient over 64 luminance values, shown twice
+        let gradient = PgsShownObject { x: 0, y: 0, forced: false, pixels: vec![(0..64).map(|x| Some((16 + x as u8 * 3, 128, 128, 255))).collect()] };
+        let format = PgsVideoFormat { width: 1920, height: 1080, frame_rate: 0x10 };
+        let (limits, mut windows, mut counts) = (PgsCompositeLimits { objects: 1, colors: MAX_PALETTE_ENTRIES }, Vec::new(), PgsCompositeCounts::default());
+        let mut stream = PgsStream::new([
+            encode_screen(&[&gradient], 0, format, limits, &mut windows, &mut counts),
+            encode_screen(&[], 90000, format, limits, &mut windows, &mut counts)
+        ].concat());
+        assert_eq!(counts.quantized, 0);
+
+        assert!(reduce_palettes(&mut stream, 1).is_err());
+        assert_eq!(reduce_palettes(&mut stream, 16).unwrap(), 1);
+        let display_set = &stream.get_display_sets()[0];
+        let palette = &display_set.pds.as_ref().unwrap().palette_entries;
+        assert_eq!(palette.len(), 16);
+        let bitmap = decode_rle_indices(display_set.ods.as_ref().unwrap()).unwrap();
+        assert!(bitmap[0].windows(2).all(|pair| palette[pair[0] as usize].luminance <= palette[pair[1] as usize].luminance));
+        assert!((palette[bitmap[0][0] as usize].luminance as i32 - 16).abs() <= 6);
+        assert_eq!(reduce_palettes(&mut stream, 16).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_stamp_overlay() {
+        let mut stream = event(0, 90000, (100, 100), (10, 5), 16);
+        // A 2x1 overlay: opaque white and half transparent white
+        let overlay = PgsOverlay { width: 2, height: 1, rgba: vec![255, 255, 255, 255, 255, 255, 255, 128] };
+        assert!(stamp_overlay(&mut stream, &PgsOverlay { width: 2, height: 2, ..overlay.clone() }, PgsOverlayAnchor::Center).is_err());
+        assert_eq!(stamp_overlay(&mut stream, &overlay, PgsOverlayAnchor::BottomRight).unwrap(), 1);
+
+        let display_set = &stream.get_display_sets()[0];
+        let bitmap = decode_rle_indices(display_set.ods.as_ref().unwrap()).unwrap();
+        let palette = &display_set.pds.as_ref().unwrap().palette_entries;
+        let luminance: Vec<u8> = bitmap[4][7..].iter().map(|&index| palette[index as usize].luminance).collect();
+        assert_eq!(luminance, vec![16, 255, 136]);
+        assert_eq!(palette[bitmap[0][9] as usize].luminance, 16);
+    }
+}
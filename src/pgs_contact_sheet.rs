@@ -0,0 +1,173 @@
+//! # PGS Contact Sheet
+//!
+//! This module renders a whole subtitle track into one image for visual checks: every Nth subtitle event is drawn
+//! as a thumbnail of the video frame, at its position on screen, with its start time below it, and the thumbnails
+//! are tiled in rows. Misplaced, garbled or badly colored subtitles stand out at a glance.
+
+use crate::{pgs_export::{export_events, format_srt_timestamp, PgsExportEvent}, Error, PgsDisplaySet, Result};
+
+/// Color of the sheet around the thumbnails.
+const SHEET_COLOR: u32 = 0xFF10_1010;
+/// Color of the video frame of a thumbnail.
+const FRAME_COLOR: u32 = 0xFF30_3030;
+/// Color of the timestamps.
+const LABEL_COLOR: u32 = 0xFFFF_FFFF;
+/// Pixels between the thumbnails and around the sheet.
+const SPACING: usize = 4;
+
+/// Glyphs of the timestamps, 3 pixels wide and 5 high, one row per byte with the leftmost pixel in bit 2.
+const GLYPHS: [(char, [u8; 5]); 12] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010])
+];
+
+/// Options controlling `contact_sheet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsContactSheetOptions {
+    /// Draw every Nth event, starting with the first.
+    pub every: usize,
+    /// Number of thumbnails per row.
+    pub columns: usize,
+    /// Width of a thumbnail in pixels; the height follows from the aspect ratio of the video.
+    pub thumbnail_width: usize,
+    /// Size of a pixel of the timestamp font in sheet pixels.
+    pub label_scale: usize
+}
+
+impl Default for PgsContactSheetOptions {
+    fn default() -> Self {
+        PgsContactSheetOptions { every: 1, columns: 4, thumbnail_width: 384, label_scale: 2 }
+    }
+}
+
+/// A contact sheet of a subtitle track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsContactSheet {
+    /// ARGB pixels of the sheet, row by row; empty if the track has no events.
+    pub pixels: Vec<Vec<u32>>,
+    /// The events drawn, in the order of the thumbnails.
+    pub events: Vec<PgsExportEvent>
+}
+
+/// Blends an ARGB pixel over an opaque one.
+fn blend(dst: u32, src: u32) -> u32 {
+    let alpha = src >> 24;
+    let channel = |shift: u32| (((src >> shift) & 0xFF) * alpha + ((dst >> shift) & 0xFF) * (255 - alpha)) / 255;
+    0xFF00_0000 | channel(16) << 16 | channel(8) << 8 | channel(0)
+}
+
+/// Draws text with the timestamp font, its top left corner at `(x, y)`; characters without a glyph are blank.
+fn draw_text(pixels: &mut [Vec<u32>], x: usize, y: usize, text: &str, scale: usize) {
+    for (index, char) in text.chars().enumerate() {
+        let Some((_, glyph)) = GLYPHS.iter().find(|(glyph, _)| *glyph == char) else {
+            continue;
+        };
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in (0..3).filter(|col| bits & (0b100 >> col) != 0) {
+                let (left, top) = (x + (4 * index + col) * scale, y + row * scale);
+                for pixel in pixels.iter_mut().skip(top).take(scale).flat_map(|line| line.iter_mut().skip(left).take(scale)) {
+                    *pixel = LABEL_COLOR;
+                }
+            }
+        }
+    }
+}
+
+/// Renders every Nth subtitle event of a track as a thumbnail and tiles the thumbnails into one image.
+///
+/// Events are built with `export::export_events`. Each thumbnail shows the video frame of the composition scaled
+/// to `thumbnail_width`, with the bitmap of the event drawn at its position (nearest neighbour), and the start time
+/// of the event as `hh:mm:ss.mmm` below it. The video size is taken from the first event.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+/// - `options`: Selection of events and layout of the sheet.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if `every`, `columns`, `thumbnail_width` or `label_scale` is zero, or
+/// `Error::InvalidRleData` if a bitmap cannot be decoded.
+///
+/// # Returns
+/// The sheet and the events drawn on it.
+pub fn contact_sheet(display_sets: &[PgsDisplaySet], options: &PgsContactSheetOptions) -> Result<PgsContactSheet> {
+    if options.every == 0 || options.columns == 0 || options.thumbnail_width == 0 || options.label_scale == 0 {
+        return Err(Error::InvalidArgument("contact sheet options must not be zero".to_string()));
+    }
+    let events: Vec<PgsExportEvent> = export_events(display_sets).into_iter().step_by(options.every).collect();
+    let Some(first) = events.first() else {
+        return Ok(PgsContactSheet { pixels: Vec::new(), events });
+    };
+    let (video_width, video_height) = display_sets[first.display_set].pcs.as_ref()
+        .filter(|pcs| pcs.width > 0 && pcs.height > 0)
+        .map_or((1920, 1080), |pcs| (pcs.width as usize, pcs.height as usize));
+
+    let (width, scale) = (options.thumbnail_width, options.label_scale);
+    let height = (width * video_height / video_width).max(1);
+    let label_height = 7 * scale;
+    let rows = events.len().div_ceil(options.columns);
+    let mut pixels = vec![vec![SHEET_COLOR; options.columns * (width + SPACING) + SPACING]; rows * (height + label_height + SPACING) + SPACING];
+
+    for (index, event) in events.iter().enumerate() {
+        let bitmap = display_sets[event.display_set].get_decoded_image(false)?;
+        let left = SPACING + index % options.columns * (width + SPACING);
+        let top = SPACING + index / options.columns * (height + label_height + SPACING);
+        for (y, line) in pixels[top..top + height].iter_mut().enumerate() {
+            // Row of the bitmap shown at this row of the thumbnail, if any
+            let row = (y * video_height / height).checked_sub(event.y as usize).and_then(|row| bitmap.get(row));
+            for (x, pixel) in line[left..left + width].iter_mut().enumerate() {
+                let src = row.and_then(|row| (x * video_width / width).checked_sub(event.x as usize).and_then(|col| row.get(col)));
+                *pixel = src.map_or(FRAME_COLOR, |&src| blend(FRAME_COLOR, src));
+            }
+        }
+        let label = format_srt_timestamp(event.start).replace(',', ".");
+        draw_text(&mut pixels, left, top + height + scale, &label, scale);
+    }
+    Ok(PgsContactSheet { pixels, events })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{composition_object, display_set_of, ods, palette_entry, pcs, pds, wds}, PgsPcsCompositionState, PgsPcsSegment};
+
+    use super::*;
+
+    /// A display set of a 160x90 video showing an opaque white 40x10 bitmap at (60, 70).
+    fn display_set(pts: u32) -> PgsDisplaySet {
+        display_set_of(
+            PgsPcsSegment { width: 160, height: 90, ..pcs(pts, PgsPcsCompositionState::Normal, vec![composition_object(0, 0, 60, 70)]) },
+            Some(wds(pts, Vec::new())),
+            Some(pds(pts, 0, vec![palette_entry(0, 235, 255)])),
+            Some(ods(pts, 0, 0, 40, 10, &vec![vec![0; 40]; 10]))
+        )
+    }
+
+    #[test]
+    fn test_contact_sheet() {
+        let display_sets: Vec<PgsDisplaySet> = (0..5).map(|index| display_set(90000 * index)).collect();
+        let options = PgsContactSheetOptions { every: 2, columns: 2, thumbnail_width: 80, label_scale: 1 };
+        let sheet = contact_sheet(&display_sets, &options).unwrap();
+        assert_eq!(sheet.events.iter().map(|event| event.display_set).collect::<Vec<_>>(), [0, 2, 4]);
+
+        // Two rows of 80x45 thumbnails with 7 rows of labels
+        assert_eq!((sheet.pixels[0].len(), sheet.pixels.len()), (2 * 84 + 4, 2 * 56 + 4));
+        assert_eq!(sheet.pixels[4][4], FRAME_COLOR);
+        let white = sheet.pixels[4 + 37][4 + 35];
+        assert_ne!(white, FRAME_COLOR);
+        assert_eq!(white >> 24, 0xFF);
+        // The first digit of the label of the second thumbnail
+        assert_eq!(sheet.pixels[4 + 45 + 1][4 + 84], LABEL_COLOR);
+
+        assert!(contact_sheet(&display_sets, &PgsContactSheetOptions { columns: 0, ..options }).is_err());
+        assert!(contact_sheet(&[], &options).unwrap().pixels.is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+//! # PGS Debug Dump
+//!
+//! This module defines `PgsDebugDump`, a wrapper whose `Debug` output is an annotated hexdump of an encoded segment:
+//! every field of the header and the payload on its own line with its offset, its bytes and its decoded value.
+//! The fields are found by walking the bytes, not by parsing them, so segments that fail to parse, or parse into
+//! something unexpected, can be dumped as well; fields missing from truncated data are marked.
+
+use std::{borrow::Cow, fmt};
+
+use crate::{pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH, PgsOdsSequenceFlag, PgsPcsCompositionState, PgsSegment, PgsSegmentType, PgsWriter, Result};
+
+/// Number of bytes per row of bulk data, such as RLE object data.
+const BYTES_PER_ROW: usize = 16;
+
+/// An encoded segment, header included, shown as an annotated hexdump by its `Debug` implementation.
+///
+/// # Example
+/// ```rust
+/// use pgs_parse::PgsDebugDump;
+///
+/// let end = [0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0, 0];
+/// println!("{:?}", PgsDebugDump::from_raw(&end).with_offset(0x1000));
+/// ```
+pub struct PgsDebugDump<'a> {
+    data: Cow<'a, [u8]>,
+    /// Offset added to the offsets shown, e.g. the file offset of the segment.
+    offset: usize
+}
+
+/// A field found in the encoded segment: its position and length, and its name and value, or `None` for bulk data.
+struct PgsDumpField {
+    position: usize,
+    length: usize,
+    name: String,
+    value: Option<String>
+}
+
+/// Walks the bytes of a segment, collecting its fields.
+struct PgsDumpLayout<'d> {
+    data: &'d [u8],
+    position: usize,
+    fields: Vec<PgsDumpField>
+}
+
+impl PgsDumpLayout<'_> {
+    /// Adds a big-endian integer field of `length` bytes, described by `describe`, and returns its value, or
+    /// `None` if the data ends before it.
+    fn field(&mut self, name: &str, length: usize, describe: impl Fn(u32) -> String) -> Option<u32> {
+        let bytes = self.data.get(self.position..self.position + length)?;
+        let value = bytes.iter().fold(0, |value, &byte| value << 8 | byte as u32);
+        self.fields.push(PgsDumpField { position: self.position, length, name: name.to_string(), value: Some(describe(value)) });
+        self.position += length;
+        Some(value)
+    }
+
+    /// Adds an integer field shown in decimal.
+    fn number(&mut self, name: &str, length: usize) -> Option<u32> {
+        self.field(name, length, |value| value.to_string())
+    }
+
+    /// Adds the remaining bytes as bulk data.
+    fn rest(&mut self, name: &str) {
+        let length = self.data.len().saturating_sub(self.position);
+        if length > 0 {
+            self.fields.push(PgsDumpField { position: self.position, length, name: format!("{name} ({length} bytes)"), value: None });
+            self.position += length;
+        }
+    }
+}
+
+impl<'a> PgsDebugDump<'a> {
+    /// Dumps a segment as it is written to a file.
+    ///
+    /// # Errors
+    /// Returns an error if the segment cannot be serialized.
+    pub fn new(segment: &PgsSegment) -> Result<PgsDebugDump<'static>> {
+        let mut writer = PgsWriter::new(Vec::new());
+        writer.write_segment(segment)?;
+        Ok(PgsDebugDump { data: Cow::Owned(writer.into_inner()), offset: 0 })
+    }
+
+    /// Dumps the raw bytes of a segment, header included, e.g. from `PgsParser::get_raw_segment`.
+    pub fn from_raw(data: &'a [u8]) -> Self {
+        PgsDebugDump { data: Cow::Borrowed(data), offset: 0 }
+    }
+
+    /// Shows offsets starting at `offset` instead of 0, e.g. the file offset of the segment.
+    pub fn with_offset(self, offset: usize) -> Self {
+        PgsDebugDump { offset, ..self }
+    }
+
+    /// Finds the fields of the segment.
+    fn fields(&self) -> Vec<PgsDumpField> {
+        let mut layout = PgsDumpLayout { data: &self.data, position: 0, fields: Vec::new() };
+        self.header_fields(&mut layout);
+        layout.rest("unexpected data");
+        layout.fields
+    }
+
+    /// Finds the fields of the header and, if it is complete, of the payload.
+    fn header_fields(&self, layout: &mut PgsDumpLayout) -> Option<()> {
+        layout.field("magic", 2, |value| format!("{:?}", String::from_utf8_lossy(&(value as u16).to_be_bytes())))?;
+        layout.number("pts", 4)?;
+        layout.number("dts", 4)?;
+        let segment_type = layout.field("segment_type", 1, |value| format!("{:#04x} {:?}", value, PgsSegmentType::from(value as u8)))? as u8;
+        let length = layout.number("segment_length", 2)? as usize;
+        // The payload ends at the segment length, anything after it is shown as unexpected data
+        let end = (PGS_SEGMENT_HEADER_LENGTH + length).min(layout.data.len());
+        let mut payload = PgsDumpLayout { data: &layout.data[..end], position: layout.position, fields: Vec::new() };
+        Self::payload_fields(&mut payload, segment_type);
+        payload.rest("unparsed data");
+        layout.position = payload.position;
+        layout.fields.append(&mut payload.fields);
+        Some(())
+    }
+
+    /// Finds the fields of the payload of a segment type.
+    fn payload_fields(layout: &mut PgsDumpLayout, segment_type: u8) -> Option<()> {
+        match PgsSegmentType::from(segment_type) {
+            PgsSegmentType::PCS => {
+                layout.number("width", 2)?;
+                layout.number("height", 2)?;
+                layout.field("frame_rate", 1, |value| format!("{value:#04x}"))?;
+                layout.number("composition_number", 2)?;
+                layout.field("composition_state", 1, |value| format!("{:#04x} {:?}", value, PgsPcsCompositionState::from(value as u8)))?;
+                layout.field("palette_update_flag", 1, |value| format!("{value:#04x}"))?;
+                layout.number("palette_id", 1)?;
+                let objects = layout.number("number_of_composition_objects", 1)?;
+                for index in 0..objects {
+                    layout.number(&format!("object[{index}].object_id"), 2)?;
+                    layout.number(&format!("object[{index}].window_id"), 1)?;
+                    let flags = layout.field(&format!("object[{index}].flags"), 1, |value| {
+                        let names = [(0x40, "cropped"), (0x80, "forced")].iter().filter(|(bit, _)| value & bit != 0).map(|(_, name)| *name).collect::<Vec<_>>();
+                        format!("{:#04x} {}", value, names.join(" ")).trim_end().to_string()
+                    })?;
+                    layout.number(&format!("object[{index}].x"), 2)?;
+                    layout.number(&format!("object[{index}].y"), 2)?;
+                    if flags & 0x40 != 0 {
+                        for name in ["crop_x", "crop_y", "crop_width", "crop_height"] {
+                            layout.number(&format!("object[{index}].{name}"), 2)?;
+                        }
+                    }
+                }
+            },
+            PgsSegmentType::WDS => {
+                let windows = layout.number("number_of_windows", 1)?;
+                for index in 0..windows {
+                    for (name, length) in [("window_id", 1), ("x", 2), ("y", 2), ("width", 2), ("height", 2)] {
+                        layout.number(&format!("window[{index}].{name}"), length)?;
+                    }
+                }
+            },
+            PgsSegmentType::PDS => {
+                layout.number("palette_id", 1)?;
+                layout.number("palette_version_number", 1)?;
+                while layout.data.len() >= layout.position + 5 {
+                    let entry = &layout.data[layout.position..layout.position + 5];
+                    let value = format!("y={} cr={} cb={} alpha={}", entry[1], entry[2], entry[3], entry[4]);
+                    layout.fields.push(PgsDumpField { position: layout.position, length: 5, name: format!("entry[{}]", entry[0]), value: Some(value) });
+                    layout.position += 5;
+                }
+            },
+            PgsSegmentType::ODS => {
+                layout.number("object_id", 2)?;
+                layout.number("object_version_number", 1)?;
+                let flag = layout.field("sequence_flag", 1, |value| format!("{:#04x} {:?}", value, PgsOdsSequenceFlag::from(value as u8)))?;
+                if flag & 0x80 != 0 {
+                    layout.number("object_data_length", 3)?;
+                    layout.number("width", 2)?;
+                    layout.number("height", 2)?;
+                }
+                layout.rest("object_data");
+            },
+            PgsSegmentType::END => layout.rest("data"),
+            PgsSegmentType::ERR => layout.rest("payload")
+        }
+        Some(())
+    }
+}
+
+impl fmt::Debug for PgsDebugDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = self.fields();
+        for field in &fields {
+            let bytes = &self.data[field.position..field.position + field.length];
+            match &field.value {
+                Some(value) => {
+                    let hex = bytes.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" ");
+                    writeln!(f, "{:08x}  {:<24}  {} = {}", self.offset + field.position, hex, field.name, value)?;
+                },
+                None => for (row, chunk) in bytes.chunks(BYTES_PER_ROW).enumerate() {
+                    let hex = chunk.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" ");
+                    let name = if row == 0 { field.name.as_str() } else { "" };
+                    let line = format!("{:08x}  {:<47}  {}", self.offset + field.position + row * BYTES_PER_ROW, hex, name);
+                    writeln!(f, "{}", line.trim_end())?;
+                }
+            }
+        }
+        // Fields cut off by the end of the data
+        if self.truncated() {
+            writeln!(f, "{:08x}  (truncated)", self.offset + self.data.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl PgsDebugDump<'_> {
+    /// Returns `true` if the data is shorter than the segment length in its header.
+    fn truncated(&self) -> bool {
+        match self.data.get(11..PGS_SEGMENT_HEADER_LENGTH) {
+            Some(length) => self.data.len() < PGS_SEGMENT_HEADER_LENGTH + u16::from_be_bytes([length[0], length[1]]) as usize,
+            None => true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PgsPcsObjectCroppedFlag, PgsPcsSegment, PgsPcsSegmentCompositionObjects, PgsRc, PgsSegmentHeader};
+
+    use super::*;
+
+    #[test]
+    fn test_debug_dump() {
+        let pcs = PgsPcsSegment {
+            header: PgsSegmentHeader { segment_type: PgsSegmentType::PCS, segment_length: 19, presentation_timestamp: 90000, decoding_timestamp: 0 },
+            width: 1920,
+            height: 1080,
+            composition_state: PgsPcsCompositionState::EpochStart,
+            number_of_composition_objects: 1,
+            composition_objects: vec![PgsPcsSegmentCompositionObjects {
+                object_id: 0,
+                window_id: 0,
+                object_cropped_flag: PgsPcsObjectCroppedFlag::Off,
+                object_forced_flag: true,
+                object_horizontal_position: 100,
+                object_vertical_position: 900,
+                object_cropping_horizontal_position: 0,
+                object_cropping_vertical_position: 0,
+                object_cropping_width: 0,
+                object_cropping_height_position: 0
+            }],
+            ..Default::default()
+        };
+        let dump = format!("{:?}", PgsDebugDump::new(&PgsSegment::Pcs(PgsRc::new(pcs))).unwrap());
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 18);
+        assert_eq!(lines[0], "00000000  50 47                     magic = \"PG\"");
+        assert_eq!(lines[3], "0000000a  16                        segment_type = 0x16 PCS");
+        assert_eq!(lines[9], "00000014  80                        composition_state = 0x80 EpochStart");
+        assert_eq!(lines[15], "0000001b  80                        object[0].flags = 0x80 forced");
+
+        // A cut ODS, shown at its file offset
+        let data = [0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x15, 0, 30, 0, 1, 0, 0xC0, 0, 0];
+        let dump = format!("{:?}", PgsDebugDump::from_raw(&data).with_offset(0x100));
+        assert!(dump.contains("0000010d  00 01                     object_id = 1"));
+        assert!(dump.contains("00000110  c0                        sequence_flag = 0xc0 Both"));
+        assert!(dump.ends_with("00000111  00 00                                            unparsed data (2 bytes)\n00000113  (truncated)\n"));
+    }
+}
@@ -0,0 +1,143 @@
+//! # PGS Decode Cache
+//!
+//! This module defines `PgsDecodeCache`, a size-bounded least recently used cache of decoded bitmaps. Viewers that
+//! show the same display sets again and again, e.g. while scrubbing back and forth through a stream, decode every
+//! object once and get the cached pixels afterwards.
+
+use crate::{PgsDisplaySet, PgsOdsSegment, PgsPdsSegment, PgsRc, Result};
+
+/// A decoded bitmap with the segments it was decoded from.
+#[derive(Debug)]
+struct PgsCachedImage {
+    ods: PgsRc<PgsOdsSegment>,
+    pds: PgsRc<PgsPdsSegment>,
+    gray: bool,
+    size: usize,
+    pixels: PgsRc<Vec<Vec<u32>>>
+}
+
+/// A cache of the bitmaps decoded by `PgsDisplaySet::get_decoded_image`, evicting the least recently used ones once
+/// their size exceeds a limit.
+///
+/// Entries are keyed by the shared ODS and PDS segments of a display set, so display sets cloned from each other
+/// or parsed by the same `PgsParser` share their entries. The cache keeps these segments alive until the entry is
+/// evicted.
+///
+/// # Example
+/// ```rust
+/// use pgs_parse::{PgsDecodeCache, PgsParser};
+///
+/// let mut cache = PgsDecodeCache::new(64 * 1024 * 1024);
+/// if let Ok(parser) = PgsParser::parse("subtitle.sup") {
+///     for display_set in parser.get_display_sets() {
+///         let pixels = cache.get_decoded_image(display_set, false);
+///     }
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct PgsDecodeCache {
+    capacity: usize,
+    size: usize,
+    // Ordered from least to most recently used
+    images: Vec<PgsCachedImage>
+}
+
+impl PgsDecodeCache {
+    /// Creates an empty cache holding up to `capacity` bytes of decoded pixels, as counted by
+    /// `PgsDisplaySet::decoded_size_bytes`.
+    pub fn new(capacity: usize) -> Self {
+        PgsDecodeCache { capacity, ..Default::default() }
+    }
+
+    /// Returns the decoded image of a display set from the cache, decoding and caching it on a miss.
+    ///
+    /// Images larger than the capacity of the cache are decoded but not cached.
+    ///
+    /// # Parameters
+    /// - `display_set`: The display set to decode.
+    /// - `gray`: A boolean flag indicating whether to decode the image in grayscale (`true`) or color (`false`).
+    ///
+    /// # Errors
+    /// Returns the errors of `PgsDisplaySet::get_decoded_image`.
+    ///
+    /// # Returns
+    /// The decoded pixels, shared with the cache.
+    pub fn get_decoded_image(&mut self, display_set: &PgsDisplaySet, gray: bool) -> Result<PgsRc<Vec<Vec<u32>>>> {
+        let position = match (&display_set.ods, &display_set.pds) {
+            (Some(ods), Some(pds)) => self.images.iter().position(|image| {
+                image.gray == gray && PgsRc::ptr_eq(&image.ods, ods) && PgsRc::ptr_eq(&image.pds, pds)
+            }),
+            _ => None
+        };
+        if let Some(position) = position {
+            let image = self.images.remove(position);
+            let pixels = image.pixels.clone();
+            self.images.push(image);
+            return Ok(pixels);
+        }
+
+        let pixels = PgsRc::new(display_set.get_decoded_image(gray)?);
+        let size = display_set.decoded_size_bytes();
+        if let (Some(ods), Some(pds), true) = (&display_set.ods, &display_set.pds, size <= self.capacity) {
+            while self.size + size > self.capacity {
+                let evicted = self.images.remove(0);
+                self.size -= evicted.size;
+            }
+            self.images.push(PgsCachedImage { ods: ods.clone(), pds: pds.clone(), gray, size, pixels: pixels.clone() });
+            self.size += size;
+        }
+        Ok(pixels)
+    }
+
+    /// Returns the number of cached images.
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Returns `true` if no image is cached.
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Returns the size in bytes of the cached images.
+    pub fn size_bytes(&self) -> usize {
+        self.size
+    }
+
+    /// Removes all cached images.
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.size = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{bitmap_display_set, palette_entry};
+
+    use super::*;
+
+    #[test]
+    fn test_decode_cache() {
+        // Complete display sets showing a single row of pixels
+        let row = |width| bitmap_display_set(0, vec![palette_entry(0, 235, 255)], &[vec![0; width]]);
+        let (first, second) = (row(100), row(200));
+        let mut cache = PgsDecodeCache::new(2 * first.decoded_size_bytes());
+
+        let pixels = cache.get_decoded_image(&first, false).unwrap();
+        assert_eq!(*pixels, first.get_decoded_image(false).unwrap());
+        assert!(PgsRc::ptr_eq(&pixels, &cache.get_decoded_image(&first.clone(), false).unwrap()));
+        assert!(!PgsRc::ptr_eq(&pixels, &cache.get_decoded_image(&first, true).unwrap()));
+        assert_eq!(cache.len(), 2);
+
+        // Caching the second image evicts both least recently used images of the first display set
+        cache.get_decoded_image(&second, false).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.size_bytes(), second.decoded_size_bytes());
+
+        cache.get_decoded_image(&row(1000), false).unwrap();
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
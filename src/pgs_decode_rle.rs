@@ -1,6 +1,11 @@
-use std::rc::Rc;
+use crate::{pgs_error::Result, Error, PgsCancelToken, pgs_memory_buffer::ReadBytes, PgsMemoryBuffer, PgsOdsSegment, PgsPdsSegment, PgsPdsSegmentPaletteEntry, PgsRc, PgsSeek, pgs_trace::decode_span};
 
-use crate::{pgs_error::Result, pgs_memory_buffer::ReadBytes, PgsMemoryBuffer, PgsOdsSegment, PgsPdsSegment, PgsSeek};
+/// Largest bitmap the decoder allocates, in pixels: the area of a 4096x4096 object, the maximum object size.
+const MAX_DECODED_PIXELS: usize = 4096 * 4096;
+
+/// Size in pixels from which objects are decoded row by row in parallel, about half of a 1080p screen.
+#[cfg(feature = "rayon")]
+const PARALLEL_DECODE_PIXELS: usize = 1920 * 1080 / 2;
 
 /// Converts a single byte to an unsigned 32-bit integer.
 pub fn byte_to_int(byte: u8) -> u32 {
@@ -42,7 +47,7 @@ pub fn calc_gray(transparency: u8, luminance: u8) -> u32 {
 }
 
 /// Retrieves the grayscale color from a PDS segment palette entry, or white if out of bounds.
-pub fn get_gray_color(color: usize, pds: &Rc<PgsPdsSegment>) -> u32 {
+pub fn get_gray_color(color: usize, pds: &PgsRc<PgsPdsSegment>) -> u32 {
     if color >= pds.palette_entries.len() { 
         0xFFFFFF
     } else {
@@ -51,7 +56,7 @@ pub fn get_gray_color(color: usize, pds: &Rc<PgsPdsSegment>) -> u32 {
 }
 
 /// Retrieves the ARGB color from a PDS segment palette entry, or white if out of bounds.
-pub fn get_argb_color(color: usize, pds: &Rc<PgsPdsSegment>) -> u32 {
+pub fn get_argb_color(color: usize, pds: &PgsRc<PgsPdsSegment>) -> u32 {
     if color >= pds.palette_entries.len() { 
         0xFFFFFF
     } else {
@@ -61,7 +66,7 @@ pub fn get_argb_color(color: usize, pds: &Rc<PgsPdsSegment>) -> u32 {
 }
 
 /// Retrieves either grayscale or ARGB color, depending on the `gray` flag.
-pub fn get_pixel_color(color: usize, pds: &Rc<PgsPdsSegment>, gray: bool) -> u32 {
+pub fn get_pixel_color(color: usize, pds: &PgsRc<PgsPdsSegment>, gray: bool) -> u32 {
     if gray { 
         get_gray_color(color, pds) 
     } else { 
@@ -78,9 +83,88 @@ pub fn get_pixel_color(color: usize, pds: &Rc<PgsPdsSegment>, gray: bool) -> u32
 ///
 /// Returns:
 /// - A 2D vector representing pixel colors decoded from the RLE data.
-pub fn decode_rle(pds: Rc<PgsPdsSegment>, ods: Rc<PgsOdsSegment>, gray: bool) -> Result<Vec<Vec<u32>>> {
-    // Create a 2D vector of pixels initialized to 0, with dimensions (width x height) based on the ODS.
-    let mut pixels: Vec<Vec<u32>> = vec![vec![0_u32; ods.width as usize]; ods.height as usize];
+pub fn decode_rle(pds: PgsRc<PgsPdsSegment>, ods: PgsRc<PgsOdsSegment>, gray: bool) -> Result<Vec<Vec<u32>>> {
+    decode_rle_with(&ods, 0, None, |color| get_pixel_color(color, &pds, gray))
+}
+
+/// Decodes a Run-Length Encoded (RLE) bitmap like `decode_rle`, checking a cancellation token after every row.
+///
+/// Arguments:
+/// - `pds`: Reference-counted pointer to a `PgsPdsSegment` for palette data.
+/// - `ods`: Reference-counted pointer to a `PgsOdsSegment` for object data (RLE).
+/// - `gray`: Boolean flag indicating if grayscale color conversion should be used.
+/// - `cancel`: The token interrupting the decoding with `Error::Cancelled`.
+///
+/// Returns:
+/// - A 2D vector representing pixel colors decoded from the RLE data.
+pub fn decode_rle_cancellable(pds: PgsRc<PgsPdsSegment>, ods: PgsRc<PgsOdsSegment>, gray: bool, cancel: &PgsCancelToken) -> Result<Vec<Vec<u32>>> {
+    decode_rle_with(&ods, 0, Some(cancel), |color| get_pixel_color(color, &pds, gray))
+}
+
+/// Decodes a Run-Length Encoded (RLE) bitmap like `decode_rle`, resolving colors through a copy of the palette
+/// entries, so the bitmap can be decoded on another thread than the one owning the segments.
+///
+/// Arguments:
+/// - `entries`: The palette entries of the PDS segment.
+/// - `ods`: The `PgsOdsSegment` holding the object data (RLE).
+/// - `gray`: Boolean flag indicating if grayscale color conversion should be used.
+/// - `cancel`: The token interrupting the decoding with `Error::Cancelled`, if any.
+///
+/// Returns:
+/// - A 2D vector representing pixel colors decoded from the RLE data.
+pub(crate) fn decode_rle_entries(entries: &[PgsPdsSegmentPaletteEntry], ods: &PgsOdsSegment, gray: bool, cancel: Option<&PgsCancelToken>) -> Result<Vec<Vec<u32>>> {
+    decode_rle_with(ods, 0, cancel, |color| match entries.get(color) {
+        None => 0xFFFFFF,
+        Some(entry) if gray => calc_gray(entry.transparency, entry.luminance),
+        Some(entry) => get_argb(entry.luminance, entry.color_difference_blue, entry.color_difference_red, entry.transparency)
+    })
+}
+
+/// Decodes a Run-Length Encoded (RLE) bitmap into palette entry ids, without resolving colors.
+///
+/// Arguments:
+/// - `ods`: The `PgsOdsSegment` holding the object data (RLE).
+///
+/// Returns:
+/// - A 2D vector of palette entry ids (rows of `ods.width` entries), e.g. for editing and re-encoding the bitmap.
+pub fn decode_rle_indices(ods: &PgsOdsSegment) -> Result<Vec<Vec<u8>>> {
+    decode_rle_with(ods, 0, None, |color| color as u8)
+}
+
+/// Returns the `Error::InvalidRleData` for RLE data of `ods` that cannot be decoded at `row` and `col`.
+fn invalid_rle_data(ods: &PgsOdsSegment, row: usize, col: usize) -> Error {
+    Error::InvalidRleData { width: ods.width, height: ods.height, position: Some((row, col)) }
+}
+
+/// Stores `count` pixels of `value` in `line`, the row `row` of the bitmap of `ods` or `None` below the last row,
+/// from `col` on and advances `col` past them.
+///
+/// Returns `Error::InvalidRleData` if the run does not fit the bitmap.
+fn fill_run<T: Copy>(ods: &PgsOdsSegment, line: Option<&mut Vec<T>>, row: usize, col: &mut usize, count: usize, value: T) -> Result<()> {
+    let run = line.and_then(|line| line.get_mut(*col..*col + count)).ok_or_else(|| invalid_rle_data(ods, row, *col))?;
+    run.fill(value);
+    *col += count;
+    Ok(())
+}
+
+/// Walks the RLE data of `ods` and stores `pixel(color)` for every decoded pixel; pixels not covered by the
+/// data keep `empty`. Runs past the end of a row or below the last row, data ending in the middle of a run, and
+/// objects larger than `MAX_DECODED_PIXELS`, return `Error::InvalidRleData`. The `cancel` token is checked at the end of every row.
+///
+/// With the `rayon` feature, objects of at least `PARALLEL_DECODE_PIXELS` pixels are decoded by `decode_rle_rows`.
+fn decode_rle_with<T: Copy + Send + Sync, F: Fn(usize) -> T>(ods: &PgsOdsSegment, empty: T, cancel: Option<&PgsCancelToken>, pixel: F) -> Result<Vec<Vec<T>>> {
+    if ods.width as usize * ods.height as usize > MAX_DECODED_PIXELS {
+        return Err(Error::InvalidRleData { width: ods.width, height: ods.height, position: None });
+    }
+    let _span = decode_span(ods);
+    #[cfg(feature = "rayon")]
+    if ods.width as usize * ods.height as usize >= PARALLEL_DECODE_PIXELS {
+        let colors: Vec<T> = (0..256).map(&pixel).collect();
+        return decode_rle_rows(ods, empty, cancel, &colors);
+    }
+
+    // Create a 2D vector of pixels initialized to `empty`, with dimensions (width x height) based on the ODS.
+    let mut pixels: Vec<Vec<T>> = vec![vec![empty; ods.width as usize]; ods.height as usize];
 
     let mut col: usize = 0;
     let mut row: usize = 0;
@@ -88,13 +172,18 @@ pub fn decode_rle(pds: Rc<PgsPdsSegment>, ods: Rc<PgsOdsSegment>, gray: bool) ->
     let data = ods.object_data.as_slice();
     let mut buffer: PgsMemoryBuffer = PgsMemoryBuffer::from(data);
     let buffer_len = buffer.len()?;
+    // Data ending in the middle of a run is invalid RLE data, like on the parallel path
+    let read = |buffer: &mut PgsMemoryBuffer, row, col| buffer.read_u8().map_err(|_| invalid_rle_data(ods, row, col));
     while buffer.pos()? < buffer_len {
-        match buffer.read_u8()? {
+        match read(&mut buffer, row, col)? {
             0x00 => { // Special case: handle new row or extended RLE data.
-                match buffer.read_u8()? {
+                match read(&mut buffer, row, col)? {
                     0x00 => {      
                         row += 1;
                         col = 0;
+                        if let Some(cancel) = cancel {
+                            cancel.check()?;
+                        }
                         if buffer.pos()? >= buffer_len { 
                             break; 
                         }
@@ -102,33 +191,19 @@ pub fn decode_rle(pds: Rc<PgsPdsSegment>, ods: Rc<PgsOdsSegment>, gray: bool) ->
                     },
                     data => {
                         match (data & 0xC0) >> 6 {
-                            0 => {
-                                for _ in 0..byte_to_int(data) {
-                                    pixels[row][col] = get_pixel_color(0, &pds, gray);
-                                    col += 1;
-                                }
-                            },
+                            0 => fill_run(ods, pixels.get_mut(row), row, &mut col, byte_to_int(data) as usize, pixel(0))?,
                             1 => {
-                                let count = byte_to_int(buffer.read_u8()?) | (byte_to_int(data & 0x3F) << 8);
-                                for _ in 0..count {
-                                    pixels[row][col] = get_pixel_color(0, &pds, gray);
-                                    col += 1;
-                                }
+                                let count = byte_to_int(read(&mut buffer, row, col)?) | (byte_to_int(data & 0x3F) << 8);
+                                fill_run(ods, pixels.get_mut(row), row, &mut col, count as usize, pixel(0))?;
                             },
                             2 => {
-                                let color = byte_to_int(buffer.read_u8()?) as usize;
-                                for _ in 0..byte_to_int(data & 0x3F) {
-                                    pixels[row][col] = get_pixel_color(color, &pds, gray);
-                                    col += 1;
-                                }
+                                let color = byte_to_int(read(&mut buffer, row, col)?) as usize;
+                                fill_run(ods, pixels.get_mut(row), row, &mut col, byte_to_int(data & 0x3F) as usize, pixel(color))?;
                             },
                             3 => {
-                                let count = byte_to_int(buffer.read_u8()?) | (byte_to_int(data & 0x3F) << 8);
-                                let color = byte_to_int(buffer.read_u8()?) as usize;
-                                for _ in 0..count {
-                                    pixels[row][col] = get_pixel_color(color, &pds, gray);
-                                    col += 1;
-                                }
+                                let count = byte_to_int(read(&mut buffer, row, col)?) | (byte_to_int(data & 0x3F) << 8);
+                                let color = byte_to_int(read(&mut buffer, row, col)?) as usize;
+                                fill_run(ods, pixels.get_mut(row), row, &mut col, count as usize, pixel(color))?;
                             },
                             _ => {}
                         }
@@ -137,14 +212,86 @@ pub fn decode_rle(pds: Rc<PgsPdsSegment>, ods: Rc<PgsOdsSegment>, gray: bool) ->
             },
             data => { // Standard case: a single color pixel.
                 let color = byte_to_int(data) as usize;
-                pixels[row][col] = get_pixel_color(color, &pds, gray);
-                col += 1;
+                fill_run(ods, pixels.get_mut(row), row, &mut col, 1, pixel(color))?;
             }
         }
     }
     Ok(pixels)
 }
 
+/// Returns the byte ranges of the rows of RLE data, without their end of line markers. Data after the last marker
+/// forms a last row, including a run or marker cut short by the end of the data, which fails to decode.
+#[cfg(feature = "rayon")]
+fn scan_rle_rows(data: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut rows: Vec<std::ops::Range<usize>> = Vec::new();
+    let (mut start, mut index) = (0, 0);
+    while index < data.len() {
+        if data[index] != 0x00 {
+            index += 1;
+            continue;
+        }
+        match data.get(index + 1) {
+            None => break,
+            Some(0x00) => {
+                rows.push(start..index);
+                index += 2;
+                start = index;
+            },
+            // The two top bits tell whether a long run length and a color byte follow
+            Some(flags) => index += 2 + [0, 1, 1, 2][(flags >> 6) as usize]
+        }
+    }
+    if start < data.len() {
+        rows.push(start..data.len());
+    }
+    rows
+}
+
+/// Decodes the RLE data of the row `row` of `ods` into `line`, resolving palette entry ids through `colors`.
+#[cfg(feature = "rayon")]
+fn decode_rle_row<T: Copy>(ods: &PgsOdsSegment, row: usize, data: &[u8], line: &mut Vec<T>, colors: &[T]) -> Result<()> {
+    let mut col: usize = 0;
+    let mut bytes = data.iter().copied();
+    while let Some(data) = bytes.next() {
+        let mut next = || bytes.next().map(byte_to_int).ok_or_else(|| invalid_rle_data(ods, row, col));
+        match data {
+            0x00 => {
+                let data = next()? as u8;
+                let (count, color) = match (data & 0xC0) >> 6 {
+                    0 => (byte_to_int(data), 0),
+                    1 => (next()? | (byte_to_int(data & 0x3F) << 8), 0),
+                    2 => (byte_to_int(data & 0x3F), next()?),
+                    _ => (next()? | (byte_to_int(data & 0x3F) << 8), next()?)
+                };
+                fill_run(ods, Some(&mut *line), row, &mut col, count as usize, colors[color as usize])?;
+            },
+            data => fill_run(ods, Some(&mut *line), row, &mut col, 1, colors[data as usize])?
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the RLE data of `ods` like `decode_rle_with`, finding the row boundaries first and then decoding all
+/// rows in parallel. Palette entry ids are resolved through `colors`, which holds the pixel of every id.
+#[cfg(feature = "rayon")]
+fn decode_rle_rows<T: Copy + Send + Sync>(ods: &PgsOdsSegment, empty: T, cancel: Option<&PgsCancelToken>, colors: &[T]) -> Result<Vec<Vec<T>>> {
+    use rayon::prelude::*;
+
+    let data = ods.object_data.as_slice();
+    let rows = scan_rle_rows(data);
+    if let Some(row) = rows.iter().skip(ods.height as usize).position(|row| !row.is_empty()) {
+        return Err(invalid_rle_data(ods, ods.height as usize + row, 0));
+    }
+    let mut pixels: Vec<Vec<T>> = vec![vec![empty; ods.width as usize]; ods.height as usize];
+    pixels.par_iter_mut().zip(rows.par_iter()).enumerate().try_for_each(|(index, (line, row))| {
+        if let Some(cancel) = cancel {
+            cancel.check()?;
+        }
+        decode_rle_row(ods, index, &data[row.clone()], line, colors)
+    })?;
+    Ok(pixels)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{PgsOdsSequenceFlag, PgsPdsSegmentPaletteEntry, PgsSegmentHeader, PgsSegmentType};
@@ -203,7 +350,7 @@ mod tests {
 
     #[test]
     fn test_get_gray_color() {
-        let pds_segment = Rc::new(PgsPdsSegment {
+        let pds_segment = PgsRc::new(PgsPdsSegment {
             header: PgsSegmentHeader {
                 segment_type: PgsSegmentType::PDS,
                 segment_length: 13,
@@ -212,11 +359,11 @@ mod tests {
             },
             palette_id: 0,
             palette_version_number: 0,            
-            palette_entries: vec![
+            palette_entries: PgsRc::new(vec![
                 PgsPdsSegmentPaletteEntry { palette_entry_id: 0, transparency: 0, luminance: 255, color_difference_blue: 0, color_difference_red: 0 }, // White color
                 PgsPdsSegmentPaletteEntry { palette_entry_id: 0, transparency: 255, luminance: 255, color_difference_blue: 0, color_difference_red: 0 }, // Black color
                 PgsPdsSegmentPaletteEntry { palette_entry_id: 0, transparency: 128, luminance: 128, color_difference_blue: 0, color_difference_red: 0 } // Gray color
-            ]
+            ])
         });
  
         // 1: Valid index, expecting white color
@@ -248,10 +395,10 @@ mod tests {
             },
             palette_id: 0,
             palette_version_number: 0,
-            palette_entries: vec![
+            palette_entries: PgsRc::new(vec![
                 PgsPdsSegmentPaletteEntry { palette_entry_id: 0, transparency: 0, luminance: 50, color_difference_blue: 100, color_difference_red: 100 },
                 PgsPdsSegmentPaletteEntry { palette_entry_id: 0, transparency: 0, luminance: 150, color_difference_blue: 200, color_difference_red: 200 }
-            ],
+            ]),
         };
     
         let ods_segment = PgsOdsSegment {
@@ -272,7 +419,7 @@ mod tests {
             ],
         };
 
-        let result = decode_rle(Rc::new(pds_segment), Rc::new(ods_segment), false).unwrap();
+        let result = decode_rle(PgsRc::new(pds_segment), PgsRc::new(ods_segment), false).unwrap();
 
         assert_eq!(result.len(), 2);
         assert_eq!(result[0].len(), 5);
@@ -283,5 +430,44 @@ mod tests {
         ];
 
         assert_eq!(result, expected, "Decoded RLE data does not match the expected output");
-    }    
-}
\ No newline at end of file
+    }
+
+    #[test]
+    fn test_rle_decoding_malformed() {
+        let ods = |object_data: Vec<u8>| PgsOdsSegment::from_object(PgsSegmentHeader::default(), 0, 0, 2, 1, &object_data).remove(0);
+
+        assert_eq!(decode_rle_indices(&ods(vec![0x01, 0x02])).unwrap(), vec![vec![1, 2]]);
+        // Row longer than the object width
+        assert!(matches!(decode_rle_indices(&ods(vec![0x00, 0x83, 0x01])), Err(Error::InvalidRleData { width: 2, height: 1, position: Some((0, 0)) })));
+        // Rows below the object height
+        assert!(matches!(decode_rle_indices(&ods(vec![0x01, 0x00, 0x00, 0x01])), Err(Error::InvalidRleData { width: 2, height: 1, position: Some((1, 0)) })));
+    }
+
+    #[test]
+    fn test_rle_decoding_truncated_run() {
+        // Extended runs missing their long run length or color byte, in an object small enough to be decoded
+        // sequentially and in one decoded in parallel with the `rayon` feature
+        for (width, height) in [(2, 1), (1920, 540)] {
+            for object_data in [vec![0x01, 0x00, 0x43], vec![0x01, 0x00, 0x82], vec![0x01, 0x00, 0xC0, 0x01]] {
+                let ods = PgsOdsSegment::from_object(PgsSegmentHeader::default(), 0, 0, width, height, &object_data).remove(0);
+                assert!(matches!(decode_rle_indices(&ods), Err(Error::InvalidRleData { position: Some((0, 1)), .. })), "{width}x{height} {object_data:02x?}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_rle_decoding_rows() {
+        let ods = |width, height, object_data: Vec<u8>| PgsOdsSegment::from_object(PgsSegmentHeader::default(), 0, 0, width, height, &object_data).remove(0);
+        let colors: Vec<u8> = (0..=255).collect();
+
+        let bitmap: Vec<Vec<u8>> = (0..40).map(|row| (0..300).map(|col| if (row + col) % 7 == 0 { 0 } else { (col / 20) as u8 }).collect()).collect();
+        let object = ods(300, 40, crate::pgs_encode_rle::encode_rle(&bitmap));
+        assert_eq!(scan_rle_rows(&object.object_data).len(), 40);
+        assert_eq!(decode_rle_rows(&object, 0, None, &colors).unwrap(), decode_rle_indices(&object).unwrap());
+
+        assert!(matches!(decode_rle_rows(&ods(2, 1, vec![0x00, 0x83, 0x01]), 0, None, &colors), Err(Error::InvalidRleData { position: Some((0, 0)), .. })));
+        assert!(matches!(decode_rle_rows(&ods(2, 1, vec![0x01, 0x00, 0x00, 0x01]), 0, None, &colors), Err(Error::InvalidRleData { position: Some((1, 0)), .. })));
+        assert!(matches!(decode_rle_rows(&ods(2, 1, vec![0x01, 0x00, 0x43]), 0, None, &colors), Err(Error::InvalidRleData { position: Some((0, 1)), .. })));
+    }
+}
@@ -6,19 +6,49 @@
 //! Definition Segment), and ODS (Object Definition Segment). The state of the display set can be
 //! used to determine if a frame is complete and ready for rendering.
 
-use std::rc::Rc;
+use std::mem::size_of;
 
-use crate::{pgs_decode_rle::decode_rle, Error, PgsOdsSegment, PgsPcsSegment, PgsPdsSegment, PgsWdsSegment, Result};
+use crate::{pgs_decode_rle::{decode_rle, decode_rle_cancellable}, pgs_fingerprint::{hash_pixels, PgsFingerprint}, pgs_transform::complete_objects, Error, PgsCancelToken, PgsOdsSegment, PgsPcsCompositionState, PgsPcsObjectCroppedFlag, PgsPcsSegment, PgsPcsSegmentCompositionObjects, PgsPdsSegment, PgsRc, PgsRectangle, PgsSegment, PgsWdsSegment, Result};
 
 /// Enum representing the state of the `PgsDisplaySet`.
+///
+/// Only `Complete` display sets can be rendered on their own. `PaletteUpdateOnly`, `ObjectReuse` and
+/// `NeedsContext` display sets are valid but take objects, windows or palettes from earlier display sets of their
+/// epoch, so they need a decoder keeping the state of the epoch; `Incomplete` ones are broken.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
 pub enum PgsDisplaySetState {
-    /// The display set is incomplete, not all required segments (PCS, WDS, PDS, ODS) are present.
+    /// The display set is broken: it has no PCS, or it starts an epoch without all the segments it needs.
     Incomplete,
     /// The display set is complete and ready to be rendered.
     Complete,
-    /// The display set contains only empty frames, meaning it has PCS and WDS but no PDS or ODS.
-    EmptyFrame
+    /// The display set clears the screen: it has PCS and WDS and shows no objects.
+    EmptyFrame,
+    /// The display set only changes the palette of the objects on screen (`palette_update_flag` set, no ODS).
+    PaletteUpdateOnly,
+    /// The display set has PCS, WDS and PDS but no ODS, and shows objects defined by earlier display sets of the
+    /// epoch, e.g. to move or crop them.
+    ObjectReuse,
+    /// The display set continues an epoch and lacks the WDS or PDS it takes from earlier display sets of it.
+    NeedsContext
+}
+
+/// A composition object of a display set with the window and object it refers to, resolved by
+/// `PgsDisplaySet::resolved_objects`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsResolvedObject<'a> {
+    pub composition_object: &'a PgsPcsSegmentCompositionObjects,
+    /// Rectangle of the window the object is shown in, or `None` if the display set does not define it.
+    pub window: Option<PgsRectangle>,
+    /// The object defined by the display set, its fragments joined into one ODS, or `None` if an earlier display
+    /// set of the epoch defines it.
+    pub ods: Option<PgsRc<PgsOdsSegment>>,
+    /// Part of the object shown, in object coordinates: the cropping rectangle clamped to the object, or the whole
+    /// object if it is not cropped. `None` if the object is not cropped and its size is unknown.
+    pub crop: Option<PgsRectangle>,
+    /// Rectangle covered on screen: the shown part of the object at its position, clipped to its window. `None` if
+    /// `crop` is unknown.
+    pub screen: Option<PgsRectangle>
 }
 
 /// Struct representing a collection of PGS segments required for rendering a single subtitle frame.
@@ -26,13 +56,16 @@ pub enum PgsDisplaySetState {
 /// - `pcs`: Presentation Composition Segment.
 /// - `wds`: Window Definition Segment.
 /// - `pds`: Palette Definition Segment.
-/// - `ods`: Object Definition Segment.
+/// - `ods`: Object Definition Segment, the last one of the display set.
+/// - `ods_segments`: Every Object Definition Segment of the display set in stream order, e.g. the fragments of a
+///   large object or the objects of a top and a bottom line.
 #[derive(Debug, Default, Clone)]
 pub struct PgsDisplaySet {
-    pub pcs: Option<Rc<PgsPcsSegment>>,
-    pub wds: Option<Rc<PgsWdsSegment>>,
-    pub pds: Option<Rc<PgsPdsSegment>>,
-    pub ods: Option<Rc<PgsOdsSegment>>
+    pub pcs: Option<PgsRc<PgsPcsSegment>>,
+    pub wds: Option<PgsRc<PgsWdsSegment>>,
+    pub pds: Option<PgsRc<PgsPdsSegment>>,
+    pub ods: Option<PgsRc<PgsOdsSegment>>,
+    pub ods_segments: Vec<PgsRc<PgsOdsSegment>>
 }
 
 impl PgsDisplaySet {
@@ -45,11 +78,44 @@ impl PgsDisplaySet {
             pcs: None,
             wds: None,
             pds: None,
-            ods: None
+            ods: None,
+            ods_segments: Vec::new()
         }
     }
 
-    /// Clears the display set by setting all segments (PCS, WDS, PDS, ODS) to `None`.
+    /// Groups segments into display sets.
+    ///
+    /// Segments are collected until an END segment is reached, which completes the current display set.
+    /// Segments following the last END segment are not part of any display set.
+    ///
+    /// # Parameters
+    /// - `segments`: The segments in stream order.
+    ///
+    /// # Returns
+    /// A vector containing one display set per END segment.
+    pub(crate) fn from_segments(segments: &[PgsSegment]) -> Vec<PgsDisplaySet> {
+        let mut display_sets: Vec<PgsDisplaySet> = Vec::new();
+        let mut ds = PgsDisplaySet::new();
+        segments.iter().for_each(|segment| {
+            match segment {
+                PgsSegment::Pcs(pcs) => ds.pcs = Some(pcs.clone()),
+                PgsSegment::Wds(wds) => ds.wds = Some(wds.clone()),
+                PgsSegment::Ods(ods) => {
+                    ds.ods = Some(ods.clone());
+                    ds.ods_segments.push(ods.clone());
+                },
+                PgsSegment::Pds(pds) => ds.pds = Some(pds.clone()),
+                PgsSegment::End(_) => {
+                    display_sets.push(ds.clone());
+                    ds.clean();
+                },
+                PgsSegment::Unknown(_) => {}
+            }
+        });
+        display_sets
+    }
+
+    /// Clears the display set by setting all segments (PCS, WDS, PDS, ODS) to `None` and removing all ODS segments.
     ///
     /// This can be used to reset the display set for reuse.
     pub(crate) fn clean(&mut self) {
@@ -57,24 +123,101 @@ impl PgsDisplaySet {
         self.wds = None;
         self.pds = None;
         self.ods = None;
+        self.ods_segments.clear();
     }
 
     /// Determines the current state of the display set.
     ///
-    /// - If PCS and WDS are present, but PDS and ODS are not, the state is `EmptyFrame`.
     /// - If all segments (PCS, WDS, PDS, ODS) are present, the state is `Complete`.
+    /// - If PCS and WDS are present and the PCS shows no objects, the state is `EmptyFrame`.
+    /// - If the PCS has its palette update flag set and there is no ODS, the state is `PaletteUpdateOnly`.
+    /// - Otherwise, if the PCS does not start an epoch, the state is `ObjectReuse` when only the ODS is missing and
+    ///   `NeedsContext` when the WDS or PDS is missing.
     /// - Otherwise, the state is `Incomplete`.
     ///
     /// # Returns
     /// The current state of the `PgsDisplaySet`.
     pub fn state(&self) -> PgsDisplaySetState {
-        if self.pcs.is_some() && self.wds.is_some() {
-            if self.pds.is_some() && self.ods.is_some() {
-                return PgsDisplaySetState::Complete;
-            }
-            return PgsDisplaySetState::EmptyFrame;
+        let Some(pcs) = &self.pcs else {
+            return PgsDisplaySetState::Incomplete;
+        };
+        match (&self.wds, &self.pds, &self.ods) {
+            (Some(_), Some(_), Some(_)) => PgsDisplaySetState::Complete,
+            (Some(_), _, _) if pcs.composition_objects.is_empty() => PgsDisplaySetState::EmptyFrame,
+            (_, _, None) if pcs.palette_update_flag != 0 => PgsDisplaySetState::PaletteUpdateOnly,
+            _ if pcs.composition_state == PgsPcsCompositionState::EpochStart => PgsDisplaySetState::Incomplete,
+            (Some(_), Some(_), None) => PgsDisplaySetState::ObjectReuse,
+            _ => PgsDisplaySetState::NeedsContext
+        }
+    }
+
+    /// Resolves the composition objects of the PCS: the window each one is shown in, the object defined for it by the
+    /// ODS segments of the display set (matched by object id, with the fragments of a fragmented object joined), the
+    /// part of the object shown and the rectangle it covers on screen.
+    ///
+    /// A cropping rectangle reaching outside its object is clamped like a lenient parse does; if the object is
+    /// defined by an earlier display set of the epoch, the cropping rectangle is taken as it is.
+    ///
+    /// # Returns
+    /// An iterator over the resolved objects in composition order, empty if the display set has no PCS.
+    pub fn resolved_objects(&self) -> impl Iterator<Item = PgsResolvedObject<'_>> + '_ {
+        // Display sets built by hand may only set `ods`
+        let ods_segments = match self.ods_segments.is_empty() {
+            true => self.ods.iter().cloned().map(PgsSegment::Ods).collect::<Vec<_>>(),
+            false => self.ods_segments.iter().cloned().map(PgsSegment::Ods).collect()
+        };
+        let objects: Vec<PgsRc<PgsOdsSegment>> = complete_objects(&ods_segments).into_iter().map(|(_, _, ods)| PgsRc::new(ods)).collect();
+        self.pcs.iter().flat_map(|pcs| pcs.composition_objects.iter()).map(move |obj| {
+            let window = self.wds.as_ref()
+                .and_then(|wds| wds.windows.iter().find(|window| window.window_id == obj.window_id))
+                .map(|window| (window.window_horizontal_position, window.window_vertical_position, window.window_width, window.window_height));
+            // An object defined twice in the display set is shown with its last definition
+            let ods = objects.iter().rev().find(|ods| ods.object_id == obj.object_id).cloned();
+            let crop = match (&ods, obj.object_cropped_flag) {
+                (Some(ods), _) => {
+                    let mut clamped = obj.clone();
+                    clamped.clamp_cropping(ods.width, ods.height);
+                    Some(match clamped.object_cropped_flag {
+                        PgsPcsObjectCroppedFlag::Off => (0, 0, ods.width, ods.height),
+                        _ => (clamped.object_cropping_horizontal_position, clamped.object_cropping_vertical_position,
+                            clamped.object_cropping_width, clamped.object_cropping_height_position)
+                    })
+                },
+                (None, PgsPcsObjectCroppedFlag::Off) => None,
+                (None, _) => Some((obj.object_cropping_horizontal_position, obj.object_cropping_vertical_position,
+                    obj.object_cropping_width, obj.object_cropping_height_position))
+            };
+            let screen = crop.map(|(_, _, width, height)| {
+                let (x, y) = (obj.object_horizontal_position as u32, obj.object_vertical_position as u32);
+                let (mut left, mut top, mut right, mut bottom) = (x, y, x + width as u32, y + height as u32);
+                if let Some((wx, wy, ww, wh)) = window {
+                    left = left.max(wx as u32);
+                    top = top.max(wy as u32);
+                    right = right.min(wx as u32 + ww as u32).max(left);
+                    bottom = bottom.min(wy as u32 + wh as u32).max(top);
+                }
+                // Clip the rectangle to the coordinate range, so objects reaching past it do not wrap around
+                let [left, top, right, bottom] = [left, top, right, bottom].map(|value| value.min(u16::MAX as u32) as u16);
+                (left, top, right - left, bottom - top)
+            });
+            PgsResolvedObject { composition_object: obj, window, ods, crop, screen }
+        })
+    }
+
+    /// Returns `true` if the display set puts objects on screen, either a new bitmap (`Complete`) or objects of
+    /// earlier display sets of the epoch (`ObjectReuse`, or `NeedsContext` with composition objects).
+    pub fn starts_presentation(&self) -> bool {
+        match self.state() {
+            PgsDisplaySetState::Complete | PgsDisplaySetState::ObjectReuse => true,
+            PgsDisplaySetState::NeedsContext => self.pcs.as_ref().is_some_and(|pcs| !pcs.composition_objects.is_empty()),
+            _ => false
         }
-        PgsDisplaySetState::Incomplete
+    }
+
+    /// Returns `true` if the display set ends what is on screen, either by clearing it (`EmptyFrame`) or by
+    /// showing other objects (see `starts_presentation`). Palette-only updates keep the current subtitle on screen.
+    pub fn ends_presentation(&self) -> bool {
+        self.state() == PgsDisplaySetState::EmptyFrame || self.starts_presentation()
     }
 
     /// Returns a reference to the RLE (Run-Length Encoded) image data contained in the ODS segment.
@@ -85,10 +228,21 @@ impl PgsDisplaySet {
     /// # Returns
     /// A reference to the raw RLE image data.    
     pub fn get_rle_image(&self) -> Result<&Vec<u8>> {
-        if self.state() != PgsDisplaySetState::Complete {
-            return Err(Error::IncompleteDisplaySet);
+        match (self.state(), &self.ods) {
+            (PgsDisplaySetState::Complete, Some(ods)) => Ok(&ods.object_data),
+            _ => Err(Error::IncompleteDisplaySet)
         }
-        Ok(&self.ods.as_ref().unwrap().object_data)
+    }
+
+    /// Returns the memory in bytes `get_decoded_image` allocates for the object of this display set: one 32-bit
+    /// value per pixel and a vector per row.
+    ///
+    /// # Returns
+    /// The number of bytes, or 0 if the display set has no ODS segment.
+    pub fn decoded_size_bytes(&self) -> usize {
+        self.ods.as_ref().map_or(0, |ods| {
+            size_of::<Vec<Vec<u32>>>() + ods.height as usize * (size_of::<Vec<u32>>() + ods.width as usize * size_of::<u32>())
+        })
     }
 
     /// Decodes the RLE image data and returns the image as a 2D array of pixels.
@@ -105,13 +259,154 @@ impl PgsDisplaySet {
     /// # Returns
     /// A 2D vector containing the decoded pixels, where each pixel is represented as a 32-bit color value.
     pub fn get_decoded_image(&self, gray: bool) -> Result<Vec<Vec<u32>>> {
-        if self.state() != PgsDisplaySetState::Complete {
+        let (PgsDisplaySetState::Complete, Some(ods), Some(pds)) = (self.state(), &self.ods, &self.pds) else {
             return Err(Error::IncompleteDisplaySet);
-        }
-
-        let ods: &Rc<PgsOdsSegment> = self.ods.as_ref().unwrap();
-        let pds = self.pds.as_ref().unwrap();
+        };
         let pixels = decode_rle(pds.clone(), ods.clone(), gray)?;
         Ok(pixels)
     }
-}
\ No newline at end of file
+
+    /// Decodes the RLE image data like `get_decoded_image`, stopping with `Error::Cancelled` as soon as the token is
+    /// cancelled.
+    ///
+    /// # Parameters
+    /// - `gray`: A boolean flag indicating whether to decode the image in grayscale (`true`) or color (`false`).
+    /// - `cancel`: The token interrupting the decoding.
+    ///
+    /// # Errors
+    /// Returns `Error::IncompleteDisplaySet` if the display set is not in the `Complete` state, or
+    /// `Error::Cancelled` if the decoding was cancelled.
+    ///
+    /// # Returns
+    /// A 2D vector containing the decoded pixels, where each pixel is represented as a 32-bit color value.
+    pub fn get_decoded_image_cancellable(&self, gray: bool, cancel: &PgsCancelToken) -> Result<Vec<Vec<u32>>> {
+        let (PgsDisplaySetState::Complete, Some(ods), Some(pds)) = (self.state(), &self.ods, &self.pds) else {
+            return Err(Error::IncompleteDisplaySet);
+        };
+        decode_rle_cancellable(pds.clone(), ods.clone(), gray, cancel)
+    }
+
+    /// Computes a perceptual fingerprint of the decoded bitmap, which matches the fingerprints of bitmaps that look
+    /// alike regardless of their palette, RLE encoding and timing.
+    ///
+    /// The display set does not know how long it is shown, so the fingerprint has no duration; see
+    /// `analysis::fingerprint_events` for fingerprints of events with durations.
+    ///
+    /// # Errors
+    /// Returns `Error::IncompleteDisplaySet` if the display set is not in the `Complete` state.
+    ///
+    /// # Returns
+    /// The fingerprint of the bitmap.
+    pub fn fingerprint(&self) -> Result<PgsFingerprint> {
+        Ok(PgsFingerprint { hash: hash_pixels(&self.get_decoded_image(false)?), duration: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_decode_rle::decode_rle_indices, pgs_encode_rle::encode_rle, pgs_test_support::{black_and_white, composition_object, display_set_of, end, header, ods, pcs, pds,
+        segments, wds, window}, PgsSegmentType};
+
+    use super::*;
+
+    /// A display set with a PCS in `composition_state` showing `objects` objects, and the segments given by `wds`,
+    /// `pds` and `ods`.
+    fn display_set(composition_state: PgsPcsCompositionState, objects: usize, palette_update: bool, segments: (bool, bool, bool)) -> PgsDisplaySet {
+        let pcs = PgsPcsSegment {
+            palette_update_flag: if palette_update { 0x80 } else { 0 },
+            ..pcs(0, composition_state, vec![composition_object(0, 0, 0, 0); objects])
+        };
+        let (has_wds, has_pds, has_ods) = segments;
+        display_set_of(pcs, has_wds.then(|| wds(0, Vec::new())), has_pds.then(|| pds(0, 0, Vec::new())),
+            has_ods.then(|| ods(0, 0, 0, 1, 1, &[vec![0]])))
+    }
+
+    #[test]
+    fn test_state() {
+        use PgsPcsCompositionState::{EpochStart, Normal};
+        let cases = [
+            (display_set(EpochStart, 1, false, (true, true, true)), PgsDisplaySetState::Complete),
+            (display_set(Normal, 0, false, (true, false, false)), PgsDisplaySetState::EmptyFrame),
+            (display_set(Normal, 1, true, (false, true, false)), PgsDisplaySetState::PaletteUpdateOnly),
+            (display_set(Normal, 1, false, (true, true, false)), PgsDisplaySetState::ObjectReuse),
+            (display_set(Normal, 1, false, (false, false, true)), PgsDisplaySetState::NeedsContext),
+            (display_set(EpochStart, 1, false, (true, true, false)), PgsDisplaySetState::Incomplete),
+            (PgsDisplaySet::new(), PgsDisplaySetState::Incomplete)
+        ];
+        for (ds, state) in cases {
+            assert_eq!(ds.state(), state);
+        }
+        assert!(display_set(Normal, 1, false, (true, true, false)).starts_presentation());
+        assert!(display_set(Normal, 1, false, (false, false, true)).starts_presentation());
+        assert!(!display_set(Normal, 0, false, (false, true, false)).starts_presentation());
+        assert!(display_set(Normal, 0, false, (true, false, false)).ends_presentation());
+        assert!(!display_set(Normal, 1, true, (false, true, false)).ends_presentation());
+    }
+
+    #[test]
+    fn test_resolved_objects() {
+        let mut ds = display_set(PgsPcsCompositionState::EpochStart, 2, false, (true, true, false));
+        let objects = &mut PgsRc::make_mut(ds.pcs.as_mut().unwrap()).composition_objects;
+        objects[0] = PgsPcsSegmentCompositionObjects {
+            object_cropped_flag: PgsPcsObjectCroppedFlag::ForceCroppedImage,
+            object_horizontal_position: 150,
+            object_vertical_position: 850,
+            object_cropping_horizontal_position: 10,
+            object_cropping_width: 300,
+            object_cropping_height_position: 80,
+            ..objects[0].clone()
+        };
+        objects[1].object_id = 1;
+        objects[1].window_id = 5;
+        ds.wds = Some(PgsRc::new(wds(0, vec![window(0, (100, 800, 400, 60))])));
+        ds.ods = Some(PgsRc::new(ods(0, 0, 0, 200, 40, &[])));
+
+        let resolved: Vec<PgsResolvedObject> = ds.resolved_objects().collect();
+        assert_eq!(resolved.len(), 2);
+        assert_eq!((resolved[0].window, resolved[0].ods.as_ref().map(|ods| ods.object_id)), (Some((100, 800, 400, 60)), Some(0)));
+        // The cropping rectangle is clamped to the 200x40 object, and the object clipped to its window
+        assert_eq!((resolved[0].crop, resolved[0].screen), (Some((10, 0, 190, 40)), Some((150, 850, 190, 10))));
+        // Object 1 is defined earlier in the epoch and its window is unknown
+        assert_eq!((resolved[1].window, resolved[1].ods.as_ref(), resolved[1].crop, resolved[1].screen), (None, None, None, None));
+
+        // A cropped object reaching past the largest coordinate is clipped to it
+        let objects = &mut PgsRc::make_mut(ds.pcs.as_mut().unwrap()).composition_objects;
+        objects[1] = PgsPcsSegmentCompositionObjects {
+            object_cropped_flag: PgsPcsObjectCroppedFlag::ForceCroppedImage,
+            object_cropping_width: 1000,
+            object_cropping_height_position: 20,
+            ..composition_object(1, 5, 65000, 100)
+        };
+        let resolved: Vec<PgsResolvedObject> = ds.resolved_objects().collect();
+        assert_eq!((resolved[1].crop, resolved[1].screen), (Some((0, 0, 1000, 20)), Some((65000, 100, 535, 20))));
+        assert_eq!(PgsDisplaySet::new().resolved_objects().count(), 0);
+    }
+
+    #[test]
+    fn test_resolved_objects_segments() {
+        // A top and a bottom line, defined by two ODS segments of the display set
+        let lines = [
+            segments(&display_set_of(pcs(0, PgsPcsCompositionState::EpochStart, vec![composition_object(0, 0, 100, 100), composition_object(1, 1, 100, 900)]),
+                Some(wds(0, vec![window(0, (100, 100, 400, 60)), window(1, (100, 900, 400, 60))])), Some(pds(0, 0, black_and_white())),
+                Some(ods(0, 0, 0, 300, 40, &[]))))[..4].to_vec(),
+            vec![PgsSegment::Ods(PgsRc::new(ods(0, 1, 0, 200, 50, &[]))), PgsSegment::End(PgsRc::new(end(0)))]
+        ].concat();
+        let ds = &PgsDisplaySet::from_segments(&lines)[0];
+        let resolved: Vec<(Option<u16>, Option<PgsRectangle>)> = ds.resolved_objects().map(|obj| (obj.ods.map(|ods| ods.object_id), obj.screen)).collect();
+        assert_eq!(resolved, [(Some(0), Some((100, 100, 300, 40))), (Some(1), Some((100, 900, 200, 50)))]);
+
+        // An object too large for a single ODS, whose last fragment has no size
+        let bitmap: Vec<Vec<u8>> = (0..20).map(|_| (0..4000).map(|col| (col % 2) as u8 + 1).collect()).collect();
+        let fragments = PgsOdsSegment::from_object(header(PgsSegmentType::ODS, 0), 0, 0, 4000, 20, &encode_rle(&bitmap));
+        assert_eq!(fragments.len(), 2);
+        let large = [
+            segments(&display_set_of(pcs(0, PgsPcsCompositionState::EpochStart, vec![composition_object(0, 0, 0, 0)]), None, None, None))[..1].to_vec(),
+            fragments.into_iter().map(|ods| PgsSegment::Ods(PgsRc::new(ods))).collect(),
+            vec![PgsSegment::End(PgsRc::new(end(0)))]
+        ].concat();
+        let ds = &PgsDisplaySet::from_segments(&large)[0];
+        let resolved: Vec<PgsResolvedObject> = ds.resolved_objects().collect();
+        assert_eq!((resolved[0].crop, resolved[0].screen), (Some((0, 0, 4000, 20)), Some((0, 0, 4000, 20))));
+        assert_eq!(decode_rle_indices(resolved[0].ods.as_ref().unwrap()).unwrap(), bitmap);
+    }
+}
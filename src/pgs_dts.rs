@@ -0,0 +1,174 @@
+//! # PGS Decoding Timestamps
+//!
+//! Every segment carries a decoding timestamp (DTS) telling the decoder when to start working on it, ahead of the
+//! presentation timestamp (PTS) of its display set. Many demuxers strip the DTS (leaving zero) or write garbage.
+//! This module reports the DTS/PTS relationship of a stream and regenerates DTS values from the decoder model of
+//! the Blu-ray specification.
+
+use crate::{is_timestamp_wrap, PgsOdsSequenceFlag, PgsPcsCompositionState, PgsSegment, PgsStream};
+
+/// Longest plausible time (90 kHz ticks) between decoding and presenting a segment. Decoding a full 1920x1080
+/// screen takes about 0.2 s, so larger distances point at a broken DTS.
+pub const PGS_MAX_DECODE_DELAY: u32 = 90000;
+
+/// Decoding timestamps of a stream compared to their presentation timestamps.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsDtsReport {
+    /// PTS minus DTS of every segment in stream order, `None` where the DTS is zero (not set).
+    pub deltas: Vec<Option<i64>>,
+    /// Number of segments with a zero DTS.
+    pub zeroed: usize,
+    /// Number of segments decoded after their presentation or more than `PGS_MAX_DECODE_DELAY` before it.
+    pub bogus: usize
+}
+
+impl PgsDtsReport {
+    /// Returns `true` if no segment has a DTS set.
+    pub fn all_zeroed(&self) -> bool {
+        self.zeroed == self.deltas.len()
+    }
+
+    /// Returns `true` if any DTS is missing or bogus, so it should be regenerated before muxing.
+    pub fn needs_regeneration(&self) -> bool {
+        self.zeroed > 0 || self.bogus > 0
+    }
+}
+
+/// Compares the decoding timestamp of every segment with its presentation timestamp.
+///
+/// # Parameters
+/// - `segments`: The segments in stream order.
+///
+/// # Returns
+/// A `PgsDtsReport` with the delta of every segment and the number of zeroed and bogus decoding timestamps.
+pub fn analyze_decoding_timestamps(segments: &[PgsSegment]) -> PgsDtsReport {
+    let mut report = PgsDtsReport::default();
+    for segment in segments {
+        let header = segment.header();
+        if header.decoding_timestamp == 0 {
+            report.zeroed += 1;
+            report.deltas.push(None);
+            continue;
+        }
+        let wrapped = is_timestamp_wrap(header.decoding_timestamp, header.presentation_timestamp);
+        let delta = header.presentation_timestamp as i64 - header.decoding_timestamp as i64 + if wrapped { 1 << 32 } else { 0 };
+        if delta < 0 || delta > PGS_MAX_DECODE_DELAY as i64 {
+            report.bogus += 1;
+        }
+        report.deltas.push(Some(delta));
+    }
+    report
+}
+
+/// Time (90 kHz ticks) to decode an object of `pixels` pixels into the object buffer at 128 Mbit/s.
+fn decode_duration(pixels: u64) -> u64 {
+    (pixels * 9).div_ceil(1600)
+}
+
+/// Time (90 kHz ticks) to clear or draw `pixels` pixels of the graphics plane at 256 Mbit/s.
+fn draw_duration(pixels: u64) -> u64 {
+    (pixels * 9).div_ceil(3200)
+}
+
+/// Regenerates the decoding timestamps of every display set from the decoder model.
+///
+/// Decoding of a display set starts early enough to clear the graphics plane (the whole video frame for an Epoch
+/// Start, otherwise the display set's windows), decode its objects one after another and draw its windows before
+/// the presentation timestamp. The PCS, WDS and PDS are decoded at that start, every ODS when the objects before
+/// it are decoded, and the END segment when all objects are decoded. Presentation timestamps are not changed.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+///
+/// # Returns
+/// The number of segments whose DTS changed.
+pub fn regenerate_decoding_timestamps(stream: &mut PgsStream) -> usize {
+    let mut segments = std::mem::take(stream.get_segments_mut());
+    let changed = segments.split_inclusive_mut(|segment| matches!(segment, PgsSegment::End(_)))
+        .map(regenerate_display_set)
+        .sum();
+    *stream.get_segments_mut() = segments;
+    changed
+}
+
+/// Regenerates the decoding timestamps of a single display set, see `regenerate_decoding_timestamps`.
+fn regenerate_display_set(display_set: &mut [PgsSegment]) -> usize {
+    let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+        return 0;
+    };
+    let presentation_timestamp = pcs.header.presentation_timestamp as u64;
+    let window_pixels: u64 = display_set.iter().filter_map(|segment| match segment {
+        PgsSegment::Wds(wds) => Some(wds.windows.iter().map(|window| window.window_width as u64 * window.window_height as u64).sum::<u64>()),
+        _ => None
+    }).sum();
+    let clear = match pcs.composition_state {
+        PgsPcsCompositionState::EpochStart => draw_duration(pcs.width as u64 * pcs.height as u64),
+        _ => draw_duration(window_pixels)
+    };
+    let decode: u64 = display_set.iter().filter_map(|segment| match segment {
+        PgsSegment::Ods(ods) if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) => {
+            Some(decode_duration(ods.width as u64 * ods.height as u64))
+        },
+        _ => None
+    }).sum();
+    let start = presentation_timestamp.saturating_sub(clear + decode + draw_duration(window_pixels));
+
+    let mut changed = 0;
+    let mut decoded = start + clear;
+    // Only the first fragment of an object carries its size
+    let mut object_pixels = 0;
+    for segment in display_set.iter_mut() {
+        let decoding_timestamp = match segment {
+            PgsSegment::Ods(ods) => {
+                if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) {
+                    object_pixels = ods.width as u64 * ods.height as u64;
+                }
+                let decoding_timestamp = decoded;
+                if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::Last | PgsOdsSequenceFlag::Both) {
+                    decoded += decode_duration(object_pixels);
+                }
+                decoding_timestamp
+            },
+            PgsSegment::End(_) => decoded,
+            _ => start
+        }.min(presentation_timestamp) as u32;
+        let header = segment.header_mut();
+        if header.decoding_timestamp != decoding_timestamp {
+            header.decoding_timestamp = decoding_timestamp;
+            changed += 1;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::timed_header, PgsEndSegment, PgsOdsSegment, PgsPcsSegment, PgsRc, PgsSegmentType, PgsWdsSegment, PgsWdsSegmentWindowDefinition};
+
+    use super::*;
+
+    #[test]
+    fn test_decoding_timestamps() {
+        let pcs = PgsPcsSegment { header: timed_header(PgsSegmentType::PCS, 90000, 0), width: 1920, height: 1080, composition_state: PgsPcsCompositionState::EpochStart, ..Default::default() };
+        let window = PgsWdsSegmentWindowDefinition { window_id: 0, window_horizontal_position: 0, window_vertical_position: 0, window_width: 800, window_height: 100 };
+        let wds = PgsWdsSegment { header: timed_header(PgsSegmentType::WDS, 90000, 95000), number_of_windows: 1, windows: vec![window] };
+        let ods = PgsOdsSegment::from_object(timed_header(PgsSegmentType::ODS, 90000, 50), 0, 0, 800, 100, &[]).remove(0);
+        let mut stream = PgsStream::new(vec![
+            PgsSegment::Pcs(PgsRc::new(pcs)),
+            PgsSegment::Wds(PgsRc::new(wds)),
+            PgsSegment::Ods(PgsRc::new(ods)),
+            PgsSegment::End(PgsRc::new(PgsEndSegment { header: timed_header(PgsSegmentType::END, 90000, 0), data: Vec::new() }))
+        ]);
+
+        let report = analyze_decoding_timestamps(stream.get_segments());
+        assert_eq!(report.deltas, vec![None, Some(-5000), Some(89950), None]);
+        assert_eq!((report.zeroed, report.bogus), (2, 1));
+        assert!(report.needs_regeneration());
+
+        // Clearing the 1920x1080 plane takes 5832 ticks, decoding the 800x100 object 450, drawing the window 225
+        assert_eq!(regenerate_decoding_timestamps(&mut stream), 4);
+        let decoding_timestamps: Vec<u32> = stream.get_segments().iter().map(|segment| segment.header().decoding_timestamp).collect();
+        assert_eq!(decoding_timestamps, vec![83493, 83493, 89325, 89775]);
+        assert!(!analyze_decoding_timestamps(stream.get_segments()).needs_regeneration());
+    }
+}
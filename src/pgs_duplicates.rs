@@ -0,0 +1,212 @@
+//! # PGS Duplicate Detection
+//!
+//! This module finds consecutive display sets that look exactly the same on screen and differ only in their
+//! composition number and timing, and removes them from a stream. Display sets are compared by their decoded
+//! pixels, so a redefined object with a new version number or a different RLE encoding of the same bitmap still
+//! counts as a duplicate.
+
+use std::{collections::{BTreeMap, HashMap}};
+
+use crate::{pgs_decode_rle::decode_rle_indices, PgsOdsSegment, PgsOdsSequenceFlag, PgsPcsCompositionState, PgsPcsObjectCroppedFlag, PgsPcsSegment, PgsRc, PgsSegment, PgsStream, Result};
+
+/// Y, Cr, Cb and transparency of a palette entry.
+pub(crate) type PgsColor = (u8, u8, u8, u8);
+
+/// An object as shown on screen: position, forced flag and the colors of its (cropped) pixels.
+#[derive(Clone, PartialEq)]
+pub(crate) struct PgsShownObject {
+    pub(crate) x: u16,
+    pub(crate) y: u16,
+    pub(crate) forced: bool,
+    pub(crate) pixels: Vec<Vec<Option<PgsColor>>>
+}
+
+/// Decoded palettes and objects of the current epoch.
+#[derive(Default)]
+pub(crate) struct PgsPixelState {
+    palettes: HashMap<u8, BTreeMap<u8, PgsColor>>,
+    objects: HashMap<u16, PgsRc<Vec<Vec<u8>>>>
+}
+
+impl PgsPixelState {
+    /// Applies the palettes and objects defined by a display set.
+    ///
+    /// # Returns
+    /// `true` if any palette or object now decodes to different pixels than before.
+    pub(crate) fn apply(&mut self, segments: &[PgsSegment]) -> Result<bool> {
+        let mut changed = false;
+        let mut fragments: Vec<&PgsRc<PgsOdsSegment>> = Vec::new();
+        for segment in segments {
+            match segment {
+                PgsSegment::Pds(pds) => {
+                    let palette = self.palettes.entry(pds.palette_id).or_default();
+                    for entry in pds.palette_entries.iter() {
+                        let color = (entry.luminance, entry.color_difference_red, entry.color_difference_blue, entry.transparency);
+                        changed |= palette.insert(entry.palette_entry_id, color) != Some(color);
+                    }
+                },
+                PgsSegment::Ods(ods) => {
+                    if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) {
+                        fragments.clear();
+                    }
+                    fragments.push(ods);
+                    if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::Last | PgsOdsSequenceFlag::Both) {
+                        let object = PgsOdsSegment {
+                            object_data: fragments.iter().flat_map(|ods| ods.object_data.iter().copied()).collect(),
+                            ..fragments[0].as_ref().clone()
+                        };
+                        let bitmap = PgsRc::new(decode_rle_indices(&object)?);
+                        changed |= self.objects.insert(object.object_id, bitmap.clone()) != Some(bitmap);
+                    }
+                },
+                _ => {}
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Returns the objects shown by a composition, with their pixels resolved through the composition palette.
+    pub(crate) fn shown_objects(&self, pcs: &PgsPcsSegment) -> Vec<Option<PgsShownObject>> {
+        let palette = self.palettes.get(&pcs.palette_id);
+        pcs.composition_objects.iter().map(|obj| {
+            let bitmap = self.objects.get(&obj.object_id)?;
+            let (mut left, mut top, mut width, mut height) = (0, 0, usize::MAX, usize::MAX);
+            if obj.object_cropped_flag == PgsPcsObjectCroppedFlag::ForceCroppedImage {
+                left = obj.object_cropping_horizontal_position as usize;
+                top = obj.object_cropping_vertical_position as usize;
+                width = obj.object_cropping_width as usize;
+                height = obj.object_cropping_height_position as usize;
+            }
+            let pixels = bitmap.iter().skip(top).take(height)
+                .map(|row| row.iter().skip(left).take(width).map(|&index| palette?.get(&index).copied()).collect())
+                .collect();
+            Some(PgsShownObject { x: obj.object_horizontal_position, y: obj.object_vertical_position, forced: obj.object_forced_flag, pixels })
+        }).collect()
+    }
+}
+
+/// A display set showing exactly what an earlier display set already shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsDuplicateDisplaySet {
+    /// Index of the display set first showing the content.
+    pub original: usize,
+    /// Index of the duplicate display set.
+    pub duplicate: usize,
+    /// Presentation timestamp (90 kHz) of the duplicate display set.
+    pub presentation_timestamp: u32
+}
+
+/// Walks the display sets of `segments` and calls `found` for every duplicate with the display set's segments and
+/// whether its palettes and objects decode to the same pixels as the ones already in the buffer.
+fn walk_duplicates<F: FnMut(PgsDuplicateDisplaySet, &[PgsSegment], bool)>(segments: &[PgsSegment], mut found: F) -> Result<()> {
+    let mut state = PgsPixelState::default();
+    // Index and shown objects of the display set currently on screen
+    let mut previous: Option<(usize, Vec<Option<PgsShownObject>>)> = None;
+    for (index, display_set) in segments.split_inclusive(|segment| matches!(segment, PgsSegment::End(_))).enumerate() {
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            continue;
+        };
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            state = PgsPixelState::default();
+        }
+        let changed = state.apply(display_set)?;
+        let shown = state.shown_objects(pcs);
+
+        match &previous {
+            Some((original, previous_shown)) if *previous_shown == shown && shown.iter().all(Option::is_some) => {
+                let duplicate = PgsDuplicateDisplaySet { original: *original, duplicate: index, presentation_timestamp: pcs.header.presentation_timestamp };
+                found(duplicate, display_set, changed);
+            },
+            _ => previous = Some((index, shown))
+        }
+    }
+    Ok(())
+}
+
+/// Finds display sets showing the same pixels at the same positions as the display set before them, differing only
+/// in composition number and timing.
+///
+/// Every duplicate in a run of identical display sets refers to the first display set of the run. Display sets are
+/// indexed as returned by `PgsStream::get_display_set_segments`.
+///
+/// # Parameters
+/// - `segments`: The segments in stream order, for example from `PgsParser::get_segments`.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The duplicates in stream order.
+pub fn find_duplicate_display_sets(segments: &[PgsSegment]) -> Result<Vec<PgsDuplicateDisplaySet>> {
+    let mut duplicates: Vec<PgsDuplicateDisplaySet> = Vec::new();
+    walk_duplicates(segments, |duplicate, _, _| duplicates.push(duplicate))?;
+    Ok(duplicates)
+}
+
+/// Removes the duplicate display sets found by `find_duplicate_display_sets`, so the original stays on screen
+/// until the next different display set.
+///
+/// Duplicates are kept if removing them could change later display sets: Epoch Starts, and display sets defining
+/// palettes or objects that decode to different pixels than the ones they replace. Compositions are renumbered if
+/// display sets were removed.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+///
+/// # Returns
+/// The number of removed display sets.
+pub fn collapse_duplicate_display_sets(stream: &mut PgsStream) -> Result<usize> {
+    let mut removed: Vec<usize> = Vec::new();
+    walk_duplicates(stream.get_segments(), |duplicate, display_set, changed| {
+        let epoch_start = matches!(display_set.first(), Some(PgsSegment::Pcs(pcs)) if pcs.composition_state == PgsPcsCompositionState::EpochStart);
+        if !epoch_start && !changed {
+            removed.push(duplicate.duplicate);
+        }
+    })?;
+    if removed.is_empty() {
+        return Ok(0);
+    }
+
+    let segments: Vec<PgsSegment> = stream.get_display_set_segments().enumerate()
+        .filter(|(index, _)| removed.binary_search(index).is_err())
+        .flat_map(|(_, display_set)| display_set.iter().cloned())
+        .collect();
+    *stream.get_segments_mut() = segments;
+    stream.renumber_compositions();
+    Ok(removed.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{composition_object, display_set_of, ods, palette_entry, pcs, pds, segments};
+
+    use super::*;
+
+    /// A display set showing a 2x1 object with the given version, drawn with a palette entry of the given luminance.
+    fn display_set(pts: u32, state: PgsPcsCompositionState, version: u8, luminance: u8) -> Vec<PgsSegment> {
+        segments(&display_set_of(
+            pcs(pts, state, vec![composition_object(0, 0, 10, 20)]),
+            None,
+            Some(pds(pts, version, vec![palette_entry(1, luminance, 255)])),
+            Some(ods(pts, 0, version, 2, 1, &[vec![1, 0]]))
+        ))
+    }
+
+    #[test]
+    fn test_duplicate_display_sets() {
+        let mut stream = PgsStream::new([
+            display_set(0, PgsPcsCompositionState::EpochStart, 0, 235),
+            display_set(90000, PgsPcsCompositionState::Normal, 1, 235),
+            display_set(180000, PgsPcsCompositionState::AcquisitionPoint, 2, 235),
+            display_set(270000, PgsPcsCompositionState::Normal, 3, 16)
+        ].concat());
+
+        let duplicates: Vec<(usize, usize)> = find_duplicate_display_sets(stream.get_segments()).unwrap().iter()
+            .map(|duplicate| (duplicate.original, duplicate.duplicate)).collect();
+        assert_eq!(duplicates, vec![(0, 1), (0, 2)]);
+
+        assert_eq!(collapse_duplicate_display_sets(&mut stream).unwrap(), 2);
+        let timestamps: Vec<u32> = stream.get_display_sets().iter().map(|ds| ds.pcs.as_ref().unwrap().header.presentation_timestamp).collect();
+        assert_eq!(timestamps, vec![0, 270000]);
+    }
+}
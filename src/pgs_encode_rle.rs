@@ -0,0 +1,71 @@
+//! # PGS RLE Encoding
+//!
+//! This module encodes bitmaps of palette entry ids into the Run-Length Encoding (RLE) used by the object data
+//! of ODS segments. It is the inverse of `decode_rle_indices`.
+
+/// Longest run that fits the 14-bit run length of the extended RLE codes.
+const MAX_RUN_LENGTH: usize = 0x3FFF;
+
+/// Appends the code for `count` pixels of `color` (1 to `MAX_RUN_LENGTH`) to `data`.
+fn encode_run(data: &mut Vec<u8>, color: u8, count: usize) {
+    match (color, count) {
+        // Single pixels are cheaper as plain bytes, up to two of them
+        (1..=255, 1..=2) => data.extend(std::iter::repeat_n(color, count)),
+        (0, 1..=63) => data.extend([0x00, count as u8]),
+        (0, _) => data.extend([0x00, 0x40 | (count >> 8) as u8, count as u8]),
+        (_, 3..=63) => data.extend([0x00, 0x80 | count as u8, color]),
+        (_, _) => data.extend([0x00, 0xC0 | (count >> 8) as u8, count as u8, color])
+    }
+}
+
+/// Encodes a bitmap of palette entry ids as RLE object data.
+///
+/// Every row is encoded as a sequence of runs followed by the `0x00 0x00` end of line marker.
+///
+/// Arguments:
+/// - `bitmap`: Rows of palette entry ids, all of the object width.
+///
+/// Returns:
+/// - The RLE encoded object data.
+pub fn encode_rle(bitmap: &[Vec<u8>]) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    for row in bitmap {
+        let mut col = 0;
+        while col < row.len() {
+            let color = row[col];
+            let count = row[col..].iter().take(MAX_RUN_LENGTH).take_while(|&&pixel| pixel == color).count();
+            encode_run(&mut data, color, count);
+            col += count;
+        }
+        data.extend([0x00, 0x00]);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_decode_rle::decode_rle_indices, PgsOdsSegment, PgsSegmentHeader};
+
+    use super::*;
+
+    #[test]
+    fn test_encode_rle() {
+        let bitmap = [
+            vec![0; 5],
+            vec![1, 2, 2, 3, 3, 3, 0],
+        ];
+        assert_eq!(encode_rle(&bitmap[..1]), vec![0x00, 0x05, 0x00, 0x00]);
+        assert_eq!(encode_rle(&bitmap[1..]), vec![0x01, 0x02, 0x02, 0x00, 0x83, 0x03, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_encode_rle_round_trip() {
+        let width = 20000;
+        let mut bitmap = vec![vec![0; width], vec![7; width], vec![0; width]];
+        bitmap[2][100..200].fill(4);
+        bitmap[2][width - 1] = 9;
+
+        let ods = PgsOdsSegment::from_object(PgsSegmentHeader::default(), 0, 0, width as u16, 3, &encode_rle(&bitmap)).remove(0);
+        assert_eq!(decode_rle_indices(&ods).unwrap(), bitmap);
+    }
+}
@@ -0,0 +1,48 @@
+//! # PGS End of Display Set Segment (END)
+//!
+//! This module defines the `PgsEndSegment` struct, which marks the end of a display set in the
+//! Presentation Graphic Stream (PGS) format. The segment normally carries no payload, but its header timestamps
+//! and any payload bytes are kept so that a stream can be written back unchanged.
+
+use crate::{Error, PgsRc, PgsSegmentHeader, Result};
+
+/// Struct representing an End of Display Set Segment (END) in a PGS file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsEndSegment {
+    pub header: PgsSegmentHeader,
+    /// Payload of the segment, empty in conforming streams.
+    pub data: Vec<u8>
+}
+
+impl PgsEndSegment {
+    fn new(header: PgsSegmentHeader, data: Vec<u8>) -> Self {
+        PgsEndSegment {
+            header,
+            data
+        }
+    }
+
+    /// Constructs a `PgsEndSegment` from the given header and raw data buffer.
+    ///
+    /// # Parameters
+    /// - `header`: The segment header.
+    /// - `data`: A slice of raw data representing the contents of the END segment (normally empty).
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidSegmentDataLength` if the length of the provided data is less than the expected length.
+    ///
+    /// # Returns
+    /// A `PgsRc<PgsEndSegment>` containing the parsed segment.
+    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<PgsRc<PgsEndSegment>> {
+        if data.len() < header.segment_length as usize {
+            return Err(Error::InvalidSegmentDataLength { length: data.len(), expected: header.segment_length as usize });
+        }
+
+        Ok(PgsRc::new(PgsEndSegment::new(header, data[..header.segment_length as usize].to_vec())))
+    }
+
+    /// Serializes the segment payload (without the header), which is the payload as read.
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        Ok(self.data.clone())
+    }
+}
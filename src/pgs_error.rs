@@ -5,30 +5,156 @@
 use core::fmt;
 use std::array::TryFromSliceError;
 
+use crate::PgsDanglingReferences;
+
 /// Enum representing different error types used in the library.
 ///
 /// Variants:
 /// - `File(std::io::Error)`: Represents an error encountered while performing I/O operations.
 /// - `InvalidInputArray`: Indicates that an input array is invalid.
 /// - `ReadInvalidSegment`: Read operation encountered an invalid segment.
-/// - `InvalidSegmentDataLength`: Segment has an incorrect data length.
+/// - `InvalidSegmentDataLength { length, expected }`: Segment data of `length` bytes does not have the `expected`
+///   length: the length a segment needs when it is read, or the largest length a segment can have when it is written.
 /// - `IncompleteDisplaySet`: Indicates that the display set is incomplete.
+/// - `InvalidPaletteDataLength`: A PDS payload does not consist of whole palette entries.
+/// - `InvalidRleData { width, height, position }`: The RLE data of a `width` x `height` object does not fit the object
+///   size at the `(row, column)` of `position`, or the object is too large to decode if `position` is `None`.
+/// - `UnsupportedSegmentType(u8)`: A segment has a type byte the parser does not know, and unknown segments are not
+///   kept.
+/// - `DanglingReferences(Vec<PgsDanglingReferences>)`: Compositions reference windows or objects that are not defined
+///   in their epoch.
+/// - `LimitExceeded { limit, value }`: The input has more segments or display sets than allowed by the `limit` option
+///   of `PgsParserOptions`, set to `value`.
+/// - `OcrFailed(String)`: An OCR engine failed to recognize an image.
+/// - `InvalidArgument(String)`: A parameter passed to an editing or export function is out of range.
+/// - `Cancelled`: The work was interrupted through a `PgsCancelToken`.
+/// - `Segment { offset, segment_type, error }`: Parsing stopped at the segment starting at `offset` of the input,
+///   whose type byte is `segment_type` if its header was read, because of `error`.
+///
+/// The enum is non-exhaustive, so new kinds of errors can be added; match on `category()` to handle whole groups
+/// of errors.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     File(std::io::Error),
     InvalidInputArray,
     ReadInvalidSegment,
-    InvalidSegmentDataLength,
-    IncompleteDisplaySet
+    InvalidSegmentDataLength { length: usize, expected: usize },
+    IncompleteDisplaySet,
+    InvalidPaletteDataLength,
+    InvalidRleData { width: u16, height: u16, position: Option<(usize, usize)> },
+    UnsupportedSegmentType(u8),
+    DanglingReferences(Vec<PgsDanglingReferences>),
+    LimitExceeded { limit: &'static str, value: usize },
+    OcrFailed(String),
+    InvalidArgument(String),
+    Cancelled,
+    Segment { offset: usize, segment_type: Option<u8>, error: Box<Error> }
+}
+
+/// The kind of problem an `Error` stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PgsErrorCategory {
+    /// Reading or writing a file failed, or the data ended early.
+    Io,
+    /// The data is not a well-formed PGS stream: bad segment headers, payloads or display sets.
+    Format,
+    /// A limit of `PgsParserOptions` was exceeded.
+    Limit,
+    /// A bitmap could not be decoded or recognized.
+    Decode,
+    /// The data uses something the library does not support, such as an unknown segment type.
+    Unsupported,
+    /// A parameter passed to the library is out of range.
+    Argument,
+    /// The work was interrupted through a `PgsCancelToken`.
+    Cancelled
+}
+
+impl Error {
+    /// Returns the kind of problem the error stands for.
+    pub fn category(&self) -> PgsErrorCategory {
+        match self {
+            Error::File(_) => PgsErrorCategory::Io,
+            Error::InvalidInputArray | Error::ReadInvalidSegment | Error::InvalidSegmentDataLength { .. } | Error::IncompleteDisplaySet
+                | Error::InvalidPaletteDataLength | Error::DanglingReferences(_) => PgsErrorCategory::Format,
+            Error::LimitExceeded { .. } => PgsErrorCategory::Limit,
+            Error::InvalidRleData { .. } | Error::OcrFailed(_) => PgsErrorCategory::Decode,
+            Error::UnsupportedSegmentType(_) => PgsErrorCategory::Unsupported,
+            Error::InvalidArgument(_) => PgsErrorCategory::Argument,
+            Error::Cancelled => PgsErrorCategory::Cancelled,
+            Error::Segment { error, .. } => error.category()
+        }
+    }
+
+    /// Returns the input offset of the segment the error was raised for, if it is known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Error::Segment { offset, .. } => Some(*offset),
+            _ => None
+        }
+    }
+
+    /// Returns `true` if the error concerns only the data it was raised for, so a lenient driver can skip that
+    /// data and continue: format, decode and unsupported data errors, and data ending early. Failing I/O,
+    /// exceeded limits, invalid arguments and cancellation end the work.
+    pub fn is_recoverable(&self) -> bool {
+        if let Error::Segment { error, .. } = self {
+            return error.is_recoverable();
+        }
+        match self.category() {
+            PgsErrorCategory::Format | PgsErrorCategory::Decode | PgsErrorCategory::Unsupported => true,
+            PgsErrorCategory::Io => matches!(self, Error::File(error) if error.kind() == std::io::ErrorKind::UnexpectedEof),
+            _ => false
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            Error::File(error) => write!(f, "I/O error: {error}"),
+            Error::InvalidInputArray => write!(f, "input data is shorter than expected"),
+            Error::ReadInvalidSegment => write!(f, "invalid segment: missing PG marker or unknown segment type"),
+            Error::InvalidSegmentDataLength { length, expected } => write!(f, "invalid segment data length of {length} bytes, expected {expected}"),
+            Error::IncompleteDisplaySet => write!(f, "display set is incomplete"),
+            Error::InvalidPaletteDataLength => write!(f, "palette data does not consist of whole palette entries"),
+            Error::InvalidRleData { width, height, position: Some((row, col)) } => write!(f, "invalid RLE data of {width}x{height} object at row {row}, column {col}"),
+            Error::InvalidRleData { width, height, position: None } => write!(f, "{width}x{height} object is too large to decode"),
+            Error::UnsupportedSegmentType(segment_type) => write!(f, "unsupported segment type {segment_type:#04x}"),
+            Error::DanglingReferences(dangling) => {
+                write!(f, "dangling references in")?;
+                for (index, references) in dangling.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { "; " };
+                    write!(f, "{separator}display set {} (composition {}):", references.display_set, references.composition_number)?;
+                    if !references.window_ids.is_empty() {
+                        write!(f, " windows {:?}", references.window_ids)?;
+                    }
+                    if !references.object_ids.is_empty() {
+                        write!(f, " objects {:?}", references.object_ids)?;
+                    }
+                }
+                Ok(())
+            },
+            Error::LimitExceeded { limit, value } => write!(f, "limit {limit} of {value} exceeded"),
+            Error::OcrFailed(message) => write!(f, "OCR failed: {message}"),
+            Error::InvalidArgument(message) => write!(f, "invalid argument: {message}"),
+            Error::Cancelled => write!(f, "cancelled"),
+            Error::Segment { offset, segment_type: Some(segment_type), error } => write!(f, "segment of type {segment_type:#04x} at offset {offset:#x}: {error}"),
+            Error::Segment { offset, segment_type: None, error } => write!(f, "segment at offset {offset:#x}: {error}")
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Segment { error, .. } => Some(error.as_ref()),
+            _ => None
+        }
+    }
+}
 
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
@@ -43,4 +169,42 @@ impl From<TryFromSliceError> for Error {
 }
 
 /// A custom result type used throughout the library.
-pub type Result<T> = core::result::Result<T, Error>;
\ No newline at end of file
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category() {
+        let eof = Error::File(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "end of file"));
+        assert_eq!((eof.category(), eof.is_recoverable()), (PgsErrorCategory::Io, true));
+        let not_found = Error::File(std::io::Error::new(std::io::ErrorKind::NotFound, "File not Exists"));
+        assert!(!not_found.is_recoverable());
+
+        assert_eq!(Error::UnsupportedSegmentType(0x99).category(), PgsErrorCategory::Unsupported);
+        assert!(Error::InvalidRleData { width: 2, height: 1, position: Some((0, 1)) }.is_recoverable());
+        assert!(Error::ReadInvalidSegment.is_recoverable());
+        assert!(!Error::LimitExceeded { limit: "max_segments", value: 5 }.is_recoverable());
+        assert!(!Error::Cancelled.is_recoverable());
+        assert!(!Error::InvalidArgument(String::new()).is_recoverable());
+
+        let segment = Error::Segment { offset: 0x40, segment_type: Some(0x99), error: Box::new(Error::UnsupportedSegmentType(0x99)) };
+        assert_eq!((segment.category(), segment.is_recoverable(), segment.offset()), (PgsErrorCategory::Unsupported, true, Some(0x40)));
+        assert_eq!(segment.to_string(), "segment of type 0x99 at offset 0x40: unsupported segment type 0x99");
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Error::InvalidSegmentDataLength { length: 3, expected: 13 }.to_string(), "invalid segment data length of 3 bytes, expected 13");
+        assert_eq!(Error::InvalidRleData { width: 2, height: 1, position: Some((0, 1)) }.to_string(), "invalid RLE data of 2x1 object at row 0, column 1");
+        assert_eq!(Error::InvalidRleData { width: 20000, height: 20000, position: None }.to_string(), "20000x20000 object is too large to decode");
+        assert_eq!(Error::LimitExceeded { limit: "max_segments", value: 5 }.to_string(), "limit max_segments of 5 exceeded");
+
+        let dangling = Error::DanglingReferences(vec![
+            PgsDanglingReferences { display_set: 1, composition_number: 4, window_ids: vec![2], object_ids: vec![3, 5] },
+            PgsDanglingReferences { display_set: 6, composition_number: 9, window_ids: Vec::new(), object_ids: vec![7] }
+        ]);
+        assert_eq!(dangling.to_string(), "dangling references in display set 1 (composition 4): windows [2] objects [3, 5]; display set 6 (composition 9): objects [7]");
+    }
+}
@@ -0,0 +1,261 @@
+//! # PGS Export
+//!
+//! This module converts parsed display sets into timed subtitle events and serializes them into
+//! interchange formats used by authoring tools, such as BDN XML and SRT.
+
+use std::io::Write;
+
+use crate::{PgsDisplaySet, PgsDisplaySetState, Result};
+
+/// Duration (90 kHz ticks) given to an event that is never cleared or replaced by a later display set.
+pub const PGS_DEFAULT_EVENT_DURATION: u32 = 5 * 90000;
+
+/// A subtitle bitmap shown on screen for a time range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsExportEvent {
+    /// Index of the display set holding the bitmap.
+    pub display_set: usize,
+    /// Presentation timestamp (90 kHz) at which the bitmap appears.
+    pub start: u32,
+    /// Presentation timestamp (90 kHz) at which the bitmap is cleared or replaced, if any.
+    pub end: Option<u32>,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    /// The event holds a forced object, shown even when subtitles are turned off.
+    pub forced: bool
+}
+
+impl PgsExportEvent {
+    /// Returns the end timestamp, falling back to `PGS_DEFAULT_EVENT_DURATION` after the start.
+    pub fn end_or_default(&self) -> u32 {
+        self.end.unwrap_or(self.start.saturating_add(PGS_DEFAULT_EVENT_DURATION))
+    }
+
+    /// Returns the file name used for the exported bitmap of this event, e.g. `00012.png`.
+    pub fn image_file_name(&self, extension: &str) -> String {
+        format!("{:05}.{}", self.display_set, extension)
+    }
+}
+
+/// Returns the presentation timestamp of the first display set after `index` that clears the screen or replaces
+/// the bitmap, if any.
+pub(crate) fn event_end(display_sets: &[PgsDisplaySet], index: usize) -> Option<u32> {
+    display_sets[index + 1..].iter()
+        .filter(|next| next.ends_presentation())
+        .find_map(|next| next.pcs.as_ref())
+        .map(|next| next.header.presentation_timestamp)
+}
+
+/// Builds one event per complete display set.
+///
+/// The event ends at the presentation time of the next display set that either clears the screen or replaces
+/// the bitmap; palette-only updates in between do not end it.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+///
+/// # Returns
+/// A vector of events, ordered as the display sets.
+pub fn export_events(display_sets: &[PgsDisplaySet]) -> Vec<PgsExportEvent> {
+    display_sets.iter().enumerate().filter_map(|(index, ds)| {
+        if ds.state() != PgsDisplaySetState::Complete {
+            return None;
+        }
+
+        let pcs = ds.pcs.as_ref()?;
+        let ods = ds.ods.as_ref()?;
+        let (x, y) = pcs.composition_objects.first()
+            .map_or((0, 0), |obj| (obj.object_horizontal_position, obj.object_vertical_position));
+        let end = event_end(display_sets, index);
+
+        Some(PgsExportEvent {
+            display_set: index,
+            start: pcs.header.presentation_timestamp,
+            end,
+            x,
+            y,
+            width: ods.width,
+            height: ods.height,
+            forced: pcs.composition_objects.iter().any(|obj| obj.object_forced_flag)
+        })
+    }).collect()
+}
+
+/// Formats a 90 kHz timestamp as a non-drop-frame `HH:MM:SS:FF` timecode for the given frame rate.
+pub fn format_timecode(ticks: u32, frame_rate: f64) -> String {
+    let base = frame_rate.round().max(1.0) as u64;
+    let frames = (ticks as f64 * frame_rate / 90000.0).round() as u64;
+    let seconds = frames / base;
+    format!("{:02}:{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60, frames % base)
+}
+
+/// A subtitle event carrying text, for example produced by OCR, ready for text subtitle formats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsTextEvent {
+    /// Presentation timestamp (90 kHz) at which the text appears.
+    pub start: u32,
+    /// Presentation timestamp (90 kHz) at which the text disappears.
+    pub end: u32,
+    /// The text, lines separated by `\n`.
+    pub text: String
+}
+
+/// Formats a 90 kHz timestamp as an SRT timestamp (`HH:MM:SS,mmm`).
+pub fn format_srt_timestamp(ticks: u32) -> String {
+    let ms = ticks as u64 / 90;
+    format!("{:02}:{:02}:{:02},{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+/// Writes text events as a SubRip (SRT) document.
+///
+/// # Parameters
+/// - `writer`: The destination of the SRT document.
+/// - `events`: The events to write, numbered from 1 in the given order.
+pub fn write_srt<W: Write>(mut writer: W, events: &[PgsTextEvent]) -> Result<()> {
+    for (index, event) in events.iter().enumerate() {
+        writeln!(writer, "{}", index + 1)?;
+        writeln!(writer, "{} --> {}", format_srt_timestamp(event.start), format_srt_timestamp(event.end))?;
+        writeln!(writer, "{}", event.text)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Escapes the characters that are not allowed in XML attribute values.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Options controlling the BDN XML output.
+#[derive(Debug, Clone)]
+pub struct PgsBdnOptions {
+    /// Title written into the `Name` element.
+    pub title: String,
+    /// ISO 639-2 language code.
+    pub language: String,
+    /// Video frame rate used for the timecodes, e.g. `23.976`.
+    pub frame_rate: f64,
+    /// Video height, used to derive the video format (`1080p`, `720p`, ...).
+    pub video_height: u16,
+    /// Extension of the bitmap files referenced by the events.
+    pub image_extension: String
+}
+
+impl Default for PgsBdnOptions {
+    fn default() -> Self {
+        PgsBdnOptions {
+            title: String::new(),
+            language: "eng".to_string(),
+            frame_rate: 23.976,
+            video_height: 1080,
+            image_extension: "png".to_string()
+        }
+    }
+}
+
+impl PgsBdnOptions {
+    /// Returns the BDN video format matching the video height.
+    fn video_format(&self) -> &'static str {
+        match self.video_height {
+            480 => "480i",
+            576 => "576i",
+            720 => "720p",
+            2160 => "2160p",
+            _ => "1080p"
+        }
+    }
+}
+
+/// Writes events as a BDN XML document. Each event references a bitmap named by
+/// `PgsExportEvent::image_file_name`, which the caller is expected to write next to the XML file.
+///
+/// # Parameters
+/// - `writer`: The destination of the XML document.
+/// - `events`: The events to write, as returned by `export_events`.
+/// - `options`: The BDN description fields.
+pub fn write_bdn<W: Write>(mut writer: W, events: &[PgsExportEvent], options: &PgsBdnOptions) -> Result<()> {
+    let timecode = |ticks: u32| format_timecode(ticks, options.frame_rate);
+    let first_in = events.first().map_or(0, |event| event.start);
+    let last_out = events.last().map_or(0, |event| event.end_or_default());
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<BDN Version="0.93" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:noNamespaceSchemaLocation="BD-03-006-0093b BDN File Format.xsd">"#)?;
+    writeln!(writer, "  <Description>")?;
+    writeln!(writer, r#"    <Name Title="{}" Content=""/>"#, escape_xml(&options.title))?;
+    writeln!(writer, r#"    <Language Code="{}"/>"#, escape_xml(&options.language))?;
+    writeln!(writer, r#"    <Format VideoFormat="{}" FrameRate="{}" DropFrame="False"/>"#, options.video_format(), options.frame_rate)?;
+    writeln!(writer, r#"    <Events Type="Graphic" FirstEventInTC="{}" LastEventOutTC="{}" NumberofEvents="{}"/>"#,
+        timecode(first_in), timecode(last_out), events.len())?;
+    writeln!(writer, "  </Description>")?;
+    writeln!(writer, "  <Events>")?;
+    for event in events {
+        writeln!(writer, r#"    <Event InTC="{}" OutTC="{}" Forced="{}">"#, timecode(event.start), timecode(event.end_or_default()),
+            if event.forced { "True" } else { "False" })?;
+        writeln!(writer, r#"      <Graphic Width="{}" Height="{}" X="{}" Y="{}">{}</Graphic>"#, event.width, event.height,
+            event.x, event.y, event.image_file_name(&options.image_extension))?;
+        writeln!(writer, "    </Event>")?;
+    }
+    writeln!(writer, "  </Events>")?;
+    writeln!(writer, "</BDN>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{bitmap_display_set, clear_display_set, display_set_of, pcs, pds}, PgsPcsCompositionState, PgsPcsSegment};
+
+    use super::*;
+
+    #[test]
+    fn test_export_events() {
+        // Shown at 1 s, palette update at 2 s, cleared at 3 s, shown again at 4 s
+        let palette_update = PgsPcsSegment { palette_update_flag: 0x80, ..pcs(180000, PgsPcsCompositionState::Normal, Vec::new()) };
+        let display_sets = [
+            bitmap_display_set(90000, Vec::new(), &vec![vec![0; 300]; 40]),
+            display_set_of(palette_update, None, Some(pds(180000, 0, Vec::new())), None),
+            clear_display_set(270000),
+            bitmap_display_set(360000, Vec::new(), &vec![vec![0; 300]; 40])
+        ];
+        assert_eq!(display_sets[1].state(), PgsDisplaySetState::PaletteUpdateOnly);
+        let events: Vec<(usize, u32, Option<u32>)> = export_events(&display_sets).iter()
+            .map(|event| (event.display_set, event.start, event.end)).collect();
+        assert_eq!(events, vec![(0, 90000, Some(270000)), (3, 360000, None)]);
+    }
+
+    #[test]
+    fn test_format_timecode() {
+        assert_eq!(format_timecode(0, 25.0), "00:00:00:00");
+        assert_eq!(format_timecode(90000 + 3600 * 2, 25.0), "00:00:01:02");
+        assert_eq!(format_timecode(90000 * 3661, 25.0), "01:01:01:00");
+        // 23.976 fps timecodes count frames with a nominal base of 24
+        assert_eq!(format_timecode(90090, 23.976), "00:00:01:00");
+    }
+
+    #[test]
+    fn test_write_srt() {
+        let events = vec![
+            PgsTextEvent { start: 90000, end: 90000 * 62 + 45, text: "Hello\nWorld".to_string() },
+            PgsTextEvent { start: 90000 * 3600, end: 90000 * 3601, text: "Bye".to_string() }
+        ];
+        let mut srt: Vec<u8> = Vec::new();
+        write_srt(&mut srt, &events).unwrap();
+
+        assert_eq!(String::from_utf8(srt).unwrap(),
+            "1\n00:00:01,000 --> 00:01:02,000\nHello\nWorld\n\n2\n01:00:00,000 --> 01:00:01,000\nBye\n\n");
+    }
+
+    #[test]
+    fn test_write_bdn() {
+        let events = vec![PgsExportEvent { display_set: 2, start: 90000, end: Some(180000), x: 10, y: 20, width: 300, height: 40, forced: false }];
+        let options = PgsBdnOptions { title: "A & B".to_string(), frame_rate: 25.0, ..Default::default() };
+        let mut xml: Vec<u8> = Vec::new();
+        write_bdn(&mut xml, &events, &options).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+
+        assert!(xml.contains(r#"<Name Title="A &amp; B" Content=""/>"#));
+        assert!(xml.contains(r#"FirstEventInTC="00:00:01:00" LastEventOutTC="00:00:02:00" NumberofEvents="1""#));
+        assert!(xml.contains(r#"<Graphic Width="300" Height="40" X="10" Y="20">00002.png</Graphic>"#));
+    }
+}
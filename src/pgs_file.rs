@@ -1,6 +1,6 @@
-use std::{fs::{File, Metadata}, io::{Read, Seek}};
+use std::{fs::{File, Metadata}, io::{Read, Seek, SeekFrom}};
 
-use crate::pgs_error::{Result, Error};
+use crate::{pgs_error::{Result, Error}, PgsSeek};
 
 /// A wrapper around a `File` that includes file metadata and provides methods to read data.
 ///
@@ -78,3 +78,17 @@ impl PgsFile {
         Ok(self.file.stream_position()? >= self.metadata.len())
     }
 }
+
+impl PgsSeek for PgsFile {
+    fn seek(&mut self, to: usize) -> Result<usize> {
+        Ok(self.file.seek(SeekFrom::Start(to as u64))? as usize)
+    }
+
+    fn pos(&mut self) -> Result<usize> {
+        Ok(self.file.stream_position()? as usize)
+    }
+
+    fn len(&self) -> Result<usize> {
+        Ok(self.metadata.len() as usize)
+    }
+}
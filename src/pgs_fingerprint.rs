@@ -0,0 +1,130 @@
+//! # PGS Fingerprints
+//!
+//! This module computes perceptual fingerprints of subtitle bitmaps: a 64-bit difference hash of the bitmap
+//! scaled down to 9x8 brightness samples. Bitmaps that look alike get hashes differing in few bits, even when
+//! they were encoded with different palettes, RLE data or timestamps, so the same subtitles can be matched across
+//! releases of a title.
+
+use crate::{pgs_export::export_events, PgsDisplaySet, Result};
+
+/// Columns of the scaled down bitmap; neighboring columns give the 8 bits of every row.
+const HASH_COLUMNS: usize = 9;
+
+/// Rows of the scaled down bitmap.
+const HASH_ROWS: usize = 8;
+
+/// A perceptual fingerprint of a subtitle bitmap, optionally with the duration it is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PgsFingerprint {
+    /// Difference hash of the bitmap: bit `8 * row + column` is set if the sample is brighter than the one to its
+    /// right.
+    pub hash: u64,
+    /// Duration in 90 kHz ticks the bitmap is shown, if known.
+    pub duration: Option<u32>
+}
+
+impl PgsFingerprint {
+    /// Returns the number of differing hash bits, from 0 for bitmaps that look alike to 64.
+    pub fn distance(&self, other: &PgsFingerprint) -> u32 {
+        (self.hash ^ other.hash).count_ones()
+    }
+
+    /// Returns `true` if the hashes differ in at most `max_distance` bits and, when both durations are known, the
+    /// durations differ by at most `duration_tolerance` ticks.
+    pub fn matches(&self, other: &PgsFingerprint, max_distance: u32, duration_tolerance: u32) -> bool {
+        let durations = match (self.duration, other.duration) {
+            (Some(duration), Some(other)) => duration.abs_diff(other) <= duration_tolerance,
+            _ => true
+        };
+        self.distance(other) <= max_distance && durations
+    }
+}
+
+/// Computes the difference hash of decoded ARGB pixels. Every pixel contributes its luminance weighted by its
+/// alpha, so transparent areas are dark.
+pub(crate) fn hash_pixels(pixels: &[Vec<u32>]) -> u64 {
+    let height = pixels.len();
+    let width = pixels.first().map_or(0, |row| row.len());
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    let brightness = |pixel: u32| {
+        let (alpha, red, green, blue) = ((pixel >> 24) & 0xFF, (pixel >> 16) & 0xFF, (pixel >> 8) & 0xFF, pixel & 0xFF);
+        (299 * red + 587 * green + 114 * blue) / 1000 * alpha / 255
+    };
+    // Range of source pixels covered by a sample, at least one pixel wide
+    let span = |index: usize, count: usize, size: usize| {
+        let start = (index * size / count).min(size - 1);
+        start..((index + 1) * size / count).max(start + 1)
+    };
+
+    let mut samples = [[0u64; HASH_COLUMNS]; HASH_ROWS];
+    for (row, line) in samples.iter_mut().enumerate() {
+        let rows = span(row, HASH_ROWS, height);
+        for (column, sample) in line.iter_mut().enumerate() {
+            let columns = span(column, HASH_COLUMNS, width);
+            let area = (rows.len() * columns.len()) as u64;
+            let sum: u64 = pixels[rows.clone()].iter()
+                .flat_map(|line| line[columns.clone()].iter())
+                .map(|&pixel| brightness(pixel) as u64)
+                .sum();
+            *sample = sum / area;
+        }
+    }
+
+    samples.iter().flat_map(|line| line.windows(2).map(|pair| pair[0] > pair[1]))
+        .enumerate()
+        .fold(0, |hash, (bit, brighter)| hash | (brighter as u64) << bit)
+}
+
+/// Computes the fingerprint of every subtitle event, with the duration the event is shown.
+///
+/// Events are built with `export::export_events`; events that are never cleared have no duration.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// One fingerprint per event, in stream order.
+pub fn fingerprint_events(display_sets: &[PgsDisplaySet]) -> Result<Vec<PgsFingerprint>> {
+    export_events(display_sets).iter().map(|event| {
+        let hash = hash_pixels(&display_sets[event.display_set].get_decoded_image(false)?);
+        Ok(PgsFingerprint { hash, duration: event.end.map(|end| end.saturating_sub(event.start)) })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{bitmap_display_set, palette_entry};
+
+    use super::*;
+
+    /// A display set showing a 60x20 bitmap with opaque pixels where `text` returns `true`.
+    fn text(pts: u32, luminance: u8, text: fn(usize, usize) -> bool) -> PgsDisplaySet {
+        let bitmap: Vec<Vec<u8>> = (0..20).map(|y| (0..60).map(|x| text(x, y) as u8).collect()).collect();
+        bitmap_display_set(pts, vec![palette_entry(0, 16, 0), palette_entry(1, luminance, 255)], &bitmap)
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let stripes = |x: usize, y: usize| (x / 7 + y / 5).is_multiple_of(2);
+        let display_sets = [
+            text(90000, 235, stripes),
+            text(270000, 200, stripes),
+            text(900000, 235, |x, _| x > 40)
+        ];
+        let fingerprints = fingerprint_events(&display_sets).unwrap();
+        assert_eq!(fingerprints.iter().map(|fingerprint| fingerprint.duration).collect::<Vec<_>>(), [Some(180000), Some(630000), None]);
+
+        // The same bitmap with a darker palette matches, another bitmap does not
+        assert_eq!(display_sets[0].fingerprint().unwrap().hash, fingerprints[0].hash);
+        assert_eq!(fingerprints[0].distance(&fingerprints[1]), 0);
+        assert!(fingerprints[0].distance(&fingerprints[2]) > 16);
+        assert!(fingerprints[0].matches(&fingerprints[1], 4, 450000));
+        assert!(!fingerprints[0].matches(&fingerprints[1], 4, 90000));
+        assert!(fingerprints[0].matches(&fingerprints[2], 64, 0));
+    }
+}
@@ -0,0 +1,125 @@
+//! # PGS Index
+//!
+//! This module defines the `PgsIndexedParser`, which scans only the segment headers of a SUP file to build an
+//! index of its display sets, and then parses just the display sets needed to show a requested time. This gives
+//! player-style random access to large files without parsing them completely.
+
+use crate::{pgs_reader::PgsReader, pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH, PgsFile, PgsPcsCompositionState, PgsSeek, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsStream, Result};
+
+/// Number of PCS payload bytes read while indexing: width, height, frame rate, composition number and state.
+const PCS_INDEX_LENGTH: usize = 8;
+
+/// Position and timing of a display set, found by scanning segment headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PgsIndexEntry {
+    /// File offset of the display set's PCS.
+    pub offset: usize,
+    /// Presentation timestamp (90 kHz) of the display set.
+    pub presentation_timestamp: u32,
+    pub composition_state: PgsPcsCompositionState
+}
+
+/// A SUP file indexed by display set, created with `PgsParser::open_indexed`.
+#[derive(Debug)]
+pub struct PgsIndexedParser<'a> {
+    sup_file_path: &'a str,
+    entries: Vec<PgsIndexEntry>
+}
+
+impl<'a> PgsIndexedParser<'a> {
+    /// Scans the segment headers of a SUP file, skipping all payloads except the start of every PCS.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or a segment header is invalid.
+    pub(crate) fn open(sup_file_path: &'a str) -> Result<Self> {
+        let mut file = PgsReader::open(sup_file_path)?;
+        let mut entries: Vec<PgsIndexEntry> = Vec::new();
+        while !file.is_eof()? {
+            let offset = file.pos()?;
+            let header = PgsSegmentHeader::from_bytes(&file.read_n_bytes::<PGS_SEGMENT_HEADER_LENGTH>()?)?;
+            if header.segment_type == PgsSegmentType::PCS && header.segment_length as usize >= PCS_INDEX_LENGTH {
+                let pcs = file.read_n_bytes::<PCS_INDEX_LENGTH>()?;
+                entries.push(PgsIndexEntry {
+                    offset,
+                    presentation_timestamp: header.presentation_timestamp,
+                    composition_state: PgsPcsCompositionState::from(pcs[7])
+                });
+            }
+            file.seek(offset + PGS_SEGMENT_HEADER_LENGTH + header.segment_length as usize)?;
+        }
+        Ok(PgsIndexedParser { sup_file_path, entries })
+    }
+
+    /// Returns the index entries of all display sets, in file order.
+    pub fn get_entries(&self) -> &[PgsIndexEntry] {
+        &self.entries
+    }
+
+    /// Returns the index of the display set on screen at `timestamp`: the last display set presented at or before
+    /// it, or `None` if the first display set is presented later.
+    pub fn find(&self, timestamp: u32) -> Option<usize> {
+        self.entries.iter().rposition(|entry| entry.presentation_timestamp <= timestamp)
+    }
+
+    /// Parses the display sets needed to show `timestamp`.
+    ///
+    /// Parsing starts at the last Epoch Start or Acquisition Point at or before the display set on screen, which
+    /// defines everything later display sets refer to, and stops after the display set on screen.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or a segment is invalid.
+    ///
+    /// # Returns
+    /// The parsed segments, whose last display set is the one on screen, or `None` if nothing is shown yet.
+    pub fn parse_at(&self, timestamp: u32) -> Result<Option<PgsStream>> {
+        let Some(target) = self.find(timestamp) else {
+            return Ok(None);
+        };
+        let start = self.entries[..=target].iter()
+            .rposition(|entry| entry.composition_state != PgsPcsCompositionState::Normal)
+            .unwrap_or(0);
+
+        let mut file = PgsReader::open(self.sup_file_path)?;
+        file.seek(self.entries[start].offset)?;
+        let mut segments: Vec<PgsSegment> = Vec::new();
+        let mut remaining = target - start + 1;
+        while remaining > 0 && !file.is_eof()? {
+            let segment = Self::read_segment(&mut file)?;
+            if let PgsSegment::End(_) = segment {
+                remaining -= 1;
+            }
+            segments.push(segment);
+        }
+        Ok(Some(PgsStream::new(segments)))
+    }
+
+    /// Reads the segment at the current file position.
+    fn read_segment(file: &mut PgsFile) -> Result<PgsSegment> {
+        let header = PgsSegmentHeader::from_bytes(&file.read_n_bytes::<PGS_SEGMENT_HEADER_LENGTH>()?)?;
+        let mut buffer = vec![0; header.segment_length as usize];
+        file.read_bytes(buffer.as_mut_slice())?;
+        PgsSegment::from_data(header, &buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{display_set_bytes, TempSupFile}, PgsParser};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_at() {
+        let data = [display_set_bytes(90000, 0, 0x80), display_set_bytes(180000, 0, 0x00), display_set_bytes(270000, 0, 0x40),
+            display_set_bytes(360000, 0, 0x00)].concat();
+        let file = TempSupFile::new("index", &data);
+
+        let parser = PgsParser::open_indexed(file.path()).unwrap();
+        assert_eq!(parser.get_entries().iter().map(|entry| entry.offset).collect::<Vec<_>>(), vec![0, 37, 74, 111]);
+        assert!(parser.parse_at(45000).unwrap().is_none());
+        let timestamps = |stream: PgsStream| stream.get_display_sets().iter()
+            .map(|ds| ds.pcs.as_ref().unwrap().header.presentation_timestamp).collect::<Vec<_>>();
+        assert_eq!(timestamps(parser.parse_at(200000).unwrap().unwrap()), vec![90000, 180000]);
+        assert_eq!(timestamps(parser.parse_at(400000).unwrap().unwrap()), vec![270000, 360000]);
+    }
+}
@@ -0,0 +1,158 @@
+//! # PGS Letterbox Placement
+//!
+//! This module infers whether a subtitle track was authored for a letterboxed picture, such as a 2.39:1 scope
+//! film inside a 16:9 frame. Such tracks keep their windows inside the active picture instead of using the black
+//! mattes, so the windows of all events fit between the mattes of the widest matching aspect ratio. The implied
+//! active-picture rectangle tells how far to move the windows when the matting changes, e.g. with `reposition`.
+
+use crate::{export::export_events, PgsDisplaySet, PgsRectangle};
+
+/// Picture aspect ratios commonly letterboxed into a wider-than-tall frame, widest first.
+pub const PGS_LETTERBOX_ASPECT_RATIOS: [f64; 5] = [2.39, 2.35, 2.20, 2.00, 1.85];
+
+/// Options controlling `detect_letterbox_with_options`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PgsLetterboxOptions {
+    /// Display aspect ratio of the frame, `None` for square pixels (width divided by height).
+    pub frame_aspect_ratio: Option<f64>,
+    /// Smallest percentage of events whose windows must fit inside the active picture of an aspect ratio.
+    pub min_inside_percentage: f64
+}
+
+impl Default for PgsLetterboxOptions {
+    fn default() -> Self {
+        PgsLetterboxOptions { frame_aspect_ratio: None, min_inside_percentage: 95.0 }
+    }
+}
+
+/// The active picture a subtitle track was authored for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsLetterboxReport {
+    pub video_width: u16,
+    pub video_height: u16,
+    /// Aspect ratio of the active picture, the frame aspect ratio if the track is not letterboxed.
+    pub aspect_ratio: f64,
+    /// Rectangle of the active picture within the frame.
+    pub active_picture: PgsRectangle,
+    /// Number of events considered.
+    pub events: usize,
+    /// Number of events whose windows fit inside the active picture.
+    pub events_inside: usize
+}
+
+impl PgsLetterboxReport {
+    /// Returns `true` if the active picture is shorter than the frame.
+    pub fn is_letterboxed(&self) -> bool {
+        self.active_picture.3 < self.video_height
+    }
+}
+
+/// Returns the active picture of an aspect ratio centered in a frame, or `None` if it is not shorter than the
+/// frame.
+///
+/// # Parameters
+/// - `video_width`, `video_height`: The size of the frame in pixels.
+/// - `frame_aspect_ratio`: The display aspect ratio of the frame.
+/// - `aspect_ratio`: The aspect ratio of the picture.
+pub fn letterbox_rectangle(video_width: u16, video_height: u16, frame_aspect_ratio: f64, aspect_ratio: f64) -> Option<PgsRectangle> {
+    let height = (video_height as f64 * frame_aspect_ratio / aspect_ratio).round() as u16;
+    (height < video_height).then(|| (0, (video_height - height) / 2, video_width, height))
+}
+
+/// Returns the vertical span `(top, bottom)` of the windows the objects of a display set are shown in, or of the
+/// event if the display set has no windows.
+fn event_span(ds: &PgsDisplaySet, y: u16, height: u16) -> (u32, u32) {
+    let windows = ds.pcs.as_ref().zip(ds.wds.as_ref()).map(|(pcs, wds)| wds.windows.iter()
+        .filter(|window| pcs.composition_objects.iter().any(|obj| obj.window_id == window.window_id))
+        .map(|window| (window.window_vertical_position as u32, window.window_vertical_position as u32 + window.window_height as u32))
+        .collect::<Vec<_>>());
+    match windows {
+        Some(windows) if !windows.is_empty() => (
+            windows.iter().map(|&(top, _)| top).min().unwrap_or(0),
+            windows.iter().map(|&(_, bottom)| bottom).max().unwrap_or(0)
+        ),
+        _ => (y as u32, y as u32 + height as u32)
+    }
+}
+
+/// Infers the active picture of a subtitle track with the default options.
+///
+/// See `detect_letterbox_with_options`.
+pub fn detect_letterbox(display_sets: &[PgsDisplaySet]) -> Option<PgsLetterboxReport> {
+    detect_letterbox_with_options(display_sets, &PgsLetterboxOptions::default())
+}
+
+/// Infers the active picture a subtitle track was authored for from the windows of its events.
+///
+/// Events are built with `export::export_events` and placed by the windows their objects are shown in. Of the
+/// aspect ratios in `PGS_LETTERBOX_ASPECT_RATIOS` wider than the frame, the widest whose active picture holds the
+/// windows of at least `min_inside_percentage` of the events is chosen; if none does, the track uses the full
+/// frame. The video size is taken from the first event.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+/// - `options`: Frame aspect ratio and share of events that must fit.
+///
+/// # Returns
+/// The inferred active picture, or `None` if the track has no events or its video size is unknown.
+pub fn detect_letterbox_with_options(display_sets: &[PgsDisplaySet], options: &PgsLetterboxOptions) -> Option<PgsLetterboxReport> {
+    let events = export_events(display_sets);
+    let pcs = display_sets[events.first()?.display_set].pcs.as_ref()?;
+    let (video_width, video_height) = (pcs.width, pcs.height);
+    if video_width == 0 || video_height == 0 {
+        return None;
+    }
+    let frame_aspect_ratio = options.frame_aspect_ratio.unwrap_or(video_width as f64 / video_height as f64);
+
+    let spans: Vec<(u32, u32)> = events.iter().map(|event| event_span(&display_sets[event.display_set], event.y, event.height)).collect();
+    let inside = |(_, y, _, height): PgsRectangle| spans.iter()
+        .filter(|&&(top, bottom)| top >= y as u32 && bottom <= y as u32 + height as u32)
+        .count();
+    let letterbox = PGS_LETTERBOX_ASPECT_RATIOS.iter()
+        .filter_map(|&aspect_ratio| Some((aspect_ratio, letterbox_rectangle(video_width, video_height, frame_aspect_ratio, aspect_ratio)?)))
+        .map(|(aspect_ratio, active_picture)| (aspect_ratio, active_picture, inside(active_picture)))
+        .find(|&(_, _, events_inside)| 100.0 * events_inside as f64 >= options.min_inside_percentage * spans.len() as f64);
+
+    let full_frame = (0, 0, video_width, video_height);
+    let (aspect_ratio, active_picture, events_inside) = letterbox.unwrap_or((frame_aspect_ratio, full_frame, inside(full_frame)));
+    Some(PgsLetterboxReport { video_width, video_height, aspect_ratio, active_picture, events: spans.len(), events_inside })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{composition_object, display_set_of, ods, pcs, pds, wds, window}, PgsPcsCompositionState, PgsPcsSegment};
+
+    use super::*;
+
+    /// A display set of a 1920x1080 video showing an object in a 60 rows high window at `y`, or clearing the
+    /// screen.
+    fn display_set(pts: u32, y: Option<u16>) -> PgsDisplaySet {
+        let objects = y.map(|y| composition_object(0, 0, 100, y)).into_iter().collect();
+        display_set_of(
+            PgsPcsSegment { width: 1920, height: 1080, ..pcs(pts, PgsPcsCompositionState::Normal, objects) },
+            Some(wds(pts, vec![window(0, (0, y.unwrap_or(0), 1920, 60))])),
+            y.map(|_| pds(pts, 0, Vec::new())),
+            y.map(|_| ods(pts, 0, 0, 1, 1, &[vec![0]]))
+        )
+    }
+
+    #[test]
+    fn test_detect_letterbox() {
+        assert_eq!(letterbox_rectangle(1920, 1080, 16.0 / 9.0, 2.39), Some((0, 138, 1920, 803)));
+        assert_eq!(letterbox_rectangle(1920, 1080, 16.0 / 9.0, 1.5), None);
+
+        // Dialogue at the bottom of a 2.39:1 picture, then one line above even a 1.85:1 one
+        let mut display_sets: Vec<PgsDisplaySet> = (0..10).flat_map(|index| [display_set(180000 * index, Some(860)), display_set(180000 * index + 90000, None)]).collect();
+        let report = detect_letterbox(&display_sets).unwrap();
+        assert_eq!((report.aspect_ratio, report.active_picture, report.events_inside), (2.39, (0, 138, 1920, 803), 10));
+        assert!(report.is_letterboxed());
+
+        display_sets.push(display_set(2_000_000, Some(10)));
+        let report = detect_letterbox(&display_sets).unwrap();
+        assert_eq!((report.active_picture, report.events, report.events_inside, report.is_letterboxed()), ((0, 0, 1920, 1080), 11, 11, false));
+        let report = detect_letterbox_with_options(&display_sets, &PgsLetterboxOptions { min_inside_percentage: 90.0, ..Default::default() }).unwrap();
+        assert_eq!((report.aspect_ratio, report.events_inside), (2.39, 10));
+
+        assert_eq!(detect_letterbox(&[]), None);
+    }
+}
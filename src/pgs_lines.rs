@@ -0,0 +1,113 @@
+//! # PGS Text Lines
+//!
+//! This module splits decoded subtitle bitmaps into text lines using the horizontal projection profile of the
+//! bitmap: rows containing visible pixels are grouped into bands, and every band that is tall enough is a line.
+//! OCR engines recognize single lines more reliably than whole bitmaps, and the number of lines of every event is
+//! useful for checking subtitles against line limits.
+
+/// The rectangle of a text line within a bitmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsTextLine {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl PgsTextLine {
+    /// Returns the pixels of the line, cut out of the bitmap it was found in.
+    pub fn crop(&self, pixels: &[Vec<u32>]) -> Vec<Vec<u32>> {
+        pixels.iter().skip(self.y).take(self.height)
+            .map(|row| row.iter().skip(self.x).take(self.width).copied().collect())
+            .collect()
+    }
+}
+
+/// Options controlling `find_text_lines_with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsLineSegmentOptions {
+    /// Pixels with at least this alpha are visible.
+    pub alpha_threshold: u8,
+    /// Largest number of empty rows within a line, keeping dots and accents above letters in their line.
+    pub max_gap: usize,
+    /// Bands of visible rows lower than this are dropped as noise.
+    pub min_height: usize
+}
+
+impl Default for PgsLineSegmentOptions {
+    fn default() -> Self {
+        PgsLineSegmentOptions { alpha_threshold: 128, max_gap: 1, min_height: 2 }
+    }
+}
+
+/// Finds the text lines of decoded ARGB pixels (as returned by `PgsDisplaySet::get_decoded_image(false)`) with the
+/// default options.
+///
+/// See `find_text_lines_with_options`.
+pub fn find_text_lines(pixels: &[Vec<u32>]) -> Vec<PgsTextLine> {
+    find_text_lines_with_options(pixels, &PgsLineSegmentOptions::default())
+}
+
+/// Finds the text lines of decoded ARGB pixels.
+///
+/// Rows with visible pixels separated by at most `max_gap` empty rows form a line, which is dropped if it is lower
+/// than `min_height` rows. The rectangle of every line is tightened to its visible pixels.
+///
+/// # Parameters
+/// - `pixels`: The decoded pixels.
+/// - `options`: Visibility threshold, largest gap within a line and smallest line height.
+///
+/// # Returns
+/// The lines from top to bottom.
+pub fn find_text_lines_with_options(pixels: &[Vec<u32>], options: &PgsLineSegmentOptions) -> Vec<PgsTextLine> {
+    let visible = |pixel: u32| (pixel >> 24) as u8 >= options.alpha_threshold;
+
+    // Bands of rows (start, end) holding visible pixels
+    let mut bands: Vec<(usize, usize)> = Vec::new();
+    for (y, row) in pixels.iter().enumerate() {
+        if !row.iter().any(|&pixel| visible(pixel)) {
+            continue;
+        }
+        match bands.last_mut() {
+            Some((_, end)) if y - *end <= options.max_gap => *end = y + 1,
+            _ => bands.push((y, y + 1))
+        }
+    }
+
+    bands.into_iter().filter(|(start, end)| end - start >= options.min_height).filter_map(|(start, end)| {
+        let columns = pixels[start..end].iter().flat_map(|row| {
+            row.iter().enumerate().filter(|(_, &pixel)| visible(pixel)).map(|(x, _)| x)
+        });
+        let (left, right) = columns.fold((usize::MAX, 0), |(left, right), x| (left.min(x), right.max(x + 1)));
+        (left < right).then_some(PgsTextLine { x: left, y: start, width: right - left, height: end - start })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_text_lines() {
+        let mut pixels = vec![vec![0x00FFFFFF; 10]; 12];
+        // A dot above the first line, one empty row apart
+        pixels[1][3] = 0xFFFFFFFF;
+        for row in &mut pixels[3..6] {
+            row[2..7].fill(0xFFFFFFFF);
+        }
+        for row in &mut pixels[9..11] {
+            row[1..4].fill(0xFF000000);
+        }
+        pixels[10][8] = 0x40FFFFFF;
+
+        let lines = find_text_lines(&pixels);
+        assert_eq!(lines, [
+            PgsTextLine { x: 2, y: 1, width: 5, height: 5 },
+            PgsTextLine { x: 1, y: 9, width: 3, height: 2 }
+        ]);
+        assert_eq!(lines[1].crop(&pixels), vec![vec![0xFF000000; 3]; 2]);
+
+        let options = PgsLineSegmentOptions { max_gap: 0, min_height: 3, ..Default::default() };
+        assert_eq!(find_text_lines_with_options(&pixels, &options), [PgsTextLine { x: 2, y: 3, width: 5, height: 3 }]);
+    }
+}
@@ -2,14 +2,14 @@
 //!
 //! This module defines the `PgsMemoryBuffer`, which represents an in-memory buffer that can be
 //! read from and seeked into. It also includes functionality for reading different byte orders.
-use std::{fmt::Debug, io::Read};
+use std::{fmt::Debug, io::{Read, Write}};
 
 use crate::{pgs_error::Result, PgsSeek};
 
 /// A trait for handling different byte orders.
 ///
 /// This trait defines methods for reading unsigned integers in various byte orders.
-pub trait ByteOrder: Default + Debug + Clone {
+pub trait ByteOrder: Debug + Clone {
     /// Reads a 16-bit unsigned integer from a byte slice.
     ///
     /// # Arguments
@@ -36,18 +36,33 @@ pub trait ByteOrder: Default + Debug + Clone {
     /// # Returns
     /// Returns a `Result` containing the 32-bit integer on success, or an `Error` if the read operation fails.
     fn read_u32(buf: &[u8]) -> Result<u32>;
+
+    /// Writes a 16-bit unsigned integer into a byte slice.
+    ///
+    /// # Arguments
+    /// * `buf` - A byte slice of at least 2 bytes receiving the data.
+    /// * `n` - The value to write.
+    fn write_u16(buf: &mut [u8], n: u16);
+
+    /// Writes the lower 24 bits of a 32-bit unsigned integer into a byte slice.
+    ///
+    /// # Arguments
+    /// * `buf` - A byte slice of at least 3 bytes receiving the data.
+    /// * `n` - The value to write.
+    fn write_u24(buf: &mut [u8], n: u32);
+
+    /// Writes a 32-bit unsigned integer into a byte slice.
+    ///
+    /// # Arguments
+    /// * `buf` - A byte slice of at least 4 bytes receiving the data.
+    /// * `n` - The value to write.
+    fn write_u32(buf: &mut [u8], n: u32);
 }
 
 /// A struct representing the big-endian byte order.
 #[derive(Clone, Copy, Debug)]
 pub enum BigEndian {}
 
-impl Default for BigEndian {
-    fn default() -> BigEndian {
-        panic!("BigEndian")
-    }
-}
-
 impl ByteOrder for BigEndian {
     #[inline]
     fn read_u16(buf: &[u8]) -> Result<u16> {
@@ -65,6 +80,21 @@ impl ByteOrder for BigEndian {
     fn read_u32(buf: &[u8]) -> Result<u32> {
         Ok(u32::from_be_bytes(buf[..4].try_into()?))
     }
+
+    #[inline]
+    fn write_u16(buf: &mut [u8], n: u16) {
+        buf[..2].copy_from_slice(&n.to_be_bytes());
+    }
+
+    #[inline]
+    fn write_u24(buf: &mut [u8], n: u32) {
+        buf[..3].copy_from_slice(&n.to_be_bytes()[1..]);
+    }
+
+    #[inline]
+    fn write_u32(buf: &mut [u8], n: u32) {
+        buf[..4].copy_from_slice(&n.to_be_bytes());
+    }
 }
 
 
@@ -72,12 +102,6 @@ impl ByteOrder for BigEndian {
 #[derive(Clone, Copy, Debug)]
 pub enum LittleEndian {}
 
-impl Default for LittleEndian {
-    fn default() -> LittleEndian {
-        panic!("LittleEndian")
-    }
-}
-
 impl ByteOrder for LittleEndian {
     #[inline]
     fn read_u16(buf: &[u8]) -> Result<u16> {
@@ -95,6 +119,21 @@ impl ByteOrder for LittleEndian {
     fn read_u32(buf: &[u8]) -> Result<u32> {
         Ok(u32::from_le_bytes(buf[..4].try_into()?))
     }
+
+    #[inline]
+    fn write_u16(buf: &mut [u8], n: u16) {
+        buf[..2].copy_from_slice(&n.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u24(buf: &mut [u8], n: u32) {
+        buf[..3].copy_from_slice(&n.to_le_bytes()[..3]);
+    }
+
+    #[inline]
+    fn write_u32(buf: &mut [u8], n: u32) {
+        buf[..4].copy_from_slice(&n.to_le_bytes());
+    }
 }
 
 /// A trait for reading bytes with support for different byte orders.
@@ -155,6 +194,66 @@ pub trait ReadBytes: Read {
 
 impl<R: Read + ?Sized> ReadBytes for R {}
 
+/// A trait for writing bytes with support for different byte orders.
+pub trait WriteBytes: Write {
+    /// Writes a single 8-bit unsigned integer.
+    ///
+    /// # Returns
+    /// Returns a `Result` indicating success, or an `Error` if the write operation fails.
+    #[inline]
+    fn write_u8(&mut self, n: u8) -> Result<()> {
+        self.write_all(&[n])?;
+        Ok(())
+    }
+
+    /// Writes a 16-bit unsigned integer using the specified byte order.
+    ///
+    /// # Type Parameters
+    /// * `T` - The byte order to use for writing the integer.
+    ///
+    /// # Returns
+    /// Returns a `Result` indicating success, or an `Error` if the write operation fails.
+    #[inline]
+    fn write_u16<T: ByteOrder>(&mut self, n: u16) -> Result<()> {
+        let mut buf: [u8; 2] = [0; 2];
+        T::write_u16(&mut buf, n);
+        self.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Writes the lower 24 bits of a 32-bit unsigned integer using the specified byte order.
+    ///
+    /// # Type Parameters
+    /// * `T` - The byte order to use for writing the integer.
+    ///
+    /// # Returns
+    /// Returns a `Result` indicating success, or an `Error` if the write operation fails.
+    #[inline]
+    fn write_u24<T: ByteOrder>(&mut self, n: u32) -> Result<()> {
+        let mut buf: [u8; 3] = [0; 3];
+        T::write_u24(&mut buf, n);
+        self.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Writes a 32-bit unsigned integer using the specified byte order.
+    ///
+    /// # Type Parameters
+    /// * `T` - The byte order to use for writing the integer.
+    ///
+    /// # Returns
+    /// Returns a `Result` indicating success, or an `Error` if the write operation fails.
+    #[inline]
+    fn write_u32<T: ByteOrder>(&mut self, n: u32) -> Result<()> {
+        let mut buf: [u8; 4] = [0; 4];
+        T::write_u32(&mut buf, n);
+        self.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+impl<W: Write + ?Sized> WriteBytes for W {}
+
 /// A memory buffer that supports reading and seeking operations.
 #[derive(Default)]
 pub struct PgsMemoryBuffer {
@@ -0,0 +1,205 @@
+//! # PGS OCR
+//!
+//! This module defines the interface between decoded subtitle bitmaps and OCR (optical character recognition)
+//! engines. `PgsOcrImage` holds a grayscale image with dark text on a light background, which is what OCR
+//! engines expect, and `PgsOcrEngine` is implemented by anything that can turn such an image into text.
+//!
+//! `prepare` applies the usual preprocessing for OCR to a decoded bitmap: binarization, a white border and integer
+//! upscaling. With the `ocr` feature enabled, `PgsTesseractEngine` runs the `tesseract` command line tool.
+
+use crate::Result;
+
+/// An 8-bit grayscale image prepared for OCR.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsOcrImage {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major luminance samples, `width * height` bytes.
+    pub data: Vec<u8>
+}
+
+impl PgsOcrImage {
+    /// Converts decoded ARGB pixels (as returned by `PgsDisplaySet::get_decoded_image(false)`) into an OCR image.
+    ///
+    /// Each pixel is composited over black using its alpha and the result is inverted, so the usually bright
+    /// subtitle fill becomes dark text and transparent areas become a white background.
+    pub fn from_argb(pixels: &[Vec<u32>]) -> Self {
+        let height = pixels.len();
+        let width = pixels.first().map_or(0, |row| row.len());
+        let data = pixels.iter().flatten().map(|&pixel| {
+            let alpha = (pixel >> 24) & 0xFF;
+            let red = (pixel >> 16) & 0xFF;
+            let green = (pixel >> 8) & 0xFF;
+            let blue = pixel & 0xFF;
+            let luma = (299 * red + 587 * green + 114 * blue) / 1000;
+            255 - (luma * alpha / 255) as u8
+        }).collect();
+
+        PgsOcrImage {
+            width,
+            height,
+            data
+        }
+    }
+
+    /// Encodes the image as a binary PGM (`P5`) file, a format every OCR tool accepts.
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut pgm = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        pgm.extend_from_slice(&self.data);
+        pgm
+    }
+}
+
+/// Options controlling `prepare_with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsOcrPrepareOptions {
+    /// Samples darker than this become black text, all others white background.
+    pub threshold: u8,
+    /// Width in pixels of the white border added around the image, before upscaling.
+    pub border: usize,
+    /// Integer upscaling factor; every pixel becomes a `scale x scale` block, keeping the edges crisp. Values below
+    /// 1 are treated as 1.
+    pub scale: usize
+}
+
+impl Default for PgsOcrPrepareOptions {
+    fn default() -> Self {
+        PgsOcrPrepareOptions { threshold: 128, border: 10, scale: 3 }
+    }
+}
+
+/// Prepares decoded ARGB pixels (as returned by `PgsDisplaySet::get_decoded_image(false)`) for OCR with the
+/// default options.
+///
+/// See `prepare_with_options`.
+pub fn prepare(decoded: &[Vec<u32>]) -> PgsOcrImage {
+    prepare_with_options(decoded, &PgsOcrPrepareOptions::default())
+}
+
+/// Prepares decoded ARGB pixels for OCR: converts them to dark text on a light background with
+/// `PgsOcrImage::from_argb`, binarizes the result, adds a white border and upscales it.
+///
+/// Binarizing drops the anti-aliased outline that subtitle fonts usually have, and the border and upscaling bring
+/// the glyphs to the size and margins OCR engines work best with.
+///
+/// # Parameters
+/// - `decoded`: The decoded pixels.
+/// - `options`: Threshold, border and scale.
+///
+/// # Returns
+/// The prepared image of `(width + 2 * border) * scale` by `(height + 2 * border) * scale` pixels.
+pub fn prepare_with_options(decoded: &[Vec<u32>], options: &PgsOcrPrepareOptions) -> PgsOcrImage {
+    let image = PgsOcrImage::from_argb(decoded);
+    let scale = options.scale.max(1);
+    let width = (image.width + 2 * options.border) * scale;
+    let height = (image.height + 2 * options.border) * scale;
+
+    let mut data = vec![255; width * height];
+    for (y, row) in image.data.chunks_exact(image.width.max(1)).enumerate() {
+        for (x, _) in row.iter().enumerate().filter(|(_, sample)| **sample < options.threshold) {
+            let left = (x + options.border) * scale;
+            for line in 0..scale {
+                let start = ((y + options.border) * scale + line) * width + left;
+                data[start..start + scale].fill(0);
+            }
+        }
+    }
+
+    PgsOcrImage {
+        width,
+        height,
+        data
+    }
+}
+
+/// An OCR engine turning a prepared image into text.
+pub trait PgsOcrEngine {
+    /// Recognizes the text in the image.
+    ///
+    /// # Returns
+    /// The recognized text with lines separated by `\n`, or an `Error` if recognition fails.
+    fn recognize(&mut self, image: &PgsOcrImage) -> Result<String>;
+}
+
+#[cfg(feature = "ocr")]
+mod tesseract {
+    use std::{fs, process::Command, sync::atomic::{AtomicUsize, Ordering}};
+
+    use crate::{Error, Result};
+
+    use super::{PgsOcrEngine, PgsOcrImage};
+
+    /// Counter making temporary image names unique within the process.
+    static IMAGE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    /// An OCR engine running the `tesseract` command line tool, which must be installed separately.
+    #[derive(Debug, Clone)]
+    pub struct PgsTesseractEngine {
+        /// Tesseract language code(s), e.g. `eng` or `eng+deu`.
+        pub language: String,
+        /// The tesseract executable.
+        pub command: String
+    }
+
+    impl PgsTesseractEngine {
+        /// Creates an engine for the given language, using `tesseract` from the `PATH`.
+        pub fn new(language: &str) -> Self {
+            PgsTesseractEngine {
+                language: language.to_string(),
+                command: "tesseract".to_string()
+            }
+        }
+    }
+
+    impl PgsOcrEngine for PgsTesseractEngine {
+        fn recognize(&mut self, image: &PgsOcrImage) -> Result<String> {
+            let path = std::env::temp_dir().join(format!("pgs-ocr-{}-{}.pgm", std::process::id(),
+                IMAGE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+            fs::write(&path, image.to_pgm())?;
+
+            // Page segmentation mode 6 treats the image as a single uniform block of text.
+            let output = Command::new(&self.command).arg(&path).arg("stdout").arg("-l").arg(&self.language)
+                .arg("--psm").arg("6").output();
+            let _ = fs::remove_file(&path);
+
+            let output = output?;
+            if !output.status.success() {
+                return Err(Error::OcrFailed(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+    }
+}
+
+#[cfg(feature = "ocr")]
+pub use tesseract::PgsTesseractEngine;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_argb() {
+        let pixels = vec![vec![0x00000000, 0xFFFFFFFF, 0xFF000000, 0x80FFFFFF]];
+        let image = PgsOcrImage::from_argb(&pixels);
+        assert_eq!(image.width, 4);
+        assert_eq!(image.height, 1);
+        // transparent -> white, opaque white -> black, opaque black -> white, half transparent white -> gray
+        assert_eq!(image.data, vec![255, 0, 255, 127]);
+    }
+
+    #[test]
+    fn test_prepare() {
+        // Opaque white fill, a black outline pixel and a transparent pixel
+        let pixels = vec![vec![0xFFFFFFFF, 0xFF000000], vec![0x00000000, 0xFFF0F0F0]];
+        let image = prepare_with_options(&pixels, &PgsOcrPrepareOptions { threshold: 128, border: 1, scale: 2 });
+        assert_eq!((image.width, image.height), (8, 8));
+
+        let rows: Vec<&[u8]> = image.data.chunks(8).collect();
+        assert!(rows[0].iter().chain(rows[1]).all(|&sample| sample == 255));
+        assert_eq!(rows[2], [255, 255, 0, 0, 255, 255, 255, 255]);
+        assert_eq!(rows[3], rows[2]);
+        assert_eq!(rows[4], [255, 255, 255, 255, 0, 0, 255, 255]);
+        assert_eq!(prepare(&pixels).width, (2 + 20) * 3);
+    }
+}
@@ -4,9 +4,7 @@
 //! in the Presentation Graphic Stream (PGS) format. The ODS contains the object data for a subtitle, 
 //! such as the image itself, along with metadata like width, height, and sequence information.
 
-use std::rc::Rc;
-
-use crate::{pgs_memory_buffer::{BigEndian, ReadBytes}, Error, PgsMemoryBuffer, PgsSegmentHeader, Result};
+use crate::{pgs_memory_buffer::{BigEndian, ReadBytes, WriteBytes}, Error, PgsMemoryBuffer, PgsRc, PgsSegmentHeader, Result};
 
 /// Enum representing the sequence flag in an ODS.
 /// The sequence flag indicates whether this segment is part of a sequence, and if it is, 
@@ -37,9 +35,21 @@ impl From<u8> for PgsOdsSequenceFlag {
     }
 }
 
+impl From<PgsOdsSequenceFlag> for u8 {
+    /// Converts a `PgsOdsSequenceFlag` back to its raw `u8` representation.
+    fn from(value: PgsOdsSequenceFlag) -> Self {
+        match value {
+            PgsOdsSequenceFlag::Last => 0x40,
+            PgsOdsSequenceFlag::First => 0x80,
+            PgsOdsSequenceFlag::Both => 0xC0,
+            PgsOdsSequenceFlag::Unknown => 0x00
+        }
+    }
+}
+
 /// Struct representing an Object Definition Segment (ODS) in a PGS file.
 /// The ODS contains the actual image data (subtitle graphics) along with metadata.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PgsOdsSegment {
     pub header: PgsSegmentHeader,
     pub object_id: u16,
@@ -83,12 +93,14 @@ impl PgsOdsSegment {
     ///
     /// # Errors
     /// Returns `Error::InvalidSegmentDataLength` if the length of the provided data is less than the expected length.
+    /// An object data length too small to cover the width and height is read as an object without data, and bytes
+    /// after the data of a single-fragment object are ignored.
     ///
     /// # Returns
-    /// An `Rc<PgsOdsSegment>` containing the parsed segment.
-    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<Rc<PgsOdsSegment>> {
+    /// A `PgsRc<PgsOdsSegment>` containing the parsed segment.
+    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<PgsRc<PgsOdsSegment>> {
         if data.len() < header.segment_length as usize {
-            return Err(Error::InvalidSegmentDataLength);
+            return Err(Error::InvalidSegmentDataLength { length: data.len(), expected: header.segment_length as usize });
         }
 
         let mut segment = PgsOdsSegment::new(header);
@@ -98,21 +110,98 @@ impl PgsOdsSegment {
         segment.object_version_number = buffer.read_u8()?;
         segment.last_in_sequence_flag = PgsOdsSequenceFlag::from(buffer.read_u8()?);
 
-        // Length have different of 4 bytes because w/h
-        segment.object_data_length = buffer.read_u24::<BigEndian>()? - 4;
-
-        let (width, height) = if segment.last_in_sequence_flag == PgsOdsSequenceFlag::First || segment.last_in_sequence_flag == PgsOdsSequenceFlag::Both {
-            let width = buffer.read_u16::<BigEndian>()?;
-            let height = buffer.read_u16::<BigEndian>()?;
-            (width, height)
-        } else {
-            (0, 0)
+        // Only the first fragment of an object carries the data length and the size, continuation fragments hold
+        // object data right after the sequence flag.
+        segment.object_data = match segment.last_in_sequence_flag {
+            PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both => {
+                // Length have different of 4 bytes because w/h. Some encoders write 0 for objects without data.
+                segment.object_data_length = buffer.read_u24::<BigEndian>()?.saturating_sub(4);
+                segment.width = buffer.read_u16::<BigEndian>()?;
+                segment.height = buffer.read_u16::<BigEndian>()?;
+                if segment.last_in_sequence_flag == PgsOdsSequenceFlag::First {
+                    buffer.remaining_slice().to_vec()
+                } else {
+                    buffer.read_into_vec(segment.object_data_length)?
+                }
+            },
+            _ => {
+                segment.object_data_length = buffer.remaining_slice().len() as u32;
+                buffer.remaining_slice().to_vec()
+            }
         };
 
-        segment.width = width;
-        segment.height = height;
-        segment.object_data = buffer.read_into_vec(segment.object_data_length)?;
+        Ok(PgsRc::new(segment))
+    }
 
-        Ok(Rc::new(segment))
+    /// Splits the RLE data of an object into as many ODS fragments as needed to respect the 16-bit segment length.
+    ///
+    /// Every fragment gets a copy of `header`. A single fragment is flagged `Both`, otherwise the fragments are
+    /// flagged `First`, `Unknown` (continuation) and `Last`.
+    ///
+    /// # Parameters
+    /// - `header`: The segment header used for every fragment.
+    /// - `object_id`: The id of the object.
+    /// - `object_version_number`: The version of the object.
+    /// - `width`: The width of the object in pixels.
+    /// - `height`: The height of the object in pixels.
+    /// - `object_data`: The complete RLE data of the object.
+    ///
+    /// # Returns
+    /// The fragments in stream order.
+    pub fn from_object(header: PgsSegmentHeader, object_id: u16, object_version_number: u8, width: u16, height: u16,
+        object_data: &[u8]) -> Vec<PgsOdsSegment> {
+        // The first fragment also holds data length, width and height
+        let first_length = (u16::MAX as usize - 11).min(object_data.len());
+        let mut chunks = vec![&object_data[..first_length]];
+        chunks.extend(object_data[first_length..].chunks(u16::MAX as usize - 4));
+
+        let count = chunks.len();
+        chunks.into_iter().enumerate().map(|(index, chunk)| PgsOdsSegment {
+            header,
+            object_id,
+            object_version_number,
+            last_in_sequence_flag: match (index, count) {
+                (_, 1) => PgsOdsSequenceFlag::Both,
+                (0, _) => PgsOdsSequenceFlag::First,
+                (index, count) if index == count - 1 => PgsOdsSequenceFlag::Last,
+                _ => PgsOdsSequenceFlag::Unknown
+            },
+            object_data_length: if index == 0 { object_data.len() as u32 } else { chunk.len() as u32 },
+            width,
+            height,
+            object_data: chunk.to_vec()
+        }).collect()
     }
-}
\ No newline at end of file
+
+    /// Serializes the segment payload (without the header).
+    ///
+    /// This is the inverse of `from_data`. For a single-fragment object the object data length is derived from
+    /// `object_data`; the first fragment of a longer sequence writes `object_data_length`, which covers all
+    /// fragments. Continuation fragments only write the object data.
+    ///
+    /// # Returns
+    /// The encoded payload bytes.
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = Vec::new();
+        data.write_u16::<BigEndian>(self.object_id)?;
+        data.write_u8(self.object_version_number)?;
+        data.write_u8(self.last_in_sequence_flag.into())?;
+        match self.last_in_sequence_flag {
+            PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both => {
+                let object_data_length = if self.last_in_sequence_flag == PgsOdsSequenceFlag::Both {
+                    self.object_data.len() as u32
+                } else {
+                    self.object_data_length
+                };
+                // Length have different of 4 bytes because w/h
+                data.write_u24::<BigEndian>(object_data_length + 4)?;
+                data.write_u16::<BigEndian>(self.width)?;
+                data.write_u16::<BigEndian>(self.height)?;
+            },
+            _ => {}
+        }
+        data.extend_from_slice(&self.object_data);
+
+        Ok(data)
+    }
+}
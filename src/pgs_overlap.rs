@@ -0,0 +1,145 @@
+//! # PGS Overlap Detection
+//!
+//! This module finds display sets that compose new content over an object of an earlier display set that is still
+//! on screen, because the new display set neither redefines the window of that object nor draws the object again.
+//! Streams authored like this usually miss a clear frame, which some players handle by showing both bitmaps.
+
+use std::collections::HashMap;
+
+use crate::{PgsDisplaySet, PgsPcsCompositionState, PgsPcsObjectCroppedFlag, PgsRectangle};
+
+/// Two display sets showing content in the same window at the same time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsWindowOverlap {
+    /// Index of the display set whose content is still on screen.
+    pub first: usize,
+    /// Index of the display set composing over that content.
+    pub second: usize,
+    /// Window of the second display set's object overlapping the content.
+    pub window_id: u8,
+    /// Presentation timestamp (90 kHz) of the second display set, where the overlap starts.
+    pub presentation_timestamp: u32
+}
+
+/// An object on screen and the display set that composed it.
+struct PgsShownObject {
+    display_set: usize,
+    object_id: u16,
+    window_id: u8,
+    rectangle: PgsRectangle
+}
+
+/// Returns `true` if two rectangles share at least one pixel.
+fn intersects(a: PgsRectangle, b: PgsRectangle) -> bool {
+    let (ax, ay, aw, ah) = (a.0 as u32, a.1 as u32, a.2 as u32, a.3 as u32);
+    let (bx, by, bw, bh) = (b.0 as u32, b.1 as u32, b.2 as u32, b.3 as u32);
+    ax < bx + bw && bx < ax + aw && ay < by + bh && by < ay + ah
+}
+
+/// Finds display sets that compose over an object of an earlier display set that is still on screen.
+///
+/// A display set clears the objects in the windows its WDS defines, and an object drawn again replaces itself, so
+/// replacing one subtitle by the next is not reported. Any other object still on screen conflicts with the new
+/// objects, or their windows, if their rectangles overlap. The rectangle of an object is its cropped size, or the
+/// size of its last definition in the epoch, at its position; objects of unknown size take their window instead.
+/// A display set without composition objects clears the screen, and an Epoch Start resets it. Palette-only updates
+/// (for example fades) keep the objects on screen and are not reported.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+///
+/// # Returns
+/// The overlaps in stream order.
+pub fn find_window_overlaps(display_sets: &[PgsDisplaySet]) -> Vec<PgsWindowOverlap> {
+    let mut overlaps: Vec<PgsWindowOverlap> = Vec::new();
+    let mut shown: Vec<PgsShownObject> = Vec::new();
+    // Size of every object defined in the epoch, and rectangle of every window
+    let mut sizes: HashMap<u16, (u16, u16)> = HashMap::new();
+    let mut windows: HashMap<u8, PgsRectangle> = HashMap::new();
+    for (index, ds) in display_sets.iter().enumerate() {
+        let Some(pcs) = &ds.pcs else {
+            continue;
+        };
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            shown.clear();
+            sizes.clear();
+            windows.clear();
+        }
+        if let Some(ods) = &ds.ods {
+            sizes.insert(ods.object_id, (ods.width, ods.height));
+        }
+        let defined: Vec<u8> = ds.wds.iter().flat_map(|wds| wds.windows.iter()).map(|window| {
+            windows.insert(window.window_id, (window.window_horizontal_position, window.window_vertical_position, window.window_width, window.window_height));
+            window.window_id
+        }).collect();
+        if pcs.palette_update_flag != 0 {
+            continue;
+        }
+        if pcs.composition_objects.is_empty() {
+            shown.clear();
+            continue;
+        }
+
+        let composed: Vec<PgsShownObject> = pcs.composition_objects.iter().filter_map(|obj| {
+            let size = match obj.object_cropped_flag {
+                PgsPcsObjectCroppedFlag::Off => sizes.get(&obj.object_id).copied(),
+                _ => Some((obj.object_cropping_width, obj.object_cropping_height_position))
+            };
+            let rectangle = size.map(|(width, height)| (obj.object_horizontal_position, obj.object_vertical_position, width, height))
+                .or_else(|| windows.get(&obj.window_id).copied())?;
+            Some(PgsShownObject { display_set: index, object_id: obj.object_id, window_id: obj.window_id, rectangle })
+        }).collect();
+        // Objects cleared with their window or drawn again are replaced, the others stay on screen
+        shown.retain(|old| !defined.contains(&old.window_id) && !composed.iter().any(|new| new.object_id == old.object_id));
+        for new in &composed {
+            let window = windows.get(&new.window_id).copied();
+            for old in &shown {
+                let conflict = intersects(old.rectangle, new.rectangle) || window.is_some_and(|window| intersects(old.rectangle, window));
+                let reported = overlaps.iter().any(|overlap| overlap.second == index && overlap.first == old.display_set && overlap.window_id == new.window_id);
+                if conflict && !reported {
+                    overlaps.push(PgsWindowOverlap { first: old.display_set, second: index, window_id: new.window_id, presentation_timestamp: pcs.header.presentation_timestamp });
+                }
+            }
+        }
+        shown.extend(composed);
+    }
+    overlaps
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{composition_object, display_set_of, ods, pcs, wds, window};
+
+    use super::*;
+
+    /// A display set defining the given windows and showing the given objects, the first one defined with its size.
+    fn display_set(state: PgsPcsCompositionState, windows: &[(u8, PgsRectangle)], objects: &[(u16, u8, PgsRectangle)]) -> PgsDisplaySet {
+        let composition_objects = objects.iter().map(|&(object_id, window_id, (x, y, _, _))| composition_object(object_id, window_id, x, y)).collect();
+        display_set_of(
+            pcs(0, state, composition_objects),
+            (!windows.is_empty()).then(|| wds(0, windows.iter().map(|&(window_id, rectangle)| window(window_id, rectangle)).collect())),
+            None,
+            objects.first().map(|&(object_id, _, (_, _, width, height))| ods(0, object_id, 0, width, height, &[]))
+        )
+    }
+
+    #[test]
+    fn test_find_window_overlaps() {
+        let (bottom, top) = ((0, 900, 1920, 100), (0, 0, 1920, 100));
+        let display_sets = [
+            display_set(PgsPcsCompositionState::EpochStart, &[(0, bottom)], &[(0, 0, (100, 900, 800, 50))]),
+            // The next subtitle replaces the first one in the redefined window
+            display_set(PgsPcsCompositionState::Normal, &[(0, bottom)], &[(1, 0, (100, 920, 800, 50))]),
+            // A subtitle at the top leaves the bottom one alone
+            display_set(PgsPcsCompositionState::Normal, &[(1, top)], &[(2, 1, (100, 10, 800, 50))]),
+            // Composed over the bottom subtitle without redefining its window
+            display_set(PgsPcsCompositionState::Normal, &[], &[(3, 0, (500, 950, 100, 20)), (2, 1, (100, 10, 800, 50))]),
+            display_set(PgsPcsCompositionState::Normal, &[], &[]),
+            display_set(PgsPcsCompositionState::Normal, &[], &[(4, 0, (100, 900, 800, 50))]),
+            display_set(PgsPcsCompositionState::EpochStart, &[(0, bottom)], &[(0, 0, (100, 900, 800, 50))])
+        ];
+        let overlaps: Vec<(usize, usize, u8)> = find_window_overlaps(&display_sets).iter()
+            .map(|overlap| (overlap.first, overlap.second, overlap.window_id)).collect();
+        assert_eq!(overlaps, vec![(1, 3, 0)]);
+    }
+}
@@ -0,0 +1,239 @@
+//! # PGS Palette Editing
+//!
+//! This module contains editing passes that only rewrite the palette entries of the PDS segments in a
+//! `PgsStream`. The object bitmaps refer to palette entries by index, so the RLE data is left untouched.
+
+use crate::{Error, PgsPdsSegmentPaletteEntry, PgsRc, PgsSegment, PgsStream, Result};
+
+/// Applies `edit` to every palette entry of every PDS in the stream.
+///
+/// # Returns
+/// The number of PDS segments with at least one modified entry.
+fn edit_palette_entries<F: FnMut(&mut PgsPdsSegmentPaletteEntry)>(stream: &mut PgsStream, mut edit: F) -> usize {
+    let mut modified = 0;
+    for segment in stream.get_segments_mut() {
+        let PgsSegment::Pds(pds) = segment else {
+            continue;
+        };
+        let mut changed = false;
+        let entries: Vec<PgsPdsSegmentPaletteEntry> = pds.palette_entries.iter().map(|entry| {
+            let mut edited = entry.clone();
+            edit(&mut edited);
+            changed |= edited != *entry;
+            edited
+        }).collect();
+        if changed {
+            PgsRc::make_mut(pds).palette_entries = PgsRc::new(entries);
+            modified += 1;
+        }
+    }
+    modified
+}
+
+/// A curve applied to the luminance (Y) of palette entries, leaving their chroma and transparency unchanged.
+///
+/// Luminance is normalized to `0.0..=1.0`, raised to the power `1 / gamma`, stretched around mid grey by
+/// `contrast` and finally multiplied by `brightness`. The identity curve is the `Default`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PgsLuminanceCurve {
+    /// Factor applied last, e.g. `0.75` to dim bright white subtitles on HDR displays.
+    pub brightness: f64,
+    /// Factor by which the distance from mid grey is stretched.
+    pub contrast: f64,
+    /// Gamma correction; values above one brighten mid tones, values below one darken them.
+    pub gamma: f64
+}
+
+impl Default for PgsLuminanceCurve {
+    fn default() -> Self {
+        PgsLuminanceCurve { brightness: 1.0, contrast: 1.0, gamma: 1.0 }
+    }
+}
+
+impl PgsLuminanceCurve {
+    /// Maps a luminance value through the curve.
+    pub fn apply(&self, luminance: u8) -> u8 {
+        let value = (luminance as f64 / 255.0).powf(1.0 / self.gamma);
+        let value = ((value - 0.5) * self.contrast + 0.5) * self.brightness;
+        (value * 255.0).round().clamp(0.0, 255.0) as u8
+    }
+}
+
+/// Applies a luminance curve to every palette entry of the stream, for example to dim overly bright subtitles.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `curve`: The curve to apply.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if a curve parameter is negative or not finite, or the gamma is zero.
+///
+/// # Returns
+/// The number of PDS segments that were modified.
+pub fn adjust_luminance(stream: &mut PgsStream, curve: PgsLuminanceCurve) -> Result<usize> {
+    let valid = |value: f64| value.is_finite() && value >= 0.0;
+    if !valid(curve.brightness) || !valid(curve.contrast) || !valid(curve.gamma) || curve.gamma == 0.0 {
+        return Err(Error::InvalidArgument(format!("invalid luminance curve: brightness {}, contrast {}, gamma {}",
+            curve.brightness, curve.contrast, curve.gamma)));
+    }
+    let table: Vec<u8> = (0..=u8::MAX).map(|luminance| curve.apply(luminance)).collect();
+    Ok(edit_palette_entries(stream, |entry| entry.luminance = table[entry.luminance as usize]))
+}
+
+/// Multiplies the transparency (alpha) of every palette entry by `factor`, for example `0.6` for semi-transparent
+/// subtitles. Factors above one make translucent entries more opaque; fully transparent entries always stay
+/// transparent.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `factor`: The factor applied to the alpha values.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the factor is negative or not finite.
+///
+/// # Returns
+/// The number of PDS segments that were modified.
+pub fn scale_transparency(stream: &mut PgsStream, factor: f64) -> Result<usize> {
+    if !factor.is_finite() || factor < 0.0 {
+        return Err(Error::InvalidArgument(format!("invalid transparency factor {factor}")));
+    }
+    Ok(edit_palette_entries(stream, |entry| {
+        entry.transparency = (entry.transparency as f64 * factor).round().clamp(0.0, 255.0) as u8;
+    }))
+}
+
+/// Converts an RGB color to Y, Cr and Cb, the inverse of the conversion used when decoding bitmaps.
+pub(crate) fn rgb_to_ycrcb((red, green, blue): (u8, u8, u8)) -> [f64; 3] {
+    let (red, green, blue) = (red as f64, green as f64, blue as f64);
+    [
+        0.299 * red + 0.587 * green + 0.114 * blue,
+        128.0 + 0.5 * red - 0.418688 * green - 0.081312 * blue,
+        128.0 - 0.168736 * red - 0.331264 * green + 0.5 * blue
+    ]
+}
+
+/// Target colors of `recolor`, as RGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsColorScheme {
+    /// Color of the letters.
+    pub fill: (u8, u8, u8),
+    /// Color of the border around the letters.
+    pub outline: (u8, u8, u8),
+    /// Color of translucent dark entries such as drop shadows, or `None` to treat them as outline.
+    pub shadow: Option<(u8, u8, u8)>
+}
+
+impl PgsColorScheme {
+    /// Classic yellow letters with a black outline.
+    pub const YELLOW: PgsColorScheme = PgsColorScheme { fill: (255, 255, 0), outline: (0, 0, 0), shadow: None };
+    /// White letters with a black outline.
+    pub const WHITE: PgsColorScheme = PgsColorScheme { fill: (255, 255, 255), outline: (0, 0, 0), shadow: None };
+}
+
+/// Maps the palettes of the stream onto a color scheme, keeping the transparency of every entry.
+///
+/// The visible entries of each PDS are classified by luminance: the brightest entries are the fill and the darkest
+/// the outline, and entries in between, which anti-alias the letter edges, get a color interpolated between the
+/// two. Dark entries less than half as opaque as the most opaque entry of the PDS are classified as shadow. Fully
+/// transparent entries are left untouched.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `scheme`: The target colors.
+///
+/// # Returns
+/// The number of PDS segments that were modified.
+pub fn recolor(stream: &mut PgsStream, scheme: PgsColorScheme) -> usize {
+    let (fill, outline) = (rgb_to_ycrcb(scheme.fill), rgb_to_ycrcb(scheme.outline));
+    let shadow = scheme.shadow.map(rgb_to_ycrcb);
+    let mut modified = 0;
+    for segment in stream.get_segments_mut() {
+        let PgsSegment::Pds(pds) = segment else {
+            continue;
+        };
+        let visible = pds.palette_entries.iter().filter(|entry| entry.transparency > 0);
+        let (darkest, brightest, opaque) = visible.fold((u8::MAX, 0, 0), |(darkest, brightest, opaque), entry| {
+            (darkest.min(entry.luminance), brightest.max(entry.luminance), opaque.max(entry.transparency))
+        });
+        let mut changed = false;
+        let entries: Vec<PgsPdsSegmentPaletteEntry> = pds.palette_entries.iter().map(|entry| {
+            if entry.transparency == 0 {
+                return entry.clone();
+            }
+            let position = match brightest > darkest {
+                true => (entry.luminance - darkest) as f64 / (brightest - darkest) as f64,
+                false => 1.0
+            };
+            let color = match shadow {
+                Some(shadow) if position < 0.5 && (entry.transparency as u16) * 2 < opaque as u16 => shadow,
+                _ => [0, 1, 2].map(|channel| outline[channel] + (fill[channel] - outline[channel]) * position)
+            };
+            let [luminance, color_difference_red, color_difference_blue] = color.map(|value| value.round().clamp(0.0, 255.0) as u8);
+            let edited = PgsPdsSegmentPaletteEntry { luminance, color_difference_red, color_difference_blue, ..entry.clone() };
+            changed |= edited != *entry;
+            edited
+        }).collect();
+        if changed {
+            PgsRc::make_mut(pds).palette_entries = PgsRc::new(entries);
+            modified += 1;
+        }
+    }
+    modified
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{palette_entry, pds};
+
+    use super::*;
+
+    /// A stream with a single PDS holding entries of the given luminance and transparency.
+    fn palette_stream(entries: &[(u8, u8)]) -> PgsStream {
+        let palette_entries = entries.iter().enumerate().map(|(id, &(luminance, transparency))| palette_entry(id as u8, luminance, transparency)).collect();
+        PgsStream::new(vec![PgsSegment::Pds(PgsRc::new(pds(0, 0, palette_entries)))])
+    }
+
+    /// Luminance and transparency of the palette entries of the first PDS.
+    fn entries(stream: &PgsStream) -> Vec<(u8, u8)> {
+        match &stream.get_segments()[0] {
+            PgsSegment::Pds(pds) => pds.palette_entries.iter().map(|entry| (entry.luminance, entry.transparency)).collect(),
+            _ => unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_adjust_luminance() {
+        let mut stream = palette_stream(&[(0, 0), (64, 255), (255, 255)]);
+        assert_eq!(adjust_luminance(&mut stream, PgsLuminanceCurve::default()).unwrap(), 0);
+        assert_eq!(adjust_luminance(&mut stream, PgsLuminanceCurve { brightness: 0.8, ..Default::default() }).unwrap(), 1);
+        assert_eq!(entries(&stream), vec![(0, 0), (51, 255), (204, 255)]);
+        assert_eq!(PgsLuminanceCurve { gamma: 2.0, ..Default::default() }.apply(64), 128);
+        assert_eq!(PgsLuminanceCurve { contrast: 2.0, ..Default::default() }.apply(64), 0);
+        assert!(adjust_luminance(&mut stream, PgsLuminanceCurve { gamma: 0.0, ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn test_scale_transparency() {
+        let mut stream = palette_stream(&[(0, 0), (64, 128), (255, 255)]);
+        assert_eq!(scale_transparency(&mut stream, 0.5).unwrap(), 1);
+        assert_eq!(entries(&stream), vec![(0, 0), (64, 64), (255, 128)]);
+        assert_eq!(scale_transparency(&mut stream, 4.0).unwrap(), 1);
+        assert_eq!(entries(&stream), vec![(0, 0), (64, 255), (255, 255)]);
+        assert!(scale_transparency(&mut stream, -1.0).is_err());
+    }
+
+    #[test]
+    fn test_recolor() {
+        let mut stream = palette_stream(&[(0, 0), (16, 255), (126, 255), (235, 255), (16, 64)]);
+        let scheme = PgsColorScheme { shadow: Some((32, 32, 32)), ..PgsColorScheme::YELLOW };
+        assert_eq!(recolor(&mut stream, scheme), 1);
+        let PgsSegment::Pds(pds) = &stream.get_segments()[0] else {
+            unreachable!()
+        };
+        let colors: Vec<(u8, u8, u8, u8)> = pds.palette_entries.iter()
+            .map(|entry| (entry.luminance, entry.color_difference_red, entry.color_difference_blue, entry.transparency)).collect();
+        // Transparent, outline, anti-aliasing, fill and shadow
+        assert_eq!(colors, vec![(0, 128, 128, 0), (0, 128, 128, 255), (113, 138, 64, 255), (226, 149, 1, 255), (32, 128, 128, 64)]);
+        assert_eq!(recolor(&mut stream, scheme), 0);
+    }
+}
@@ -1,8 +1,184 @@
 //! This module defines the `PgsParser` struct and its associated methods for parsing and handling PGS (Presentation Graphics Stream) files.
 
-use log::{debug, error, trace};
+use std::{collections::{HashMap, HashSet}, fmt::Display, mem::size_of, sync::mpsc::{self, SyncSender}, thread, time::{Duration, Instant}};
 
-use crate::{pgs_reader::PgsReader, pgs_segment::PgsSegment, Error, PgsDisplaySet, PgsFile, PgsOdsSegment, PgsPcsSegment, PgsPdsSegment, PgsSegmentHeader, PgsSegmentType, PgsWdsSegment, Result};
+use log::debug;
+
+use crate::{pgs_export::event_end, pgs_reader::PgsReader, pgs_segment::PgsSegment, pgs_trace::parse_span, PgsCancelToken, PgsDisplaySet, PgsDisplaySetState, PgsFile, PgsIndexedParser, PgsOdsSegment, PgsParseEvent, PgsPcsCompositionState, PgsPdsSegmentPaletteEntry, PgsPushParser, PgsRc, PgsSeek, PgsSegmentArena, PgsVisitFlow, PgsVisitor, PgsStream, Result};
+
+/// Size of the chunks read from the file and pushed into the parser.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Size up to which files are read with a single call and parsed from memory, unless `PgsParserOptions::slurp_limit`
+/// sets another limit.
+pub const PGS_DEFAULT_SLURP_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Options controlling how `PgsParser` handles its input.
+#[derive(Debug, Clone, Default)]
+pub struct PgsParserOptions {
+    /// Skip damaged data instead of failing: when a segment cannot be read, scan forward for the next `PG`
+    /// magic followed by a known segment type and continue from there. Every skipped range is recorded as a
+    /// `PgsSkippedRegion`. A file cut in the middle of its last segment ends the parse with a warning, keeping
+    /// all complete display sets. A PCS following segments that were not ended by an END segment starts a new
+    /// display set, and an END segment is inserted before it. Cropping rectangles that are empty or outside their
+    /// object are clamped to the object.
+    pub lenient: bool,
+    /// Keep segments with an unknown type byte as `PgsSegment::Unknown` instead of failing with
+    /// `Error::UnsupportedSegmentType`. They are not part of any display set.
+    pub keep_unknown_segments: bool,
+    /// Keep the bytes every segment was read from, header included, available through
+    /// `PgsParser::get_raw_segment`, e.g. for forensic tools, exact re-muxing or fixtures cut from real files.
+    pub keep_raw_segments: bool,
+    /// Maximum number of segments to read before failing with `Error::LimitExceeded` (wrapped in `Error::Segment`
+    /// like other segment errors), to bound the memory used by hostile or broken inputs. `None` reads any number
+    /// of segments.
+    pub max_segments: Option<usize>,
+    /// Maximum number of display sets to read before failing with `Error::LimitExceeded`. `None` reads any
+    /// number of display sets.
+    pub max_display_sets: Option<usize>,
+    /// Token checked before every segment; the parse fails with `Error::Cancelled` once it is cancelled.
+    pub cancel: Option<PgsCancelToken>,
+    /// Read the file on a separate thread while parsing, with up to this many chunks read ahead, to hide the
+    /// latency of slow disks and network mounts. `None` reads and parses on the calling thread. Takes precedence
+    /// over `slurp_limit`, so files of any size are read ahead when it is set.
+    pub read_ahead: Option<usize>,
+    /// Files up to this size in bytes are read with a single call and parsed from memory, which is much faster than
+    /// reading them chunk by chunk. `None` uses `PGS_DEFAULT_SLURP_LIMIT`; `Some(0)` always reads in chunks. Ignored
+    /// when `read_ahead` is set.
+    pub slurp_limit: Option<usize>
+}
+
+/// How `PgsParser::drive` reads a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PgsReadMode {
+    /// Chunk by chunk on a separate thread, with up to this many chunks read ahead.
+    ReadAhead(usize),
+    /// With a single call.
+    Slurp,
+    /// Chunk by chunk on the calling thread.
+    Chunks
+}
+
+impl PgsReadMode {
+    /// Chooses how to read a file of `length` bytes: `read_ahead` takes precedence over the slurp limit.
+    fn new(options: &PgsParserOptions, length: usize) -> Self {
+        match options.read_ahead {
+            Some(chunks) => PgsReadMode::ReadAhead(chunks),
+            None if length <= options.slurp_limit.unwrap_or(PGS_DEFAULT_SLURP_LIMIT) => PgsReadMode::Slurp,
+            None => PgsReadMode::Chunks
+        }
+    }
+}
+
+/// A range of the input skipped by a lenient parse because it could not be read as segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsSkippedRegion {
+    /// File offset of the first skipped byte.
+    pub offset: usize,
+    /// Number of skipped bytes.
+    pub length: usize
+}
+
+/// Severity of a problem found while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PgsParseSeverity {
+    /// Data was parsed but is suspicious (e.g. out of order timestamps) or not part of the returned display sets.
+    Warning,
+    /// Data could not be parsed and was skipped, or the file could not be read at all.
+    Error
+}
+
+/// A problem found while parsing, with the file offset it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsParseIssue {
+    pub severity: PgsParseSeverity,
+    /// File offset of the data the issue refers to.
+    pub offset: usize,
+    pub message: String
+}
+
+impl Display for PgsParseIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} at offset {:#x}: {}", self.severity, self.offset, self.message)
+    }
+}
+
+/// Counters collected while parsing a file, e.g. for batch systems logging per-file processing telemetry.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgsParseMetrics {
+    pub pcs_segments: usize,
+    pub wds_segments: usize,
+    pub pds_segments: usize,
+    pub ods_segments: usize,
+    /// END segments, including the ones inserted by a lenient parse.
+    pub end_segments: usize,
+    /// Segments kept with `PgsParserOptions::keep_unknown_segments`.
+    pub unknown_segments: usize,
+    /// Bytes read from the file.
+    pub bytes_read: usize,
+    pub display_sets: usize,
+    /// Number of ranges skipped by a lenient parse, and their total length in bytes.
+    pub skipped_regions: usize,
+    pub skipped_bytes: usize,
+    /// Number of issues by severity.
+    pub warnings: usize,
+    pub errors: usize,
+    /// Time taken to open, read and parse the file.
+    pub wall_time: Duration
+}
+
+impl PgsParseMetrics {
+    /// Returns the number of segments of all types.
+    pub fn segments(&self) -> usize {
+        self.pcs_segments + self.wds_segments + self.pds_segments + self.ods_segments + self.end_segments + self.unknown_segments
+    }
+
+    /// Counts a parse event.
+    fn count(&mut self, event: &PgsParseEvent) {
+        match event {
+            PgsParseEvent::Segment { segment, .. } => match segment {
+                PgsSegment::Pcs(_) => self.pcs_segments += 1,
+                PgsSegment::Wds(_) => self.wds_segments += 1,
+                PgsSegment::Pds(_) => self.pds_segments += 1,
+                PgsSegment::Ods(_) => self.ods_segments += 1,
+                PgsSegment::End(_) => self.end_segments += 1,
+                PgsSegment::Unknown(_) => self.unknown_segments += 1
+            },
+            PgsParseEvent::DisplaySet(_) => self.display_sets += 1,
+            PgsParseEvent::Skipped(region) => {
+                self.skipped_regions += 1;
+                self.skipped_bytes += region.length;
+            },
+            PgsParseEvent::Issue(issue) if issue.severity == PgsParseSeverity::Warning => self.warnings += 1,
+            PgsParseEvent::Issue(_) => self.errors += 1,
+            PgsParseEvent::Error(_) => {}
+        }
+    }
+}
+
+impl Display for PgsParseMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} bytes, {} segments (pcs={} wds={} pds={} ods={} end={} unknown={}), {} display sets, {} skipped regions ({} bytes), {} warnings, {} errors in {:.3}s",
+            self.bytes_read, self.segments(), self.pcs_segments, self.wds_segments, self.pds_segments, self.ods_segments,
+            self.end_segments, self.unknown_segments, self.display_sets, self.skipped_regions, self.skipped_bytes,
+            self.warnings, self.errors, self.wall_time.as_secs_f64())
+    }
+}
+
+/// The outcome of `PgsParser::parse_collect`: everything that could be parsed, and what went wrong.
+#[derive(Debug, Default)]
+pub struct PgsParseResult {
+    pub segments: Vec<PgsSegment>,
+    pub display_sets: Vec<PgsDisplaySet>,
+    pub issues: Vec<PgsParseIssue>
+}
+
+impl PgsParseResult {
+    /// Returns `true` if no issue with `Error` severity was found.
+    pub fn is_clean(&self) -> bool {
+        !self.issues.iter().any(|issue| issue.severity == PgsParseSeverity::Error)
+    }
+}
 
 /// A parser for PGS files.
 ///
@@ -12,11 +188,25 @@ use crate::{pgs_reader::PgsReader, pgs_segment::PgsSegment, Error, PgsDisplaySet
 /// - `sup_file_path`: The path to the SUP file to be parsed.
 /// - `segments`: A vector storing the parsed PGS segments.
 /// - `display_sets`: A vector of display sets created from the parsed segments.
+/// - `options`: The options the file is parsed with.
+/// - `skipped_regions`: The damaged ranges skipped by a lenient parse.
+/// - `issues`: The problems found while parsing.
+/// - `raw_segments`: The bytes of every segment, if the options keep them.
+/// - `metrics`: The counters collected while parsing.
+/// - `object_index`, `palette_index`, `window_index`: The display sets using every object, palette and window id.
 #[derive(Debug)]
 pub struct PgsParser<'a> {
     sup_file_path: &'a str,
     segments: Vec<PgsSegment>,
-    display_sets: Vec<PgsDisplaySet>
+    raw_segments: Vec<Option<Vec<u8>>>,
+    display_sets: Vec<PgsDisplaySet>,
+    options: PgsParserOptions,
+    skipped_regions: Vec<PgsSkippedRegion>,
+    issues: Vec<PgsParseIssue>,
+    metrics: PgsParseMetrics,
+    object_index: HashMap<u16, Vec<usize>>,
+    palette_index: HashMap<u8, Vec<usize>>,
+    window_index: HashMap<u8, Vec<usize>>
 }
 
 impl<'a> PgsParser<'a> {
@@ -27,11 +217,19 @@ impl<'a> PgsParser<'a> {
     ///
     /// # Returns
     /// A new `PgsParser` instance with empty segments and display sets.
-    fn new(sup_file_path: &'a str) -> Self {
+    fn new(sup_file_path: &'a str, options: PgsParserOptions) -> Self {
         PgsParser {
             segments: Vec::new(),
+            raw_segments: Vec::new(),
             display_sets: Vec::new(),
-            sup_file_path
+            sup_file_path,
+            options,
+            skipped_regions: Vec::new(),
+            issues: Vec::new(),
+            metrics: PgsParseMetrics::default(),
+            object_index: HashMap::new(),
+            palette_index: HashMap::new(),
+            window_index: HashMap::new()
         }
     }
 
@@ -43,96 +241,281 @@ impl<'a> PgsParser<'a> {
         self.display_sets.as_ref()
     }
 
-    /// Reads and parses a segment from the given file.
+    /// Returns a reference to the vector of parsed segments, in stream order.
     ///
-    /// # Arguments
-    /// * `file` - A mutable reference to the `PgsFile` from which to read the segment.
+    /// # Returns
+    /// A reference to the `Vec<PgsSegment>`.
+    pub fn get_segments(&self) -> &Vec<PgsSegment> {
+        self.segments.as_ref()
+    }
+
+    /// Returns the subtitle events of the stream: every complete display set with the time it is shown.
+    ///
+    /// An event ends at the next display set that clears the screen (`EmptyFrame`) or shows a new bitmap.
+    /// Display sets that only update the palette do not end it.
+    ///
+    /// # Returns
+    /// An iterator over `(start, end, display_set)` tuples in stream order, with 90 kHz timestamps. `end` is `None`
+    /// for a last event that is never cleared.
+    pub fn events(&self) -> impl Iterator<Item = (u32, Option<u32>, &PgsDisplaySet)> + '_ {
+        self.display_sets.iter().enumerate().filter_map(|(index, ds)| {
+            if ds.state() != PgsDisplaySetState::Complete {
+                return None;
+            }
+            let start = ds.pcs.as_ref()?.header.presentation_timestamp;
+            Some((start, event_end(&self.display_sets, index), ds))
+        })
+    }
+
+    /// Returns the bytes the segment at `index` of `get_segments` was read from: its header followed by its payload,
+    /// exactly as in the file and before any repair of a lenient parse.
     ///
     /// # Returns
-    /// A `Result` containing either a `PgsSegment` or an `Error` if reading or parsing fails.
-    fn read_segment(&mut self, file: &mut PgsFile) -> Result<PgsSegment> {
-        let buffer = file.read_n_bytes::<13>()?;
-        let header = PgsSegmentHeader::from_data(&buffer)?;
-        
-        let pg = header.segment_type;
-        if pg == PgsSegmentType::ERR {
-            return Err(Error::ReadInvalidSegment);
+    /// The raw segment, or `None` if the parser was not created with `PgsParserOptions::keep_raw_segments`, the
+    /// index is out of range, or the segment is an END segment inserted by a lenient parse.
+    pub fn get_raw_segment(&self, index: usize) -> Option<&[u8]> {
+        self.raw_segments.get(index)?.as_deref()
+    }
+
+    /// Returns the ranges of the input skipped by a lenient parse, in file order.
+    ///
+    /// # Returns
+    /// A slice of `PgsSkippedRegion`, empty if the whole file could be parsed.
+    pub fn get_skipped_regions(&self) -> &[PgsSkippedRegion] {
+        &self.skipped_regions
+    }
+
+    /// Returns the problems found while parsing, in file order.
+    ///
+    /// # Returns
+    /// A slice of `PgsParseIssue`, empty if the whole file could be parsed into display sets.
+    pub fn get_issues(&self) -> &[PgsParseIssue] {
+        &self.issues
+    }
+
+    /// Returns the display sets using an object: defining it with an ODS or showing it in their composition.
+    ///
+    /// Object ids are only unique within an epoch, so the display sets may belong to several epochs.
+    ///
+    /// # Returns
+    /// The indices into `get_display_sets` of the display sets, in stream order.
+    pub fn find_by_object_id(&self, object_id: u16) -> &[usize] {
+        self.object_index.get(&object_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the display sets using a palette: defining it with a PDS, or selecting it in a composition that shows
+    /// objects or updates the palette.
+    ///
+    /// Palette ids are only unique within an epoch, so the display sets may belong to several epochs.
+    ///
+    /// # Returns
+    /// The indices into `get_display_sets` of the display sets, in stream order.
+    pub fn find_by_palette_id(&self, palette_id: u8) -> &[usize] {
+        self.palette_index.get(&palette_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the display sets using a window: defining it with a WDS or showing an object in it.
+    ///
+    /// Window ids are only unique within an epoch, so the display sets may belong to several epochs.
+    ///
+    /// # Returns
+    /// The indices into `get_display_sets` of the display sets, in stream order.
+    pub fn display_sets_in_window(&self, window_id: u8) -> &[usize] {
+        self.window_index.get(&window_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds the display set at `index` to the object, palette and window indices.
+    fn index_display_set(&mut self, index: usize, ds: &PgsDisplaySet) {
+        let mut objects: Vec<u16> = ds.ods_segments.iter().chain(&ds.ods).map(|ods| ods.object_id).collect();
+        let mut palettes: Vec<u8> = ds.pds.iter().map(|pds| pds.palette_id).collect();
+        let mut windows: Vec<u8> = ds.wds.iter().flat_map(|wds| wds.windows.iter().map(|window| window.window_id)).collect();
+        if let Some(pcs) = &ds.pcs {
+            // A composition without objects, such as a clear, selects a palette without using it
+            if !pcs.composition_objects.is_empty() || pcs.palette_update_flag != 0 {
+                palettes.push(pcs.palette_id);
+            }
+            objects.extend(pcs.composition_objects.iter().map(|obj| obj.object_id));
+            windows.extend(pcs.composition_objects.iter().map(|obj| obj.window_id));
         }
-    
-        if header.segment_type == PgsSegmentType::END {
-            return Ok(PgsSegment::End);
+        Self::index_ids(objects, index, &mut self.object_index);
+        Self::index_ids(palettes, index, &mut self.palette_index);
+        Self::index_ids(windows, index, &mut self.window_index);
+    }
+
+    /// Records the display set at `index` once for every id in `ids`.
+    fn index_ids<K: Copy + Ord + std::hash::Hash>(mut ids: Vec<K>, index: usize, map: &mut HashMap<K, Vec<usize>>) {
+        ids.sort_unstable();
+        ids.dedup();
+        for id in ids {
+            map.entry(id).or_default().push(index);
         }
+    }
 
-        let mut buffer = vec![0; header.segment_length as usize];
-        file.read_bytes(buffer.as_mut_slice())?;
-    
-        let segment = match header.segment_type {
-            PgsSegmentType::PCS => {            
-                PgsSegment::Pcs(PgsPcsSegment::from_data(header, &buffer)?)
-            },
-            PgsSegmentType::WDS => {
-                PgsSegment::Wds(PgsWdsSegment::from_data(header, &buffer)?)
-            },
-            PgsSegmentType::PDS => {
-                PgsSegment::Pds(PgsPdsSegment::from_data(header, &buffer)?)
-            },
-            PgsSegmentType::ODS => {
-                PgsSegment::Ods(PgsOdsSegment::from_data(header, &buffer)?)
-            },
-            _ => PgsSegment::End
-        };
-    
-        Ok(segment)
+    /// Returns the counters collected while parsing: segments by type, bytes read, display sets, skipped data,
+    /// issues and the time taken.
+    ///
+    /// # Returns
+    /// A reference to the `PgsParseMetrics`.
+    pub fn metrics(&self) -> &PgsParseMetrics {
+        &self.metrics
     }
-    
-    /// Parses the PGS file and reads all segments.
+
+    /// Returns an estimate of the memory held by the parser in bytes: the segments, the display sets sharing them,
+    /// the recorded issues, the kept raw segments and the id indices. Allocations shared between segments, such as
+    /// interned palettes, are counted once.
     ///
-    /// This method continuously reads segments from the file until the end of the file is reached.
+    /// Decoded bitmaps are not included; see `PgsDisplaySet::decoded_size_bytes` for the memory they need.
     ///
     /// # Returns
-    /// A `Result` indicating success or failure of the parsing process.    
-    fn parse_inner(&mut self) -> Result<()> {
-        let mut file = PgsReader::open(self.sup_file_path)?;
+    /// The estimated number of bytes.
+    pub fn estimated_memory(&self) -> usize {
+        let mut palettes = HashSet::new();
+        let segments: usize = self.segments.iter().map(|segment| {
+            let palette = match segment {
+                PgsSegment::Pds(pds) if palettes.insert(PgsRc::as_ptr(&pds.palette_entries)) =>
+                    2 * size_of::<usize>() + size_of::<Vec<PgsPdsSegmentPaletteEntry>>()
+                        + pds.palette_entries.capacity() * size_of::<PgsPdsSegmentPaletteEntry>(),
+                _ => 0
+            };
+            segment.heap_size() + palette
+        }).sum();
+        let issues: usize = self.issues.iter().map(|issue| issue.message.capacity()).sum();
+        let raw_segments: usize = self.raw_segments.iter().flatten().map(|raw| raw.capacity()).sum();
+        let ods_segments: usize = self.display_sets.iter().map(|ds| ds.ods_segments.capacity() * size_of::<PgsRc<PgsOdsSegment>>()).sum();
+        let indices: usize = self.object_index.values().chain(self.palette_index.values()).chain(self.window_index.values())
+            .map(|display_sets| size_of::<Vec<usize>>() + display_sets.capacity() * size_of::<usize>()).sum();
+        size_of::<Self>() + segments
+            + self.segments.capacity() * size_of::<PgsSegment>()
+            + self.raw_segments.capacity() * size_of::<Option<Vec<u8>>>() + raw_segments
+            + self.display_sets.capacity() * size_of::<PgsDisplaySet>() + ods_segments
+            + self.skipped_regions.capacity() * size_of::<PgsSkippedRegion>()
+            + self.issues.capacity() * size_of::<PgsParseIssue>() + issues + indices
+    }
+
+    /// Consumes the parser and returns its segments as an editable `PgsStream`.
+    ///
+    /// # Returns
+    /// A `PgsStream` containing all parsed segments.
+    pub fn into_stream(self) -> PgsStream {
+        PgsStream::new(self.segments)
+    }
+
+    /// Reads the next chunk of a file.
+    ///
+    /// # Returns
+    /// The chunk, or `None` at the end of the file.
+    fn read_chunk(file: &mut PgsFile) -> Result<Option<Vec<u8>>> {
+        if file.is_eof()? {
+            return Ok(None);
+        }
+        let mut chunk = vec![0; READ_CHUNK_SIZE.min(file.len()? - file.pos()?)];
+        file.read_bytes(&mut chunk)?;
+        Ok(Some(chunk))
+    }
+
+    /// Reads a file chunk by chunk into `sender`, until the end of the file, a read error, or the receiving parser
+    /// stopping.
+    fn read_ahead(mut file: PgsFile, sender: SyncSender<Result<Vec<u8>>>) {
+        while let Some(chunk) = Self::read_chunk(&mut file).transpose() {
+            let failed = chunk.is_err();
+            if sender.send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    }
+
+    /// Pushes a PGS file through a `PgsPushParser` chunk by chunk, handing every event to `handle` until it
+    /// returns `false`. With `read_ahead` set in the options, the file is read on a separate thread; otherwise files
+    /// up to the slurp limit of the options are pushed in one piece.
+    ///
+    /// # Returns
+    /// A `Result` containing either the number of bytes read from the file or an `Error` if the parsing fails.
+    fn drive<F: FnMut(PgsParseEvent) -> Result<bool>>(sup_file_path: &str, options: PgsParserOptions, mut handle: F) -> Result<usize> {
+        let mut file = PgsReader::open(sup_file_path)?;
         debug!("{:?}", file);
-    
-        loop {
-            match self.read_segment(&mut file) {
-                Ok(segment) => {
-                    trace!("{:?}", segment);
-                    self.segments.push(segment);
-                    if file.is_eof()? {
-                        return Ok(());
-                    }
+        let _span = parse_span(sup_file_path, file.len()?);
+
+        let mode = PgsReadMode::new(&options, file.len()?);
+        let mut parser = PgsPushParser::new(options);
+        let mut read = 0;
+        // Parses the next chunk, or finishes the parse at the end of the file
+        let mut feed = |chunk: Option<Vec<u8>>| -> Result<bool> {
+            let events = match chunk {
+                Some(chunk) => {
+                    read += chunk.len();
+                    parser.push_vec(chunk)
                 },
-                Err(error) => {
-                    error!("{:?}", error);
-                    return Err(error)                   
+                None => parser.finish()
+            };
+            for event in events {
+                if !handle(event)? {
+                    return Ok(false);
                 }
             }
+            Ok(true)
+        };
+
+        let completed = match mode {
+            PgsReadMode::Slurp => {
+                let mut data = vec![0; file.len()? - file.pos()?];
+                file.read_bytes(&mut data)?;
+                feed(Some(data))?
+            },
+            PgsReadMode::Chunks => loop {
+                match Self::read_chunk(&mut file)? {
+                    Some(chunk) => if !feed(Some(chunk))? {
+                        break false;
+                    },
+                    None => break true
+                }
+            },
+            PgsReadMode::ReadAhead(chunks) => thread::scope(|scope| -> Result<bool> {
+                let (sender, receiver) = mpsc::sync_channel(chunks.max(1));
+                scope.spawn(move || Self::read_ahead(file, sender));
+                // Returning drops the receiver, which stops the reader thread
+                for chunk in receiver {
+                    if !feed(Some(chunk?))? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            })?
+        };
+        if completed {
+            feed(None)?;
         }
+        Ok(read)
     }
 
-    /// Creates display sets from the parsed segments.
-    ///
-    /// This method processes the segments to construct display sets and adds them to the `display_sets` vector.
+    /// Parses the PGS file, collecting the segments, display sets, skipped regions and issues.
     ///
     /// # Returns
-    /// A `Result` indicating success or failure of the display set creation process.
-    fn create_display_sets(&mut self) -> Result<()> {
-        let mut ds = PgsDisplaySet::new();
-        self.segments.iter().for_each(|segment| {
-            match segment {
-                PgsSegment::Pcs(pcs) => ds.pcs = Some(pcs.clone()),
-                PgsSegment::Wds(wds) => ds.wds = Some(wds.clone()),
-                PgsSegment::Ods(ods) => ds.ods = Some(ods.clone()),
-                PgsSegment::Pds(pds) => ds.pds = Some(pds.clone()),
-                PgsSegment::End => {
-                    self.display_sets.push(ds.clone());
-                    ds.clean();
-                }
+    /// A `Result` indicating success or failure of the parsing process.
+    fn parse_inner(&mut self) -> Result<()> {
+        let (sup_file_path, options) = (self.sup_file_path, self.options.clone());
+        let started = Instant::now();
+        self.metrics = PgsParseMetrics::default();
+        let result = Self::drive(sup_file_path, options, |event| {
+            self.metrics.count(&event);
+            match event {
+                PgsParseEvent::Segment { segment, raw, .. } => {
+                    self.segments.push(segment);
+                    if self.options.keep_raw_segments {
+                        self.raw_segments.push(raw);
+                    }
+                },
+                PgsParseEvent::DisplaySet(display_set) => {
+                    self.index_display_set(self.display_sets.len(), &display_set);
+                    self.display_sets.push(display_set);
+                },
+                PgsParseEvent::Skipped(region) => self.skipped_regions.push(region),
+                PgsParseEvent::Issue(issue) => self.issues.push(issue),
+                PgsParseEvent::Error(error) => return Err(error)
             }
+            Ok(true)
         });
-
+        self.metrics.wall_time = started.elapsed();
+        self.metrics.bytes_read = result?;
         Ok(())
     }
 
@@ -144,9 +527,432 @@ impl<'a> PgsParser<'a> {
     /// # Returns
     /// A `Result` containing either the `PgsParser` instance or an `Error` if the parsing fails.
     pub fn parse(sup_file_path: &'a str) -> Result<PgsParser<'a>> {
-        let mut parser = PgsParser::new(sup_file_path);
+        PgsParser::parse_with_options(sup_file_path, PgsParserOptions::default())
+    }
+
+    /// Indexes a PGS file by scanning only its segment headers, for random access to the display set shown at a
+    /// given time without parsing the whole file.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path to the SUP file to be indexed.
+    ///
+    /// # Returns
+    /// A `Result` containing either the `PgsIndexedParser` or an `Error` if a segment header cannot be read.
+    pub fn open_indexed(sup_file_path: &'a str) -> Result<PgsIndexedParser<'a>> {
+        PgsIndexedParser::open(sup_file_path)
+    }
+
+    /// Parses a PGS file with the given options and creates display sets.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path to the SUP file to be parsed.
+    /// * `options` - The `PgsParserOptions`, e.g. to enable lenient parsing of damaged files.
+    ///
+    /// # Returns
+    /// A `Result` containing either the `PgsParser` instance or an `Error` if the parsing fails.
+    pub fn parse_with_options(sup_file_path: &'a str, options: PgsParserOptions) -> Result<PgsParser<'a>> {
+        let mut parser = PgsParser::new(sup_file_path, options);
         parser.parse_inner()?;
-        parser.create_display_sets()?;
         Ok(parser)
     }
-}
\ No newline at end of file
+
+    /// Parses a PGS file and hands every segment, display set and issue to a visitor as soon as it is read, without
+    /// keeping them. The parse stops early when a callback returns `PgsVisitFlow::Stop`.
+    ///
+    /// The segments of a display set are visited when its END segment is read, right before the display set.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path to the SUP file to be parsed.
+    /// * `visitor` - The `PgsVisitor` receiving the callbacks.
+    ///
+    /// # Returns
+    /// A `Result` indicating success or failure of the parsing process. Stopping early is a success.
+    pub fn parse_with_visitor<V: PgsVisitor>(sup_file_path: &str, visitor: &mut V) -> Result<()> {
+        PgsParser::parse_with_visitor_options(sup_file_path, PgsParserOptions::default(), visitor)
+    }
+
+    /// Parses a PGS file with the given options and hands every segment, display set and issue to a visitor, like
+    /// `parse_with_visitor`.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path to the SUP file to be parsed.
+    /// * `options` - The `PgsParserOptions`, e.g. to enable lenient parsing of damaged files.
+    /// * `visitor` - The `PgsVisitor` receiving the callbacks.
+    ///
+    /// # Returns
+    /// A `Result` indicating success or failure of the parsing process. Stopping early is a success.
+    pub fn parse_with_visitor_options<V: PgsVisitor>(sup_file_path: &str, options: PgsParserOptions, visitor: &mut V) -> Result<()> {
+        PgsParser::drive(sup_file_path, options, |event| {
+            let flow = match event {
+                PgsParseEvent::Segment { offset, segment, .. } => visitor.on_segment(offset, &segment),
+                PgsParseEvent::DisplaySet(display_set) => {
+                    let epoch_start = display_set.pcs.as_ref()
+                        .is_some_and(|pcs| pcs.composition_state == PgsPcsCompositionState::EpochStart);
+                    if epoch_start && visitor.on_epoch_start(&display_set) == PgsVisitFlow::Stop {
+                        PgsVisitFlow::Stop
+                    } else {
+                        visitor.on_display_set(&display_set)
+                    }
+                },
+                PgsParseEvent::Issue(issue) => visitor.on_issue(&issue),
+                PgsParseEvent::Skipped(_) => PgsVisitFlow::Continue,
+                PgsParseEvent::Error(error) => return Err(error)
+            };
+            Ok(flow == PgsVisitFlow::Continue)
+        })?;
+        Ok(())
+    }
+
+    /// Parses a PGS file into a `PgsSegmentArena`, which stores all segments in one buffer instead of one
+    /// allocation per segment, for analyses over very large streams.
+    ///
+    /// Issues and skipped regions are not collected; use `parse_with_visitor` to inspect them.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path to the SUP file to be parsed.
+    /// * `options` - The `PgsParserOptions`, e.g. to enable lenient parsing of damaged files.
+    ///
+    /// # Returns
+    /// A `Result` containing either the `PgsSegmentArena` or an `Error` if the parsing fails.
+    pub fn parse_into_arena(sup_file_path: &str, options: PgsParserOptions) -> Result<PgsSegmentArena> {
+        let mut arena = PgsSegmentArena::new();
+        PgsParser::drive(sup_file_path, options, |event| {
+            match event {
+                PgsParseEvent::Segment { segment, .. } => arena.push(&segment)?,
+                PgsParseEvent::Error(error) => return Err(error),
+                _ => {}
+            }
+            Ok(true)
+        })?;
+        Ok(arena)
+    }
+
+    /// Parses a PGS file leniently and never fails, collecting every problem instead.
+    ///
+    /// This is intended for batch tools triaging many files: errors that would abort `parse`, including a file
+    /// that cannot be opened, are returned as issues next to whatever could be parsed.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path to the SUP file to be parsed.
+    ///
+    /// # Returns
+    /// A `PgsParseResult` with the parsed segments, display sets and issues.
+    pub fn parse_collect(sup_file_path: &'a str) -> PgsParseResult {
+        let mut parser = PgsParser::new(sup_file_path, PgsParserOptions { lenient: true, ..Default::default() });
+        if let Err(error) = parser.parse_inner() {
+            parser.issues.push(PgsParseIssue { severity: PgsParseSeverity::Error, offset: error.offset().unwrap_or(0), message: error.to_string() });
+        }
+        PgsParseResult {
+            segments: parser.segments,
+            display_sets: parser.display_sets,
+            issues: parser.issues
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH, pgs_test_support::{display_set_bytes, ods, TempSupFile}, Error};
+
+    use super::*;
+
+    #[test]
+    fn test_lenient_parse() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        let damaged = data.len();
+        data.extend([0x50, 0x47, 0xFF, 0x12, 0x34]);
+        data.extend(display_set_bytes(0, 1, 0x80));
+
+        let file = TempSupFile::new("parser", &data);
+        let path = file.path();
+
+        assert!(PgsParser::parse(path).is_err());
+        let parser = PgsParser::parse_with_options(path, PgsParserOptions { lenient: true, ..Default::default() }).unwrap();
+
+        assert_eq!(parser.get_display_sets().len(), 2);
+        assert_eq!(parser.get_skipped_regions(), [PgsSkippedRegion { offset: damaged, length: 5 }]);
+    }
+
+    #[test]
+    fn test_parse_collect() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        data.extend([0x50, 0x47, 0xFF]);
+        let trailing = data.len();
+        data.extend(&display_set_bytes(0, 1, 0x80)[..24]);
+
+        let file = TempSupFile::new("parser-collect", &data);
+        let result = PgsParser::parse_collect(file.path());
+
+        assert_eq!(result.display_sets.len(), 1);
+        assert_eq!(result.issues.iter().map(|issue| (issue.severity, issue.offset)).collect::<Vec<_>>(),
+            [(PgsParseSeverity::Error, trailing - 3), (PgsParseSeverity::Warning, trailing)]);
+        assert!(!result.is_clean());
+
+        let result = PgsParser::parse_collect("missing.sup");
+        assert_eq!(result.issues.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_segments() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        data.splice(24..24, [0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x99, 0, 2, 0xAB, 0xCD]);
+
+        let file = TempSupFile::new("parser-unknown", &data);
+        let path = file.path();
+
+        assert!(matches!(PgsParser::parse(path), Err(Error::Segment { offset: 24, segment_type: Some(0x99), error })
+            if matches!(*error, Error::UnsupportedSegmentType(0x99))));
+        let parser = PgsParser::parse_with_options(path, PgsParserOptions { keep_unknown_segments: true, ..Default::default() }).unwrap();
+
+        assert_eq!(parser.get_display_sets().len(), 1);
+        let PgsSegment::Unknown(unknown) = &parser.get_segments()[1] else { panic!("Expected unknown segment") };
+        assert_eq!((unknown.segment_type, unknown.data.as_slice()), (0x99, [0xAB, 0xCD].as_slice()));
+
+        let mut writer = crate::PgsWriter::new(Vec::new());
+        writer.write_segments(parser.get_segments()).unwrap();
+        assert_eq!(writer.into_inner(), data);
+    }
+
+    #[test]
+    fn test_truncated_parse() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        let truncated = data.len();
+        data.extend(&display_set_bytes(0, 1, 0x80)[..20]);
+
+        let file = TempSupFile::new("parser-truncated", &data);
+        let path = file.path();
+
+        assert!(PgsParser::parse(path).is_err());
+        let parser = PgsParser::parse_with_options(path, PgsParserOptions { lenient: true, ..Default::default() }).unwrap();
+
+        assert_eq!(parser.get_display_sets().len(), 1);
+        assert!(parser.get_skipped_regions().is_empty());
+        assert_eq!(parser.get_issues().iter().map(|issue| (issue.severity, issue.offset)).collect::<Vec<_>>(),
+            [(PgsParseSeverity::Warning, truncated)]);
+    }
+
+    #[test]
+    fn test_missing_end() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        data.truncate(data.len() - PGS_SEGMENT_HEADER_LENGTH);
+        let missing = data.len();
+        data.extend(display_set_bytes(0, 1, 0x80));
+
+        let file = TempSupFile::new("parser-missing-end", &data);
+        let path = file.path();
+
+        assert_eq!(PgsParser::parse(path).unwrap().get_display_sets().len(), 1);
+        let parser = PgsParser::parse_with_options(path, PgsParserOptions { lenient: true, ..Default::default() }).unwrap();
+
+        assert_eq!(parser.get_display_sets().len(), 2);
+        assert_eq!(parser.get_segments().len(), 4);
+        assert_eq!(parser.get_issues().iter().map(|issue| (issue.severity, issue.offset)).collect::<Vec<_>>(),
+            [(PgsParseSeverity::Warning, missing)]);
+    }
+
+    #[test]
+    fn test_raw_segments() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        data.truncate(data.len() - PGS_SEGMENT_HEADER_LENGTH);
+        data.extend(display_set_bytes(0, 1, 0x80));
+
+        let file = TempSupFile::new("parser-raw", &data);
+        let path = file.path();
+
+        assert_eq!(PgsParser::parse(path).unwrap().get_raw_segment(0), None);
+        let parser = PgsParser::parse_with_options(path, PgsParserOptions { lenient: true, keep_raw_segments: true, ..Default::default() }).unwrap();
+
+        // The END segment inserted before the second PCS was never in the file
+        let pcs_length = display_set_bytes(0, 0, 0x80).len() - PGS_SEGMENT_HEADER_LENGTH;
+        assert_eq!(parser.get_raw_segment(0), Some(&data[..pcs_length]));
+        assert_eq!(parser.get_raw_segment(1), None);
+        assert_eq!(parser.get_raw_segment(3), Some(&data[data.len() - PGS_SEGMENT_HEADER_LENGTH..]));
+        assert_eq!(parser.get_raw_segment(4), None);
+    }
+
+    #[test]
+    fn test_empty_object_data() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        let ods = data.len() - PGS_SEGMENT_HEADER_LENGTH;
+        // Single-fragment object declaring no data, followed by two padding bytes
+        let segment = [0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x15, 0, 13, 0, 1, 0, 0xC0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.splice(ods..ods, segment);
+
+        let file = TempSupFile::new("parser-empty-object", &data);
+        let path = file.path();
+        let parser = PgsParser::parse(path).unwrap();
+
+        let messages: Vec<(usize, &str)> = parser.get_issues().iter().map(|issue| (issue.offset, issue.message.as_str())).collect();
+        assert_eq!(messages, [
+            (ods, "ODS fragment of object 1 has no object data"),
+            (ods, "2 padding bytes after the data of object 1 ignored")
+        ]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let data = [display_set_bytes(0, 0, 0x80), display_set_bytes(0, 1, 0x80), display_set_bytes(0, 2, 0x80)].concat();
+        let file = TempSupFile::new("parser-limits", &data);
+        let path = file.path();
+
+        let limits = |max_segments, max_display_sets| PgsParserOptions { lenient: true, max_segments, max_display_sets, ..Default::default() };
+        assert!(PgsParser::parse_with_options(path, limits(Some(6), Some(3))).is_ok());
+        // Both limits are exceeded by the END segment of the third display set
+        assert!(matches!(PgsParser::parse_with_options(path, limits(Some(5), None)),
+            Err(Error::Segment { offset: 98, segment_type: Some(0x80), error }) if matches!(*error, Error::LimitExceeded { limit: "max_segments", value: 5 })));
+        assert!(matches!(PgsParser::parse_with_options(path, limits(None, Some(2))),
+            Err(Error::Segment { offset: 98, segment_type: Some(0x80), error }) if matches!(*error, Error::LimitExceeded { limit: "max_display_sets", value: 2 })));
+    }
+
+    #[test]
+    fn test_parse_with_visitor() {
+        /// Records visited segment offsets and display sets, stopping at the second Epoch Start.
+        #[derive(Default)]
+        struct Recorder {
+            offsets: Vec<usize>,
+            display_sets: usize,
+            epoch_starts: usize
+        }
+
+        impl PgsVisitor for Recorder {
+            fn on_segment(&mut self, offset: usize, _segment: &PgsSegment) -> PgsVisitFlow {
+                self.offsets.push(offset);
+                PgsVisitFlow::Continue
+            }
+
+            fn on_display_set(&mut self, _display_set: &PgsDisplaySet) -> PgsVisitFlow {
+                self.display_sets += 1;
+                PgsVisitFlow::Continue
+            }
+
+            fn on_epoch_start(&mut self, _display_set: &PgsDisplaySet) -> PgsVisitFlow {
+                self.epoch_starts += 1;
+                if self.epoch_starts == 2 { PgsVisitFlow::Stop } else { PgsVisitFlow::Continue }
+            }
+        }
+
+        let data = [display_set_bytes(0, 0, 0x80), display_set_bytes(0, 1, 0x80), display_set_bytes(0, 2, 0x80)].concat();
+        let file = TempSupFile::new("parser-visitor", &data);
+        let mut recorder = Recorder::default();
+        PgsParser::parse_with_visitor(file.path(), &mut recorder).unwrap();
+
+        assert_eq!(recorder.offsets, [0, 24, 37, 61]);
+        assert_eq!((recorder.display_sets, recorder.epoch_starts), (1, 2));
+
+        // The options apply to the visited parse, so the limit stops it before the second display set completes
+        let mut recorder = Recorder::default();
+        let options = PgsParserOptions { max_display_sets: Some(1), ..Default::default() };
+        assert!(matches!(PgsParser::parse_with_visitor_options(file.path(), options, &mut recorder),
+            Err(Error::Segment { offset: 61, error, .. }) if matches!(*error, Error::LimitExceeded { limit: "max_display_sets", value: 1 })));
+        assert_eq!(recorder.offsets, [0, 24, 37]);
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let mut data = [display_set_bytes(0, 0, 0x80), display_set_bytes(0, 1, 0x80)].concat();
+        data.extend([0x50, 0x47, 0xFF]);
+        data.extend(display_set_bytes(0, 2, 0x80));
+        let file = TempSupFile::new("parser-read-ahead", &data);
+        let path = file.path();
+
+        let options = PgsParserOptions { lenient: true, read_ahead: Some(2), slurp_limit: Some(0), ..Default::default() };
+        let parser = PgsParser::parse_with_options(path, options).unwrap();
+        let mut offsets: Vec<usize> = Vec::new();
+        PgsParser::drive(path, PgsParserOptions { read_ahead: Some(1), slurp_limit: Some(0), ..Default::default() }, |event| {
+            if let PgsParseEvent::Segment { offset, .. } = event {
+                offsets.push(offset);
+            }
+            Ok(offsets.len() < 3)
+        }).unwrap();
+
+        assert_eq!(parser.get_display_sets().len(), 3);
+        assert_eq!(parser.get_skipped_regions(), [PgsSkippedRegion { offset: 74, length: 3 }]);
+        assert_eq!(offsets, [0, 24, 37]);
+    }
+
+    #[test]
+    fn test_slurp() {
+        let mut data = [display_set_bytes(0, 0, 0x80), display_set_bytes(0, 1, 0x80)].concat();
+        data.extend([0x50, 0x47, 0xFF]);
+        data.extend(display_set_bytes(0, 2, 0x80));
+        data.extend(&display_set_bytes(0, 3, 0x80)[..20]);
+        let file = TempSupFile::new("parser-slurp", &data);
+        let path = file.path();
+
+        let summary = |slurp_limit| {
+            let parser = PgsParser::parse_with_options(path, PgsParserOptions { lenient: true, slurp_limit, ..Default::default() }).unwrap();
+            (parser.get_segments().len(), parser.get_display_sets().len(), parser.get_issues().to_vec())
+        };
+        let (slurped, chunked) = (summary(None), summary(Some(0)));
+
+        assert_eq!(slurped, chunked);
+        assert_eq!((slurped.0, slurped.1, slurped.2.len()), (6, 3, 2));
+    }
+
+    #[test]
+    fn test_read_mode() {
+        let options = |read_ahead, slurp_limit| PgsParserOptions { read_ahead, slurp_limit, ..Default::default() };
+        assert_eq!(PgsReadMode::new(&options(None, None), 1024), PgsReadMode::Slurp);
+        assert_eq!(PgsReadMode::new(&options(None, Some(0)), 1024), PgsReadMode::Chunks);
+        // Read-ahead is not ignored for files below the slurp limit
+        assert_eq!(PgsReadMode::new(&options(Some(4), None), 1024), PgsReadMode::ReadAhead(4));
+        assert_eq!(PgsReadMode::new(&options(Some(4), Some(usize::MAX)), 1024), PgsReadMode::ReadAhead(4));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut data = [display_set_bytes(0, 0, 0x80), display_set_bytes(0, 1, 0x80)].concat();
+        data.extend([0x50, 0x47, 0xFF]);
+        data.extend(display_set_bytes(0, 2, 0x80));
+        data.extend(&display_set_bytes(0, 3, 0x80)[..20]);
+        let file = TempSupFile::new("parser-metrics", &data);
+        let path = file.path();
+        let parser = PgsParser::parse_with_options(path, PgsParserOptions { lenient: true, ..Default::default() }).unwrap();
+
+        let metrics = parser.metrics();
+        assert_eq!((metrics.pcs_segments, metrics.end_segments, metrics.segments()), (3, 3, 6));
+        assert_eq!((metrics.bytes_read, metrics.display_sets), (data.len(), 3));
+        assert_eq!((metrics.skipped_regions, metrics.skipped_bytes, metrics.warnings, metrics.errors), (1, 3, 1, 1));
+        assert!(metrics.to_string().starts_with(&format!("{} bytes, 6 segments (pcs=3 wds=0 pds=0 ods=0 end=3 unknown=0), 3 display sets", data.len())));
+    }
+
+    #[test]
+    fn test_find_by_id() {
+        // Object 3 shown in window 1 with palette 2 and object 4 defined before it, then a display set clearing the
+        // screen with palette 0 and a palette update of palette 0
+        let mut data = vec![0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x16, 0, 19];
+        data.extend([0x07, 0x80, 0x04, 0x38, 0x10, 0, 0, 0x80, 0, 2, 1, 0, 3, 1, 0, 0, 0x10, 0, 0x20]);
+        data.extend([0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x17, 0, 10, 1, 1, 0, 0, 0, 0, 0, 0x10, 0, 0x10]);
+        data.extend([0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x14, 0, 7, 2, 0, 1, 0xEB, 0x80, 0x80, 0xFF]);
+        data.extend([0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x15, 0, 14, 0, 4, 0, 0xC0, 0, 0, 7, 0, 1, 0, 1, 1, 0, 0]);
+        data.extend([0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x15, 0, 14, 0, 3, 0, 0xC0, 0, 0, 7, 0, 1, 0, 1, 1, 0, 0]);
+        data.extend([0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0, 0]);
+        data.extend(display_set_bytes(0, 1, 0x80));
+        data.extend([0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x16, 0, 11, 0x07, 0x80, 0x04, 0x38, 0x10, 0, 2, 0, 0x80, 0, 0]);
+        data.extend([0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0, 0]);
+        let file = TempSupFile::new("parser-find", &data);
+        let path = file.path();
+        let parser = PgsParser::parse(path).unwrap();
+
+        assert_eq!(parser.get_display_sets().len(), 3);
+        assert_eq!((parser.find_by_object_id(3), parser.find_by_object_id(4)), ([0].as_slice(), [0].as_slice()));
+        assert_eq!((parser.find_by_palette_id(2), parser.find_by_palette_id(0)), ([0].as_slice(), [2].as_slice()));
+        assert_eq!(parser.display_sets_in_window(1), [0]);
+        assert!(parser.find_by_object_id(7).is_empty());
+    }
+
+    #[test]
+    fn test_estimated_memory() {
+        let file = TempSupFile::new("parser-memory", &display_set_bytes(0, 0, 0x80));
+        let single = PgsParser::parse(file.path()).unwrap().estimated_memory();
+        file.write(&[display_set_bytes(0, 0, 0x80), display_set_bytes(0, 1, 0x80), display_set_bytes(0, 2, 0x80)].concat());
+        let parser = PgsParser::parse(file.path()).unwrap();
+
+        assert!(single > size_of::<PgsParser>());
+        assert!(parser.estimated_memory() > single);
+        assert_eq!(parser.get_display_sets()[0].decoded_size_bytes(), 0);
+
+        let display_set = PgsDisplaySet { ods: Some(PgsRc::new(ods(0, 0, 0, 4, 2, &[]))), ..PgsDisplaySet::new() };
+        assert_eq!(display_set.decoded_size_bytes(), size_of::<Vec<Vec<u32>>>() + 2 * (size_of::<Vec<u32>>() + 16));
+    }
+}
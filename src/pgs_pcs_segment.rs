@@ -4,13 +4,11 @@
 //! in the Presentation Graphic Stream (PGS) format. The PCS provides the details of how the subtitles (or other
 //! graphic elements) are arranged and displayed on the screen.
 
-use std::rc::Rc;
-
-use crate::{pgs_memory_buffer::{BigEndian, ReadBytes}, pgs_segment_header::PgsSegmentHeader, Error, PgsMemoryBuffer, Result};
+use crate::{pgs_memory_buffer::{BigEndian, ReadBytes, WriteBytes}, pgs_segment_header::PgsSegmentHeader, Error, PgsMemoryBuffer, PgsRc, Result};
 
 /// Enum representing the object cropping flag in a PCS.
 /// This flag indicates whether the object (subtitle image) is cropped and whether a forced cropped image should be used.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PgsPcsObjectCroppedFlag {
     ForceCroppedImage = 0x40,
     Off = 0x00
@@ -32,13 +30,22 @@ impl From<u8> for PgsPcsObjectCroppedFlag {
     }
 }
 
+impl From<PgsPcsObjectCroppedFlag> for u8 {
+    /// Converts a `PgsPcsObjectCroppedFlag` back to its raw `u8` representation.
+    fn from(value: PgsPcsObjectCroppedFlag) -> Self {
+        value as u8
+    }
+}
+
 /// Struct representing a composition object in a PCS.
 /// Composition objects describe the individual graphic elements that make up the subtitle image and its placement on the screen.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PgsPcsSegmentCompositionObjects {
     pub object_id: u16,
     pub window_id: u8,
     pub object_cropped_flag: PgsPcsObjectCroppedFlag,
+    /// The object is shown even when subtitles are turned off (forced subtitles, e.g. for foreign dialogue).
+    pub object_forced_flag: bool,
     pub object_horizontal_position: u16,
     pub object_vertical_position: u16,
     pub object_cropping_horizontal_position: u16,
@@ -53,6 +60,7 @@ impl PgsPcsSegmentCompositionObjects {
             object_id: 0,
             window_id: 0,
             object_cropped_flag: PgsPcsObjectCroppedFlag::Off,
+            object_forced_flag: false,
             object_horizontal_position: 0,
             object_vertical_position: 0,
             object_cropping_horizontal_position: 0,
@@ -61,6 +69,40 @@ impl PgsPcsSegmentCompositionObjects {
             object_cropping_height_position: 0
         }
     }
+
+    /// Returns `true` if the object is not cropped, or its cropping rectangle is non-empty and lies inside an
+    /// object of the given size.
+    pub fn cropping_fits(&self, width: u16, height: u16) -> bool {
+        if self.object_cropped_flag == PgsPcsObjectCroppedFlag::Off {
+            return true;
+        }
+        self.object_cropping_width > 0 && self.object_cropping_height_position > 0
+            && self.object_cropping_horizontal_position as u32 + self.object_cropping_width as u32 <= width as u32
+            && self.object_cropping_vertical_position as u32 + self.object_cropping_height_position as u32 <= height as u32
+    }
+
+    /// Clamps the cropping rectangle to an object of the given size. A rectangle that is empty or starts outside the
+    /// object is dropped and the whole object is shown.
+    ///
+    /// # Returns
+    /// `true` if the cropping fields were changed.
+    pub fn clamp_cropping(&mut self, width: u16, height: u16) -> bool {
+        if self.cropping_fits(width, height) {
+            return false;
+        }
+        let (x, y) = (self.object_cropping_horizontal_position, self.object_cropping_vertical_position);
+        if self.object_cropping_width == 0 || self.object_cropping_height_position == 0 || x >= width || y >= height {
+            self.object_cropped_flag = PgsPcsObjectCroppedFlag::Off;
+            self.object_cropping_horizontal_position = 0;
+            self.object_cropping_vertical_position = 0;
+            self.object_cropping_width = 0;
+            self.object_cropping_height_position = 0;
+        } else {
+            self.object_cropping_width = self.object_cropping_width.min(width - x);
+            self.object_cropping_height_position = self.object_cropping_height_position.min(height - y);
+        }
+        true
+    }
 }
 
 /// Enum representing the composition state of a PCS.
@@ -92,7 +134,21 @@ impl From<u8> for PgsPcsCompositionState {
     }
 }
 
-#[derive(Debug)]
+impl From<PgsPcsCompositionState> for u8 {
+    /// Converts a `PgsPcsCompositionState` back to its raw `u8` representation.
+    fn from(value: PgsPcsCompositionState) -> Self {
+        match value {
+            PgsPcsCompositionState::EpochStart => 0x80,
+            PgsPcsCompositionState::AcquisitionPoint => 0x40,
+            PgsPcsCompositionState::Normal => 0x00
+        }
+    }
+}
+
+/// Frame rate codes of the PCS `frame_rate` field and the video frame rates they stand for.
+pub const PGS_FRAME_RATE_CODES: [(u8, f64); 6] = [(0x10, 23.976), (0x20, 24.0), (0x30, 25.0), (0x40, 29.97), (0x60, 50.0), (0x70, 59.94)];
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PgsPcsSegment {
     pub header: PgsSegmentHeader,
     pub width: u16,
@@ -124,6 +180,17 @@ impl PgsPcsSegment {
         }
     }
 
+    /// Returns the frame rate code for a video frame rate, accepting `23.98` and `29.97` style approximations of the
+    /// NTSC rates, or `None` if the frame rate has no code.
+    pub fn frame_rate_code(frame_rate: f64) -> Option<u8> {
+        PGS_FRAME_RATE_CODES.iter().find(|(_, rate)| (rate - frame_rate).abs() < 0.01).map(|&(code, _)| code)
+    }
+
+    /// Returns the video frame rate given by the `frame_rate` field, or `None` for an unknown code.
+    pub fn frames_per_second(&self) -> Option<f64> {
+        PGS_FRAME_RATE_CODES.iter().find(|(code, _)| *code == self.frame_rate).map(|&(_, rate)| rate)
+    }
+
     /// Creates a new, empty `PgsPcsSegment`.
     ///
     /// # Parameters
@@ -131,9 +198,9 @@ impl PgsPcsSegment {
     ///
     /// # Returns
     /// A new `PgsPcsSegment` instance with default values for the composition objects.
-    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<Rc<PgsPcsSegment>> {
+    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<PgsRc<PgsPcsSegment>> {
         if data.len() < header.segment_length as usize {
-            return Err(Error::InvalidSegmentDataLength);
+            return Err(Error::InvalidSegmentDataLength { length: data.len(), expected: header.segment_length as usize });
         }
 
         let mut segment = PgsPcsSegment::new(header);
@@ -141,7 +208,7 @@ impl PgsPcsSegment {
         let mut buffer: PgsMemoryBuffer = PgsMemoryBuffer::from(data);
         segment.width = buffer.read_u16::<BigEndian>()?;
         segment.height = buffer.read_u16::<BigEndian>()?;
-        let _ = buffer.read_u8()?;
+        segment.frame_rate = buffer.read_u8()?;
         segment.composition_number = buffer.read_u16::<BigEndian>()?;
         segment.composition_state = PgsPcsCompositionState::from(buffer.read_u8()?);
         segment.palette_update_flag = buffer.read_u8()?;
@@ -152,7 +219,10 @@ impl PgsPcsSegment {
             let mut com_obj = PgsPcsSegmentCompositionObjects::new();
             com_obj.object_id = buffer.read_u16::<BigEndian>()?;
             com_obj.window_id = buffer.read_u8()?;
-            com_obj.object_cropped_flag = PgsPcsObjectCroppedFlag::from(buffer.read_u8()?);
+            // The flags byte holds the cropped flag (0x40) and the forced on flag (0x80)
+            let flags = buffer.read_u8()?;
+            com_obj.object_cropped_flag = PgsPcsObjectCroppedFlag::from(flags & 0x40);
+            com_obj.object_forced_flag = flags & 0x80 != 0;
             com_obj.object_horizontal_position = buffer.read_u16::<BigEndian>()?;
             com_obj.object_vertical_position = buffer.read_u16::<BigEndian>()?;
             if com_obj.object_cropped_flag == PgsPcsObjectCroppedFlag::ForceCroppedImage {
@@ -165,7 +235,43 @@ impl PgsPcsSegment {
             segment.composition_objects.push(com_obj);
         }
 
-        Ok(Rc::new(segment))
+        Ok(PgsRc::new(segment))
+    }
+
+    /// Serializes the segment payload (without the header).
+    ///
+    /// This is the inverse of `from_data`. The number of composition objects is taken from
+    /// `composition_objects`, and the cropping fields are only written for objects with the
+    /// `ForceCroppedImage` flag.
+    ///
+    /// # Returns
+    /// The encoded payload bytes.
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = Vec::new();
+        data.write_u16::<BigEndian>(self.width)?;
+        data.write_u16::<BigEndian>(self.height)?;
+        data.write_u8(self.frame_rate)?;
+        data.write_u16::<BigEndian>(self.composition_number)?;
+        data.write_u8(self.composition_state.into())?;
+        data.write_u8(self.palette_update_flag)?;
+        data.write_u8(self.palette_id)?;
+        data.write_u8(self.composition_objects.len() as u8)?;
+
+        for com_obj in &self.composition_objects {
+            data.write_u16::<BigEndian>(com_obj.object_id)?;
+            data.write_u8(com_obj.window_id)?;
+            data.write_u8(u8::from(com_obj.object_cropped_flag) | if com_obj.object_forced_flag { 0x80 } else { 0 })?;
+            data.write_u16::<BigEndian>(com_obj.object_horizontal_position)?;
+            data.write_u16::<BigEndian>(com_obj.object_vertical_position)?;
+            if com_obj.object_cropped_flag == PgsPcsObjectCroppedFlag::ForceCroppedImage {
+                data.write_u16::<BigEndian>(com_obj.object_cropping_horizontal_position)?;
+                data.write_u16::<BigEndian>(com_obj.object_cropping_vertical_position)?;
+                data.write_u16::<BigEndian>(com_obj.object_cropping_width)?;
+                data.write_u16::<BigEndian>(com_obj.object_cropping_height_position)?;
+            }
+        }
+
+        Ok(data)
     }
 }
 
@@ -173,4 +279,32 @@ impl Default for PgsPcsSegment {
     fn default() -> Self {
         Self::new(Default::default())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_cropping() {
+        let mut com_obj = PgsPcsSegmentCompositionObjects::new();
+        assert!(!com_obj.clamp_cropping(200, 50));
+
+        com_obj.object_cropped_flag = PgsPcsObjectCroppedFlag::ForceCroppedImage;
+        (com_obj.object_cropping_horizontal_position, com_obj.object_cropping_width, com_obj.object_cropping_height_position) = (150, 100, 80);
+        assert!(com_obj.clamp_cropping(200, 50));
+        assert_eq!((com_obj.object_cropping_width, com_obj.object_cropping_height_position), (50, 50));
+        assert!(com_obj.cropping_fits(200, 50));
+
+        com_obj.object_cropping_width = 0;
+        assert!(com_obj.clamp_cropping(200, 50));
+        assert_eq!(com_obj.object_cropped_flag, PgsPcsObjectCroppedFlag::Off);
+    }
+
+    #[test]
+    fn test_frame_rate_code() {
+        assert_eq!(PgsPcsSegment::frame_rate_code(23.976), Some(0x10));
+        assert_eq!(PgsPcsSegment::frame_rate_code(25.0), Some(0x30));
+        assert_eq!(PgsPcsSegment::frame_rate_code(30.0), None);
+        assert_eq!(PgsPcsSegment { frame_rate: 0x60, ..Default::default() }.frames_per_second(), Some(50.0));
+    }
+}
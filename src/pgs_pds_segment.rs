@@ -4,13 +4,17 @@
 //! in the Presentation Graphic Stream (PGS) format. The PDS defines color palettes used by the subtitles
 //! or other graphical elements in a PGS file.
 
-use std::{io::Read, rc::Rc};
+use crate::{pgs_memory_buffer::{ReadBytes, WriteBytes}, Error, PgsMemoryBuffer, PgsRc, PgsSegmentHeader, Result};
 
-use crate::{pgs_memory_buffer::ReadBytes, Error, PgsMemoryBuffer, PgsSegmentHeader, Result};
+/// Length of the palette ID and version number at the start of the payload.
+const PDS_PALETTE_HEADER_LENGTH: usize = 2;
+
+/// Length of a single palette entry: entry ID, Y, Cr, Cb and transparency.
+const PDS_PALETTE_ENTRY_LENGTH: usize = 5;
 
 /// Struct representing an individual palette entry in a PDS.
 /// Each palette entry consists of the palette ID and its corresponding color values (Y, Cr, Cb).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PgsPdsSegmentPaletteEntry {
     pub palette_entry_id: u8,
     pub luminance: u8, // (Y)
@@ -37,12 +41,14 @@ impl PgsPdsSegmentPaletteEntry {
 
 /// Struct representing a Palette Definition Segment (PDS) in a PGS file.
 /// The PDS defines a color palette that can be used by various objects in the PGS file.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PgsPdsSegment {
     pub header: PgsSegmentHeader,
     pub palette_id: u8,
     pub palette_version_number: u8,
-    pub palette_entries: Vec<PgsPdsSegmentPaletteEntry>
+    /// The palette entries, shared between segments defining the same entries when parsed, so that repeated
+    /// palettes are stored once.
+    pub palette_entries: PgsRc<Vec<PgsPdsSegmentPaletteEntry>>
 }
 
 impl PgsPdsSegment {
@@ -54,10 +60,16 @@ impl PgsPdsSegment {
             header,
             palette_id,
             palette_version_number,
-            palette_entries
+            palette_entries: PgsRc::new(palette_entries)
         }
     }
 
+    /// Returns `true` if both segments define the same palette entries. Entries shared by interning compare in
+    /// constant time.
+    pub fn same_entries(&self, other: &PgsPdsSegment) -> bool {
+        PgsRc::ptr_eq(&self.palette_entries, &other.palette_entries) || self.palette_entries == other.palette_entries
+    }
+
     /// Parses a `PgsPdsSegment` from the provided header and raw data buffer.
     ///
     /// This method reads the palette ID, version number, and individual palette entries from the data buffer.
@@ -68,26 +80,29 @@ impl PgsPdsSegment {
     /// - `data`: A slice of raw data representing the contents of the PDS segment.
     ///
     /// # Errors
-    /// Returns `Error::InvalidSegmentDataLength` if the length of the provided data is less than expected.
+    /// Returns `Error::InvalidSegmentDataLength` if the length of the provided data is less than expected, or
+    /// `Error::InvalidPaletteDataLength` if the segment length is not the palette ID and version number followed
+    /// by whole palette entries.
     ///
     /// # Returns
-    /// An `Rc<PgsPdsSegment>` containing the parsed segment.
-    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<Rc<PgsPdsSegment>> {
+    /// A `PgsRc<PgsPdsSegment>` containing the parsed segment.
+    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<PgsRc<PgsPdsSegment>> {
         if data.len() < header.segment_length as usize {
-            return Err(Error::InvalidSegmentDataLength);
+            return Err(Error::InvalidSegmentDataLength { length: data.len(), expected: header.segment_length as usize });
         }
 
-        let mut buffer: PgsMemoryBuffer = PgsMemoryBuffer::from(data);
+        // The payload is the palette ID and version number (2 bytes) followed by 5 bytes per entry, so
+        // segment_length = 2 + 5 * palette_count.
+        let segment_length = header.segment_length as usize;
+        if segment_length < PDS_PALETTE_HEADER_LENGTH || !(segment_length - PDS_PALETTE_HEADER_LENGTH).is_multiple_of(PDS_PALETTE_ENTRY_LENGTH) {
+            return Err(Error::InvalidPaletteDataLength);
+        }
+        let palette_count = (segment_length - PDS_PALETTE_HEADER_LENGTH) / PDS_PALETTE_ENTRY_LENGTH;
+
+        let mut buffer: PgsMemoryBuffer = PgsMemoryBuffer::from(&data[..segment_length]);
         let palette_id = buffer.read_u8()?;
         let palette_version_number = buffer.read_u8()?;
 
-        let mut palette_buf: Vec<u8> = Vec::new();
-        buffer.read_to_end(&mut palette_buf)?;
-
-        // TODO: Return error if palette_buf.len() % 5 is not 0
-        let palette_count = (palette_buf.len() as u32  - 2) / 5;
-
-        let mut buffer: PgsMemoryBuffer = PgsMemoryBuffer::from(palette_buf);
         let mut palette_entries: Vec<PgsPdsSegmentPaletteEntry> = Vec::new();
         for _ in 0..palette_count {
             let palette_entry_id = buffer.read_u8()?;
@@ -98,7 +113,57 @@ impl PgsPdsSegment {
             palette_entries.push(PgsPdsSegmentPaletteEntry::new(palette_entry_id, luminance, color_difference_red, color_difference_blue, transparency))
         }
 
+        Ok(PgsRc::new(PgsPdsSegment::new(header, palette_id, palette_version_number, palette_entries)))
+    }
+
+    /// Serializes the segment payload (without the header).
+    ///
+    /// # Returns
+    /// The encoded payload: palette ID, version number and 5 bytes per palette entry.
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = Vec::new();
+        data.write_u8(self.palette_id)?;
+        data.write_u8(self.palette_version_number)?;
+        for entry in self.palette_entries.iter() {
+            data.write_u8(entry.palette_entry_id)?;
+            data.write_u8(entry.luminance)?;
+            data.write_u8(entry.color_difference_red)?;
+            data.write_u8(entry.color_difference_blue)?;
+            data.write_u8(entry.transparency)?;
+        }
 
-        Ok(Rc::new(PgsPdsSegment::new(header, palette_id, palette_version_number, palette_entries)))
+        Ok(data)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PgsSegmentType;
+
+    use super::*;
+
+    fn header(segment_length: u16) -> PgsSegmentHeader {
+        PgsSegmentHeader { segment_type: PgsSegmentType::PDS, segment_length, presentation_timestamp: 0, decoding_timestamp: 0 }
+    }
+
+    #[test]
+    fn test_palette_entries() {
+        let data = [0x01, 0x02, 0x00, 0x10, 0x80, 0x80, 0x00, 0x01, 0xEB, 0x80, 0x80, 0xFF];
+        let pds = PgsPdsSegment::from_data(header(12), &data).unwrap();
+        assert_eq!((pds.palette_id, pds.palette_version_number), (1, 2));
+        assert_eq!(*pds.palette_entries, vec![
+            PgsPdsSegmentPaletteEntry::new(0, 0x10, 0x80, 0x80, 0x00),
+            PgsPdsSegmentPaletteEntry::new(1, 0xEB, 0x80, 0x80, 0xFF)
+        ]);
+
+        assert!(PgsPdsSegment::from_data(header(2), &data).unwrap().palette_entries.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_palette() {
+        let data = [0x01, 0x02, 0x00, 0x10, 0x80, 0x80, 0x00, 0x01];
+        assert!(matches!(PgsPdsSegment::from_data(header(8), &data), Err(Error::InvalidPaletteDataLength)));
+        assert!(matches!(PgsPdsSegment::from_data(header(1), &data), Err(Error::InvalidPaletteDataLength)));
+        assert!(matches!(PgsPdsSegment::from_data(header(12), &data), Err(Error::InvalidSegmentDataLength { length: 8, expected: 12 })));
+    }
+}
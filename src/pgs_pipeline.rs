@@ -0,0 +1,250 @@
+//! # PGS Transformation Pipeline
+//!
+//! This module wraps the editing passes of the `transform` and `retime` modules behind a common `PgsTransform`
+//! trait, so they can be chained into a `PgsPipeline` that applies them in order and collects a report from
+//! every step.
+
+use crate::{pgs_retime::{shift, stretch, PgsRetimeFactor}, pgs_transform::{filter, normalize_compositions, optimize_with_options, rescale_with_filter, trim, PgsOptimizeOptions, PgsScaleFilter}, PgsPcsSegmentCompositionObjects, PgsSegment, PgsStream, Result};
+
+/// What a single transform changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgsTransformReport {
+    /// Name of the transform, e.g. `shift`.
+    pub transform: String,
+    /// Number of changes; what is counted depends on the transform and is described by `summary`.
+    pub changes: usize,
+    /// Short description of the changes, e.g. `3 display sets removed`.
+    pub summary: String
+}
+
+impl PgsTransformReport {
+    fn new(transform: &str, changes: usize, summary: String) -> Self {
+        PgsTransformReport { transform: transform.to_string(), changes, summary }
+    }
+}
+
+/// An editing pass that modifies a stream in place.
+///
+/// Closures taking a `&mut PgsStream` and returning a `Result<PgsTransformReport>` implement this trait, so
+/// custom passes can be added to a pipeline without defining a type.
+pub trait PgsTransform {
+    /// Applies the transform to the stream.
+    ///
+    /// # Errors
+    /// Returns the error of the wrapped pass; the stream may be partially modified in that case.
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport>;
+}
+
+impl<F: Fn(&mut PgsStream) -> Result<PgsTransformReport>> PgsTransform for F {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        self(stream)
+    }
+}
+
+/// Shifts every timestamp by a fixed offset in 90 kHz ticks (see `retime::shift`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsShift {
+    pub delta: i64
+}
+
+impl PgsTransform for PgsShift {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        let clamped = shift(stream, self.delta);
+        Ok(PgsTransformReport::new("shift", clamped, format!("{clamped} timestamps clamped")))
+    }
+}
+
+/// Scales every timestamp by a factor (see `retime::stretch`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsStretch {
+    pub factor: PgsRetimeFactor
+}
+
+impl PgsTransform for PgsStretch {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        let clamped = stretch(stream, self.factor)?;
+        Ok(PgsTransformReport::new("stretch", clamped, format!("{clamped} timestamps clamped")))
+    }
+}
+
+/// Cuts the stream down to a time range (see `transform::trim`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsTrim {
+    pub start: u32,
+    pub end: u32,
+    pub rebase: bool
+}
+
+impl PgsTransform for PgsTrim {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        let before = stream.get_display_set_segments().count();
+        trim(stream, self.start, self.end, self.rebase)?;
+        let removed = before - stream.get_display_set_segments().count();
+        Ok(PgsTransformReport::new("trim", removed, format!("{removed} display sets removed")))
+    }
+}
+
+/// Keeps only the composition objects accepted by a predicate, e.g. `transform::forced_only()` (see
+/// `transform::filter`).
+#[derive(Debug, Clone, Copy)]
+pub struct PgsFilter {
+    pub predicate: fn(&PgsPcsSegmentCompositionObjects) -> bool
+}
+
+impl PgsTransform for PgsFilter {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        let removed = filter(stream, self.predicate);
+        Ok(PgsTransformReport::new("filter", removed, format!("{removed} display sets removed")))
+    }
+}
+
+/// Rescales the stream to another video resolution (see `transform::rescale_with_filter`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsRescale {
+    pub from: (u16, u16),
+    pub to: (u16, u16),
+    pub filter: PgsScaleFilter
+}
+
+impl PgsTransform for PgsRescale {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        rescale_with_filter(stream, self.from, self.to, self.filter)?;
+        let compositions = stream.get_segments().iter().filter(|segment| matches!(segment, PgsSegment::Pcs(_))).count();
+        Ok(PgsTransformReport::new("rescale", compositions, format!("{compositions} compositions rescaled to {}x{}", self.to.0, self.to.1)))
+    }
+}
+
+/// Removes redundant display sets, objects and palettes (see `transform::optimize_with_options`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PgsOptimize {
+    pub options: PgsOptimizeOptions
+}
+
+impl PgsTransform for PgsOptimize {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        let report = optimize_with_options(stream, self.options)?;
+        let removed = report.display_sets_removed + report.objects_removed + report.palettes_removed;
+        Ok(PgsTransformReport::new("optimize", removed, format!("{} display sets, {} objects and {} palettes removed, {} bytes saved",
+            report.display_sets_removed, report.objects_removed, report.palettes_removed, report.saved())))
+    }
+}
+
+/// Renumbers the compositions and repairs composition states (see `transform::normalize_compositions`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PgsNormalize;
+
+impl PgsTransform for PgsNormalize {
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        let report = normalize_compositions(stream);
+        let repaired = report.epoch_starts_added + report.acquisition_points_downgraded + report.palette_update_flags_cleared;
+        Ok(PgsTransformReport::new("normalize", report.compositions_renumbered + repaired,
+            format!("{} compositions renumbered, {repaired} composition states repaired", report.compositions_renumbered)))
+    }
+}
+
+/// The reports of every step of a pipeline, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgsPipelineReport {
+    pub steps: Vec<PgsTransformReport>
+}
+
+impl PgsPipelineReport {
+    /// Returns the number of changes made by all steps.
+    pub fn changes(&self) -> usize {
+        self.steps.iter().map(|step| step.changes).sum()
+    }
+}
+
+/// A sequence of transforms applied to a stream one after another.
+///
+/// # Example
+/// ```rust
+/// use pgs_parse::{transform::{forced_only, PgsFilter, PgsNormalize, PgsPipeline, PgsShift}, PgsStream};
+///
+/// let pipeline = PgsPipeline::new()
+///     .then(PgsShift { delta: -90000 })
+///     .then(PgsFilter { predicate: forced_only() })
+///     .then(PgsNormalize);
+/// let mut stream = PgsStream::default();
+/// let report = pipeline.run(&mut stream).unwrap();
+/// assert_eq!(report.steps.len(), 3);
+/// ```
+#[derive(Default)]
+pub struct PgsPipeline {
+    transforms: Vec<Box<dyn PgsTransform>>
+}
+
+impl PgsPipeline {
+    /// Creates an empty pipeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a transform to the pipeline.
+    pub fn then<T: PgsTransform + 'static>(mut self, transform: T) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Applies every transform in order.
+    ///
+    /// # Errors
+    /// Returns the error of the first failing transform. The stream keeps the changes of the transforms before it.
+    ///
+    /// # Returns
+    /// The report of every transform.
+    pub fn run(&self, stream: &mut PgsStream) -> Result<PgsPipelineReport> {
+        let steps = self.transforms.iter().map(|transform| transform.apply(stream)).collect::<Result<Vec<_>>>()?;
+        Ok(PgsPipelineReport { steps })
+    }
+}
+
+impl PgsTransform for PgsPipeline {
+    /// Applies every transform in order, combining their reports into one.
+    fn apply(&self, stream: &mut PgsStream) -> Result<PgsTransformReport> {
+        let report = self.run(stream)?;
+        let summary = report.steps.iter().map(|step| format!("{}: {}", step.transform, step.summary)).collect::<Vec<_>>().join("; ");
+        Ok(PgsTransformReport::new("pipeline", report.changes(), summary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{display_set_of, pcs, segments}, PgsPcsCompositionState, PgsPcsSegment};
+
+    use super::*;
+
+    #[test]
+    fn test_pipeline() {
+        // Display sets made of a PCS of composition 5 and an END segment
+        let display_set = |pts, composition_state| segments(&display_set_of(PgsPcsSegment { composition_number: 5, ..pcs(pts, composition_state, Vec::new()) }, None, None, None));
+        let mut stream = PgsStream::new([
+            display_set(90000, PgsPcsCompositionState::EpochStart),
+            display_set(180000, PgsPcsCompositionState::Normal),
+            display_set(900000, PgsPcsCompositionState::Normal)
+        ].concat());
+        let count_segments = |stream: &mut PgsStream| -> Result<PgsTransformReport> {
+            let segments = stream.get_segments().len();
+            Ok(PgsTransformReport { transform: "count".to_string(), changes: 0, summary: format!("{segments} segments") })
+        };
+        let pipeline = PgsPipeline::new()
+            .then(PgsShift { delta: -90000 })
+            .then(PgsTrim { start: 100000, end: 450000, rebase: false })
+            .then(PgsNormalize)
+            .then(count_segments);
+
+        let report = pipeline.run(&mut stream).unwrap();
+        let steps: Vec<(&str, usize)> = report.steps.iter().map(|step| (step.transform.as_str(), step.changes)).collect();
+        assert_eq!(steps, vec![("shift", 0), ("trim", 1), ("normalize", 2), ("count", 0)]);
+        assert_eq!(report.steps[3].summary, "4 segments");
+        assert_eq!(report.changes(), 3);
+
+        let compositions: Vec<(u32, u16, PgsPcsCompositionState)> = stream.get_display_sets().iter().map(|ds| {
+            let pcs = ds.pcs.as_ref().unwrap();
+            (pcs.header.presentation_timestamp, pcs.composition_number, pcs.composition_state)
+        }).collect();
+        assert_eq!(compositions, vec![(100000, 0, PgsPcsCompositionState::EpochStart), (450000, 1, PgsPcsCompositionState::Normal)]);
+
+        assert!(PgsPipeline::new().then(PgsTrim { start: 1, end: 0, rebase: false }).apply(&mut stream).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! # PGS Screen Positions
+//!
+//! This module classifies where subtitle events appear on screen, from the windows they are shown in relative to
+//! the video height, and summarizes the positions of a whole track. Dialogue tracks are almost entirely at the
+//! bottom, while tracks with many top or centered events usually carry signs, songs or forced translations.
+
+use std::fmt::{self, Display};
+
+use crate::{export::export_events, PgsDisplaySet};
+
+/// Vertical region of the screen a subtitle is shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PgsScreenPosition {
+    /// The upper third of the video.
+    Top,
+    /// The middle third of the video.
+    Center,
+    /// The lower third of the video.
+    Bottom
+}
+
+impl PgsScreenPosition {
+    /// Classifies a vertical span of the screen by its middle row: the upper third of the video is `Top`, the lower
+    /// third `Bottom` and everything in between `Center`.
+    ///
+    /// # Parameters
+    /// - `y`: The top row of the span.
+    /// - `height`: The height of the span in rows.
+    /// - `video_height`: The height of the video; spans reaching below it extend the video.
+    pub fn classify(y: u16, height: u16, video_height: u16) -> PgsScreenPosition {
+        let bottom = y as u32 + height as u32;
+        let video_height = (video_height as u32).max(bottom).max(1);
+        // Six times the middle row, compared against six times the thirds of the video height to avoid rounding
+        let middle = 3 * (2 * y as u32 + height as u32);
+        if middle < 2 * video_height {
+            PgsScreenPosition::Top
+        } else if middle > 4 * video_height {
+            PgsScreenPosition::Bottom
+        } else {
+            PgsScreenPosition::Center
+        }
+    }
+}
+
+impl Display for PgsScreenPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PgsScreenPosition::Top => "top",
+            PgsScreenPosition::Center => "center",
+            PgsScreenPosition::Bottom => "bottom"
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The screen position of a subtitle event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsEventPosition {
+    /// Index of the display set holding the bitmap.
+    pub display_set: usize,
+    /// Presentation timestamp (90 kHz) at which the bitmap appears.
+    pub start: u32,
+    /// Top row of the windows the event is shown in.
+    pub y: u16,
+    /// Height in rows of the windows the event is shown in, from the top of the highest to the bottom of the
+    /// lowest one.
+    pub height: u16,
+    pub position: PgsScreenPosition
+}
+
+/// Number of events shown in each region of the screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PgsPositionSummary {
+    pub top: usize,
+    pub center: usize,
+    pub bottom: usize
+}
+
+impl PgsPositionSummary {
+    /// Counts the positions of the events.
+    pub fn from_positions(positions: &[PgsEventPosition]) -> Self {
+        let count = |position: PgsScreenPosition| positions.iter().filter(|event| event.position == position).count();
+        PgsPositionSummary {
+            top: count(PgsScreenPosition::Top),
+            center: count(PgsScreenPosition::Center),
+            bottom: count(PgsScreenPosition::Bottom)
+        }
+    }
+
+    /// Returns the number of events.
+    pub fn total(&self) -> usize {
+        self.top + self.center + self.bottom
+    }
+
+    /// Returns the number of events shown in a region.
+    pub fn count(&self, position: PgsScreenPosition) -> usize {
+        match position {
+            PgsScreenPosition::Top => self.top,
+            PgsScreenPosition::Center => self.center,
+            PgsScreenPosition::Bottom => self.bottom
+        }
+    }
+
+    /// Returns the percentage of events shown in a region, `0.0` without events.
+    pub fn percentage(&self, position: PgsScreenPosition) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => 100.0 * self.count(position) as f64 / total as f64
+        }
+    }
+}
+
+/// Formats the summary as rounded percentages of the regions holding events, most frequent first, e.g.
+/// `97% bottom, 3% top`.
+impl Display for PgsPositionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut positions = [PgsScreenPosition::Bottom, PgsScreenPosition::Top, PgsScreenPosition::Center];
+        positions.sort_by_key(|&position| std::cmp::Reverse(self.count(position)));
+        let parts = positions.iter()
+            .filter(|&&position| self.count(position) > 0)
+            .map(|&position| format!("{:.0}% {position}", self.percentage(position)))
+            .collect::<Vec<_>>();
+        match parts.is_empty() {
+            true => write!(f, "no events"),
+            false => write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Returns the vertical span `(y, height)` of the windows the objects of a display set are shown in, or of all
+/// windows if no object references one.
+fn window_span(ds: &PgsDisplaySet) -> Option<(u16, u16)> {
+    let (pcs, wds) = (ds.pcs.as_ref()?, ds.wds.as_ref()?);
+    let shown = wds.windows.iter()
+        .filter(|window| pcs.composition_objects.iter().any(|obj| obj.window_id == window.window_id))
+        .collect::<Vec<_>>();
+    let windows = match shown.is_empty() {
+        true => wds.windows.iter().collect(),
+        false => shown
+    };
+    let top = windows.iter().map(|window| window.window_vertical_position).min()?;
+    let bottom = windows.iter().map(|window| window.window_vertical_position as u32 + window.window_height as u32).max()?;
+    Some((top, (bottom - top as u32).min(u16::MAX as u32) as u16))
+}
+
+/// Classifies the screen position of every subtitle event.
+///
+/// Events are built with `export::export_events`. An event is placed by the windows its objects are shown in,
+/// relative to the video height of its composition (see `PgsScreenPosition::classify`); events of display sets
+/// without windows are placed by their object.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+///
+/// # Returns
+/// One position per event, in stream order.
+pub fn classify_positions(display_sets: &[PgsDisplaySet]) -> Vec<PgsEventPosition> {
+    export_events(display_sets).into_iter().map(|event| {
+        let ds = &display_sets[event.display_set];
+        let (y, height) = window_span(ds).unwrap_or((event.y, event.height));
+        let video_height = ds.pcs.as_ref().map_or(0, |pcs| pcs.height);
+        PgsEventPosition {
+            display_set: event.display_set,
+            start: event.start,
+            y,
+            height,
+            position: PgsScreenPosition::classify(y, height, video_height)
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{composition_object, display_set_of, ods, pcs, pds, wds, window}, PgsPcsCompositionState, PgsPcsSegment};
+
+    use super::*;
+
+    /// A display set of a 1080 rows high video showing an object in a 100 rows high window at `y`.
+    fn display_set(pts: u32, y: u16) -> PgsDisplaySet {
+        let windows = vec![window(0, (0, 0, 1920, 100)), window(1, (0, y, 1920, 100))];
+        display_set_of(
+            PgsPcsSegment { width: 1920, height: 1080, ..pcs(pts, PgsPcsCompositionState::Normal, vec![composition_object(0, 1, 100, y)]) },
+            Some(wds(pts, windows)),
+            Some(pds(pts, 0, Vec::new())),
+            Some(ods(pts, 0, 0, 10, 10, &vec![vec![0; 10]; 10]))
+        )
+    }
+
+    #[test]
+    fn test_classify_positions() {
+        let mut display_sets: Vec<PgsDisplaySet> = (0..30).map(|index| display_set(90000 * index, 900)).collect();
+        display_sets.push(display_set(90000 * 30, 40));
+        display_sets.push(display_set(90000 * 31, 500));
+
+        let positions = classify_positions(&display_sets);
+        assert_eq!(positions[30], PgsEventPosition { display_set: 30, start: 2_700_000, y: 40, height: 100, position: PgsScreenPosition::Top });
+        assert_eq!(positions[31].position, PgsScreenPosition::Center);
+
+        let summary = PgsPositionSummary::from_positions(&positions);
+        assert_eq!(summary, PgsPositionSummary { top: 1, center: 1, bottom: 30 });
+        assert_eq!(summary.to_string(), "94% bottom, 3% top, 3% center");
+        assert_eq!(PgsPositionSummary::default().to_string(), "no events");
+
+        assert_eq!(PgsScreenPosition::classify(1000, 200, 1080), PgsScreenPosition::Bottom);
+        assert_eq!(PgsScreenPosition::classify(360, 360, 1080), PgsScreenPosition::Center);
+    }
+}
@@ -0,0 +1,210 @@
+//! # PGS Preview
+//!
+//! This module renders decoded subtitle bitmaps directly to a terminal, so a display set can be checked without
+//! exporting image files. Terminals supporting the kitty graphics protocol or sixel graphics show the bitmap at
+//! full resolution; any other terminal gets an approximation drawn with colored Unicode half-blocks.
+
+use std::{collections::HashMap, io::Write};
+
+use crate::Result;
+
+/// Pixels with an alpha value below this are treated as transparent.
+const ALPHA_THRESHOLD: u32 = 0x80;
+
+/// Terminal graphics protocol used to render a preview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgsPreviewProtocol {
+    /// Unicode upper half-blocks with 24-bit ANSI colors, two pixel rows per text row. Works in any modern
+    /// terminal.
+    HalfBlocks,
+    /// DEC sixel graphics (xterm with sixel enabled, mlterm, foot, ...).
+    Sixel,
+    /// The kitty graphics protocol (kitty, WezTerm, Ghostty, ...).
+    Kitty
+}
+
+impl PgsPreviewProtocol {
+    /// Guesses the best protocol supported by the current terminal from the environment (`TERM`,
+    /// `TERM_PROGRAM`, `KITTY_WINDOW_ID`), falling back to `HalfBlocks`.
+    pub fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).unwrap_or_default().to_lowercase();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if std::env::var_os("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || term.contains("ghostty")
+            || program == "wezterm" || program == "ghostty" {
+            PgsPreviewProtocol::Kitty
+        } else if term.contains("sixel") || term.starts_with("mlterm") || term.starts_with("foot") {
+            PgsPreviewProtocol::Sixel
+        } else {
+            PgsPreviewProtocol::HalfBlocks
+        }
+    }
+}
+
+/// Shrinks an ARGB bitmap (nearest neighbour) so it is at most `max_width` pixels wide, keeping the aspect ratio.
+fn fit_width(pixels: &[Vec<u32>], max_width: usize) -> Vec<Vec<u32>> {
+    let width = pixels.first().map_or(0, |row| row.len());
+    if max_width == 0 || width <= max_width {
+        return pixels.to_vec();
+    }
+    let height = (pixels.len() * max_width / width).max(1);
+    (0..height).map(|y| {
+        let row = &pixels[y * pixels.len() / height];
+        (0..max_width).map(|x| row[x * width / max_width]).collect()
+    }).collect()
+}
+
+/// Returns the RGB components of an ARGB pixel, or `None` if it is transparent.
+fn opaque_rgb(pixel: u32) -> Option<(u8, u8, u8)> {
+    (pixel >> 24 >= ALPHA_THRESHOLD).then_some(((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8))
+}
+
+/// Draws the bitmap with `▀` characters: the foreground color is the upper pixel, the background color the lower
+/// one. Transparent pixels keep the terminal background.
+fn render_half_blocks<W: Write>(writer: &mut W, pixels: &[Vec<u32>]) -> Result<()> {
+    for rows in pixels.chunks(2) {
+        for (col, &top) in rows[0].iter().enumerate() {
+            let bottom = rows.get(1).map_or(0, |row| row[col]);
+            match (opaque_rgb(top), opaque_rgb(bottom)) {
+                (None, None) => write!(writer, "\x1b[0m ")?,
+                (Some((r, g, b)), None) => write!(writer, "\x1b[0m\x1b[38;2;{r};{g};{b}m▀")?,
+                (None, Some((r, g, b))) => write!(writer, "\x1b[0m\x1b[38;2;{r};{g};{b}m▄")?,
+                (Some((r, g, b)), Some((br, bg, bb))) => write!(writer, "\x1b[38;2;{r};{g};{b};48;2;{br};{bg};{bb}m▀")?
+            }
+        }
+        writeln!(writer, "\x1b[0m")?;
+    }
+    Ok(())
+}
+
+/// Encodes the bitmap as a sixel image. Transparent pixels are left unpainted.
+fn render_sixel<W: Write>(writer: &mut W, pixels: &[Vec<u32>]) -> Result<()> {
+    // Color registers; PGS palettes have at most 256 entries, reduce to RGB 3-3-2 if a bitmap has more colors
+    let mut colors: Vec<(u8, u8, u8)> = pixels.iter().flatten().filter_map(|&pixel| opaque_rgb(pixel)).collect();
+    colors.sort_unstable();
+    colors.dedup();
+    let reduce = colors.len() > 256;
+    let key = |(r, g, b): (u8, u8, u8)| if reduce { (r & 0xE0, g & 0xE0, b & 0xC0) } else { (r, g, b) };
+    if reduce {
+        colors = colors.into_iter().map(key).collect();
+        colors.sort_unstable();
+        colors.dedup();
+    }
+    let registers: HashMap<(u8, u8, u8), usize> = colors.iter().enumerate().map(|(index, &color)| (color, index)).collect();
+
+    let width = pixels.first().map_or(0, |row| row.len());
+    write!(writer, "\x1bP0;1;0q\"1;1;{};{}", width, pixels.len())?;
+    for (index, (r, g, b)) in colors.iter().enumerate() {
+        let percent = |value: &u8| *value as u32 * 100 / 255;
+        write!(writer, "#{};2;{};{};{}", index, percent(r), percent(g), percent(b))?;
+    }
+
+    for band in pixels.chunks(6) {
+        // Sixel bits of every column, per color register used in this band
+        let mut planes: HashMap<usize, Vec<u8>> = HashMap::new();
+        for (bit, row) in band.iter().enumerate() {
+            for (col, &pixel) in row.iter().enumerate() {
+                if let Some(rgb) = opaque_rgb(pixel) {
+                    planes.entry(registers[&key(rgb)]).or_insert_with(|| vec![0; width])[col] |= 1 << bit;
+                }
+            }
+        }
+
+        let mut registers_used: Vec<&usize> = planes.keys().collect();
+        registers_used.sort_unstable();
+        for register in registers_used {
+            write!(writer, "#{register}")?;
+            for run in planes[register].chunk_by(|a, b| a == b) {
+                let symbol = (0x3F + run[0]) as char;
+                match run.len() {
+                    1..=3 => write!(writer, "{}", symbol.to_string().repeat(run.len()))?,
+                    count => write!(writer, "!{count}{symbol}")?
+                }
+            }
+            write!(writer, "$")?;
+        }
+        write!(writer, "-")?;
+    }
+    writeln!(writer, "\x1b\\")?;
+    Ok(())
+}
+
+/// Encodes bytes as standard base64 with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Sends the bitmap as raw RGBA data using the kitty graphics protocol, in chunks of 4096 base64 characters.
+fn render_kitty<W: Write>(writer: &mut W, pixels: &[Vec<u32>]) -> Result<()> {
+    let width = pixels.first().map_or(0, |row| row.len());
+    let rgba: Vec<u8> = pixels.iter().flatten()
+        .flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, (pixel >> 24) as u8])
+        .collect();
+    let payload = base64(&rgba);
+
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(4096).collect();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = if index + 1 < chunks.len() { 1 } else { 0 };
+        if index == 0 {
+            write!(writer, "\x1b_Ga=T,f=32,s={},v={},m={};", width, pixels.len(), more)?;
+        } else {
+            write!(writer, "\x1b_Gm={more};")?;
+        }
+        writer.write_all(chunk)?;
+        write!(writer, "\x1b\\")?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Renders a decoded ARGB bitmap (as returned by `PgsDisplaySet::get_decoded_image`) to a terminal.
+///
+/// # Parameters
+/// - `writer`: The terminal, usually `std::io::stdout()`.
+/// - `pixels`: Rows of ARGB pixels.
+/// - `protocol`: The terminal graphics protocol to use.
+/// - `max_width`: Maximum width of the rendered image in pixels (one pixel is one text column for
+///   `HalfBlocks`); larger bitmaps are shrunk. `0` keeps the original size.
+pub fn render_preview<W: Write>(mut writer: W, pixels: &[Vec<u32>], protocol: PgsPreviewProtocol, max_width: usize) -> Result<()> {
+    let pixels = fit_width(pixels, max_width);
+    match protocol {
+        PgsPreviewProtocol::HalfBlocks => render_half_blocks(&mut writer, &pixels)?,
+        PgsPreviewProtocol::Sixel => render_sixel(&mut writer, &pixels)?,
+        PgsPreviewProtocol::Kitty => render_kitty(&mut writer, &pixels)?
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(protocol: PgsPreviewProtocol) -> String {
+        let pixels = vec![vec![0xFFFF0000, 0x00000000], vec![0xFF0000FF, 0xFFFF0000]];
+        let mut output: Vec<u8> = Vec::new();
+        render_preview(&mut output, &pixels, protocol, 0).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_render_preview() {
+        assert_eq!(render(PgsPreviewProtocol::HalfBlocks),
+            "\x1b[38;2;255;0;0;48;2;0;0;255m▀\x1b[0m\x1b[38;2;255;0;0m▄\x1b[0m\n");
+        assert_eq!(render(PgsPreviewProtocol::Sixel),
+            "\x1bP0;1;0q\"1;1;2;2#0;2;0;0;100#1;2;100;0;0#0A?$#1@A$-\x1b\\\n");
+        assert_eq!(render(PgsPreviewProtocol::Kitty),
+            "\x1b_Ga=T,f=32,s=2,v=2,m=0;/wAA/wAAAAAAAP///wAA/w==\x1b\\\n");
+    }
+}
@@ -0,0 +1,720 @@
+//! # PGS Push Parser
+//!
+//! This module defines `PgsPushParser`, the I/O free core of `PgsParser`. Bytes are pushed in as they arrive, in
+//! chunks of any size, and parsed segments, display sets and problems come out as `PgsParseEvent`s. Reading from a
+//! file, a socket or an in-memory buffer is left to the caller, so the parser works the same with blocking,
+//! asynchronous or embedded I/O and can be tested without files.
+
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, error, trace, warn};
+
+use crate::{pgs_const::PG, pgs_memory_buffer::{ReadBytes, WriteBytes}, pgs_parser::{PgsParseIssue, PgsParseSeverity, PgsParserOptions, PgsSkippedRegion}, pgs_pcs_segment::PgsPcsSegmentCompositionObjects, pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH, pgs_trace::PgsTraceSpans, is_timestamp_wrap, BigEndian, Error, PgsDisplaySet, PgsEndSegment, PgsMemoryBuffer, PgsOdsSegment, PgsOdsSequenceFlag, PgsPcsCompositionState, PgsPdsSegmentPaletteEntry, PgsRc, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsUnknownSegment, PgsWriter, Result};
+
+/// Length of the object id, version, sequence flag, data length, width and height preceding the data of a first
+/// ODS fragment.
+const ODS_FIRST_FRAGMENT_HEADER_LENGTH: usize = 11;
+
+/// Magic and format version at the start of a suspended parser state.
+const STATE_MAGIC: &[u8; 4] = b"PGSP";
+const STATE_VERSION: u8 = 1;
+
+/// Something found by a `PgsPushParser` in the bytes pushed so far.
+#[derive(Debug)]
+pub enum PgsParseEvent {
+    /// A parsed segment with the stream offset of its header. Segments are held back until their display set is
+    /// ended, since a lenient parse may still repair them, and come out in stream order. With
+    /// `PgsParserOptions::keep_raw_segments`, `raw` holds the bytes the segment was read from, header included and
+    /// before any repair; it is `None` otherwise and for END segments inserted by a lenient parse.
+    Segment { offset: usize, segment: PgsSegment, raw: Option<Vec<u8>> },
+    /// A display set ended by an END segment, following the `Segment` events of its segments.
+    DisplaySet(PgsDisplaySet),
+    /// A range of the input skipped by a lenient parse.
+    Skipped(PgsSkippedRegion),
+    /// A problem found while parsing.
+    Issue(PgsParseIssue),
+    /// Parsing failed. This is the last event, following the `Segment` events of the display set that was being
+    /// read; further input is ignored.
+    Error(Error)
+}
+
+/// Damaged data a lenient parse is skipping while it waits for the next segment header.
+#[derive(Debug)]
+struct PgsDamagedData {
+    /// Stream offset of the first damaged byte.
+    offset: usize,
+    /// Description of the error the data caused.
+    error: String
+}
+
+/// A parser for PGS data that is fed bytes instead of reading them.
+///
+/// The parser applies the same checks and lenient repairs as `PgsParser`, which is a file driver on top of it.
+///
+/// # Example
+/// ```rust
+/// use pgs_parse::{PgsParseEvent, PgsParserOptions, PgsPushParser};
+///
+/// let mut parser = PgsPushParser::new(PgsParserOptions::default());
+/// let data = std::fs::read("subtitle.sup").unwrap_or_default();
+/// let mut events = Vec::new();
+/// for chunk in data.chunks(4096) {
+///     events.extend(parser.push_bytes(chunk));
+/// }
+/// events.extend(parser.finish());
+/// let display_sets = events.iter().filter(|event| matches!(event, PgsParseEvent::DisplaySet(_))).count();
+/// ```
+#[derive(Debug, Default)]
+pub struct PgsPushParser {
+    options: PgsParserOptions,
+    /// Bytes pushed but not consumed yet, starting at `buffer_offset` in the stream.
+    buffer: Vec<u8>,
+    buffer_offset: usize,
+    /// Index of the first unconsumed byte in `buffer`.
+    position: usize,
+    damaged: Option<PgsDamagedData>,
+    /// Segments of the display set being read, with their stream offsets and raw bytes.
+    display_set: Vec<(usize, PgsSegment, Option<Vec<u8>>)>,
+    /// Presentation timestamp of the previous PCS.
+    previous_pts: Option<u32>,
+    /// Width and height of the objects defined in the current epoch, by object id.
+    object_sizes: HashMap<u16, (u16, u16)>,
+    /// Every distinct set of palette entries parsed so far, shared by all PDS defining it.
+    palettes: HashSet<PgsRc<Vec<PgsPdsSegmentPaletteEntry>>>,
+    segment_count: usize,
+    display_set_count: usize,
+    /// Open `tracing` spans of the current epoch and display set.
+    trace: PgsTraceSpans,
+    /// Set by `finish` or a failed parse; no more events are produced after it.
+    done: bool
+}
+
+impl PgsPushParser {
+    /// Creates a new `PgsPushParser` instance.
+    ///
+    /// # Arguments
+    /// * `options` - The `PgsParserOptions`, e.g. to enable lenient parsing of damaged data.
+    pub fn new(options: PgsParserOptions) -> Self {
+        PgsPushParser { options, ..Default::default() }
+    }
+
+    /// Pushes the next bytes of the stream and parses as far as they allow.
+    ///
+    /// A segment split across pushes is parsed once its last byte arrives.
+    ///
+    /// # Arguments
+    /// * `data` - The bytes following the ones pushed before.
+    ///
+    /// # Returns
+    /// The events produced by the new bytes, ending with `PgsParseEvent::Error` if parsing failed.
+    pub fn push_bytes(&mut self, data: &[u8]) -> Vec<PgsParseEvent> {
+        let mut events: Vec<PgsParseEvent> = Vec::new();
+        if self.done {
+            return events;
+        }
+        self.buffer.extend_from_slice(data);
+        let result = self.process(false, &mut events);
+        self.end_push(result, &mut events);
+        events
+    }
+
+    /// Pushes bytes like `push_bytes`, taking over the vector instead of copying it when no bytes are buffered,
+    /// e.g. for a whole file read into memory.
+    pub(crate) fn push_vec(&mut self, data: Vec<u8>) -> Vec<PgsParseEvent> {
+        if self.buffer.is_empty() && !self.done {
+            self.buffer = data;
+            let mut events: Vec<PgsParseEvent> = Vec::new();
+            let result = self.process(false, &mut events);
+            self.end_push(result, &mut events);
+            return events;
+        }
+        self.push_bytes(&data)
+    }
+
+    /// Ends the stream: parses the bytes still buffered, reporting a truncated last segment, and returns the
+    /// segments after the last END segment, which do not form a display set.
+    ///
+    /// # Returns
+    /// The remaining events, ending with `PgsParseEvent::Error` if parsing failed.
+    pub fn finish(&mut self) -> Vec<PgsParseEvent> {
+        let mut events: Vec<PgsParseEvent> = Vec::new();
+        if self.done {
+            return events;
+        }
+        let result = self.process(true, &mut events);
+        self.end_push(result, &mut events);
+        self.done = true;
+
+        if let Some(&(offset, _, _)) = self.display_set.first() {
+            events.push(PgsParseEvent::Issue(PgsParseIssue {
+                severity: PgsParseSeverity::Warning,
+                offset,
+                message: format!("{} segments after the last END segment do not form a display set", self.display_set.len())
+            }));
+            self.flush(false, &mut events);
+        }
+        events
+    }
+
+    /// Returns the stream offset of the next byte to push, e.g. where to continue reading a file after `resume`.
+    pub fn offset(&self) -> usize {
+        self.buffer_offset + self.buffer.len()
+    }
+
+    /// Serializes the state of the parse: the bytes pushed but not parsed yet, the segments of the display set
+    /// being read and the context of the current epoch. A parser restored from it with `resume` continues exactly
+    /// where this one stopped, e.g. after a checkpoint of a long job or in a later session.
+    ///
+    /// The options are not part of the state, and palettes parsed before suspending are no longer shared with the
+    /// ones parsed after resuming.
+    ///
+    /// # Errors
+    /// Returns an error if a segment of the display set being read cannot be serialized.
+    pub fn suspend(&self) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = STATE_MAGIC.to_vec();
+        data.write_u8(STATE_VERSION)?;
+        data.write_u8(self.done as u8)?;
+        write_u64(&mut data, (self.buffer_offset + self.position) as u64)?;
+        write_bytes(&mut data, &self.buffer[self.position..])?;
+        match &self.damaged {
+            Some(damaged) => {
+                data.write_u8(1)?;
+                write_u64(&mut data, damaged.offset as u64)?;
+                write_bytes(&mut data, damaged.error.as_bytes())?;
+            },
+            None => data.write_u8(0)?
+        }
+        match self.previous_pts {
+            Some(pts) => {
+                data.write_u8(1)?;
+                data.write_u32::<BigEndian>(pts)?;
+            },
+            None => data.write_u8(0)?
+        }
+        data.write_u32::<BigEndian>(self.object_sizes.len() as u32)?;
+        for (&object_id, &(width, height)) in &self.object_sizes {
+            data.write_u16::<BigEndian>(object_id)?;
+            data.write_u16::<BigEndian>(width)?;
+            data.write_u16::<BigEndian>(height)?;
+        }
+        write_u64(&mut data, self.segment_count as u64)?;
+        write_u64(&mut data, self.display_set_count as u64)?;
+        data.write_u32::<BigEndian>(self.display_set.len() as u32)?;
+        for (offset, segment, raw) in &self.display_set {
+            write_u64(&mut data, *offset as u64)?;
+            match raw {
+                Some(raw) => write_bytes(&mut data, raw)?,
+                None => {
+                    let mut writer = PgsWriter::new(Vec::new());
+                    writer.write_segment(segment)?;
+                    write_bytes(&mut data, &writer.into_inner())?;
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Restores a parser suspended with `suspend`.
+    ///
+    /// # Arguments
+    /// * `options` - The options to continue with, normally the ones the suspended parser was created with.
+    /// * `state` - The serialized state.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidArgument` if `state` is not a parser state written by `suspend`.
+    pub fn resume(options: PgsParserOptions, state: &[u8]) -> Result<Self> {
+        Self::read_state(options, state).map_err(|error| Error::InvalidArgument(format!("invalid parser state: {:?}", error)))
+    }
+
+    /// Deserializes the state written by `suspend`.
+    fn read_state(options: PgsParserOptions, state: &[u8]) -> Result<Self> {
+        let mut buffer = PgsMemoryBuffer::from(state);
+        if &buffer.read_bytes::<4>()? != STATE_MAGIC || buffer.read_u8()? != STATE_VERSION {
+            return Err(Error::ReadInvalidSegment);
+        }
+        let mut parser = PgsPushParser::new(options);
+        parser.done = buffer.read_u8()? != 0;
+        parser.buffer_offset = read_u64(&mut buffer)? as usize;
+        parser.buffer = read_bytes(&mut buffer)?;
+        if buffer.read_u8()? != 0 {
+            let offset = read_u64(&mut buffer)? as usize;
+            let error = String::from_utf8_lossy(&read_bytes(&mut buffer)?).into_owned();
+            parser.damaged = Some(PgsDamagedData { offset, error });
+        }
+        if buffer.read_u8()? != 0 {
+            parser.previous_pts = Some(buffer.read_u32::<BigEndian>()?);
+        }
+        for _ in 0..buffer.read_u32::<BigEndian>()? {
+            let object_id = buffer.read_u16::<BigEndian>()?;
+            let size = (buffer.read_u16::<BigEndian>()?, buffer.read_u16::<BigEndian>()?);
+            parser.object_sizes.insert(object_id, size);
+        }
+        parser.segment_count = read_u64(&mut buffer)? as usize;
+        parser.display_set_count = read_u64(&mut buffer)? as usize;
+        for _ in 0..buffer.read_u32::<BigEndian>()? {
+            let offset = read_u64(&mut buffer)? as usize;
+            let segment = read_bytes(&mut buffer)?;
+            if segment.len() < PGS_SEGMENT_HEADER_LENGTH {
+                return Err(Error::InvalidSegmentDataLength { length: segment.len(), expected: PGS_SEGMENT_HEADER_LENGTH });
+            }
+            let raw = parser.options.keep_raw_segments.then(|| segment.clone());
+            parser.display_set.push((offset, Self::read_segment(&segment)?, raw));
+        }
+        Ok(parser)
+    }
+
+    /// Drops the consumed bytes and turns a failed parse into the final event.
+    fn end_push(&mut self, result: Result<()>, events: &mut Vec<PgsParseEvent>) {
+        self.buffer.drain(..self.position);
+        self.buffer_offset += self.position;
+        self.position = 0;
+        if let Err(error) = result {
+            error!("{:?}", error);
+            self.done = true;
+            self.flush(false, events);
+            events.push(PgsParseEvent::Error(error));
+        }
+    }
+
+    /// Parses the buffered bytes.
+    ///
+    /// # Arguments
+    /// * `at_end` - `true` if no more bytes follow, so an incomplete segment is truncated.
+    fn process(&mut self, at_end: bool, events: &mut Vec<PgsParseEvent>) -> Result<()> {
+        loop {
+            if let Some(damaged) = self.damaged.take() {
+                let next = match self.find_next_header(damaged.offset + 1) {
+                    Some(next) => next,
+                    None if at_end => self.buffer_offset + self.buffer.len(),
+                    None => {
+                        // Keep the bytes that may still start a header once more data arrives
+                        let keep = self.buffer.len().saturating_sub(PGS_SEGMENT_HEADER_LENGTH - 1);
+                        let scanned = (damaged.offset + 1).saturating_sub(self.buffer_offset);
+                        self.position = self.position.max(keep).max(scanned).min(self.buffer.len());
+                        self.damaged = Some(damaged);
+                        return Ok(());
+                    }
+                };
+                warn!("skipping {} damaged bytes at offset {:#x}: {}", next - damaged.offset, damaged.offset, damaged.error);
+                events.push(PgsParseEvent::Skipped(PgsSkippedRegion { offset: damaged.offset, length: next - damaged.offset }));
+                events.push(PgsParseEvent::Issue(PgsParseIssue {
+                    severity: PgsParseSeverity::Error,
+                    offset: damaged.offset,
+                    message: format!("{} bytes skipped: {}", next - damaged.offset, damaged.error)
+                }));
+                self.position = next - self.buffer_offset;
+            }
+            if self.position == self.buffer.len() {
+                return Ok(());
+            }
+            if let Some(cancel) = &self.options.cancel {
+                cancel.check()?;
+            }
+
+            let offset = self.buffer_offset + self.position;
+            let length = match self.segment_length() {
+                Ok(Some(length)) => length,
+                Ok(None) if !at_end => return Ok(()),
+                Ok(None) => {
+                    let error = Error::File(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "end of file"));
+                    if self.options.lenient && self.find_next_header(offset + 1).is_none() {
+                        // The data was cut in the middle of the last segment; keep everything read so far
+                        let left = self.buffer.len() - self.position;
+                        warn!("file truncated in the segment at offset {:#x}", offset);
+                        events.push(PgsParseEvent::Issue(PgsParseIssue {
+                            severity: PgsParseSeverity::Warning,
+                            offset,
+                            message: format!("truncated segment ({} bytes left in the file)", left)
+                        }));
+                        self.position = self.buffer.len();
+                        return Ok(());
+                    }
+                    self.damage(offset, error)?;
+                    continue;
+                },
+                Err(error) => {
+                    self.damage(offset, error)?;
+                    continue;
+                }
+            };
+
+            let data = &self.buffer[self.position..self.position + length];
+            let _span = self.trace.segment(offset, data);
+            match Self::read_segment(data) {
+                Ok(segment) => {
+                    let raw = self.options.keep_raw_segments.then(|| data.to_vec());
+                    self.position += length;
+                    self.accept(offset, segment, raw, events)?;
+                },
+                Err(error) => self.damage(offset, error)?
+            }
+        }
+    }
+
+    /// Returns the length of the segment at the current position including its header, or `None` if it has not
+    /// been pushed completely yet.
+    ///
+    /// # Errors
+    /// Returns an error if the segment header is invalid.
+    fn segment_length(&self) -> Result<Option<usize>> {
+        let data = &self.buffer[self.position..];
+        if data.len() < PGS_SEGMENT_HEADER_LENGTH {
+            return Ok(None);
+        }
+        let header = PgsSegmentHeader::from_data(&data[..PGS_SEGMENT_HEADER_LENGTH])?;
+        if header.segment_type == PgsSegmentType::ERR && !self.options.keep_unknown_segments {
+            return Err(Error::UnsupportedSegmentType(data[10]));
+        }
+        let length = PGS_SEGMENT_HEADER_LENGTH + header.segment_length as usize;
+        Ok((data.len() >= length).then_some(length))
+    }
+
+    /// Parses a complete segment, header included.
+    fn read_segment(data: &[u8]) -> Result<PgsSegment> {
+        let (header_data, buffer) = data.split_at(PGS_SEGMENT_HEADER_LENGTH);
+        let header = PgsSegmentHeader::from_data(header_data)?;
+        if header.segment_type == PgsSegmentType::ERR {
+            debug!("keeping unknown segment type {:#04x}", header_data[10]);
+            return Ok(PgsSegment::Unknown(PgsUnknownSegment::from_data(header, header_data[10], buffer)?));
+        }
+        PgsSegment::from_data(header, buffer)
+    }
+
+    /// Handles a segment that cannot be read: a lenient parse skips ahead to the next segment header if the error is
+    /// recoverable, otherwise the error ends the parse, wrapped in `Error::Segment` with the offset and type of the
+    /// segment.
+    fn damage(&mut self, offset: usize, error: Error) -> Result<()> {
+        if !self.options.lenient || !error.is_recoverable() {
+            let segment_type = self.buffer.get(self.position + 10).copied();
+            return Err(Error::Segment { offset, segment_type, error: Box::new(error) });
+        }
+        self.damaged = Some(PgsDamagedData { offset, error: format!("{:?}", error) });
+        Ok(())
+    }
+
+    /// Finds the stream offset of the next buffered segment header at or after `from`: a `PG` magic followed by a
+    /// known segment type.
+    ///
+    /// # Returns
+    /// The offset of the header, or `None` if no complete header is buffered.
+    fn find_next_header(&self, from: usize) -> Option<usize> {
+        let start = from.saturating_sub(self.buffer_offset).max(self.position).min(self.buffer.len());
+        self.buffer[start..].windows(PGS_SEGMENT_HEADER_LENGTH).position(|header| {
+            u16::from_be_bytes([header[0], header[1]]) == PG && PgsSegmentType::from(header[10]) != PgsSegmentType::ERR
+        }).map(|position| self.buffer_offset + start + position)
+    }
+
+    /// Checks a parsed segment and adds it to the display set being read, ending the display set at an END
+    /// segment, or in a lenient parse at a PCS following segments without an END segment.
+    fn accept(&mut self, offset: usize, mut segment: PgsSegment, raw: Option<Vec<u8>>, events: &mut Vec<PgsParseEvent>) -> Result<()> {
+        trace!("{:?}", segment);
+        self.intern_palette(&mut segment);
+        self.check_timestamps(offset, &segment, events);
+        if let PgsSegment::Ods(ods) = &segment {
+            Self::check_object_data(offset, ods, events);
+        }
+        if let (PgsSegment::Pcs(_), true, false) = (&segment, self.options.lenient, self.display_set.is_empty()) {
+            self.clamp_cropping(events);
+            self.close_display_set(offset, events)?;
+        }
+        let end = matches!(segment, PgsSegment::End(_));
+        if end && self.options.lenient {
+            self.clamp_cropping(events);
+        }
+        self.count_segment(offset, &segment)?;
+        self.display_set.push((offset, segment, raw));
+        if end {
+            self.flush(true, events);
+        }
+        Ok(())
+    }
+
+    /// Replaces the entries of a PDS by the equal entries of an earlier PDS, so display sets re-sending the same
+    /// palette share a single copy of it.
+    fn intern_palette(&mut self, segment: &mut PgsSegment) {
+        let PgsSegment::Pds(pds) = segment else {
+            return;
+        };
+        match self.palettes.get(&pds.palette_entries) {
+            Some(entries) => PgsRc::make_mut(pds).palette_entries = entries.clone(),
+            None => {
+                self.palettes.insert(pds.palette_entries.clone());
+            }
+        }
+    }
+
+    /// Emits the segments of the display set being read, followed by the display set if it was `ended`.
+    fn flush(&mut self, ended: bool, events: &mut Vec<PgsParseEvent>) {
+        let display_set = std::mem::take(&mut self.display_set);
+        let segments: Vec<PgsSegment> = display_set.iter().map(|(_, segment, _)| segment.clone()).collect();
+        events.extend(display_set.into_iter().map(|(offset, segment, raw)| PgsParseEvent::Segment { offset, segment, raw }));
+        if ended {
+            let bytes = segments.iter().map(|segment| PGS_SEGMENT_HEADER_LENGTH + segment.header().segment_length as usize).sum();
+            self.trace.end_display_set(segments.len(), bytes);
+            events.extend(PgsDisplaySet::from_segments(&segments).into_iter().map(PgsParseEvent::DisplaySet));
+        }
+    }
+
+    /// Reports timestamps that break muxers: a decoding timestamp after the presentation timestamp, or a
+    /// presentation timestamp going backwards from the previous display set. A wrap around of the 32-bit
+    /// presentation timestamp is reported separately.
+    ///
+    /// # Arguments
+    /// * `offset` - The stream offset of the segment.
+    /// * `segment` - The segment just read.
+    fn check_timestamps(&mut self, offset: usize, segment: &PgsSegment, events: &mut Vec<PgsParseEvent>) {
+        let header = segment.header();
+        if header.decoding_timestamp > header.presentation_timestamp && !is_timestamp_wrap(header.decoding_timestamp, header.presentation_timestamp) {
+            events.push(PgsParseEvent::Issue(PgsParseIssue {
+                severity: PgsParseSeverity::Warning,
+                offset,
+                message: format!("decoding timestamp {} is after the presentation timestamp {}", header.decoding_timestamp,
+                    header.presentation_timestamp)
+            }));
+        }
+        if let PgsSegment::Pcs(pcs) = segment {
+            match self.previous_pts.replace(pcs.header.presentation_timestamp) {
+                Some(previous) if is_timestamp_wrap(previous, pcs.header.presentation_timestamp) => events.push(PgsParseEvent::Issue(PgsParseIssue {
+                    severity: PgsParseSeverity::Warning,
+                    offset,
+                    message: format!("presentation timestamp wraps around from {} to {}", previous, pcs.header.presentation_timestamp)
+                })),
+                Some(previous) if pcs.header.presentation_timestamp < previous => events.push(PgsParseEvent::Issue(PgsParseIssue {
+                    severity: PgsParseSeverity::Warning,
+                    offset,
+                    message: format!("presentation timestamp goes backwards from {} to {}", previous, pcs.header.presentation_timestamp)
+                })),
+                _ => {}
+            }
+        }
+    }
+
+    /// Clamps cropping rectangles that are empty or outside their object in the composition of the display set
+    /// being read, recording a warning for every changed composition object. The sizes of the objects defined in
+    /// the current epoch are updated with the display set's objects.
+    fn clamp_cropping(&mut self, events: &mut Vec<PgsParseEvent>) {
+        let object_sizes = &mut self.object_sizes;
+        for (_, segment, _) in &self.display_set {
+            match segment {
+                PgsSegment::Pcs(pcs) if pcs.composition_state == PgsPcsCompositionState::EpochStart => object_sizes.clear(),
+                PgsSegment::Ods(ods) if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) => {
+                    object_sizes.insert(ods.object_id, (ods.width, ods.height));
+                },
+                _ => {}
+            }
+        }
+
+        let Some((offset, PgsSegment::Pcs(pcs), _)) = self.display_set.first_mut() else {
+            return;
+        };
+        let fits = |com_obj: &PgsPcsSegmentCompositionObjects| object_sizes.get(&com_obj.object_id)
+            .is_none_or(|&(width, height)| com_obj.cropping_fits(width, height));
+        if pcs.composition_objects.iter().all(fits) {
+            return;
+        }
+        for com_obj in &mut PgsRc::make_mut(pcs).composition_objects {
+            let Some(&(width, height)) = object_sizes.get(&com_obj.object_id) else {
+                continue;
+            };
+            if com_obj.clamp_cropping(width, height) {
+                warn!("clamped the cropping rectangle of object {} in the PCS at offset {:#x}", com_obj.object_id, offset);
+                events.push(PgsParseEvent::Issue(PgsParseIssue {
+                    severity: PgsParseSeverity::Warning,
+                    offset: *offset,
+                    message: format!("cropping rectangle of object {} is empty or outside the {}x{} object, clamped", com_obj.object_id,
+                        width, height)
+                }));
+            }
+        }
+    }
+
+    /// Ends the display set being read with an END segment, for streams where the END segment is missing before
+    /// the PCS at `offset`. The END segment takes the timestamps of the display set's first segment.
+    fn close_display_set(&mut self, offset: usize, events: &mut Vec<PgsParseEvent>) -> Result<()> {
+        let header = self.display_set[0].1.header();
+        let header = PgsSegmentHeader {
+            segment_type: PgsSegmentType::END,
+            segment_length: 0,
+            presentation_timestamp: header.presentation_timestamp,
+            decoding_timestamp: header.presentation_timestamp
+        };
+        warn!("missing END segment before the PCS at offset {:#x}", offset);
+        events.push(PgsParseEvent::Issue(PgsParseIssue {
+            severity: PgsParseSeverity::Warning,
+            offset,
+            message: "missing END segment before this PCS, display set ended here".to_string()
+        }));
+        let end = PgsSegment::End(PgsRc::new(PgsEndSegment { header, data: Vec::new() }));
+        self.count_segment(offset, &end)?;
+        self.display_set.push((offset, end, None));
+        self.flush(true, events);
+        Ok(())
+    }
+
+    /// Counts a segment against the segment and display set limits of the options.
+    ///
+    /// # Arguments
+    /// * `offset` - The stream offset of the segment.
+    /// * `segment` - The segment to count.
+    ///
+    /// # Errors
+    /// Returns `Error::LimitExceeded`, wrapped in `Error::Segment` with the offset and type of the segment, if the
+    /// segment would exceed `max_segments`, or complete more than `max_display_sets` display sets.
+    fn count_segment(&mut self, offset: usize, segment: &PgsSegment) -> Result<()> {
+        let limit_exceeded = |limit, value| Error::Segment { offset, segment_type: Some(segment.segment_type_byte()), error: Box::new(Error::LimitExceeded { limit, value }) };
+        if let Some(max) = self.options.max_segments.filter(|max| self.segment_count >= *max) {
+            error!("more than {max} segments");
+            return Err(limit_exceeded("max_segments", max));
+        }
+        if let PgsSegment::End(_) = segment {
+            if let Some(max) = self.options.max_display_sets.filter(|max| self.display_set_count >= *max) {
+                error!("more than {max} display sets");
+                return Err(limit_exceeded("max_display_sets", max));
+            }
+            self.display_set_count += 1;
+        }
+        self.segment_count += 1;
+        Ok(())
+    }
+
+    /// Records warnings for ODS fragments without object data, and for padding after the data of a
+    /// single-fragment object.
+    ///
+    /// # Arguments
+    /// * `offset` - The stream offset of the segment.
+    /// * `ods` - The ODS just read.
+    fn check_object_data(offset: usize, ods: &PgsOdsSegment, events: &mut Vec<PgsParseEvent>) {
+        if ods.object_data.is_empty() {
+            events.push(PgsParseEvent::Issue(PgsParseIssue {
+                severity: PgsParseSeverity::Warning,
+                offset,
+                message: format!("ODS fragment of object {} has no object data", ods.object_id)
+            }));
+        }
+        let padding = (ods.header.segment_length as usize).saturating_sub(ODS_FIRST_FRAGMENT_HEADER_LENGTH + ods.object_data.len());
+        if ods.last_in_sequence_flag == PgsOdsSequenceFlag::Both && padding > 0 {
+            events.push(PgsParseEvent::Issue(PgsParseIssue {
+                severity: PgsParseSeverity::Warning,
+                offset,
+                message: format!("{} padding bytes after the data of object {} ignored", padding, ods.object_id)
+            }));
+        }
+    }
+}
+
+/// Writes a 64-bit big endian value.
+fn write_u64(data: &mut Vec<u8>, value: u64) -> Result<()> {
+    data.write_u32::<BigEndian>((value >> 32) as u32)?;
+    data.write_u32::<BigEndian>(value as u32)
+}
+
+/// Reads a 64-bit big endian value.
+fn read_u64(buffer: &mut PgsMemoryBuffer) -> Result<u64> {
+    Ok(((buffer.read_u32::<BigEndian>()? as u64) << 32) | buffer.read_u32::<BigEndian>()? as u64)
+}
+
+/// Writes a byte string prefixed with its 32-bit length.
+fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+    data.write_u32::<BigEndian>(bytes.len() as u32)?;
+    data.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// Reads a byte string written by `write_bytes`.
+fn read_bytes(buffer: &mut PgsMemoryBuffer) -> Result<Vec<u8>> {
+    let length = buffer.read_u32::<BigEndian>()?;
+    if length as usize > buffer.remaining_slice().len() {
+        return Err(Error::InvalidSegmentDataLength { length: buffer.remaining_slice().len(), expected: length as usize });
+    }
+    buffer.read_into_vec(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::display_set_bytes, PgsPdsSegment};
+
+    use super::*;
+
+    /// Summarizes events as segment offsets, display set counts, skipped regions and issue offsets.
+    fn summary(events: &[PgsParseEvent]) -> Vec<String> {
+        events.iter().map(|event| match event {
+            PgsParseEvent::Segment { offset, .. } => format!("segment {offset}"),
+            PgsParseEvent::DisplaySet(_) => "display set".to_string(),
+            PgsParseEvent::Skipped(region) => format!("skipped {} {}", region.offset, region.length),
+            PgsParseEvent::Issue(issue) => format!("{:?} {}", issue.severity, issue.offset),
+            PgsParseEvent::Error(error) => format!("{:?}", error)
+        }).collect()
+    }
+
+    #[test]
+    fn test_push_bytes() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        data.extend([0x50, 0x47, 0xFF, 0x12, 0x34]);
+        data.extend(display_set_bytes(0, 1, 0x80));
+        data.extend(&display_set_bytes(0, 2, 0x80)[..20]);
+
+        let options = PgsParserOptions { lenient: true, ..Default::default() };
+        let mut parser = PgsPushParser::new(options.clone());
+        let mut whole = parser.push_bytes(&data);
+        whole.extend(parser.finish());
+        assert_eq!(summary(&whole), [
+            "segment 0", "segment 24", "display set", "skipped 37 5", "Error 37", "segment 42", "segment 66", "display set", "Warning 79"
+        ]);
+
+        let mut parser = PgsPushParser::new(options);
+        let mut bytewise: Vec<PgsParseEvent> = data.iter().flat_map(|byte| parser.push_bytes(&[*byte])).collect();
+        bytewise.extend(parser.finish());
+        assert_eq!(summary(&bytewise), summary(&whole));
+
+        let mut parser = PgsPushParser::new(PgsParserOptions::default());
+        let events = parser.push_bytes(&data);
+        assert!(matches!(events.last(), Some(PgsParseEvent::Error(Error::Segment { offset: 37, segment_type: Some(0), error }))
+            if matches!(**error, Error::UnsupportedSegmentType(0))));
+        assert!(parser.push_bytes(&data).is_empty());
+    }
+
+    #[test]
+    fn test_intern_palettes() {
+        let pds = |pts: u8, luminance: u8| vec![0x50, 0x47, 0, 0, 0, pts, 0, 0, 0, 0, 0x14, 0, 7, 0, 0, 1, luminance, 0x80, 0x80, 0xFF];
+        let data = [pds(1, 0xEB), pds(2, 0xEB), pds(3, 0x10)].concat();
+
+        let mut parser = PgsPushParser::new(PgsParserOptions::default());
+        let mut events = parser.push_bytes(&data);
+        events.extend(parser.finish());
+        let palettes: Vec<PgsRc<PgsPdsSegment>> = events.into_iter().filter_map(|event| match event {
+            PgsParseEvent::Segment { segment: PgsSegment::Pds(pds), .. } => Some(pds),
+            _ => None
+        }).collect();
+        assert_eq!(palettes.len(), 3);
+        assert!(PgsRc::ptr_eq(&palettes[0].palette_entries, &palettes[1].palette_entries));
+        assert_eq!(palettes[1].header.presentation_timestamp, 2);
+        assert!(palettes[0].same_entries(&palettes[1]));
+        assert!(!palettes[1].same_entries(&palettes[2]));
+    }
+
+    #[test]
+    fn test_suspend_resume() {
+        let mut data = display_set_bytes(0, 0, 0x80);
+        data.extend([0x50, 0x47, 0xFF, 0x12, 0x34]);
+        data.extend(display_set_bytes(0, 1, 0x80));
+        data.extend(display_set_bytes(0, 2, 0x80));
+
+        let options = PgsParserOptions { lenient: true, ..Default::default() };
+        let mut parser = PgsPushParser::new(options.clone());
+        let mut whole = parser.push_bytes(&data);
+        whole.extend(parser.finish());
+
+        // Suspend in the damaged bytes, and again in the middle of a display set and of a segment
+        let mut parser = PgsPushParser::new(options.clone());
+        let mut resumed = Vec::new();
+        for (start, end) in [(0, 40), (40, 70), (70, data.len())] {
+            parser = PgsPushParser::resume(options.clone(), &parser.suspend().unwrap()).unwrap();
+            assert_eq!(parser.offset(), start);
+            resumed.extend(parser.push_bytes(&data[start..end]));
+        }
+        resumed.extend(parser.finish());
+        assert_eq!(summary(&resumed), summary(&whole));
+
+        assert!(PgsPushParser::resume(options, &data).is_err());
+    }
+}
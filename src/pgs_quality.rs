@@ -0,0 +1,223 @@
+//! # PGS Quality Report
+//!
+//! This module combines the validation of a stream with the analyses of its subtitle events into one report of
+//! findings graded as errors, warnings or information. How serious a finding is depends on where the track is
+//! going: a missing decoding timestamp breaks a Blu-ray disc but is harmless in a Matroska file, so the findings
+//! are graded by a selectable profile, which also decides whether the track passes.
+
+use std::fmt::{self, Display};
+
+use crate::{pgs_blank::{find_blank_display_sets, PGS_DEFAULT_STRAY_PIXELS}, pgs_dts::analyze_decoding_timestamps, pgs_duplicates::find_duplicate_display_sets,
+    pgs_export::export_events, pgs_overlap::find_window_overlaps, pgs_sizes::event_sizes, pgs_statistics::statistics, validate_segments,
+    PgsStream, PgsValidationCategory, PgsValidationSeverity, Result};
+
+/// Grade of a quality finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PgsQualityLevel {
+    /// Worth knowing, without a problem.
+    Info,
+    /// Unusual or wasteful, but the track plays.
+    Warning,
+    /// The track fails the profile.
+    Error
+}
+
+/// The check that produced a quality finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgsQualityCheck {
+    /// A violation of the PGS specification found by `validate_segments`.
+    Validation(PgsValidationCategory),
+    /// Missing or implausible decoding timestamps.
+    DecodingTimestamps,
+    /// Events overlapping in time.
+    EventOverlap,
+    /// Display sets composing into a window still showing an earlier one.
+    WindowOverlap,
+    /// Display sets repeating the screen content of the previous one.
+    Duplicate,
+    /// Display sets showing nothing but a few stray pixels.
+    Blank,
+    /// Bitmaps covering most of the screen.
+    Oversized,
+    /// Facts about the track as a whole.
+    Summary
+}
+
+/// Delivery targets the findings of a quality report are graded for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgsQualityProfile {
+    /// Authoring for Blu-ray discs, where hardware players reject or misplay anything outside the specification.
+    BdRomStrict,
+    /// Soft subtitles muxed into files for software players, which tolerate most deviations. Only broken
+    /// references keep their grade; every other finding is graded one level lower.
+    SoftSubLenient
+}
+
+impl PgsQualityProfile {
+    /// Grades a finding of a check that would have the level `strict` under `BdRomStrict`.
+    pub fn grade(&self, check: PgsQualityCheck, strict: PgsQualityLevel) -> PgsQualityLevel {
+        match (self, check) {
+            (PgsQualityProfile::BdRomStrict, _) | (_, PgsQualityCheck::Validation(PgsValidationCategory::Reference)) => strict,
+            (PgsQualityProfile::SoftSubLenient, _) => match strict {
+                PgsQualityLevel::Error => PgsQualityLevel::Warning,
+                _ => PgsQualityLevel::Info
+            }
+        }
+    }
+}
+
+impl Display for PgsQualityProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PgsQualityProfile::BdRomStrict => "BD-ROM strict",
+            PgsQualityProfile::SoftSubLenient => "soft-sub lenient"
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A single finding of a quality report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsQualityFinding {
+    pub level: PgsQualityLevel,
+    pub check: PgsQualityCheck,
+    /// Index of the display set the finding is about, `None` for findings about the whole track.
+    pub display_set: Option<usize>,
+    /// Presentation timestamp (90 kHz) of the display set.
+    pub presentation_timestamp: Option<u32>,
+    pub message: String
+}
+
+impl Display for PgsQualityFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.display_set, self.presentation_timestamp) {
+            (Some(display_set), Some(pts)) => write!(f, "{:?} [{:?}] display set {} (pts {}): {}", self.level, self.check, display_set, pts, self.message),
+            (Some(display_set), None) => write!(f, "{:?} [{:?}] display set {}: {}", self.level, self.check, display_set, self.message),
+            _ => write!(f, "{:?} [{:?}] {}", self.level, self.check, self.message)
+        }
+    }
+}
+
+/// The findings of all checks of a stream, graded by a profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsQualityReport {
+    pub profile: PgsQualityProfile,
+    /// The findings, grouped by check in the order the checks run.
+    pub findings: Vec<PgsQualityFinding>
+}
+
+impl PgsQualityReport {
+    /// Returns `true` if no finding is graded as an error.
+    pub fn passed(&self) -> bool {
+        self.count(PgsQualityLevel::Error) == 0
+    }
+
+    /// Returns the number of findings with a level.
+    pub fn count(&self, level: PgsQualityLevel) -> usize {
+        self.findings.iter().filter(|finding| finding.level == level).count()
+    }
+}
+
+/// Checks a stream with the validator and the event analyses and grades the findings for a profile.
+///
+/// The checks, with their grade under `BdRomStrict`:
+/// - Validation: the issues of `validate_segments`, errors as errors and warnings as warnings.
+/// - Decoding timestamps: missing or bogus decoding timestamps (`analysis::analyze_decoding_timestamps`), an error.
+/// - Event overlaps: events overlapping in time (`analysis::statistics`), an error.
+/// - Window overlaps, duplicates, blank and oversized display sets: warnings.
+/// - Summary: the number of events and forced events, information.
+///
+/// # Parameters
+/// - `stream`: The stream to check.
+/// - `profile`: The profile grading the findings.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// The graded findings.
+pub fn check_quality(stream: &PgsStream, profile: PgsQualityProfile) -> Result<PgsQualityReport> {
+    let segments = stream.get_segments();
+    let display_sets = stream.get_display_sets();
+    let events = export_events(&display_sets);
+    let mut findings: Vec<PgsQualityFinding> = Vec::new();
+    let mut add = |check: PgsQualityCheck, strict: PgsQualityLevel, display_set: Option<usize>, presentation_timestamp: Option<u32>, message: String| {
+        findings.push(PgsQualityFinding { level: profile.grade(check, strict), check, display_set, presentation_timestamp, message });
+    };
+
+    for issue in validate_segments(segments).issues {
+        let strict = match issue.severity {
+            PgsValidationSeverity::Error => PgsQualityLevel::Error,
+            PgsValidationSeverity::Warning => PgsQualityLevel::Warning
+        };
+        add(PgsQualityCheck::Validation(issue.category), strict, Some(issue.display_set), Some(issue.presentation_timestamp), issue.message);
+    }
+
+    let dts = analyze_decoding_timestamps(segments);
+    if dts.needs_regeneration() {
+        add(PgsQualityCheck::DecodingTimestamps, PgsQualityLevel::Error, None, None,
+            format!("{} of {} segments have no decoding timestamp and {} a bogus one", dts.zeroed, dts.deltas.len(), dts.bogus));
+    }
+
+    let stats = statistics(&events);
+    for &(first, second) in &stats.overlaps {
+        add(PgsQualityCheck::EventOverlap, PgsQualityLevel::Error, Some(events[second].display_set), Some(events[second].start),
+            format!("starts before the event of display set {} ends", events[first].display_set));
+    }
+    for overlap in find_window_overlaps(&display_sets) {
+        add(PgsQualityCheck::WindowOverlap, PgsQualityLevel::Warning, Some(overlap.second), Some(overlap.presentation_timestamp),
+            format!("composes in window {} over display set {} still on screen", overlap.window_id, overlap.first));
+    }
+    for duplicate in find_duplicate_display_sets(segments)? {
+        add(PgsQualityCheck::Duplicate, PgsQualityLevel::Warning, Some(duplicate.duplicate), Some(duplicate.presentation_timestamp),
+            format!("repeats the screen of display set {}", duplicate.original));
+    }
+    for blank in find_blank_display_sets(segments, PGS_DEFAULT_STRAY_PIXELS)? {
+        add(PgsQualityCheck::Blank, PgsQualityLevel::Warning, Some(blank.display_set), Some(blank.presentation_timestamp),
+            format!("shows only {} visible pixels", blank.visible_pixels));
+    }
+    for size in event_sizes(&display_sets)?.into_iter().filter(|size| size.oversized) {
+        add(PgsQualityCheck::Oversized, PgsQualityLevel::Warning, Some(size.display_set), Some(size.start),
+            format!("{}x{} bitmap covers {:.1}% of the screen", size.width, size.height, size.screen_percentage));
+    }
+
+    add(PgsQualityCheck::Summary, PgsQualityLevel::Info, None, None, format!("{} events, {} forced", stats.events, stats.forced_events));
+    Ok(PgsQualityReport { profile, findings })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{black_and_white, ods, pds, segments, window_display_set}, PgsDisplaySet, PgsPcsCompositionState, PgsRc, PgsSegment};
+
+    use super::*;
+
+    /// An Epoch Start of a 1920x1080 video showing a 10x10 object with `visible` opaque pixels, without decoding
+    /// timestamps.
+    fn display_set(pts: u32, visible: usize) -> Vec<PgsSegment> {
+        let bitmap: Vec<Vec<u8>> = (0..10).map(|y| (0..10).map(|x| (10 * y + x < visible) as u8).collect()).collect();
+        segments(&PgsDisplaySet {
+            pds: Some(PgsRc::new(pds(pts, 0, black_and_white()))),
+            ods: Some(PgsRc::new(ods(pts, 0, 0, 10, 10, &bitmap))),
+            ..window_display_set(pts, PgsPcsCompositionState::EpochStart, (1920, 1080), (10, 20, 10, 10))
+        })
+    }
+
+    #[test]
+    fn test_check_quality() {
+        let stream = PgsStream::new([display_set(90000, 50), display_set(180000, 0)].concat());
+
+        let report = check_quality(&stream, PgsQualityProfile::BdRomStrict).unwrap();
+        let checks: Vec<(PgsQualityCheck, PgsQualityLevel)> = report.findings.iter().map(|finding| (finding.check, finding.level)).collect();
+        assert_eq!(checks, [
+            (PgsQualityCheck::DecodingTimestamps, PgsQualityLevel::Error),
+            (PgsQualityCheck::Blank, PgsQualityLevel::Warning),
+            (PgsQualityCheck::Summary, PgsQualityLevel::Info)
+        ]);
+        assert!(!report.passed());
+
+        let report = check_quality(&stream, PgsQualityProfile::SoftSubLenient).unwrap();
+        assert_eq!((report.count(PgsQualityLevel::Error), report.count(PgsQualityLevel::Warning), report.count(PgsQualityLevel::Info)), (0, 1, 2));
+        assert!(report.passed());
+        assert_eq!(PgsQualityProfile::SoftSubLenient.grade(PgsQualityCheck::Validation(PgsValidationCategory::Reference), PgsQualityLevel::Error), PgsQualityLevel::Error);
+    }
+}
@@ -0,0 +1,7 @@
+//! # PGS Rectangle
+//!
+//! This module defines `PgsRectangle`, the rectangle type shared by windows, cropping rectangles and the screen
+//! areas of objects.
+
+/// A rectangle as position and size: x, y, width and height.
+pub type PgsRectangle = (u16, u16, u16, u16);
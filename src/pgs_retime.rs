@@ -0,0 +1,732 @@
+//! # PGS Retiming
+//!
+//! This module changes the timing of a `PgsStream`, for example to fix a subtitle track that is out of sync with
+//! its video. The retimed stream can be written back as a new SUP file with `PgsStream::write`.
+
+use log::warn;
+
+use crate::{Error, PgsDisplaySetState, PgsEndSegment, PgsPcsCompositionState, PgsPcsSegment, PgsRc, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsStream, PgsWdsSegment, Result};
+
+/// Shifts every presentation and decoding timestamp of a stream by a fixed offset.
+///
+/// Timestamps that would become negative are set to zero, and timestamps beyond the `u32` range are clamped to
+/// its maximum; a warning is logged for them. A zero DTS means the encoder did not set one, so it is left
+/// unchanged.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `delta`: The offset in 90 kHz ticks, negative to make subtitles appear earlier.
+///
+/// # Returns
+/// The number of timestamps that had to be clamped.
+pub fn shift(stream: &mut PgsStream, delta: i64) -> usize {
+    let clamped = stream.shift_timestamps(delta);
+    if clamped > 0 {
+        warn!("{} timestamps shifted by {} ticks were clamped to the valid range", clamped, delta);
+    }
+    clamped
+}
+
+/// Ratio by which timestamps are multiplied, kept as a fraction so retiming is exact at 90 kHz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgsRetimeFactor {
+    pub numerator: u64,
+    pub denominator: u64
+}
+
+impl PgsRetimeFactor {
+    /// Returns the exact fraction of a frame rate: NTSC rates such as 23.976 or 29.97 are taken as
+    /// 24000/1001 and 30000/1001, other rates are rounded to a thousandth of a frame.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidArgument` if the frame rate is not positive and finite, or rounds to zero.
+    pub(crate) fn frame_rate_fraction(frame_rate: f64) -> Result<(u64, u64)> {
+        if !frame_rate.is_finite() || frame_rate <= 0.0 {
+            return Err(Error::InvalidArgument(format!("invalid frame rate {frame_rate}")));
+        }
+        let nominal = (frame_rate * 1.001).round();
+        let fraction = if frame_rate.fract() != 0.0 && (frame_rate - nominal * 1000.0 / 1001.0).abs() < 0.005 {
+            ((nominal * 1000.0) as u64, 1001)
+        } else {
+            ((frame_rate * 1000.0).round() as u64, 1000)
+        };
+        match fraction {
+            (0, _) => Err(Error::InvalidArgument(format!("frame rate {frame_rate} is below a thousandth of a frame per second"))),
+            fraction => Ok(fraction)
+        }
+    }
+
+    /// Creates the factor that converts timestamps of a stream played at `from` frames per second to a video
+    /// played at `to` frames per second, e.g. 25 to 23.976 (x1.042708...) to undo a PAL speed-up.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidArgument` if a frame rate is not positive and finite, is below a thousandth of a
+    /// frame per second, or is too large for the factor.
+    pub fn from_frame_rates(from: f64, to: f64) -> Result<Self> {
+        let invalid = || Error::InvalidArgument(format!("cannot convert from {from} to {to} frames per second"));
+        let ((from_num, from_den), (to_num, to_den)) = (Self::frame_rate_fraction(from)?, Self::frame_rate_fraction(to)?);
+        let numerator = from_num.checked_mul(to_den).ok_or_else(invalid)?;
+        let denominator = from_den.checked_mul(to_num).ok_or_else(invalid)?;
+        Ok(Self::new(numerator, denominator))
+    }
+
+    /// Creates a factor from a fraction, reduced to lowest terms.
+    pub fn new(numerator: u64, denominator: u64) -> Self {
+        let (mut a, mut b) = (numerator, denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        let divisor = a.max(1);
+        PgsRetimeFactor { numerator: numerator / divisor, denominator: denominator / divisor }
+    }
+
+    /// Multiplies a timestamp by the factor, rounding to the nearest tick.
+    ///
+    /// # Returns
+    /// The scaled timestamp, or `None` if it does not fit into a `u32`.
+    pub(crate) fn apply(&self, timestamp: u32) -> Option<u32> {
+        let scaled = (timestamp as u128 * self.numerator as u128 + self.denominator as u128 / 2) / self.denominator as u128;
+        u32::try_from(scaled).ok()
+    }
+}
+
+/// Multiplies every presentation and decoding timestamp of a stream by a factor, the usual fix for subtitles
+/// drifting out of sync after a frame-rate conversion.
+///
+/// Timestamps are scaled with integer math and rounded to the nearest 90 kHz tick. Timestamps beyond the `u32`
+/// range are clamped to its maximum, and a zero DTS is left unchanged.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `factor`: The factor, for example from `PgsRetimeFactor::from_frame_rates`.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the factor is zero or has a zero denominator.
+///
+/// # Returns
+/// The number of timestamps that had to be clamped.
+pub fn stretch(stream: &mut PgsStream, factor: PgsRetimeFactor) -> Result<usize> {
+    if factor.numerator == 0 || factor.denominator == 0 {
+        return Err(Error::InvalidArgument(format!("invalid retiming factor {}/{}", factor.numerator, factor.denominator)));
+    }
+    let mut clamped = 0;
+    let mut scale = |timestamp: u32| factor.apply(timestamp).unwrap_or_else(|| {
+        clamped += 1;
+        u32::MAX
+    });
+    for segment in stream.get_segments_mut() {
+        let header = segment.header_mut();
+        header.presentation_timestamp = scale(header.presentation_timestamp);
+        if header.decoding_timestamp != 0 {
+            header.decoding_timestamp = scale(header.decoding_timestamp);
+        }
+    }
+    if clamped > 0 {
+        warn!("{} timestamps stretched by {}/{} were clamped to the valid range", clamped, factor.numerator, factor.denominator);
+    }
+    Ok(clamped)
+}
+
+/// Moves every display set starting with a PCS to a new presentation timestamp, shifting the PTS and DTS of all
+/// its segments by the same amount.
+///
+/// # Parameters
+/// - `timestamps`: The new presentation timestamp of every display set, indexed as `PgsStream::get_display_sets`.
+///
+/// # Returns
+/// The number of display sets that were moved.
+fn move_display_sets(stream: &mut PgsStream, timestamps: &[u32]) -> usize {
+    let mut moved = 0;
+    let mut index = 0;
+    let mut delta: Option<i64> = None;
+    for segment in stream.get_segments_mut() {
+        let Some(&timestamp) = timestamps.get(index) else {
+            break;
+        };
+        if let PgsSegment::Pcs(pcs) = segment {
+            let shift = timestamp as i64 - pcs.header.presentation_timestamp as i64;
+            moved += (shift != 0) as usize;
+            delta = Some(shift);
+        }
+        let header = segment.header_mut();
+        if let Some(delta) = delta.filter(|&delta| delta != 0) {
+            header.presentation_timestamp = (header.presentation_timestamp as i64 + delta).clamp(0, u32::MAX as i64) as u32;
+            if header.decoding_timestamp != 0 {
+                header.decoding_timestamp = (header.decoding_timestamp as i64 + delta).clamp(0, u32::MAX as i64) as u32;
+            }
+        }
+        if let PgsSegment::End(_) = segment {
+            index += 1;
+            delta = None;
+        }
+    }
+    moved
+}
+
+/// Moves display sets so that every event starts and ends on a video frame boundary, and events are separated by
+/// at least `min_gap_frames` frames, as required by Blu-ray authoring tools.
+///
+/// Every display set is moved to the nearest frame boundary. When a display set clearing the screen is followed
+/// by a new subtitle less than `min_gap_frames` frames later, the clear is moved earlier, but never to or before
+/// the frame of the display set preceding it. The segments of a moved display set keep their PTS/DTS differences.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `frame_rate`: The frame rate of the video, e.g. `23.976`.
+/// - `min_gap_frames`: The minimum number of frames between the end of an event and the start of the next one.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the frame rate is not positive and finite, or is below a thousandth of a
+/// frame per second.
+///
+/// # Returns
+/// The number of display sets that were moved.
+pub fn snap_to_frames(stream: &mut PgsStream, frame_rate: f64, min_gap_frames: u32) -> Result<usize> {
+    let (numerator, denominator) = PgsRetimeFactor::frame_rate_fraction(frame_rate)?;
+    let ticks = 90000 * denominator as u128;
+    let to_frame = |timestamp: u32| (timestamp as u128 * numerator as u128 * 2 + ticks) / (ticks * 2);
+    let to_timestamp = |frame: u128| u32::try_from((frame * ticks + numerator as u128 / 2) / numerator as u128).unwrap_or(u32::MAX);
+
+    let display_sets = stream.get_display_sets();
+    let mut frames: Vec<u128> = display_sets.iter()
+        .map(|ds| to_frame(ds.pcs.as_ref().map_or(0, |pcs| pcs.header.presentation_timestamp)))
+        .collect();
+    for index in 1..display_sets.len().saturating_sub(1) {
+        let (clear, next) = (&display_sets[index], &display_sets[index + 1]);
+        if clear.state() == PgsDisplaySetState::EmptyFrame && next.starts_presentation()
+            && frames[index + 1] < frames[index] + min_gap_frames as u128 {
+            frames[index] = frames[index + 1].saturating_sub(min_gap_frames as u128).max(frames[index - 1] + 1).min(frames[index]);
+        }
+    }
+
+    let timestamps: Vec<u32> = frames.into_iter().map(to_timestamp).collect();
+    Ok(move_display_sets(stream, &timestamps))
+}
+
+/// Summary of the changes made by `set_frame_rate`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsFrameRateReport {
+    /// PCS segments whose frame rate code was changed.
+    pub compositions_changed: usize,
+    /// Display sets moved to a frame boundary of the new frame rate; always zero without `snap`.
+    pub moved_display_sets: usize
+}
+
+/// Sets the frame rate code of every PCS, for a stream retargeted to a master with another frame rate, and with
+/// `snap` also moves every display set to the nearest frame boundary of the new frame rate (see `snap_to_frames`).
+///
+/// Timestamps are otherwise left unchanged; use `stretch` first if the subtitles have to follow a speed change.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `frame_rate`: The new frame rate, one of `PGS_FRAME_RATE_CODES`.
+/// - `snap`: Whether to snap the display sets to the frames of the new frame rate.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the frame rate has no PCS frame rate code.
+///
+/// # Returns
+/// A `PgsFrameRateReport` with the number of changed frame rate codes and moved display sets.
+pub fn set_frame_rate(stream: &mut PgsStream, frame_rate: f64, snap: bool) -> Result<PgsFrameRateReport> {
+    let code = PgsPcsSegment::frame_rate_code(frame_rate)
+        .ok_or_else(|| Error::InvalidArgument(format!("frame rate {frame_rate} cannot be stored in a PCS")))?;
+    let mut report = PgsFrameRateReport::default();
+    for segment in stream.get_segments_mut() {
+        if let PgsSegment::Pcs(pcs) = segment {
+            if pcs.frame_rate != code {
+                PgsRc::make_mut(pcs).frame_rate = code;
+                report.compositions_changed += 1;
+            }
+        }
+    }
+    if snap {
+        report.moved_display_sets = snap_to_frames(stream, frame_rate, 0)?;
+    }
+    Ok(report)
+}
+
+/// Minimum timing constraints enforced by `enforce_timing`, in 90 kHz ticks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PgsTimingRules {
+    /// Minimum time an event stays on screen.
+    pub min_duration: u32,
+    /// Minimum time between the end of an event and the start of the next one.
+    pub min_gap: u32
+}
+
+/// Why `enforce_timing` moved a display set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgsTimingAdjustmentReason {
+    /// The event was shorter than the minimum duration and was extended.
+    MinimumDuration,
+    /// The event ended after the next event started and was trimmed.
+    Overlap,
+    /// The event ended too close to the start of the next event and was trimmed.
+    MinimumGap
+}
+
+/// A display set clearing the screen that was moved by `enforce_timing`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgsTimingAdjustment {
+    /// Index of the moved display set.
+    pub display_set: usize,
+    /// Presentation timestamp (90 kHz) before the adjustment.
+    pub old_timestamp: u32,
+    /// Presentation timestamp (90 kHz) after the adjustment.
+    pub new_timestamp: u32,
+    pub reason: PgsTimingAdjustmentReason
+}
+
+/// Extends events shorter than `rules.min_duration`, trims events overlapping the next one, and trims events
+/// ending less than `rules.min_gap` before the next one starts.
+///
+/// Events are changed by moving the display sets that clear the screen; subtitles never move. An event replaced
+/// directly by the next one has no end to move and is left as it is. When the minimum duration and the minimum
+/// gap cannot both be met, the gap wins, and an event always keeps at least one tick on screen.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `rules`: The constraints to enforce.
+///
+/// # Returns
+/// Every adjustment made, in stream order.
+pub fn enforce_timing(stream: &mut PgsStream, rules: PgsTimingRules) -> Vec<PgsTimingAdjustment> {
+    let display_sets = stream.get_display_sets();
+    let mut timestamps: Vec<u32> = display_sets.iter()
+        .map(|ds| ds.pcs.as_ref().map_or(0, |pcs| pcs.header.presentation_timestamp))
+        .collect();
+    let mut adjustments: Vec<PgsTimingAdjustment> = Vec::new();
+    for (start, ds) in display_sets.iter().enumerate() {
+        if !ds.starts_presentation() {
+            continue;
+        }
+        let Some(end) = (start + 1..display_sets.len()).find(|&index| display_sets[index].ends_presentation()) else {
+            continue;
+        };
+        if display_sets[end].state() != PgsDisplaySetState::EmptyFrame {
+            continue;
+        }
+        let next = (end + 1..display_sets.len()).find(|&index| display_sets[index].starts_presentation())
+            .map(|index| timestamps[index]);
+
+        let (shown, old_timestamp) = (timestamps[start], timestamps[end]);
+        let mut timestamp = old_timestamp;
+        let mut reason = None;
+        if timestamp < shown.saturating_add(rules.min_duration) {
+            timestamp = shown.saturating_add(rules.min_duration);
+            reason = Some(PgsTimingAdjustmentReason::MinimumDuration);
+        }
+        if let Some(next) = next {
+            let latest = next.saturating_sub(rules.min_gap).max(shown.saturating_add(1)).min(next);
+            if old_timestamp > next {
+                (timestamp, reason) = (latest, Some(PgsTimingAdjustmentReason::Overlap));
+            } else if timestamp > latest {
+                timestamp = latest;
+                if timestamp < old_timestamp {
+                    reason = Some(PgsTimingAdjustmentReason::MinimumGap);
+                }
+            }
+        }
+        if let (Some(reason), true) = (reason, timestamp != old_timestamp) {
+            timestamps[end] = timestamp;
+            adjustments.push(PgsTimingAdjustment { display_set: end, old_timestamp, new_timestamp: timestamp, reason });
+        }
+    }
+
+    move_display_sets(stream, &timestamps);
+    adjustments
+}
+
+/// Adds display sets clearing the screen after events that are never cleared, which some players would otherwise
+/// keep on screen.
+///
+/// An event is never cleared if the display sets after it show no empty frame before the next epoch starts or the
+/// stream ends; an event replaced by another one within the same epoch is cleared by it. The clear is placed
+/// `max_duration` after the last display set showing the event, or at the start of the next epoch if that comes
+/// earlier. It repeats the windows of the event, so decoders erase them, and compositions are renumbered.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `max_duration`: Time an uncleared event stays on screen, in 90 kHz ticks.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if `max_duration` is zero.
+///
+/// # Returns
+/// The presentation timestamps of the inserted display sets.
+pub fn insert_missing_clears(stream: &mut PgsStream, max_duration: u32) -> Result<Vec<u32>> {
+    if max_duration == 0 {
+        return Err(Error::InvalidArgument("maximum duration must be positive".to_string()));
+    }
+
+    let display_sets = stream.get_display_sets();
+    let epoch_start = |index: usize| display_sets[index].pcs.as_ref().is_some_and(|pcs| pcs.composition_state == PgsPcsCompositionState::EpochStart);
+    // Index of the last display set showing every uncleared event, with the event's windows and the clear time
+    let mut clears: Vec<(usize, PgsRc<PgsWdsSegment>, u32)> = Vec::new();
+    for (start, ds) in display_sets.iter().enumerate() {
+        if !ds.starts_presentation() {
+            continue;
+        }
+        // A display set continuing the epoch may take its windows from an earlier one
+        let Some(wds) = display_sets[..=start].iter().rev().find_map(|ds| ds.wds.as_ref()) else {
+            continue;
+        };
+        let end = (start + 1..display_sets.len()).find(|&index| display_sets[index].ends_presentation() || epoch_start(index));
+        if end.is_some_and(|end| display_sets[end].state() == PgsDisplaySetState::EmptyFrame || !epoch_start(end)) {
+            continue;
+        }
+        let last = end.map_or(display_sets.len(), |end| end) - 1;
+        let shown = display_sets[last].pcs.as_ref().map_or(0, |pcs| pcs.header.presentation_timestamp);
+        let next = end.and_then(|end| display_sets[end].pcs.as_ref()).map_or(u32::MAX, |pcs| pcs.header.presentation_timestamp);
+        clears.push((last, wds.clone(), shown.saturating_add(max_duration).min(next)));
+    }
+    if clears.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut segments: Vec<PgsSegment> = Vec::new();
+    let mut pending = clears.iter().peekable();
+    for (index, display_set) in stream.get_display_set_segments().enumerate() {
+        segments.extend(display_set.iter().cloned());
+        let Some((_, wds, pts)) = pending.next_if(|(last, _, _)| *last == index) else {
+            continue;
+        };
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            continue;
+        };
+        let dts = if pcs.header.decoding_timestamp == 0 { 0 } else { *pts };
+        let header = |segment_type| PgsSegmentHeader { segment_type, segment_length: 0, presentation_timestamp: *pts, decoding_timestamp: dts };
+        let clear = PgsPcsSegment {
+            header: header(PgsSegmentType::PCS),
+            composition_state: PgsPcsCompositionState::Normal,
+            palette_update_flag: 0,
+            number_of_composition_objects: 0,
+            composition_objects: Vec::new(),
+            ..pcs.as_ref().clone()
+        };
+        segments.push(PgsSegment::Pcs(PgsRc::new(clear)));
+        segments.push(PgsSegment::Wds(PgsRc::new(PgsWdsSegment { header: header(PgsSegmentType::WDS), ..wds.as_ref().clone() })));
+        segments.push(PgsSegment::End(PgsRc::new(PgsEndSegment { header: header(PgsSegmentType::END), data: Vec::new() })));
+    }
+    *stream.get_segments_mut() = segments;
+    stream.renumber_compositions();
+    Ok(clears.into_iter().map(|(_, _, pts)| pts).collect())
+}
+
+/// Retimes a stream piecewise-linearly through anchor points, for tracks where different parts (reels,
+/// chapters) drift by different amounts.
+///
+/// Every anchor maps an old timestamp to its new value. Timestamps between two anchors are interpolated linearly
+/// (rounded to the nearest tick); timestamps before the first or after the last anchor are shifted by the offset
+/// of that anchor. Results outside the `u32` range are clamped, and a zero DTS is left unchanged.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `anchors`: `(old, new)` timestamp pairs in 90 kHz ticks, sorted by old timestamp.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if there are no anchors, the old timestamps are not strictly increasing, or
+/// the new timestamps decrease (which would reorder display sets).
+///
+/// # Returns
+/// The number of timestamps that had to be clamped.
+pub fn remap(stream: &mut PgsStream, anchors: &[(u32, u32)]) -> Result<usize> {
+    if anchors.is_empty() {
+        return Err(Error::InvalidArgument("no retiming anchors given".to_string()));
+    }
+    if anchors.windows(2).any(|pair| pair[0].0 >= pair[1].0 || pair[0].1 > pair[1].1) {
+        return Err(Error::InvalidArgument("retiming anchors must be in increasing order".to_string()));
+    }
+
+    let map = |timestamp: u32| -> i64 {
+        let next = anchors.partition_point(|&(old, _)| old <= timestamp);
+        let (old, new) = anchors[next.saturating_sub(1)];
+        match anchors.get(next) {
+            Some(&(next_old, next_new)) if next > 0 => {
+                let (span, elapsed) = ((next_old - old) as i64, (timestamp - old) as i64);
+                new as i64 + ((next_new - new) as i64 * elapsed * 2 + span) / (span * 2)
+            },
+            // Before the first or after the last anchor
+            _ => timestamp as i64 + new as i64 - old as i64
+        }
+    };
+    let mut clamped = 0;
+    let mut remap = |timestamp: u32| {
+        let mapped = map(timestamp);
+        if mapped < 0 || mapped > u32::MAX as i64 {
+            clamped += 1;
+        }
+        mapped.clamp(0, u32::MAX as i64) as u32
+    };
+    for segment in stream.get_segments_mut() {
+        let header = segment.header_mut();
+        header.presentation_timestamp = remap(header.presentation_timestamp);
+        if header.decoding_timestamp != 0 {
+            header.decoding_timestamp = remap(header.decoding_timestamp);
+        }
+    }
+    if clamped > 0 {
+        warn!("{} remapped timestamps were clamped to the valid range", clamped);
+    }
+    Ok(clamped)
+}
+
+/// A chapter of the video, starting at `start` and lasting until the next chapter, whose subtitles are shifted
+/// by `offset`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PgsChapterOffset {
+    /// Start of the chapter in the subtitle timing (90 kHz).
+    pub start: u32,
+    /// Offset in 90 kHz ticks added to the timestamps of the chapter's events.
+    pub offset: i64
+}
+
+/// Shifts the events of every chapter by the chapter's own offset, for example to follow the cuts of an edit
+/// decision list.
+///
+/// An event belongs to the chapter it starts in, so a display set clearing the screen moves with the event it
+/// ends even if it lies beyond the chapter boundary. Display sets before the first chapter are not moved. If the
+/// offsets would make a display set start before the one preceding it, it is moved one tick after that display set
+/// instead, keeping the stream in presentation order; a warning is logged for these.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `chapters`: The chapters, ordered by strictly increasing start.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if no chapters are given or they are not in increasing order.
+///
+/// # Returns
+/// The number of display sets that were moved.
+pub fn shift_chapters(stream: &mut PgsStream, chapters: &[PgsChapterOffset]) -> Result<usize> {
+    if chapters.is_empty() {
+        return Err(Error::InvalidArgument("no chapters given".to_string()));
+    }
+    if chapters.windows(2).any(|pair| pair[0].start >= pair[1].start) {
+        return Err(Error::InvalidArgument("chapters must be in increasing order".to_string()));
+    }
+
+    let mut timestamps: Vec<u32> = Vec::new();
+    let mut offset = 0;
+    let mut reordered = 0;
+    for display_set in stream.get_display_sets() {
+        let timestamp = display_set.pcs.as_ref().map_or(0, |pcs| pcs.header.presentation_timestamp);
+        if display_set.state() != PgsDisplaySetState::EmptyFrame || timestamps.is_empty() {
+            let chapter = chapters.partition_point(|chapter| chapter.start <= timestamp);
+            offset = chapter.checked_sub(1).map_or(0, |chapter| chapters[chapter].offset);
+        }
+        let mut shifted = (timestamp as i64).saturating_add(offset).clamp(0, u32::MAX as i64) as u32;
+        if let Some(&previous) = timestamps.last() {
+            if shifted <= previous {
+                reordered += 1;
+                shifted = previous.saturating_add(1);
+            }
+        }
+        timestamps.push(shifted);
+    }
+    if reordered > 0 {
+        warn!("{} display sets were moved after the display set preceding them to keep the stream in order", reordered);
+    }
+    Ok(move_display_sets(stream, &timestamps))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{bitmap_display_set, clear_display_set, composition_object, display_set_of, pcs, pds, segments, timed_header, wds},
+        PgsEndSegment, PgsRc, PgsSegment, PgsSegmentType};
+
+    use super::*;
+
+    fn end(pts: u32, dts: u32) -> PgsSegment {
+        PgsSegment::End(PgsRc::new(PgsEndSegment { header: timed_header(PgsSegmentType::END, pts, dts), data: Vec::new() }))
+    }
+
+    /// A display set showing an object, or clearing the screen, with the given DTS to PTS distance.
+    fn display_set(pts: u32, decoding_time: u32, clear: bool) -> Vec<PgsSegment> {
+        let display_set = if clear { clear_display_set(pts) } else { bitmap_display_set(pts, Vec::new(), &[vec![0]]) };
+        let mut segments = segments(&display_set);
+        segments.iter_mut().for_each(|segment| segment.header_mut().decoding_timestamp = pts - decoding_time);
+        segments
+    }
+
+    #[test]
+    fn test_snap_to_frames() {
+        // 3600 ticks per frame at 25 fps: a one frame gap between the clear at 2 s and the next subtitle
+        let mut stream = PgsStream::new([
+            display_set(91000, 1000, false),
+            display_set(181700, 0, true),
+            display_set(183600, 500, false)
+        ].concat());
+        assert_eq!(snap_to_frames(&mut stream, 25.0, 2).unwrap(), 2);
+
+        let timestamps: Vec<(u32, u32)> = stream.get_display_set_segments()
+            .map(|ds| (ds[0].header().presentation_timestamp, ds[0].header().decoding_timestamp)).collect();
+        assert_eq!(timestamps, vec![(90000, 89000), (176400, 176400), (183600, 183100)]);
+        // A frame rate rounding to zero frames per thousand seconds
+        assert!(matches!(snap_to_frames(&mut stream, 0.0004, 2), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut stream = PgsStream::new(vec![end(1000, 0), end(90000, 89000)]);
+        assert_eq!(shift(&mut stream, -2000), 1);
+
+        let timestamps: Vec<(u32, u32)> = stream.get_segments().iter()
+            .map(|segment| (segment.header().presentation_timestamp, segment.header().decoding_timestamp)).collect();
+        assert_eq!(timestamps, vec![(0, 0), (88000, 87000)]);
+        // Offsets beyond the range of the timestamps
+        assert_eq!(shift(&mut stream, i64::MAX), 3);
+        assert_eq!(shift(&mut stream, i64::MIN), 3);
+        assert_eq!(stream.get_segments()[1].header().presentation_timestamp, 0);
+    }
+
+    #[test]
+    fn test_stretch() {
+        let factor = PgsRetimeFactor::from_frame_rates(25.0, 23.976).unwrap();
+        assert_eq!(factor, PgsRetimeFactor { numerator: 1001, denominator: 960 });
+        assert_eq!(PgsRetimeFactor::from_frame_rates(29.97, 24.0).unwrap(), PgsRetimeFactor { numerator: 1250, denominator: 1001 });
+        for (from, to) in [(f64::INFINITY, 25.0), (25.0, f64::NAN), (1e17, 25.0), (25.0, 1e17), (0.0001, 25.0), (-25.0, 25.0)] {
+            assert!(matches!(PgsRetimeFactor::from_frame_rates(from, to), Err(Error::InvalidArgument(_))));
+        }
+
+        let mut stream = PgsStream::new(vec![end(90000, 0), end(u32::MAX - 10, 86400)]);
+        assert_eq!(stretch(&mut stream, factor).unwrap(), 1);
+        let timestamps: Vec<(u32, u32)> = stream.get_segments().iter()
+            .map(|segment| (segment.header().presentation_timestamp, segment.header().decoding_timestamp)).collect();
+        assert_eq!(timestamps, vec![(93844, 0), (u32::MAX, 90090)]);
+        assert!(stretch(&mut stream, PgsRetimeFactor::new(1, 0)).is_err());
+    }
+
+    #[test]
+    fn test_enforce_timing() {
+        let mut stream = PgsStream::new([
+            // Too short, extended up to the minimum gap before the next event
+            display_set(90000, 0, false),
+            display_set(99000, 0, true),
+            // Ends too close to the next event
+            display_set(120000, 0, false),
+            display_set(210000, 0, true),
+            // Ends after the next event starts
+            display_set(215000, 0, false),
+            display_set(400000, 0, true),
+            display_set(390000, 0, false),
+            display_set(500000, 0, true)
+        ].concat());
+
+        let rules = PgsTimingRules { min_duration: 90000, min_gap: 7200 };
+        let adjustments: Vec<(usize, u32, PgsTimingAdjustmentReason)> = enforce_timing(&mut stream, rules).iter()
+            .map(|adjustment| (adjustment.display_set, adjustment.new_timestamp, adjustment.reason)).collect();
+        assert_eq!(adjustments, vec![
+            (1, 112800, PgsTimingAdjustmentReason::MinimumDuration),
+            (3, 207800, PgsTimingAdjustmentReason::MinimumGap),
+            (5, 382800, PgsTimingAdjustmentReason::Overlap)
+        ]);
+        assert_eq!(stream.get_display_set_segments().nth(5).unwrap()[0].header().presentation_timestamp, 382800);
+    }
+
+    #[test]
+    fn test_object_reuse() {
+        // Moves the object shown by the previous display set
+        let reuse = |pts| segments(&display_set_of(pcs(pts, PgsPcsCompositionState::Normal, vec![composition_object(0, 0, 0, 0)]),
+            Some(wds(pts, Vec::new())), Some(pds(pts, 0, Vec::new())), None));
+        let stream = PgsStream::new([
+            display_set(90000, 0, false),
+            reuse(180000),
+            display_set(190000, 0, true),
+            display_set(400000, 0, false),
+            // Never cleared before the stream ends
+            reuse(500000)
+        ].concat());
+
+        let mut timed = stream.clone();
+        let adjustments: Vec<(usize, u32, PgsTimingAdjustmentReason)> = enforce_timing(&mut timed, PgsTimingRules { min_duration: 90000, min_gap: 0 })
+            .iter().map(|adjustment| (adjustment.display_set, adjustment.new_timestamp, adjustment.reason)).collect();
+        assert_eq!(adjustments, vec![(2, 270000, PgsTimingAdjustmentReason::MinimumDuration)]);
+
+        let mut cleared = stream.clone();
+        assert_eq!(insert_missing_clears(&mut cleared, 450000).unwrap(), vec![950000]);
+        assert_eq!(cleared.get_display_sets().last().unwrap().state(), PgsDisplaySetState::EmptyFrame);
+    }
+
+    #[test]
+    fn test_remap() {
+        let mut stream = PgsStream::new(vec![end(1000, 0), end(90000, 45000), end(180000, 0), end(400000, 0)]);
+        // The first reel is 1000 ticks late, the second drifts to 2000 ticks late
+        assert_eq!(remap(&mut stream, &[(0, 1000), (90000, 91000), (180000, 182000)]).unwrap(), 0);
+
+        let timestamps: Vec<(u32, u32)> = stream.get_segments().iter()
+            .map(|segment| (segment.header().presentation_timestamp, segment.header().decoding_timestamp)).collect();
+        assert_eq!(timestamps, vec![(2000, 0), (91000, 46000), (182000, 0), (402000, 0)]);
+        assert!(remap(&mut stream, &[(90000, 0), (0, 0)]).is_err());
+    }
+
+    #[test]
+    fn test_shift_chapters() {
+        let mut stream = PgsStream::new([
+            display_set(90000, 0, false),
+            display_set(180000, 0, false),
+            // Clear of the second event, beyond the start of the second chapter
+            display_set(950000, 1000, true),
+            display_set(1000000, 1000, false),
+            display_set(1100000, 0, true)
+        ].concat());
+        let chapters = [
+            PgsChapterOffset { start: 100000, offset: 9000 },
+            PgsChapterOffset { start: 900000, offset: -100000 }
+        ];
+        assert_eq!(shift_chapters(&mut stream, &chapters).unwrap(), 4);
+
+        let timestamps: Vec<(u32, u32)> = stream.get_display_set_segments()
+            .map(|ds| (ds[0].header().presentation_timestamp, ds[0].header().decoding_timestamp)).collect();
+        assert_eq!(timestamps, vec![(90000, 90000), (189000, 189000), (959000, 958000), (959001, 958001), (1000000, 1000000)]);
+        assert!(shift_chapters(&mut stream, &[chapters[1], chapters[0]]).is_err());
+
+        // Offsets beyond the range of the timestamps are clamped
+        let chapters = [PgsChapterOffset { start: 0, offset: i64::MIN }, PgsChapterOffset { start: 500000, offset: i64::MAX }];
+        shift_chapters(&mut stream, &chapters).unwrap();
+        let timestamps: Vec<u32> = stream.get_display_set_segments().map(|ds| ds[0].header().presentation_timestamp).collect();
+        assert_eq!(timestamps, vec![0, 1, 2, u32::MAX, u32::MAX]);
+    }
+
+    #[test]
+    fn test_set_frame_rate() {
+        let mut stream = PgsStream::new([display_set(90000, 0, false), display_set(181700, 0, true)].concat());
+        assert_eq!(set_frame_rate(&mut stream, 25.0, true).unwrap(), PgsFrameRateReport { compositions_changed: 2, moved_display_sets: 1 });
+        let display_sets = stream.get_display_sets();
+        assert_eq!(display_sets.iter().map(|ds| ds.pcs.as_ref().unwrap().frame_rate).collect::<Vec<u8>>(), vec![0x30, 0x30]);
+        assert_eq!(display_sets[1].pcs.as_ref().unwrap().header.presentation_timestamp, 180000);
+        assert!(set_frame_rate(&mut stream, 30.0, false).is_err());
+    }
+
+    #[test]
+    fn test_insert_missing_clears() {
+        let mut stream = PgsStream::new([
+            // Cleared
+            display_set(90000, 0, false),
+            display_set(180000, 0, true),
+            // Replaced within its epoch by the next event, which is never cleared before the next epoch
+            display_set(270000, 0, false),
+            display_set(300000, 0, false),
+            display_set(1000000, 0, false),
+            // Never cleared before the stream ends
+            display_set(1100000, 1000, false)
+        ].concat());
+        let mut segments = stream.get_segments().clone();
+        for segment in &mut segments {
+            if let PgsSegment::Pcs(pcs) = segment {
+                if [1000000, 1100000].contains(&pcs.header.presentation_timestamp) {
+                    PgsRc::make_mut(pcs).composition_state = PgsPcsCompositionState::EpochStart;
+                }
+            }
+        }
+        *stream.get_segments_mut() = segments;
+
+        assert!(insert_missing_clears(&mut stream, 0).is_err());
+        assert_eq!(insert_missing_clears(&mut stream, 450000).unwrap(), vec![750000, 1100000, 1550000]);
+        let states: Vec<(u32, PgsDisplaySetState)> = stream.get_display_sets().iter()
+            .map(|ds| (ds.pcs.as_ref().unwrap().header.presentation_timestamp, ds.state())).collect();
+        assert_eq!(states[4..], [(750000, PgsDisplaySetState::EmptyFrame), (1000000, PgsDisplaySetState::Complete),
+            (1100000, PgsDisplaySetState::EmptyFrame), (1100000, PgsDisplaySetState::Complete), (1550000, PgsDisplaySetState::EmptyFrame)]);
+        assert!(insert_missing_clears(&mut stream, 450000).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+//! # PGS Safe Area
+//!
+//! This module keeps the windows of a `PgsStream` inside the title-safe area of the video, the region inset by a
+//! margin on every side that is guaranteed to be visible on any display.
+
+use std::collections::HashMap;
+
+use crate::{pgs_transform::{rescale_object, PgsAxisScale, PgsScaleFilter}, Error, PgsOdsSegment, PgsOdsSequenceFlag, PgsPcsCompositionState, PgsPcsObjectCroppedFlag, PgsPcsSegmentCompositionObjects, PgsRc, PgsRectangle, PgsSegment, PgsStream, Result};
+
+/// A window moved or shrunk by `enforce_safe_area`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsSafeAreaAdjustment {
+    /// Index of the display set defining the window.
+    pub display_set: usize,
+    /// Presentation timestamp (90 kHz) of the display set.
+    pub presentation_timestamp: u32,
+    pub window_id: u8,
+    /// Window rectangle before the adjustment.
+    pub old_window: PgsRectangle,
+    /// Window rectangle after the adjustment.
+    pub new_window: PgsRectangle,
+    /// `true` if the objects shown in the window were scaled down, `false` if they were moved or cropped.
+    pub scaled: bool
+}
+
+/// Adjustment of a window, applied to the composition objects shown in it.
+#[derive(Clone, Copy)]
+struct PgsWindowChange {
+    old: PgsRectangle,
+    new: PgsRectangle,
+    scale: Option<PgsAxisScale>
+}
+
+/// Moves a window into the safe area, shrinking it if it does not fit.
+fn fit_window(window: PgsRectangle, safe: PgsRectangle, scale_objects: bool) -> PgsWindowChange {
+    let (x, y, width, height) = window;
+    let (safe_x, safe_y, safe_width, safe_height) = safe;
+    let scale = match scale_objects && (width > safe_width || height > safe_height) {
+        // Scale both axes by the ratio of the more constrained one
+        true if safe_width as u32 * height as u32 <= safe_height as u32 * width as u32 => Some(PgsAxisScale { from: width as u32, to: safe_width as u32 }),
+        true => Some(PgsAxisScale { from: height as u32, to: safe_height as u32 }),
+        false => None
+    };
+    let (new_width, new_height) = match scale {
+        Some(scale) => (scale.floor(width).min(safe_width), scale.floor(height).min(safe_height)),
+        None => (width.min(safe_width), height.min(safe_height))
+    };
+    let new_x = x.clamp(safe_x, safe_x + safe_width - new_width);
+    let new_y = y.clamp(safe_y, safe_y + safe_height - new_height);
+    PgsWindowChange { old: window, new: (new_x, new_y, new_width, new_height), scale }
+}
+
+/// Moves a composition object along with its window, scaling its position and cropping inside the window if the
+/// window was scaled, and cropping it to the window otherwise.
+fn move_object(obj: &mut PgsPcsSegmentCompositionObjects, change: PgsWindowChange, size: Option<(u16, u16)>) {
+    let (old_x, old_y, _, _) = change.old;
+    let (new_x, new_y, new_width, new_height) = change.new;
+    let relative_x = obj.object_horizontal_position.saturating_sub(old_x);
+    let relative_y = obj.object_vertical_position.saturating_sub(old_y);
+    if let Some(scale) = change.scale {
+        obj.object_horizontal_position = new_x + scale.floor(relative_x);
+        obj.object_vertical_position = new_y + scale.floor(relative_y);
+        if obj.object_cropped_flag == PgsPcsObjectCroppedFlag::ForceCroppedImage {
+            obj.object_cropping_horizontal_position = scale.floor(obj.object_cropping_horizontal_position);
+            obj.object_cropping_vertical_position = scale.floor(obj.object_cropping_vertical_position);
+            obj.object_cropping_width = scale.floor(obj.object_cropping_width).max(1);
+            obj.object_cropping_height_position = scale.floor(obj.object_cropping_height_position).max(1);
+        }
+        return;
+    }
+    let (x, y) = (new_x as u32 + relative_x as u32, new_y as u32 + relative_y as u32);
+
+    // Shown part of the object, relative to the object
+    let Some((width, height)) = size else {
+        obj.object_horizontal_position = x.min(u16::MAX as u32) as u16;
+        obj.object_vertical_position = y.min(u16::MAX as u32) as u16;
+        return;
+    };
+    let (crop_x, crop_y, crop_width, crop_height) = match obj.object_cropped_flag {
+        PgsPcsObjectCroppedFlag::ForceCroppedImage => (obj.object_cropping_horizontal_position as u32, obj.object_cropping_vertical_position as u32,
+            obj.object_cropping_width as u32, obj.object_cropping_height_position as u32),
+        PgsPcsObjectCroppedFlag::Off => (0, 0, width as u32, height as u32)
+    };
+    let right = (x + crop_width).min(new_x as u32 + new_width as u32);
+    let bottom = (y + crop_height).min(new_y as u32 + new_height as u32);
+    let (left, top) = (x.max(new_x as u32), y.max(new_y as u32));
+    obj.object_horizontal_position = left.min(u16::MAX as u32) as u16;
+    obj.object_vertical_position = top.min(u16::MAX as u32) as u16;
+    if right <= left || bottom <= top || (left, top, right, bottom) == (x, y, x + crop_width, y + crop_height) {
+        return;
+    }
+    obj.object_cropped_flag = PgsPcsObjectCroppedFlag::ForceCroppedImage;
+    obj.object_cropping_horizontal_position = (crop_x + left - x) as u16;
+    obj.object_cropping_vertical_position = (crop_y + top - y) as u16;
+    obj.object_cropping_width = (right - left) as u16;
+    obj.object_cropping_height_position = (bottom - top) as u16;
+}
+
+/// Keeps every window inside the title-safe area, inset from each edge of the video by `margin` times the video
+/// width or height (e.g. `0.05` for 5%).
+///
+/// Windows outside the safe area are moved into it, and the composition objects shown in them move along. Windows
+/// larger than the safe area are shrunk: with `scale_objects` their objects are scaled down (nearest neighbour)
+/// to fit, otherwise they are cropped to the shrunk window.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `margin`: The margin as a fraction of the video size, below `0.5`.
+/// - `scale_objects`: Whether objects of windows larger than the safe area are scaled instead of cropped.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the margin is negative, not finite or not below `0.5`, or any error from
+/// decoding the object bitmaps when scaling.
+///
+/// # Returns
+/// The adjusted windows in stream order.
+pub fn enforce_safe_area(stream: &mut PgsStream, margin: f64, scale_objects: bool) -> Result<Vec<PgsSafeAreaAdjustment>> {
+    if !margin.is_finite() || !(0.0..0.5).contains(&margin) {
+        return Err(Error::InvalidArgument(format!("invalid safe area margin {margin}")));
+    }
+    let mut adjustments: Vec<PgsSafeAreaAdjustment> = Vec::new();
+    // Window changes and object sizes of the current epoch, and the scale of every object of every epoch
+    let mut changes: HashMap<u8, PgsWindowChange> = HashMap::new();
+    let mut sizes: HashMap<u16, (u16, u16)> = HashMap::new();
+    let mut scales: Vec<HashMap<u16, PgsAxisScale>> = Vec::new();
+    let segments = stream.get_segments_mut();
+    for (index, display_set) in segments.split_inclusive_mut(|segment| matches!(segment, PgsSegment::End(_))).enumerate() {
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            continue;
+        };
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart || scales.is_empty() {
+            changes.clear();
+            sizes.clear();
+            scales.push(HashMap::new());
+        }
+        let (width, height, timestamp) = (pcs.width, pcs.height, pcs.header.presentation_timestamp);
+        let (margin_x, margin_y) = ((width as f64 * margin).round() as u16, (height as f64 * margin).round() as u16);
+        let safe = (margin_x, margin_y, width.saturating_sub(margin_x * 2).max(1), height.saturating_sub(margin_y * 2).max(1));
+
+        for segment in display_set.iter_mut() {
+            match segment {
+                PgsSegment::Wds(wds) => {
+                    for window in &mut PgsRc::make_mut(wds).windows {
+                        let rectangle = (window.window_horizontal_position, window.window_vertical_position, window.window_width, window.window_height);
+                        let change = fit_window(rectangle, safe, scale_objects);
+                        changes.insert(window.window_id, change);
+                        if change.new == rectangle {
+                            continue;
+                        }
+                        (window.window_horizontal_position, window.window_vertical_position, window.window_width, window.window_height) = change.new;
+                        adjustments.push(PgsSafeAreaAdjustment {
+                            display_set: index,
+                            presentation_timestamp: timestamp,
+                            window_id: window.window_id,
+                            old_window: rectangle,
+                            new_window: change.new,
+                            scaled: change.scale.is_some()
+                        });
+                    }
+                },
+                PgsSegment::Ods(ods) if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) => {
+                    sizes.insert(ods.object_id, (ods.width, ods.height));
+                },
+                _ => {}
+            }
+        }
+
+        let PgsSegment::Pcs(pcs) = &mut display_set[0] else {
+            continue;
+        };
+        if pcs.composition_objects.iter().any(|obj| changes.get(&obj.window_id).is_some_and(|change| change.new != change.old)) {
+            let epoch_scales = scales.last_mut().expect("an epoch was started");
+            for obj in &mut PgsRc::make_mut(pcs).composition_objects {
+                let Some(&change) = changes.get(&obj.window_id).filter(|change| change.new != change.old) else {
+                    continue;
+                };
+                move_object(obj, change, sizes.get(&obj.object_id).copied());
+                if let Some(scale) = change.scale {
+                    epoch_scales.insert(obj.object_id, scale);
+                }
+            }
+        }
+    }
+
+    if scales.iter().any(|epoch_scales| !epoch_scales.is_empty()) {
+        scale_objects_by_epoch(stream, &scales)?;
+    }
+    Ok(adjustments)
+}
+
+/// Scales the objects listed for every epoch, in all display sets of that epoch.
+fn scale_objects_by_epoch(stream: &mut PgsStream, scales: &[HashMap<u16, PgsAxisScale>]) -> Result<()> {
+    let mut segments: Vec<PgsSegment> = Vec::with_capacity(stream.get_segments().len());
+    let mut fragments: Vec<PgsRc<PgsOdsSegment>> = Vec::new();
+    let mut epoch: Option<usize> = None;
+    for segment in stream.get_segments_mut().drain(..) {
+        let PgsSegment::Ods(ods) = segment else {
+            if let PgsSegment::Pcs(pcs) = &segment {
+                if pcs.composition_state == PgsPcsCompositionState::EpochStart || epoch.is_none() {
+                    epoch = Some(epoch.map_or(0, |epoch| epoch + 1));
+                }
+            }
+            segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+            segments.push(segment);
+            continue;
+        };
+        if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) {
+            segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+        }
+        fragments.push(ods);
+        let Some(last) = fragments.last().filter(|ods| matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::Last | PgsOdsSequenceFlag::Both)) else {
+            continue;
+        };
+        match epoch.and_then(|epoch| scales.get(epoch)).and_then(|epoch_scales| epoch_scales.get(&last.object_id)) {
+            Some(&scale) => {
+                segments.extend(rescale_object(&fragments, scale, scale, PgsScaleFilter::Nearest, None)?);
+                fragments.clear();
+            },
+            None => segments.extend(fragments.drain(..).map(PgsSegment::Ods))
+        }
+    }
+    segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+
+    *stream.get_segments_mut() = segments;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{ods, segments, window_display_set}, PgsDisplaySet};
+
+    use super::*;
+
+    /// An Epoch Start showing a single object filling a window.
+    fn display_set(rectangle: PgsRectangle) -> Vec<PgsSegment> {
+        let (_, _, width, height) = rectangle;
+        let object = ods(0, 0, 0, width, height, &vec![vec![1; width as usize]; height as usize]);
+        segments(&PgsDisplaySet { ods: Some(PgsRc::new(object)), ..window_display_set(0, PgsPcsCompositionState::EpochStart, (1000, 500), rectangle) })
+    }
+
+    /// Window rectangle, object position and cropping, and object size of a display set.
+    type PgsLayout = (PgsRectangle, (u16, u16), Option<PgsRectangle>, (u16, u16));
+
+    fn layout(stream: &PgsStream) -> Vec<PgsLayout> {
+        stream.get_display_sets().iter().map(|ds| {
+            let window = &ds.wds.as_ref().unwrap().windows[0];
+            let obj = &ds.pcs.as_ref().unwrap().composition_objects[0];
+            let ods = ds.ods.as_ref().unwrap();
+            let cropping = (obj.object_cropped_flag == PgsPcsObjectCroppedFlag::ForceCroppedImage).then_some((obj.object_cropping_horizontal_position,
+                obj.object_cropping_vertical_position, obj.object_cropping_width, obj.object_cropping_height_position));
+            ((window.window_horizontal_position, window.window_vertical_position, window.window_width, window.window_height),
+                (obj.object_horizontal_position, obj.object_vertical_position), cropping, (ods.width, ods.height))
+        }).collect()
+    }
+
+    #[test]
+    fn test_enforce_safe_area() {
+        // Safe area of 900x450 at (50, 25)
+        let segments = [display_set((100, 450, 400, 50)), display_set((0, 100, 1000, 100)), display_set((100, 100, 100, 100))].concat();
+
+        let mut stream = PgsStream::new(segments.clone());
+        let adjustments = enforce_safe_area(&mut stream, 0.05, false).unwrap();
+        assert_eq!(adjustments.iter().map(|adjustment| (adjustment.display_set, adjustment.new_window)).collect::<Vec<_>>(),
+            vec![(0, (100, 425, 400, 50)), (1, (50, 100, 900, 100))]);
+        assert_eq!(layout(&stream), vec![
+            ((100, 425, 400, 50), (100, 425), None, (400, 50)),
+            ((50, 100, 900, 100), (50, 100), Some((0, 0, 900, 100)), (1000, 100)),
+            ((100, 100, 100, 100), (100, 100), None, (100, 100))
+        ]);
+
+        let mut stream = PgsStream::new(segments);
+        assert!(enforce_safe_area(&mut stream, 0.05, true).unwrap()[1].scaled);
+        assert_eq!(layout(&stream)[1], ((50, 100, 900, 90), (50, 100), None, (900, 90)));
+        assert!(enforce_safe_area(&mut stream, 0.5, true).is_err());
+    }
+}
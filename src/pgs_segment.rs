@@ -1,14 +1,190 @@
-use std::rc::Rc;
+use std::mem::size_of;
 
-use crate::{PgsOdsSegment, PgsPcsSegment, PgsPdsSegment, PgsWdsSegment};
+use crate::{Error, PgsEndSegment, PgsOdsSegment, PgsPcsSegment, PgsPcsSegmentCompositionObjects, PgsPdsSegment, PgsSegmentHeader, PgsSegmentType, PgsUnknownSegment, PgsWdsSegment, PgsWdsSegmentWindowDefinition, Result};
+
+/// The shared pointer holding parsed segments: `Rc`, or `Arc` with the `sync` feature, so segments, display sets
+/// and streams can be sent to and shared between threads.
+#[cfg(not(feature = "sync"))]
+pub type PgsRc<T> = std::rc::Rc<T>;
+/// The shared pointer holding parsed segments: `Rc`, or `Arc` with the `sync` feature, so segments, display sets
+/// and streams can be sent to and shared between threads.
+#[cfg(feature = "sync")]
+pub type PgsRc<T> = std::sync::Arc<T>;
 
 /// Enum representing different types of PGS (Presentation Graphic Stream) segments.
 /// These segments are used in Blu-ray subtitles to define various aspects of the subtitle data.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PgsSegment {
-    Pcs(Rc<PgsPcsSegment>),
-    Wds(Rc<PgsWdsSegment>),
-    Pds(Rc<PgsPdsSegment>),
-    Ods(Rc<PgsOdsSegment>),
-    End,
-}
\ No newline at end of file
+    Pcs(PgsRc<PgsPcsSegment>),
+    Wds(PgsRc<PgsWdsSegment>),
+    Pds(PgsRc<PgsPdsSegment>),
+    Ods(PgsRc<PgsOdsSegment>),
+    End(PgsRc<PgsEndSegment>),
+    /// A segment with a type byte not defined by the specification, kept by parsers configured to preserve them.
+    Unknown(PgsRc<PgsUnknownSegment>),
+}
+
+impl PgsSegment {
+    /// Parses a `PgsSegment` from an already parsed header and the raw segment payload.
+    ///
+    /// The header's segment type selects which segment parser is used for the payload.
+    ///
+    /// # Parameters
+    /// - `header`: The segment header.
+    /// - `data`: A slice of raw data representing the segment payload (without the header).
+    ///
+    /// # Errors
+    /// Returns `Error::ReadInvalidSegment` if the header has an unknown segment type, or any error returned by the
+    /// segment specific parser.
+    ///
+    /// # Returns
+    /// The parsed `PgsSegment`.
+    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<PgsSegment> {
+        let segment = match header.segment_type {
+            PgsSegmentType::PCS => PgsSegment::Pcs(PgsPcsSegment::from_data(header, data)?),
+            PgsSegmentType::WDS => PgsSegment::Wds(PgsWdsSegment::from_data(header, data)?),
+            PgsSegmentType::PDS => PgsSegment::Pds(PgsPdsSegment::from_data(header, data)?),
+            PgsSegmentType::ODS => PgsSegment::Ods(PgsOdsSegment::from_data(header, data)?),
+            PgsSegmentType::END => PgsSegment::End(PgsEndSegment::from_data(header, data)?),
+            PgsSegmentType::ERR => return Err(Error::ReadInvalidSegment)
+        };
+
+        Ok(segment)
+    }
+
+    /// Serializes the segment payload (without the header).
+    ///
+    /// # Returns
+    /// The encoded payload bytes of the wrapped segment.
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        match self {
+            PgsSegment::Pcs(pcs) => pcs.to_data(),
+            PgsSegment::Wds(wds) => wds.to_data(),
+            PgsSegment::Pds(pds) => pds.to_data(),
+            PgsSegment::Ods(ods) => ods.to_data(),
+            PgsSegment::End(end) => end.to_data(),
+            PgsSegment::Unknown(unknown) => unknown.to_data()
+        }
+    }
+
+    /// Returns the segment type byte written to the header: the raw type byte for `Unknown` segments, the
+    /// header's `segment_type` otherwise.
+    pub fn segment_type_byte(&self) -> u8 {
+        match self {
+            PgsSegment::Unknown(unknown) => unknown.segment_type,
+            segment => segment.header().segment_type as u8
+        }
+    }
+
+    /// Returns a reference to the header of the wrapped segment.
+    pub fn header(&self) -> &PgsSegmentHeader {
+        match self {
+            PgsSegment::Pcs(pcs) => &pcs.header,
+            PgsSegment::Wds(wds) => &wds.header,
+            PgsSegment::Pds(pds) => &pds.header,
+            PgsSegment::Ods(ods) => &ods.header,
+            PgsSegment::End(end) => &end.header,
+            PgsSegment::Unknown(unknown) => &unknown.header
+        }
+    }
+
+    /// Returns the heap memory in bytes owned by the wrapped segment: its shared allocation with the reference
+    /// counts and the vectors it owns. The palette entries of a PDS are left out, as they may be shared between
+    /// segments.
+    pub(crate) fn heap_size(&self) -> usize {
+        let counts = 2 * size_of::<usize>();
+        counts + match self {
+            PgsSegment::Pcs(pcs) => size_of::<PgsPcsSegment>() + pcs.composition_objects.capacity() * size_of::<PgsPcsSegmentCompositionObjects>(),
+            PgsSegment::Wds(wds) => size_of::<PgsWdsSegment>() + wds.windows.capacity() * size_of::<PgsWdsSegmentWindowDefinition>(),
+            PgsSegment::Pds(_) => size_of::<PgsPdsSegment>(),
+            PgsSegment::Ods(ods) => size_of::<PgsOdsSegment>() + ods.object_data.capacity(),
+            PgsSegment::End(end) => size_of::<PgsEndSegment>() + end.data.capacity(),
+            PgsSegment::Unknown(unknown) => size_of::<PgsUnknownSegment>() + unknown.data.capacity()
+        }
+    }
+
+    /// Returns a mutable reference to the header of the wrapped segment.
+    ///
+    /// The segment is cloned first if it is shared with other owners (for example a `PgsDisplaySet`), so the
+    /// change is only visible through this `PgsSegment`.
+    pub fn header_mut(&mut self) -> &mut PgsSegmentHeader {
+        match self {
+            PgsSegment::Pcs(pcs) => &mut PgsRc::make_mut(pcs).header,
+            PgsSegment::Wds(wds) => &mut PgsRc::make_mut(wds).header,
+            PgsSegment::Pds(pds) => &mut PgsRc::make_mut(pds).header,
+            PgsSegment::Ods(ods) => &mut PgsRc::make_mut(ods).header,
+            PgsSegment::End(end) => &mut PgsRc::make_mut(end).header,
+            PgsSegment::Unknown(unknown) => &mut PgsRc::make_mut(unknown).header
+        }
+    }
+}
+
+impl TryFrom<(PgsSegmentHeader, &[u8])> for PgsSegment {
+    type Error = Error;
+
+    /// Parses a segment from an already parsed header and the raw segment payload, like `from_data`.
+    fn try_from((header, data): (PgsSegmentHeader, &[u8])) -> Result<Self> {
+        PgsSegment::from_data(header, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_decode_rle::decode_rle_indices, pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH};
+
+    use super::*;
+
+    #[test]
+    fn test_arbitrary_input() {
+        // xorshift, so the inputs are the same on every run
+        let mut state: u32 = 0x9E37_79B9;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for round in 0..20000 {
+            let mut data: Vec<u8> = (0..next() % 64 + PGS_SEGMENT_HEADER_LENGTH as u32).map(|_| next() as u8).collect();
+            data[0..2].copy_from_slice(b"PG");
+            data[10] = [0x14, 0x15, 0x16, 0x17, 0x80][round % 5];
+            if round % 4 != 0 {
+                // Mostly consistent segment lengths and single fragment objects, to reach the segment parsers and the
+                // RLE decoder
+                let length = (data.len() - PGS_SEGMENT_HEADER_LENGTH) as u16;
+                data[11..13].copy_from_slice(&length.to_be_bytes());
+                if let Some(flag) = data.get_mut(PGS_SEGMENT_HEADER_LENGTH + 3) {
+                    *flag = 0xC0;
+                }
+            }
+
+            let Ok(header) = PgsSegmentHeader::from_data(&data) else { continue };
+            if let Ok(PgsSegment::Ods(ods)) = PgsSegment::from_data(header, &data[PGS_SEGMENT_HEADER_LENGTH..]) {
+                let _ = decode_rle_indices(&ods);
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_from_packet() {
+        let end = PgsSegment::End(PgsRc::new(PgsEndSegment { header: PgsSegmentHeader { segment_type: PgsSegmentType::END, ..Default::default() }, data: Vec::new() }));
+        let packet = [end.header().to_data().as_slice(), &end.to_data().unwrap()].concat();
+
+        let header = PgsSegmentHeader::try_from(packet.as_slice()).unwrap();
+        let segment = PgsSegment::try_from((header, &packet[PGS_SEGMENT_HEADER_LENGTH..])).unwrap();
+        assert!(matches!(segment, PgsSegment::End(_)));
+        assert_eq!(segment.header(), end.header());
+
+        let header = PgsSegmentHeader { segment_type: PgsSegmentType::ERR, ..header };
+        assert!(matches!(PgsSegment::try_from((header, &[][..])), Err(Error::ReadInvalidSegment)));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::PgsStream>();
+        assert_send_sync::<crate::PgsDisplaySet>();
+    }
+}
@@ -3,16 +3,15 @@
 //! This module defines the `PgsSegmentHeader` struct, which represents the header of a PGS segment.
 
 use crate::pgs_const::PG;
-use crate::pgs_memory_buffer::{BigEndian, ReadBytes};
+use crate::pgs_memory_buffer::{BigEndian, ByteOrder};
 use crate::pgs_segment_type::PgsSegmentType;
 use crate::pgs_error::{Result, Error};
-use crate::PgsMemoryBuffer;
 
 /// Constant defining the length of a PGS segment header.
 pub const PGS_SEGMENT_HEADER_LENGTH: usize = 13;
 
 /// Struct representing the header of a PGS segment.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PgsSegmentHeader {
     // The type of the segment, as defined by the PGS specification.
     pub segment_type: PgsSegmentType,
@@ -48,24 +47,59 @@ impl PgsSegmentHeader {
     /// # Returns
     /// A `PgsSegmentHeader` constructed from the provided data.
     pub fn from_data(data: &[u8]) -> Result<PgsSegmentHeader> {
-        if data.len() < PGS_SEGMENT_HEADER_LENGTH {
-            return Err(Error::InvalidSegmentDataLength);
-        }
-
-        let mut buffer: PgsMemoryBuffer = PgsMemoryBuffer::from(data);
+        let Some(data) = data.first_chunk::<PGS_SEGMENT_HEADER_LENGTH>() else {
+            return Err(Error::InvalidSegmentDataLength { length: data.len(), expected: PGS_SEGMENT_HEADER_LENGTH });
+        };
+        Self::from_bytes(data)
+    }
 
-        let pg = buffer.read_u16::<BigEndian>()?;
-        if pg != PG {
+    /// Parses a `PgsSegmentHeader` from exactly the 13 bytes of an encoded header, without copying them.
+    ///
+    /// # Parameters
+    /// - `data`: The encoded header.
+    ///
+    /// # Errors
+    /// Returns `Error::ReadInvalidSegment` if the data does not start with the `PG` marker.
+    ///
+    /// # Returns
+    /// A `PgsSegmentHeader` constructed from the provided data.
+    pub fn from_bytes(data: &[u8; PGS_SEGMENT_HEADER_LENGTH]) -> Result<PgsSegmentHeader> {
+        if u16::from_be_bytes([data[0], data[1]]) != PG {
             return Err(Error::ReadInvalidSegment);
         }
-        
-        let pts = buffer.read_u32::<BigEndian>()?;
-        let dts = buffer.read_u32::<BigEndian>()?;
-        let s_type = PgsSegmentType::from(buffer.read_u8()?);
-        let s_size = buffer.read_u16::<BigEndian>()?;
+
+        let pts = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
+        let dts = u32::from_be_bytes([data[6], data[7], data[8], data[9]]);
+        let s_type = PgsSegmentType::from(data[10]);
+        let s_size = u16::from_be_bytes([data[11], data[12]]);
 
         Ok(PgsSegmentHeader::new(s_type, pts, dts, s_size))
     }
+
+    /// Serializes the header into its on-disk representation.
+    ///
+    /// This is the inverse of `from_data`: the `PG` marker followed by the PTS, DTS, segment type and segment length.
+    ///
+    /// # Returns
+    /// The 13 bytes of the encoded header.
+    pub fn to_data(&self) -> [u8; PGS_SEGMENT_HEADER_LENGTH] {
+        let mut data = [0; PGS_SEGMENT_HEADER_LENGTH];
+        BigEndian::write_u16(&mut data[0..], PG);
+        BigEndian::write_u32(&mut data[2..], self.presentation_timestamp);
+        BigEndian::write_u32(&mut data[6..], self.decoding_timestamp);
+        data[10] = self.segment_type as u8;
+        BigEndian::write_u16(&mut data[11..], self.segment_length);
+        data
+    }
+}
+
+impl TryFrom<&[u8]> for PgsSegmentHeader {
+    type Error = Error;
+
+    /// Parses a header from the start of a raw packet, like `from_data`; bytes after the header are ignored.
+    fn try_from(data: &[u8]) -> Result<Self> {
+        PgsSegmentHeader::from_data(data)
+    }
 }
 
 impl Default for PgsSegmentHeader {
@@ -75,3 +109,23 @@ impl Default for PgsSegmentHeader {
         Self { segment_type: PgsSegmentType::ERR, segment_length: 0, presentation_timestamp: 0, decoding_timestamp: 0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        let header = PgsSegmentHeader::new(PgsSegmentType::ODS, 0x01020304, 0x00ABCDEF, 0x1234);
+        let data = header.to_data();
+        assert_eq!(data, [0x50, 0x47, 0x01, 0x02, 0x03, 0x04, 0x00, 0xAB, 0xCD, 0xEF, 0x15, 0x12, 0x34]);
+        assert_eq!(PgsSegmentHeader::from_bytes(&data).unwrap(), header);
+        assert_eq!(PgsSegmentHeader::from_data(&[data.as_slice(), &[0xFF]].concat()).unwrap(), header);
+
+        assert_eq!(PgsSegmentHeader::try_from(data.as_slice()).unwrap(), header);
+
+        assert!(matches!(PgsSegmentHeader::from_data(&data[..12]), Err(Error::InvalidSegmentDataLength { length: 12, expected: 13 })));
+        assert!(matches!(PgsSegmentHeader::try_from(&data[..12]), Err(Error::InvalidSegmentDataLength { length: 12, expected: 13 })));
+        assert!(matches!(PgsSegmentHeader::from_bytes(&[0; PGS_SEGMENT_HEADER_LENGTH]), Err(Error::ReadInvalidSegment)));
+    }
+}
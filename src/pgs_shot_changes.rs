@@ -0,0 +1,153 @@
+//! # PGS Shot Changes
+//!
+//! This module checks the timing of subtitle events against the shot changes of their video. Authoring guidelines
+//! ask subtitles not to appear or disappear a few frames away from a cut, which reads as flicker; such in- and
+//! out-times should be snapped to the cut instead. The shot changes come from outside, e.g. exported by a scene
+//! detector.
+
+use crate::{pgs_export::export_events, pgs_retime::PgsRetimeFactor, PgsDisplaySet, Result};
+
+/// Options controlling `check_shot_changes`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PgsShotChangeOptions {
+    /// Frame rate of the video.
+    pub frame_rate: f64,
+    /// Events starting less than this many frames before or after a shot change should start on it.
+    pub in_frames: u32,
+    /// Events ending less than this many frames before or after a shot change should end `out_gap_frames` before
+    /// it.
+    pub out_frames: u32,
+    /// Frames between the suggested out-time and the shot change.
+    pub out_gap_frames: u32
+}
+
+impl Default for PgsShotChangeOptions {
+    fn default() -> Self {
+        PgsShotChangeOptions { frame_rate: 23.976, in_frames: 12, out_frames: 12, out_gap_frames: 2 }
+    }
+}
+
+/// Which edge of an event is too close to a shot change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgsEventEdge {
+    /// The in-time, where the event appears.
+    Start,
+    /// The out-time, where the event is cleared or replaced.
+    End
+}
+
+/// An in- or out-time too close to a shot change, with the timestamp it should be snapped to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgsShotChangeViolation {
+    /// Index of the display set showing the event.
+    pub display_set: usize,
+    pub edge: PgsEventEdge,
+    /// Presentation timestamp (90 kHz) of the edge.
+    pub timestamp: u32,
+    /// Timestamp (90 kHz) of the shot change.
+    pub shot_change: u32,
+    /// Distance of the edge from the shot change in frames, negative before it.
+    pub frames: i64,
+    /// Suggested timestamp (90 kHz) of the edge.
+    pub suggested: u32
+}
+
+/// Checks the in- and out-times of every subtitle event against the shot changes of the video.
+///
+/// Events are built with `export::export_events`; events that are never cleared have no out-time to check. An
+/// in-time closer than `in_frames` to the nearest shot change, but not on it, should be moved onto the shot
+/// change. An out-time closer than `out_frames`, but not `out_gap_frames` before it, should be moved to
+/// `out_gap_frames` before the shot change. Suggestions that would leave an event without duration are not made.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+/// - `shot_changes`: The timestamps (90 kHz) of the shot changes, in any order.
+/// - `options`: Frame rate and distances of the check.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the frame rate is not positive and finite, or is below a thousandth of a
+/// frame per second.
+///
+/// # Returns
+/// The violations in stream order, the in-time of an event before its out-time.
+pub fn check_shot_changes(display_sets: &[PgsDisplaySet], shot_changes: &[u32], options: &PgsShotChangeOptions) -> Result<Vec<PgsShotChangeViolation>> {
+    let (numerator, denominator) = PgsRetimeFactor::frame_rate_fraction(options.frame_rate)?;
+    // Signed distance in frames, rounded to the nearest frame, and the ticks of a number of frames
+    let to_frames = |ticks: i64| {
+        let scaled = ticks as i128 * numerator as i128;
+        let frame = 90000 * denominator as i128;
+        ((scaled + scaled.signum() * frame / 2) / frame) as i64
+    };
+    let to_ticks = |frames: u32| ((frames as u128 * 90000 * denominator as u128 + numerator as u128 / 2) / numerator as u128) as u32;
+    let out_gap = to_ticks(options.out_gap_frames);
+
+    let mut shot_changes = shot_changes.to_vec();
+    shot_changes.sort_unstable();
+    let nearest = |timestamp: u32| {
+        let index = shot_changes.partition_point(|&shot_change| shot_change < timestamp);
+        [index.checked_sub(1), Some(index)].into_iter().flatten()
+            .filter_map(|index| shot_changes.get(index).copied())
+            .min_by_key(|&shot_change| shot_change.abs_diff(timestamp))
+    };
+
+    let mut violations: Vec<PgsShotChangeViolation> = Vec::new();
+    for event in export_events(display_sets) {
+        let end = event.end.unwrap_or(u32::MAX);
+        if let Some(shot_change) = nearest(event.start) {
+            let frames = to_frames(event.start as i64 - shot_change as i64);
+            if frames.unsigned_abs() < options.in_frames as u64 && event.start != shot_change && shot_change < end {
+                violations.push(PgsShotChangeViolation { display_set: event.display_set, edge: PgsEventEdge::Start,
+                    timestamp: event.start, shot_change, frames, suggested: shot_change });
+            }
+        }
+        let Some(end) = event.end else {
+            continue;
+        };
+        if let Some(shot_change) = nearest(end.saturating_add(out_gap)) {
+            let suggested = shot_change.saturating_sub(out_gap);
+            let frames = to_frames(end as i64 - shot_change as i64);
+            if frames.unsigned_abs() < options.out_frames as u64 && end != suggested && suggested > event.start {
+                violations.push(PgsShotChangeViolation { display_set: event.display_set, edge: PgsEventEdge::End,
+                    timestamp: end, shot_change, frames, suggested });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgs_test_support::{bitmap_display_set, clear_display_set};
+
+    use super::*;
+
+    /// Ticks of one frame at 25 frames per second.
+    const FRAME: u32 = 3600;
+
+    #[test]
+    fn test_check_shot_changes() {
+        let display_sets = [
+            // Starts 3 frames after a cut and ends 4 frames after the next one
+            bitmap_display_set(100 * FRAME, Vec::new(), &[vec![0]]),
+            clear_display_set(204 * FRAME),
+            // Starts on a cut and ends 2 frames before the next one
+            bitmap_display_set(300 * FRAME, Vec::new(), &[vec![0]]),
+            clear_display_set(398 * FRAME),
+            // Far from any cut
+            bitmap_display_set(600 * FRAME, Vec::new(), &[vec![0]]),
+            clear_display_set(700 * FRAME)
+        ];
+        let shot_changes = [400 * FRAME, 97 * FRAME, 200 * FRAME, 300 * FRAME];
+        let options = PgsShotChangeOptions { frame_rate: 25.0, ..Default::default() };
+
+        let violations = check_shot_changes(&display_sets, &shot_changes, &options).unwrap();
+        assert_eq!(violations, [
+            PgsShotChangeViolation { display_set: 0, edge: PgsEventEdge::Start, timestamp: 100 * FRAME, shot_change: 97 * FRAME, frames: 3, suggested: 97 * FRAME },
+            PgsShotChangeViolation { display_set: 0, edge: PgsEventEdge::End, timestamp: 204 * FRAME, shot_change: 200 * FRAME, frames: 4, suggested: 198 * FRAME }
+        ]);
+
+        let options = PgsShotChangeOptions { in_frames: 3, out_frames: 3, ..options };
+        assert!(check_shot_changes(&display_sets, &shot_changes, &options).unwrap().is_empty());
+        assert!(check_shot_changes(&display_sets, &shot_changes, &PgsShotChangeOptions { frame_rate: 0.0, ..options }).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! # PGS Event Sizes
+//!
+//! This module measures the bitmaps of subtitle events: how many pixels are visible, the bounding box of the
+//! visible pixels and how much of the screen the bitmap covers. Events with bitmaps covering most of the screen,
+//! such as full-screen credits, are flagged, as they often exceed what hardware decoders can compose in time.
+
+use crate::{pgs_decode_rle::decode_rle_indices, pgs_export::export_events, PgsDisplaySet, Result};
+
+/// Options controlling `event_sizes_with_options`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PgsEventSizeOptions {
+    /// Events whose bitmap covers more than this percentage of the screen are oversized.
+    pub max_screen_percentage: f64
+}
+
+impl Default for PgsEventSizeOptions {
+    fn default() -> Self {
+        PgsEventSizeOptions { max_screen_percentage: 50.0 }
+    }
+}
+
+/// Size metrics of the bitmap of a subtitle event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsEventSize {
+    /// Index of the display set holding the bitmap.
+    pub display_set: usize,
+    /// Presentation timestamp (90 kHz) at which the bitmap appears.
+    pub start: u32,
+    pub width: u16,
+    pub height: u16,
+    /// Number of pixels that are not fully transparent.
+    pub visible_pixels: u64,
+    /// Bounding box of the visible pixels on screen as x, y, width and height, or `None` if no pixel is visible.
+    pub bounds: Option<(u16, u16, u16, u16)>,
+    /// Percentage of the video area covered by the bitmap, `0.0` if the video size is unknown.
+    pub screen_percentage: f64,
+    /// The bitmap covers more than `PgsEventSizeOptions::max_screen_percentage` of the screen.
+    pub oversized: bool
+}
+
+/// Measures the bitmap of every subtitle event with the default options.
+///
+/// See `event_sizes_with_options`.
+pub fn event_sizes(display_sets: &[PgsDisplaySet]) -> Result<Vec<PgsEventSize>> {
+    event_sizes_with_options(display_sets, &PgsEventSizeOptions::default())
+}
+
+/// Measures the bitmap of every subtitle event.
+///
+/// Events are built with `export::export_events`, placed at their first composition object. The screen
+/// percentage relates the whole bitmap, including its transparent pixels, to the video size of the composition,
+/// as decoders compose the whole bitmap.
+///
+/// # Parameters
+/// - `display_sets`: The display sets in stream order.
+/// - `options`: The screen percentage above which events are oversized.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded.
+///
+/// # Returns
+/// One size per event, in stream order.
+pub fn event_sizes_with_options(display_sets: &[PgsDisplaySet], options: &PgsEventSizeOptions) -> Result<Vec<PgsEventSize>> {
+    export_events(display_sets).into_iter().filter_map(|event| {
+        let ds = &display_sets[event.display_set];
+        let (ods, pds, pcs) = (ds.ods.as_ref()?, ds.pds.as_ref()?, ds.pcs.as_ref()?);
+        let indices = match decode_rle_indices(ods) {
+            Ok(indices) => indices,
+            Err(err) => return Some(Err(err))
+        };
+        let visible = |index: u8| pds.palette_entries.get(index as usize).is_some_and(|entry| entry.transparency > 0);
+
+        // Visible pixels and their bounds (left, top, right, bottom) within the bitmap
+        let mut visible_pixels = 0;
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for (y, row) in indices.iter().enumerate() {
+            for (x, _) in row.iter().enumerate().filter(|(_, &index)| visible(index)) {
+                visible_pixels += 1;
+                bounds = Some(bounds.map_or((x, y, x + 1, y + 1), |(left, top, right, bottom)| (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1))));
+            }
+        }
+        let bounds = bounds.map(|(left, top, right, bottom)| (
+            event.x.saturating_add(left as u16),
+            event.y.saturating_add(top as u16),
+            (right - left) as u16,
+            (bottom - top) as u16
+        ));
+
+        let screen = pcs.width as u64 * pcs.height as u64;
+        let screen_percentage = match screen {
+            0 => 0.0,
+            screen => 100.0 * (event.width as u64 * event.height as u64) as f64 / screen as f64
+        };
+        Some(Ok(PgsEventSize {
+            display_set: event.display_set,
+            start: event.start,
+            width: event.width,
+            height: event.height,
+            visible_pixels,
+            bounds,
+            screen_percentage,
+            oversized: screen_percentage > options.max_screen_percentage
+        }))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{black_and_white, composition_object, display_set_of, ods, pcs, pds, wds}, PgsPcsCompositionState, PgsPcsSegment};
+
+    use super::*;
+
+    /// A display set of a 100x100 video showing a bitmap at (10, 20) with an opaque rectangle `(x, y, width,
+    /// height)` on a transparent background.
+    fn display_set(pts: u32, width: u16, height: u16, rectangle: (usize, usize, usize, usize)) -> PgsDisplaySet {
+        let (left, top, right, bottom) = (rectangle.0, rectangle.1, rectangle.0 + rectangle.2, rectangle.1 + rectangle.3);
+        let bitmap: Vec<Vec<u8>> = (0..height as usize)
+            .map(|y| (0..width as usize).map(|x| ((left..right).contains(&x) && (top..bottom).contains(&y)) as u8).collect())
+            .collect();
+        display_set_of(
+            PgsPcsSegment { width: 100, height: 100, ..pcs(pts, PgsPcsCompositionState::Normal, vec![composition_object(0, 0, 10, 20)]) },
+            Some(wds(pts, Vec::new())),
+            Some(pds(pts, 0, black_and_white())),
+            Some(ods(pts, 0, 0, width, height, &bitmap))
+        )
+    }
+
+    #[test]
+    fn test_event_sizes() {
+        let display_sets = [
+            display_set(90000, 40, 10, (5, 2, 20, 6)),
+            display_set(180000, 90, 60, (0, 0, 90, 60)),
+            display_set(270000, 10, 10, (0, 0, 0, 0))
+        ];
+        let sizes = event_sizes(&display_sets).unwrap();
+        assert_eq!(sizes[0], PgsEventSize {
+            display_set: 0,
+            start: 90000,
+            width: 40,
+            height: 10,
+            visible_pixels: 120,
+            bounds: Some((15, 22, 20, 6)),
+            screen_percentage: 4.0,
+            oversized: false
+        });
+        assert_eq!((sizes[1].screen_percentage, sizes[1].oversized), (54.0, true));
+        assert_eq!((sizes[2].visible_pixels, sizes[2].bounds), (0, None));
+
+        let options = PgsEventSizeOptions { max_screen_percentage: 60.0 };
+        assert!(event_sizes_with_options(&display_sets, &options).unwrap().iter().all(|size| !size.oversized));
+    }
+}
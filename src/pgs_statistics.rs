@@ -0,0 +1,245 @@
+//! # PGS Statistics
+//!
+//! This module summarizes the timing of subtitle events: how long events stay on screen, the gaps between them,
+//! overlaps, how densely they are spread over the runtime and how many of them are forced, and how much of the
+//! runtime is covered by subtitles.
+
+use crate::export::PgsExportEvent;
+
+/// Number of one-second buckets in `PgsStatistics::duration_histogram`; the last bucket collects everything longer.
+pub const PGS_DURATION_HISTOGRAM_BUCKETS: usize = 11;
+
+/// Number of 90 kHz ticks in one minute.
+const TICKS_PER_MINUTE: u32 = 60 * 90000;
+
+/// Count, minimum, maximum and mean of a set of time spans (90 kHz ticks).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsTimeSummary {
+    pub count: usize,
+    pub min: u32,
+    pub max: u32,
+    pub mean: u32,
+    pub total: u64
+}
+
+impl PgsTimeSummary {
+    /// Summarizes the given spans.
+    fn from_spans(spans: &[u32]) -> Self {
+        let total: u64 = spans.iter().map(|&span| span as u64).sum();
+        PgsTimeSummary {
+            count: spans.len(),
+            min: spans.iter().copied().min().unwrap_or(0),
+            max: spans.iter().copied().max().unwrap_or(0),
+            mean: total.checked_div(spans.len() as u64).unwrap_or(0) as u32,
+            total
+        }
+    }
+}
+
+/// Timing statistics of a subtitle track.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsStatistics {
+    /// Number of events.
+    pub events: usize,
+    /// Number of events holding a forced object.
+    pub forced_events: usize,
+    /// How long events stay on screen.
+    pub durations: PgsTimeSummary,
+    /// Number of events per duration: bucket `i` counts durations from `i` up to `i + 1` seconds, the last bucket
+    /// everything from `PGS_DURATION_HISTOGRAM_BUCKETS - 1` seconds on.
+    pub duration_histogram: [usize; PGS_DURATION_HISTOGRAM_BUCKETS],
+    /// Time between the end of an event and the start of the next one, for events that do not touch.
+    pub gaps: PgsTimeSummary,
+    /// Pairs of consecutive event indexes (into the given events) whose time ranges overlap.
+    pub overlaps: Vec<(usize, usize)>,
+    /// Number of events starting in each minute of the runtime, from minute 0 to the last event.
+    pub per_minute: Vec<usize>
+}
+
+/// Computes the timing statistics of a list of events, such as returned by `export::export_events`.
+///
+/// Events without an end use `PgsExportEvent::end_or_default`.
+///
+/// # Parameters
+/// - `events`: The events in stream order.
+///
+/// # Returns
+/// The `PgsStatistics` of the events.
+pub fn statistics(events: &[PgsExportEvent]) -> PgsStatistics {
+    let durations: Vec<u32> = events.iter().map(|event| event.end_or_default().saturating_sub(event.start)).collect();
+
+    let mut duration_histogram = [0; PGS_DURATION_HISTOGRAM_BUCKETS];
+    for duration in &durations {
+        duration_histogram[(*duration as usize / 90000).min(PGS_DURATION_HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+
+    let mut gaps: Vec<u32> = Vec::new();
+    let mut overlaps: Vec<(usize, usize)> = Vec::new();
+    for (index, pair) in events.windows(2).enumerate() {
+        let (end, next_start) = (pair[0].end_or_default(), pair[1].start);
+        if next_start < end {
+            overlaps.push((index, index + 1));
+        } else if next_start > end {
+            gaps.push(next_start - end);
+        }
+    }
+
+    let mut per_minute = vec![0; events.iter().map(|event| event.start / TICKS_PER_MINUTE + 1).max().unwrap_or(0) as usize];
+    for event in events {
+        per_minute[(event.start / TICKS_PER_MINUTE) as usize] += 1;
+    }
+
+    PgsStatistics {
+        events: events.len(),
+        forced_events: events.iter().filter(|event| event.forced).count(),
+        durations: PgsTimeSummary::from_spans(&durations),
+        duration_histogram,
+        gaps: PgsTimeSummary::from_spans(&gaps),
+        overlaps,
+        per_minute
+    }
+}
+
+/// Subtitle events and covered time within one minute of the runtime.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsMinuteCoverage {
+    /// Number of events starting in the minute.
+    pub events: usize,
+    /// Ticks (90 kHz) of the minute during which a subtitle is shown.
+    pub covered: u32
+}
+
+impl PgsMinuteCoverage {
+    /// Returns the percentage of the minute during which a subtitle is shown.
+    pub fn percentage(&self) -> f64 {
+        100.0 * self.covered as f64 / TICKS_PER_MINUTE as f64
+    }
+}
+
+/// How much of the runtime is covered by subtitles.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsCoverage {
+    /// Runtime (90 kHz ticks) the coverage refers to.
+    pub runtime: u32,
+    /// Ticks during which at least one subtitle is shown; overlapping events count once.
+    pub covered: u64,
+    /// Events and covered time of each minute of the runtime, from minute 0 on.
+    pub per_minute: Vec<PgsMinuteCoverage>,
+    /// Longest time range `(start, end)` without subtitles, including before the first and after the last event.
+    pub longest_gap: Option<(u32, u32)>
+}
+
+impl PgsCoverage {
+    /// Returns the percentage of the runtime covered by subtitles, `0.0` for an empty runtime.
+    pub fn percentage(&self) -> f64 {
+        match self.runtime {
+            0 => 0.0,
+            runtime => 100.0 * self.covered as f64 / runtime as f64
+        }
+    }
+}
+
+/// Computes how much of the runtime is covered by a list of events, such as returned by `export::export_events`.
+///
+/// Events without an end use `PgsExportEvent::end_or_default`; parts of events after the runtime are ignored.
+///
+/// # Parameters
+/// - `events`: The events in stream order.
+/// - `runtime`: The runtime of the video in 90 kHz ticks, or `None` to end it with the last event.
+///
+/// # Returns
+/// The `PgsCoverage` of the events.
+pub fn coverage(events: &[PgsExportEvent], runtime: Option<u32>) -> PgsCoverage {
+    let runtime = runtime.unwrap_or_else(|| events.iter().map(|event| event.end_or_default()).max().unwrap_or(0));
+
+    // Union of the event time ranges within the runtime
+    let mut ranges: Vec<(u32, u32)> = events.iter()
+        .map(|event| (event.start.min(runtime), event.end_or_default().min(runtime)))
+        .filter(|(start, end)| start < end)
+        .collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end))
+        }
+    }
+
+    let mut per_minute = vec![PgsMinuteCoverage::default(); runtime.div_ceil(TICKS_PER_MINUTE) as usize];
+    for event in events.iter().filter(|event| event.start < runtime) {
+        per_minute[(event.start / TICKS_PER_MINUTE) as usize].events += 1;
+    }
+    for &(start, end) in &merged {
+        let mut time = start;
+        while time < end {
+            let minute = time / TICKS_PER_MINUTE;
+            let next = end.min((minute + 1).saturating_mul(TICKS_PER_MINUTE));
+            per_minute[minute as usize].covered += next - time;
+            time = next;
+        }
+    }
+
+    let bounds = [0].into_iter().chain(merged.iter().flat_map(|&(start, end)| [start, end])).chain([runtime]).collect::<Vec<_>>();
+    let longest_gap = bounds.chunks(2)
+        .map(|pair| (pair[0], pair[1]))
+        .filter(|(start, end)| start < end)
+        .max_by_key(|(start, end)| (end - start, std::cmp::Reverse(*start)));
+
+    PgsCoverage {
+        runtime,
+        covered: merged.iter().map(|(start, end)| (end - start) as u64).sum(),
+        per_minute,
+        longest_gap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(start: u32, end: u32, forced: bool) -> PgsExportEvent {
+        PgsExportEvent { display_set: 0, start, end: Some(end), x: 0, y: 0, width: 1, height: 1, forced }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let events = [
+            event(90000, 270000, false),
+            event(360000, 450000, true),
+            event(400000, 1_440_000, false),
+            event(TICKS_PER_MINUTE * 2, TICKS_PER_MINUTE * 2 + 45000, false)
+        ];
+        let stats = statistics(&events);
+
+        assert_eq!(stats.events, 4);
+        assert_eq!(stats.forced_events, 1);
+        assert_eq!(stats.durations, PgsTimeSummary { count: 4, min: 45000, max: 1_040_000, mean: 338750, total: 1_355_000 });
+        assert_eq!(stats.duration_histogram, [1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(stats.gaps, PgsTimeSummary { count: 2, min: 90000, max: 9_360_000, mean: 4_725_000, total: 9_450_000 });
+        assert_eq!(stats.overlaps, vec![(1, 2)]);
+        assert_eq!(stats.per_minute, vec![3, 0, 1]);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let events = [
+            event(TICKS_PER_MINUTE / 2, TICKS_PER_MINUTE * 3 / 2, false),
+            event(TICKS_PER_MINUTE, TICKS_PER_MINUTE + 90000, false),
+            event(TICKS_PER_MINUTE * 4, TICKS_PER_MINUTE * 4 + 90000, false)
+        ];
+        let report = coverage(&events, None);
+        assert_eq!(report.runtime, TICKS_PER_MINUTE * 4 + 90000);
+        assert_eq!(report.covered, TICKS_PER_MINUTE as u64 + 90000);
+        assert_eq!(report.per_minute.iter().map(|minute| (minute.events, minute.covered)).collect::<Vec<_>>(),
+            [(1, TICKS_PER_MINUTE / 2), (1, TICKS_PER_MINUTE / 2), (0, 0), (0, 0), (1, 90000)]);
+        assert_eq!(report.per_minute[0].percentage(), 50.0);
+        assert_eq!(report.longest_gap, Some((TICKS_PER_MINUTE * 3 / 2, TICKS_PER_MINUTE * 4)));
+
+        let report = coverage(&events, Some(TICKS_PER_MINUTE * 10));
+        assert_eq!(report.per_minute.len(), 10);
+        assert_eq!(report.percentage(), 100.0 * (TICKS_PER_MINUTE + 90000) as f64 / (TICKS_PER_MINUTE * 10) as f64);
+        assert_eq!(report.longest_gap, Some((TICKS_PER_MINUTE * 4 + 90000, TICKS_PER_MINUTE * 10)));
+        assert_eq!(coverage(&[], None), PgsCoverage::default());
+    }
+}
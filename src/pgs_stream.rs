@@ -0,0 +1,246 @@
+//! # PGS Stream
+//!
+//! This module defines the `PgsStream` struct, an editable, ordered list of all segments of a PGS file.
+//! Unlike the display sets built by `PgsParser`, the stream keeps every segment in its original order, so it
+//! can be modified and written back as a SUP file without losing data.
+
+use std::io::Write;
+
+use crate::{pgs_transform::epoch_start_display_set, PgsDisplaySet, PgsPcsCompositionState, PgsRc, PgsSegment, PgsTimestampUnwrapper, PgsWriter, Result};
+
+/// An ordered, editable list of PGS segments.
+#[derive(Debug, Default, Clone)]
+pub struct PgsStream {
+    segments: Vec<PgsSegment>
+}
+
+impl PgsStream {
+    /// Creates a new `PgsStream` from a list of segments.
+    pub fn new(segments: Vec<PgsSegment>) -> Self {
+        PgsStream {
+            segments
+        }
+    }
+
+    /// Returns a reference to the vector of segments.
+    pub fn get_segments(&self) -> &Vec<PgsSegment> {
+        &self.segments
+    }
+
+    /// Returns a mutable reference to the vector of segments.
+    pub fn get_segments_mut(&mut self) -> &mut Vec<PgsSegment> {
+        &mut self.segments
+    }
+
+    /// Builds the display sets described by the current segments.
+    ///
+    /// # Returns
+    /// A vector of display sets, one for every END segment in the stream.
+    pub fn get_display_sets(&self) -> Vec<PgsDisplaySet> {
+        PgsDisplaySet::from_segments(&self.segments)
+    }
+
+    /// Returns an iterator over the segments of each display set, every group ending with its END segment.
+    /// Segments following the last END segment are returned as a final, unterminated group.
+    pub fn get_display_set_segments(&self) -> impl Iterator<Item = &[PgsSegment]> {
+        self.segments.split_inclusive(|segment| matches!(segment, PgsSegment::End(_)))
+    }
+
+    /// Returns the largest presentation timestamp in the stream, or `None` if the stream is empty.
+    pub fn end_timestamp(&self) -> Option<u32> {
+        self.segments.iter().map(|segment| segment.header().presentation_timestamp).max()
+    }
+
+    /// Returns the presentation timestamp of every segment as a monotonic 64-bit value, counting wraps of the
+    /// 32-bit timestamps (see `PgsTimestampUnwrapper`).
+    pub fn unwrapped_timestamps(&self) -> Vec<u64> {
+        let mut unwrapper = PgsTimestampUnwrapper::new();
+        self.segments.iter().map(|segment| unwrapper.unwrap(segment.header().presentation_timestamp)).collect()
+    }
+
+    /// Adds `delta` ticks (90 kHz) to the timestamps of every segment.
+    ///
+    /// Results are clamped to the valid `u32` range. A zero DTS means the encoder did not set one, so it is left
+    /// unchanged.
+    ///
+    /// # Returns
+    /// The number of timestamps that had to be clamped.
+    pub fn shift_timestamps(&mut self, delta: i64) -> usize {
+        let mut clamped = 0;
+        let mut shift = |timestamp: u32| {
+            let shifted = (timestamp as i64).saturating_add(delta);
+            if shifted < 0 || shifted > u32::MAX as i64 {
+                clamped += 1;
+            }
+            shifted.clamp(0, u32::MAX as i64) as u32
+        };
+
+        for segment in &mut self.segments {
+            let header = segment.header_mut();
+            header.presentation_timestamp = shift(header.presentation_timestamp);
+            if header.decoding_timestamp != 0 {
+                header.decoding_timestamp = shift(header.decoding_timestamp);
+            }
+        }
+        clamped
+    }
+
+    /// Renumbers the compositions of all PCS segments consecutively from 0, wrapping at 65536.
+    pub fn renumber_compositions(&mut self) {
+        let mut composition_number: u16 = 0;
+        for segment in &mut self.segments {
+            if let PgsSegment::Pcs(pcs) = segment {
+                PgsRc::make_mut(pcs).composition_number = composition_number;
+                composition_number = composition_number.wrapping_add(1);
+            }
+        }
+    }
+
+    /// Merges streams into one, interleaving their display sets by presentation timestamp.
+    ///
+    /// Display sets with equal timestamps keep the order of the input streams. Since every stream has epochs of its
+    /// own, a display set following one of another stream is turned into an Epoch Start carrying the windows,
+    /// palettes and objects of its epoch, so the result stays a single valid stream. A subtitle is still replaced by
+    /// the next display set of any stream, so the streams should not show subtitles at the same time (see
+    /// `transform::merge_events` to show them together). Compositions are renumbered afterwards.
+    pub fn merge(streams: Vec<PgsStream>) -> PgsStream {
+        let inputs: Vec<Vec<&[PgsSegment]>> = streams.iter().map(|stream| stream.get_display_set_segments().collect()).collect();
+        // Every display set as its presentation timestamp, stream and index in the stream
+        let mut order: Vec<(u32, usize, usize)> = inputs.iter().enumerate()
+            .flat_map(|(stream, display_sets)| display_sets.iter().enumerate().map(move |(index, segments)| {
+                (segments.first().map_or(0, |segment| segment.header().presentation_timestamp), stream, index)
+            }))
+            .collect();
+        order.sort_by_key(|&(presentation_timestamp, _, _)| presentation_timestamp);
+
+        let mut segments: Vec<PgsSegment> = Vec::new();
+        let mut previous: Option<usize> = None;
+        for (_, stream, index) in order {
+            let display_set = inputs[stream][index];
+            let epoch_start = matches!(display_set.first(), Some(PgsSegment::Pcs(pcs)) if pcs.composition_state == PgsPcsCompositionState::EpochStart);
+            match previous.is_some_and(|previous| previous != stream) && !epoch_start {
+                true => segments.extend(epoch_start_display_set(&inputs[stream], index)),
+                false => segments.extend(display_set.iter().cloned())
+            }
+            previous = Some(stream);
+        }
+
+        let mut merged = PgsStream::new(segments);
+        merged.renumber_compositions();
+        merged
+    }
+
+    /// Concatenates streams in order and renumbers the compositions. Timestamps are kept, so later streams are
+    /// expected to be shifted behind earlier ones first (see `shift_timestamps`).
+    pub fn concat(streams: Vec<PgsStream>) -> PgsStream {
+        let mut concatenated = PgsStream::new(streams.into_iter().flat_map(|stream| stream.segments).collect());
+        concatenated.renumber_compositions();
+        concatenated
+    }
+
+    /// Writes all segments to a SUP file.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path of the file to be written.
+    pub fn write(&self, sup_file_path: &str) -> Result<()> {
+        let mut writer = PgsWriter::create(sup_file_path)?;
+        writer.write_segments(&self.segments)?;
+        writer.flush()
+    }
+
+    /// Writes all segments to the given writer.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = PgsWriter::new(writer);
+        writer.write_segments(&self.segments)?;
+        writer.flush()
+    }
+}
+
+impl From<Vec<PgsSegment>> for PgsStream {
+    /// Creates a `PgsStream` from a vector of segments.
+    fn from(segments: Vec<PgsSegment>) -> Self {
+        PgsStream::new(segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_decode_rle::decode_rle_indices, pgs_test_support::{black_and_white, composition_object, display_set_of, ods, pcs, pds, segments, wds, window},
+        check_references, PgsPcsSegment};
+
+    use super::*;
+
+    /// A stream of display sets made of a PCS of composition 7 and an END segment, at the given times and states.
+    fn stream(display_sets: &[(u32, PgsPcsCompositionState)]) -> PgsStream {
+        PgsStream::new(display_sets.iter().flat_map(|&(pts, composition_state)| {
+            segments(&display_set_of(PgsPcsSegment { composition_number: 7, ..pcs(pts, composition_state, Vec::new()) }, None, None, None))
+        }).collect())
+    }
+
+    /// Presentation timestamp, composition number and state of every display set.
+    fn compositions(stream: &PgsStream) -> Vec<(u32, u16, PgsPcsCompositionState)> {
+        stream.get_display_sets().iter().map(|ds| {
+            let pcs = ds.pcs.as_ref().unwrap();
+            (pcs.header.presentation_timestamp, pcs.composition_number, pcs.composition_state)
+        }).collect()
+    }
+
+    #[test]
+    fn test_merge() {
+        use PgsPcsCompositionState::{EpochStart, Normal};
+        let first = stream(&[(90000, EpochStart), (270000, Normal), (450000, Normal)]);
+        let second = stream(&[(180000, EpochStart), (270000, Normal)]);
+
+        // Equal timestamps keep the order of the streams, and a display set following one of the other stream
+        // starts a new epoch
+        let merged = PgsStream::merge(vec![first, second]);
+        assert_eq!(compositions(&merged), [
+            (90000, 0, EpochStart),
+            (180000, 1, EpochStart),
+            (270000, 2, EpochStart),
+            (270000, 3, EpochStart),
+            (450000, 4, EpochStart)
+        ]);
+        assert_eq!(merged.get_segments().len(), 10);
+    }
+
+    #[test]
+    fn test_merge_epochs() {
+        use PgsPcsCompositionState::{EpochStart, Normal};
+        let subtitle = |pts, color| segments(&display_set_of(pcs(pts, EpochStart, vec![composition_object(0, 0, 100, 900)]),
+            Some(wds(pts, vec![window(0, (100, 900, 2, 1))])), Some(pds(pts, 0, black_and_white())), Some(ods(pts, 0, 0, 2, 1, &[vec![color, color]]))));
+        let moved = |pts| segments(&display_set_of(pcs(pts, Normal, vec![composition_object(0, 0, 101, 900)]), None, None, None));
+        let clear = |pts| segments(&display_set_of(pcs(pts, Normal, Vec::new()), None, None, None));
+        let first = PgsStream::new([subtitle(90000, 1), moved(270000), clear(450000)].concat());
+        let second = PgsStream::new([subtitle(180000, 0), clear(240000)].concat());
+
+        // The moved subtitle follows the epoch of the second stream, so it defines its window, palette and object again
+        let merged = PgsStream::merge(vec![first, second]);
+        let states: Vec<(u32, PgsPcsCompositionState)> = compositions(&merged).into_iter().map(|(pts, _, state)| (pts, state)).collect();
+        assert_eq!(states, [(90000, EpochStart), (180000, EpochStart), (240000, Normal), (270000, EpochStart), (450000, Normal)]);
+        assert!(check_references(merged.get_segments()).is_ok());
+        let display_sets = merged.get_display_sets();
+        assert_eq!(display_sets[3].pcs.as_ref().unwrap().composition_objects[0].object_horizontal_position, 101);
+        assert_eq!(decode_rle_indices(display_sets[3].ods.as_ref().unwrap()).unwrap(), [[1, 1]]);
+        assert!(display_sets[3].wds.is_some() && display_sets[3].pds.is_some());
+    }
+
+    #[test]
+    fn test_concat() {
+        use PgsPcsCompositionState::{EpochStart, Normal};
+        let first = stream(&[(90000, EpochStart), (180000, Normal)]);
+        let mut second = stream(&[(0, EpochStart), (90000, Normal)]);
+        second.shift_timestamps(first.end_timestamp().unwrap() as i64);
+
+        let concatenated = PgsStream::concat(vec![first, second]);
+        assert_eq!(compositions(&concatenated), [
+            (90000, 0, EpochStart),
+            (180000, 1, Normal),
+            (180000, 2, EpochStart),
+            (270000, 3, Normal)
+        ]);
+        // Timestamps are kept as they are, even if they are out of order
+        let unshifted = PgsStream::concat(vec![stream(&[(90000, EpochStart)]), stream(&[(0, EpochStart)])]);
+        assert_eq!(compositions(&unshifted), [(90000, 0, EpochStart), (0, 1, EpochStart)]);
+    }
+}
@@ -0,0 +1,160 @@
+//! # PGS Test Support
+//!
+//! Fixtures shared by the unit tests: segment headers, the segments of a display set, and the display set or
+//! segment list built from them, as well as encoded display sets and temporary SUP files for the parser tests.
+//! Timestamps are presentation timestamps at 90 kHz, decoding timestamps are left unset.
+
+use std::{fs, path::PathBuf, process};
+
+use crate::{pgs_encode_rle::encode_rle, PgsDisplaySet, PgsEndSegment, PgsOdsSegment, PgsPcsCompositionState, PgsPcsObjectCroppedFlag,
+    PgsPcsSegment, PgsPcsSegmentCompositionObjects, PgsPdsSegment, PgsPdsSegmentPaletteEntry, PgsRc, PgsRectangle, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsWdsSegment,
+    PgsWdsSegmentWindowDefinition};
+
+/// The header of a segment presented at `pts`, with the segment length left to the writer.
+pub(crate) fn header(segment_type: PgsSegmentType, pts: u32) -> PgsSegmentHeader {
+    timed_header(segment_type, pts, 0)
+}
+
+/// The header of a segment decoded at `dts` and presented at `pts`.
+pub(crate) fn timed_header(segment_type: PgsSegmentType, pts: u32, dts: u32) -> PgsSegmentHeader {
+    PgsSegmentHeader { segment_type, segment_length: 0, presentation_timestamp: pts, decoding_timestamp: dts }
+}
+
+/// An uncropped composition object at `(x, y)`.
+pub(crate) fn composition_object(object_id: u16, window_id: u8, x: u16, y: u16) -> PgsPcsSegmentCompositionObjects {
+    PgsPcsSegmentCompositionObjects {
+        object_id,
+        window_id,
+        object_cropped_flag: PgsPcsObjectCroppedFlag::Off,
+        object_forced_flag: false,
+        object_horizontal_position: x,
+        object_vertical_position: y,
+        object_cropping_horizontal_position: 0,
+        object_cropping_vertical_position: 0,
+        object_cropping_width: 0,
+        object_cropping_height_position: 0
+    }
+}
+
+/// A window covering `rectangle`.
+pub(crate) fn window(window_id: u8, rectangle: PgsRectangle) -> PgsWdsSegmentWindowDefinition {
+    let (x, y, width, height) = rectangle;
+    PgsWdsSegmentWindowDefinition { window_id, window_horizontal_position: x, window_vertical_position: y, window_width: width, window_height: height }
+}
+
+/// A grey palette entry of the given luminance and alpha.
+pub(crate) fn palette_entry(palette_entry_id: u8, luminance: u8, transparency: u8) -> PgsPdsSegmentPaletteEntry {
+    PgsPdsSegmentPaletteEntry { palette_entry_id, luminance, color_difference_red: 128, color_difference_blue: 128, transparency }
+}
+
+/// A palette of transparent black as entry 0 and opaque white as entry 1.
+pub(crate) fn black_and_white() -> Vec<PgsPdsSegmentPaletteEntry> {
+    vec![palette_entry(0, 16, 0), palette_entry(1, 235, 255)]
+}
+
+/// A PCS showing `objects`, of a video of unset size.
+pub(crate) fn pcs(pts: u32, composition_state: PgsPcsCompositionState, objects: Vec<PgsPcsSegmentCompositionObjects>) -> PgsPcsSegment {
+    PgsPcsSegment {
+        header: header(PgsSegmentType::PCS, pts),
+        composition_state,
+        number_of_composition_objects: objects.len() as u8,
+        composition_objects: objects,
+        ..Default::default()
+    }
+}
+
+/// A WDS defining `windows`.
+pub(crate) fn wds(pts: u32, windows: Vec<PgsWdsSegmentWindowDefinition>) -> PgsWdsSegment {
+    PgsWdsSegment { header: header(PgsSegmentType::WDS, pts), number_of_windows: windows.len() as u8, windows }
+}
+
+/// A PDS of palette 0 with the given version and entries.
+pub(crate) fn pds(pts: u32, palette_version_number: u8, entries: Vec<PgsPdsSegmentPaletteEntry>) -> PgsPdsSegment {
+    PgsPdsSegment { header: header(PgsSegmentType::PDS, pts), palette_id: 0, palette_version_number, palette_entries: PgsRc::new(entries) }
+}
+
+/// A single ODS of a `width` x `height` object holding `bitmap`, which may be empty to leave the object without
+/// data.
+pub(crate) fn ods(pts: u32, object_id: u16, object_version_number: u8, width: u16, height: u16, bitmap: &[Vec<u8>]) -> PgsOdsSegment {
+    PgsOdsSegment::from_object(header(PgsSegmentType::ODS, pts), object_id, object_version_number, width, height, &encode_rle(bitmap)).remove(0)
+}
+
+/// An END segment.
+pub(crate) fn end(pts: u32) -> PgsEndSegment {
+    PgsEndSegment { header: header(PgsSegmentType::END, pts), data: Vec::new() }
+}
+
+/// A display set of the given segments.
+pub(crate) fn display_set_of(pcs: PgsPcsSegment, wds: Option<PgsWdsSegment>, pds: Option<PgsPdsSegment>, ods: Option<PgsOdsSegment>) -> PgsDisplaySet {
+    let ods = ods.map(PgsRc::new);
+    PgsDisplaySet { pcs: Some(PgsRc::new(pcs)), wds: wds.map(PgsRc::new), pds: pds.map(PgsRc::new), ods_segments: ods.iter().cloned().collect(), ods }
+}
+
+/// A complete display set showing `bitmap` with a palette of `entries`, in a PCS without composition objects or
+/// windows.
+pub(crate) fn bitmap_display_set(pts: u32, entries: Vec<PgsPdsSegmentPaletteEntry>, bitmap: &[Vec<u8>]) -> PgsDisplaySet {
+    let (width, height) = (bitmap.first().map_or(0, |row| row.len()) as u16, bitmap.len() as u16);
+    display_set_of(pcs(pts, PgsPcsCompositionState::Normal, Vec::new()), Some(wds(pts, Vec::new())), Some(pds(pts, 0, entries)),
+        Some(ods(pts, 0, 0, width, height, bitmap)))
+}
+
+/// A display set of a video of `video_size` showing object 0 at the top left corner of window 0, which covers
+/// `rectangle`, without defining the object or a palette.
+pub(crate) fn window_display_set(pts: u32, composition_state: PgsPcsCompositionState, video_size: (u16, u16), rectangle: PgsRectangle) -> PgsDisplaySet {
+    let ((width, height), (x, y, _, _)) = (video_size, rectangle);
+    display_set_of(PgsPcsSegment { width, height, ..pcs(pts, composition_state, vec![composition_object(0, 0, x, y)]) },
+        Some(wds(pts, vec![window(0, rectangle)])), None, None)
+}
+
+/// A display set clearing the screen: a PCS without composition objects and a WDS without windows.
+pub(crate) fn clear_display_set(pts: u32) -> PgsDisplaySet {
+    display_set_of(pcs(pts, PgsPcsCompositionState::Normal, Vec::new()), Some(wds(pts, Vec::new())), None, None)
+}
+
+/// The segments of a display set in stream order, closed by an END segment at the time of its PCS.
+pub(crate) fn segments(display_set: &PgsDisplaySet) -> Vec<PgsSegment> {
+    let pts = display_set.pcs.as_ref().map_or(0, |pcs| pcs.header.presentation_timestamp);
+    display_set.pcs.iter().cloned().map(PgsSegment::Pcs)
+        .chain(display_set.wds.iter().cloned().map(PgsSegment::Wds))
+        .chain(display_set.pds.iter().cloned().map(PgsSegment::Pds))
+        .chain(display_set.ods.iter().cloned().map(PgsSegment::Ods))
+        .chain([PgsSegment::End(PgsRc::new(end(pts)))])
+        .collect()
+}
+
+/// The bytes of a display set made of an empty 1920x1080 PCS and an END segment.
+pub(crate) fn display_set_bytes(pts: u32, composition_number: u8, composition_state: u8) -> Vec<u8> {
+    let pts = pts.to_be_bytes();
+    let mut data = vec![0x50, 0x47, pts[0], pts[1], pts[2], pts[3], 0, 0, 0, 0, 0x16, 0, 11];
+    data.extend([0x07, 0x80, 0x04, 0x38, 0x10, 0, composition_number, composition_state, 0, 0, 0]);
+    data.extend([0x50, 0x47, pts[0], pts[1], pts[2], pts[3], 0, 0, 0, 0, 0x80, 0, 0]);
+    data
+}
+
+/// A SUP file in the temporary directory, removed when dropped.
+pub(crate) struct TempSupFile(PathBuf);
+
+impl TempSupFile {
+    /// Writes `data` to a file named after `name` and the process id, so parallel tests and test runs do not
+    /// share files.
+    pub(crate) fn new(name: &str, data: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("pgs-{}-{}.sup", name, process::id()));
+        fs::write(&path, data).unwrap();
+        TempSupFile(path)
+    }
+
+    /// Replaces the content of the file.
+    pub(crate) fn write(&self, data: &[u8]) {
+        fs::write(&self.0, data).unwrap();
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempSupFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,179 @@
+//! # PGS Text Extraction
+//!
+//! This module turns a subtitle track into timed text. The screen content of every display set is decoded,
+//! each shown object is prepared for OCR and recognized, and the texts of all objects on screen are combined into
+//! one event in reading order. Consecutive display sets showing the same text form a single event.
+
+use crate::{pgs_composite::screens, pgs_decode_rle::get_argb, pgs_duplicates::PgsShownObject, pgs_export::{PgsTextEvent, PGS_DEFAULT_EVENT_DURATION}, pgs_lines::find_text_lines, pgs_ocr::{prepare_with_options, PgsOcrEngine, PgsOcrPrepareOptions}, PgsParser, PgsStream, Result};
+
+/// Options controlling `extract_text`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PgsTextExtractOptions {
+    /// Preprocessing applied to every object before OCR.
+    pub prepare: PgsOcrPrepareOptions,
+    /// Only recognize forced objects, e.g. to extract the foreign language parts of a track.
+    pub forced_only: bool,
+    /// Split every object into text lines with `analysis::find_text_lines` and recognize them one by one, which
+    /// helps engines that expect a single line of text.
+    pub split_lines: bool
+}
+
+/// Converts the pixels of a shown object to ARGB, transparent where the object has no color.
+fn to_argb(obj: &PgsShownObject) -> Vec<Vec<u32>> {
+    obj.pixels.iter().map(|row| {
+        row.iter().map(|color| color.map_or(0, |(y, cr, cb, alpha)| get_argb(y, cb, cr, alpha))).collect()
+    }).collect()
+}
+
+/// Trims every line, collapses runs of whitespace into one space and drops empty lines.
+fn normalize_text(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Recognizes the objects shown on screen and combines their texts in reading order: objects sharing rows of the
+/// screen are joined with a space from left to right, objects below each other with a line break.
+fn recognize_objects<E: PgsOcrEngine + ?Sized>(objects: &[PgsShownObject], engine: &mut E, options: &PgsTextExtractOptions) -> Result<String> {
+    let mut objects: Vec<&PgsShownObject> = objects.iter().filter(|obj| obj.forced || !options.forced_only).collect();
+    objects.sort_by_key(|obj| (obj.y, obj.x));
+
+    // Bottom row and text of every line of objects
+    let mut lines: Vec<(u32, String)> = Vec::new();
+    for obj in objects {
+        let pixels = to_argb(obj);
+        let images = match options.split_lines {
+            true => find_text_lines(&pixels).iter().map(|line| line.crop(&pixels)).collect(),
+            false => vec![pixels]
+        };
+        let texts = images.iter()
+            .map(|image| engine.recognize(&prepare_with_options(image, &options.prepare)))
+            .collect::<Result<Vec<_>>>()?;
+        let text = normalize_text(&texts.join("\n"));
+        if text.is_empty() {
+            continue;
+        }
+        let bottom = obj.y as u32 + obj.pixels.len() as u32;
+        match lines.last_mut() {
+            Some((line_bottom, line)) if (obj.y as u32) < *line_bottom => {
+                line.push(' ');
+                line.push_str(&text);
+                *line_bottom = (*line_bottom).max(bottom);
+            },
+            _ => lines.push((bottom, text))
+        }
+    }
+    Ok(lines.into_iter().map(|(_, text)| text).collect::<Vec<_>>().join("\n"))
+}
+
+/// Recognizes the text of every subtitle event of a stream.
+///
+/// An event lasts from the display set showing it until the next display set, or `PGS_DEFAULT_EVENT_DURATION` for
+/// the last one. Display sets without recognized text end the previous event; consecutive display sets with the
+/// same text, such as palette animations, are merged into one event.
+///
+/// # Parameters
+/// - `stream`: The stream to read.
+/// - `engine`: The OCR engine recognizing every shown object.
+/// - `options`: Preprocessing and object selection.
+///
+/// # Errors
+/// Returns `Error::InvalidRleData` if an object cannot be decoded, or the error of the OCR engine.
+///
+/// # Returns
+/// The text events in stream order, with normalized whitespace and lines separated by `\n`.
+pub fn extract_stream_text<E: PgsOcrEngine + ?Sized>(stream: &PgsStream, engine: &mut E, options: &PgsTextExtractOptions) -> Result<Vec<PgsTextEvent>> {
+    let screens = screens(stream)?;
+    let mut events: Vec<PgsTextEvent> = Vec::new();
+    for (index, screen) in screens.iter().enumerate() {
+        let text = recognize_objects(&screen.objects, engine, options)?;
+        if text.is_empty() {
+            continue;
+        }
+        let start = screen.presentation_timestamp;
+        let end = screens.get(index + 1)
+            .map_or(start.saturating_add(PGS_DEFAULT_EVENT_DURATION), |next| next.presentation_timestamp);
+        match events.last_mut() {
+            Some(last) if last.end == start && last.text == text => last.end = end,
+            _ => events.push(PgsTextEvent { start, end, text })
+        }
+    }
+    Ok(events)
+}
+
+/// Parses a SUP file and recognizes the text of every subtitle event, see `extract_stream_text`.
+///
+/// # Example
+/// ```rust,no_run
+/// use pgs_parse::{export::write_srt, ocr::{extract_text, PgsOcrEngine, PgsOcrImage, PgsTextExtractOptions}, Result};
+///
+/// struct Engine;
+///
+/// impl PgsOcrEngine for Engine {
+///     fn recognize(&mut self, image: &PgsOcrImage) -> Result<String> {
+///         Ok(String::new())
+///     }
+/// }
+///
+/// let events = extract_text("subtitle.sup", &mut Engine, &PgsTextExtractOptions::default()).unwrap();
+/// write_srt(std::io::stdout(), &events).unwrap();
+/// ```
+pub fn extract_text<E: PgsOcrEngine + ?Sized>(sup_file_path: &str, engine: &mut E, options: &PgsTextExtractOptions) -> Result<Vec<PgsTextEvent>> {
+    let stream = PgsParser::parse(sup_file_path)?.into_stream();
+    extract_stream_text(&stream, engine, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{pgs_ocr::PgsOcrImage, pgs_test_support::{composition_object, end, ods, palette_entry, pcs, pds}, PgsPcsCompositionState, PgsRc, PgsSegment};
+
+    use super::*;
+
+    /// Returns a fixed text for every image width.
+    struct WidthEngine(HashMap<usize, &'static str>);
+
+    impl PgsOcrEngine for WidthEngine {
+        fn recognize(&mut self, image: &PgsOcrImage) -> Result<String> {
+            Ok(self.0.get(&image.width).copied().unwrap_or_default().to_string())
+        }
+    }
+
+    /// A display set showing objects of the given widths at the given positions, or clearing the screen.
+    fn display_set(pts: u32, objects: &[(u16, u16, u16)]) -> Vec<PgsSegment> {
+        let composition_objects = objects.iter().enumerate().map(|(id, &(x, y, _))| composition_object(id as u16, 0, x, y)).collect();
+        let mut segments = vec![
+            PgsSegment::Pcs(PgsRc::new(pcs(pts, PgsPcsCompositionState::EpochStart, composition_objects))),
+            PgsSegment::Pds(PgsRc::new(pds(pts, 0, vec![palette_entry(1, 235, 255)])))
+        ];
+        for (id, &(_, _, width)) in objects.iter().enumerate() {
+            segments.push(PgsSegment::Ods(PgsRc::new(ods(pts, id as u16, 0, width, 1, &[vec![1; width as usize]]))));
+        }
+        segments.push(PgsSegment::End(PgsRc::new(end(pts))));
+        segments
+    }
+
+    #[test]
+    fn test_extract_text() {
+        let objects = [(300, 900, 4), (100, 800, 6), (200, 900, 8)];
+        let stream = PgsStream::new([
+            display_set(90000, &objects),
+            display_set(180000, &objects),
+            display_set(270000, &[]),
+            display_set(360000, &objects[..1])
+        ].concat());
+        let width = |object: u16| (object as usize + 20) * 3;
+        let mut engine = WidthEngine(HashMap::from([(width(4), "  world \n\n"), (width(6), "first   line"), (width(8), "hello")]));
+
+        let events = extract_stream_text(&stream, &mut engine, &PgsTextExtractOptions::default()).unwrap();
+        assert_eq!(events, [
+            PgsTextEvent { start: 90000, end: 270000, text: "first line\nhello world".to_string() },
+            PgsTextEvent { start: 360000, end: 360000 + PGS_DEFAULT_EVENT_DURATION, text: "world".to_string() }
+        ]);
+        let forced = PgsTextExtractOptions { forced_only: true, ..Default::default() };
+        assert!(extract_stream_text(&stream, &mut engine, &forced).unwrap().is_empty());
+    }
+}
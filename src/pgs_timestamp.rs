@@ -0,0 +1,76 @@
+//! # PGS Timestamps
+//!
+//! SUP files store presentation and decoding timestamps as 32-bit values of a 90 kHz clock, taken from the 33-bit
+//! MPEG PTS. Long streams, or streams cut from a broadcast where the PTS did not start at zero, can therefore wrap
+//! around to small values. This module detects such wraps and turns the timestamps into monotonic 64-bit values,
+//! so events sort correctly across the wrap.
+
+/// Number of distinct 32-bit timestamps; a wrapped timestamp is this much smaller than its unwrapped value.
+pub const PGS_TIMESTAMP_WRAP: u64 = 1 << 32;
+
+/// Returns `true` if going from `previous` to `current` is a wrap around rather than a jump backwards: the
+/// timestamp decreases by more than half of the timestamp range.
+pub fn is_timestamp_wrap(previous: u32, current: u32) -> bool {
+    current < previous && (previous - current) as u64 > PGS_TIMESTAMP_WRAP / 2
+}
+
+/// Converts a sequence of 32-bit timestamps into monotonic 64-bit timestamps by counting wraps.
+#[derive(Debug, Default, Clone)]
+pub struct PgsTimestampUnwrapper {
+    previous: Option<u32>,
+    wraps: u64
+}
+
+impl PgsTimestampUnwrapper {
+    /// Creates an unwrapper expecting the first timestamp of a stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of wraps seen so far.
+    pub fn wraps(&self) -> u64 {
+        self.wraps
+    }
+
+    /// Unwraps the next timestamp in stream order.
+    ///
+    /// A timestamp lying just before a wrap that was already seen (e.g. a decoding timestamp slightly before its
+    /// presentation timestamp) is placed before the wrap without undoing it.
+    ///
+    /// # Returns
+    /// The timestamp plus `PGS_TIMESTAMP_WRAP` for every wrap before it.
+    pub fn unwrap(&mut self, timestamp: u32) -> u64 {
+        match self.previous {
+            Some(previous) if is_timestamp_wrap(previous, timestamp) => self.wraps += 1,
+            Some(previous) if self.wraps > 0 && is_timestamp_wrap(timestamp, previous) => {
+                return (self.wraps - 1) * PGS_TIMESTAMP_WRAP + timestamp as u64;
+            },
+            _ => {}
+        }
+        self.previous = Some(timestamp);
+        self.wraps * PGS_TIMESTAMP_WRAP + timestamp as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap() {
+        assert!(is_timestamp_wrap(u32::MAX - 90000, 90000));
+        assert!(!is_timestamp_wrap(180000, 90000));
+
+        let mut unwrapper = PgsTimestampUnwrapper::new();
+        let unwrapped: Vec<u64> = [u32::MAX - 90000, 45000, u32::MAX - 1000, 90000, 80000].into_iter()
+            .map(|timestamp| unwrapper.unwrap(timestamp)).collect();
+        assert_eq!(unwrapped, vec![
+            u32::MAX as u64 - 90000,
+            PGS_TIMESTAMP_WRAP + 45000,
+            u32::MAX as u64 - 1000,
+            PGS_TIMESTAMP_WRAP + 90000,
+            PGS_TIMESTAMP_WRAP + 80000
+        ]);
+        assert_eq!(unwrapper.wraps(), 1);
+    }
+}
@@ -0,0 +1,104 @@
+//! # PGS Tracing
+//!
+//! This module holds the `tracing` instrumentation of the parser and the decoder. With the `tracing` feature,
+//! parsing a file opens a `pgs_parse` span, and within it a `pgs_epoch` span per epoch, a `pgs_display_set` span
+//! per display set and a `pgs_segment` span per segment; decoding a bitmap opens a `pgs_decode` span. The spans
+//! are at debug level and carry offsets, timestamps and sizes, while their timing is left to the subscriber, e.g.
+//! `tracing_subscriber::fmt` with span close events. Without the feature, every function here compiles to nothing.
+//! The `log` messages of the parser are kept either way.
+
+use crate::PgsOdsSegment;
+
+#[cfg(feature = "tracing")]
+use crate::{pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH, PgsPcsCompositionState, PgsSegmentType};
+
+/// Guard keeping a span entered until it is dropped.
+#[cfg(feature = "tracing")]
+pub(crate) type PgsTraceGuard = tracing::span::EnteredSpan;
+
+/// Guard keeping a span entered until it is dropped.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct PgsTraceGuard;
+
+/// The epoch and display set spans of a `PgsPushParser`, which stay open across pushed chunks.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "tracing"), derive(Default))]
+pub(crate) struct PgsTraceSpans {
+    #[cfg(feature = "tracing")]
+    epoch: tracing::Span,
+    #[cfg(feature = "tracing")]
+    display_set: tracing::Span,
+    /// Display sets and bytes of the current epoch.
+    #[cfg(feature = "tracing")]
+    epoch_size: (usize, usize)
+}
+
+#[cfg(feature = "tracing")]
+impl Default for PgsTraceSpans {
+    fn default() -> Self {
+        PgsTraceSpans { epoch: tracing::Span::none(), display_set: tracing::Span::none(), epoch_size: (0, 0) }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl PgsTraceSpans {
+    /// Enters the span of the segment at `offset`, whose bytes, header included, are `data`. An epoch start PCS
+    /// opens a new epoch span, and the first segment of a display set a new display set span.
+    pub(crate) fn segment(&mut self, offset: usize, data: &[u8]) -> PgsTraceGuard {
+        let pts = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
+        let segment_type = PgsSegmentType::from(data[10]);
+        let epoch_start = segment_type == PgsSegmentType::PCS && data.get(PGS_SEGMENT_HEADER_LENGTH + 7)
+            .is_some_and(|&state| PgsPcsCompositionState::from(state) == PgsPcsCompositionState::EpochStart);
+        if epoch_start {
+            self.epoch = tracing::debug_span!("pgs_epoch", offset, pts, display_sets = tracing::field::Empty, bytes = tracing::field::Empty);
+            self.epoch_size = (0, 0);
+        }
+        if self.display_set.is_none() {
+            self.display_set = tracing::debug_span!(parent: &self.epoch, "pgs_display_set", offset, pts, segments = tracing::field::Empty, bytes = tracing::field::Empty);
+        }
+        tracing::debug_span!(parent: &self.display_set, "pgs_segment", offset, ?segment_type, length = data.len()).entered()
+    }
+
+    /// Closes the display set span, recording the number of segments and bytes of the display set.
+    pub(crate) fn end_display_set(&mut self, segments: usize, bytes: usize) {
+        self.display_set.record("segments", segments).record("bytes", bytes);
+        self.display_set = tracing::Span::none();
+        self.epoch_size = (self.epoch_size.0 + 1, self.epoch_size.1 + bytes);
+        self.epoch.record("display_sets", self.epoch_size.0).record("bytes", self.epoch_size.1);
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl PgsTraceSpans {
+    /// Enters the span of the segment at `offset`, whose bytes, header included, are `data`.
+    pub(crate) fn segment(&mut self, _offset: usize, _data: &[u8]) -> PgsTraceGuard {
+        PgsTraceGuard
+    }
+
+    /// Closes the display set span, recording the number of segments and bytes of the display set.
+    pub(crate) fn end_display_set(&mut self, _segments: usize, _bytes: usize) {}
+}
+
+/// Enters the span of parsing the file at `path` of `length` bytes.
+#[cfg(feature = "tracing")]
+pub(crate) fn parse_span(path: &str, length: usize) -> PgsTraceGuard {
+    tracing::debug_span!("pgs_parse", path, length).entered()
+}
+
+/// Enters the span of parsing the file at `path` of `length` bytes.
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn parse_span(_path: &str, _length: usize) -> PgsTraceGuard {
+    PgsTraceGuard
+}
+
+/// Enters the span of decoding the bitmap of an object.
+#[cfg(feature = "tracing")]
+pub(crate) fn decode_span(ods: &PgsOdsSegment) -> PgsTraceGuard {
+    tracing::debug_span!("pgs_decode", object_id = ods.object_id, width = ods.width, height = ods.height, length = ods.object_data.len()).entered()
+}
+
+/// Enters the span of decoding the bitmap of an object.
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn decode_span(_ods: &PgsOdsSegment) -> PgsTraceGuard {
+    PgsTraceGuard
+}
@@ -0,0 +1,1251 @@
+//! # PGS Transform
+//!
+//! This module contains editing passes that modify a `PgsStream` in place, so the result can be written back as
+//! a new SUP file with `PgsStream::write`.
+
+use std::{collections::{BTreeMap, HashMap}};
+
+use crate::{pgs_decode_rle::decode_rle_indices, pgs_encode_rle::encode_rle, pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH, Error, PgsOdsSegment, PgsOdsSequenceFlag, PgsPcsCompositionState, PgsPcsSegment, PgsPcsSegmentCompositionObjects, PgsPdsSegment, PgsPdsSegmentPaletteEntry, PgsRc, PgsSegment, PgsSegmentHeader, PgsSegmentType, PgsStream, PgsWdsSegmentWindowDefinition, Result};
+
+/// Maps coordinates from one resolution to another along a single axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PgsAxisScale {
+    pub(crate) from: u32,
+    pub(crate) to: u32
+}
+
+impl PgsAxisScale {
+    /// Scales a position, rounding down.
+    pub(crate) fn floor(&self, value: u16) -> u16 {
+        (value as u32 * self.to / self.from).min(u16::MAX as u32) as u16
+    }
+
+    /// Scales a position, rounding up.
+    fn ceil(&self, value: u16) -> u16 {
+        (value as u32 * self.to).div_ceil(self.from).min(u16::MAX as u32) as u16
+    }
+
+    /// Scales a size, keeping it at least one pixel.
+    fn size(&self, value: u16) -> u16 {
+        self.floor(value).max(1)
+    }
+}
+
+/// Scales a bitmap of palette entry ids to a new size using nearest-neighbour sampling, which keeps every pixel a
+/// valid entry of the original palette.
+fn scale_bitmap(bitmap: &[Vec<u8>], width: u16, height: u16) -> Vec<Vec<u8>> {
+    let (src_height, src_width) = (bitmap.len(), bitmap.first().map_or(0, |row| row.len()));
+    (0..height as usize).map(|y| {
+        let row = &bitmap[y * src_height / height as usize];
+        (0..width as usize).map(|x| row[x * src_width / width as usize]).collect()
+    }).collect()
+}
+
+/// Resampling filter used when rescaling object bitmaps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PgsScaleFilter {
+    /// Nearest neighbour sampling, keeping the original pixels.
+    #[default]
+    Nearest,
+    /// Bilinear interpolation of the palette colors, requantized to the nearest entry of the object's palette,
+    /// which gives smoother edges when upscaling anti-aliased text.
+    Bilinear
+}
+
+/// Palette entries of a palette, by entry id.
+type PgsPalette = HashMap<u8, PgsPdsSegmentPaletteEntry>;
+
+/// Returns the alpha-premultiplied Y, Cr, Cb and alpha of a palette entry. Missing entries are transparent.
+fn premultiplied_color(palette: &PgsPalette, index: u8) -> [f32; 4] {
+    palette.get(&index).map_or([0.0; 4], |entry| {
+        let alpha = entry.transparency as f32 / 255.0;
+        [entry.luminance as f32 * alpha, entry.color_difference_red as f32 * alpha, entry.color_difference_blue as f32 * alpha, entry.transparency as f32]
+    })
+}
+
+/// Scales a bitmap of palette entry ids to a new size using bilinear interpolation of the palette colors, mapping
+/// every interpolated color back to the closest entry of `palette`.
+fn scale_bitmap_bilinear(bitmap: &[Vec<u8>], width: u16, height: u16, palette: &PgsPalette) -> Vec<Vec<u8>> {
+    let (src_height, src_width) = (bitmap.len(), bitmap.first().map_or(0, |row| row.len()));
+    let mut candidates: Vec<(u8, [f32; 4])> = palette.keys().map(|&index| (index, premultiplied_color(palette, index))).collect();
+    candidates.sort_unstable_by_key(|&(index, _)| index);
+    let requantize = |color: [f32; 4]| candidates.iter()
+        .min_by(|(_, a), (_, b)| {
+            let distance = |entry: &[f32; 4]| entry.iter().zip(color).map(|(entry, color)| (entry - color) * (entry - color)).sum::<f32>();
+            distance(a).total_cmp(&distance(b))
+        })
+        .map_or(0, |&(index, _)| index);
+    // Source position and weight of the next pixel along an axis
+    let sample = |position: usize, size: usize, src_size: usize| {
+        let source = ((position as f32 + 0.5) * src_size as f32 / size as f32 - 0.5).clamp(0.0, (src_size - 1) as f32);
+        let low = source.floor() as usize;
+        (low, (low + 1).min(src_size - 1), source - low as f32)
+    };
+
+    (0..height as usize).map(|y| {
+        let (top, bottom, dy) = sample(y, height as usize, src_height);
+        (0..width as usize).map(|x| {
+            let (left, right, dx) = sample(x, width as usize, src_width);
+            let corners = [
+                (bitmap[top][left], (1.0 - dx) * (1.0 - dy)),
+                (bitmap[top][right], dx * (1.0 - dy)),
+                (bitmap[bottom][left], (1.0 - dx) * dy),
+                (bitmap[bottom][right], dx * dy)
+            ];
+            // Areas of a single color keep their original palette entry
+            if corners.iter().all(|&(index, _)| index == corners[0].0) {
+                return corners[0].0;
+            }
+            let mut color = [0.0; 4];
+            for (index, weight) in corners {
+                for (channel, value) in color.iter_mut().zip(premultiplied_color(palette, index)) {
+                    *channel += value * weight;
+                }
+            }
+            requantize(color)
+        }).collect()
+    }).collect()
+}
+
+/// Decodes, scales and re-encodes an object given by its ODS fragments, returning the new fragments.
+pub(crate) fn rescale_object(fragments: &[PgsRc<PgsOdsSegment>], horizontal: PgsAxisScale, vertical: PgsAxisScale, filter: PgsScaleFilter, palette: Option<&PgsPalette>) -> Result<Vec<PgsSegment>> {
+    let first = &fragments[0];
+    let object = PgsOdsSegment {
+        object_data: fragments.iter().flat_map(|ods| ods.object_data.iter().copied()).collect(),
+        ..first.as_ref().clone()
+    };
+
+    let bitmap = decode_rle_indices(&object)?;
+    // An empty object has no pixels to sample and is kept as it is
+    if bitmap.first().is_none_or(|row| row.is_empty()) {
+        return Ok(fragments.iter().map(|ods| PgsSegment::Ods(ods.clone())).collect());
+    }
+    let (width, height) = (horizontal.size(object.width), vertical.size(object.height));
+    let bitmap = match (filter, palette) {
+        (PgsScaleFilter::Bilinear, Some(palette)) if !bitmap.is_empty() && !palette.is_empty() => scale_bitmap_bilinear(&bitmap, width, height, palette),
+        _ => scale_bitmap(&bitmap, width, height)
+    };
+    Ok(PgsOdsSegment::from_object(first.header, first.object_id, first.object_version_number, width, height, &encode_rle(&bitmap))
+        .into_iter().map(|ods| PgsSegment::Ods(PgsRc::new(ods))).collect())
+}
+
+/// Rescales a stream from one video resolution to another.
+///
+/// The PCS video size, window rectangles, object positions and cropping rectangles are scaled, and every object
+/// bitmap is decoded, resampled (nearest neighbour) and re-encoded, split into as many ODS fragments as needed.
+/// Windows are rounded outwards so they keep enclosing their objects. Palettes are left untouched.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `from`: The current video resolution (width, height).
+/// - `to`: The target video resolution (width, height).
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if a resolution has a zero dimension, or any error from decoding the
+/// object bitmaps.
+pub fn rescale(stream: &mut PgsStream, from: (u16, u16), to: (u16, u16)) -> Result<()> {
+    rescale_with_filter(stream, from, to, PgsScaleFilter::Nearest)
+}
+
+/// Rescales a stream from one video resolution to another like `rescale`, resampling the object bitmaps with the
+/// given filter.
+///
+/// With `PgsScaleFilter::Bilinear` every object is interpolated using the palette of the composition that
+/// defines it, and the result is requantized to that palette, so the palettes still stay untouched. Objects
+/// without a known palette fall back to nearest neighbour sampling.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if a resolution has a zero dimension, or any error from decoding the
+/// object bitmaps.
+pub fn rescale_with_filter(stream: &mut PgsStream, from: (u16, u16), to: (u16, u16), filter: PgsScaleFilter) -> Result<()> {
+    if from.0 == 0 || from.1 == 0 || to.0 == 0 || to.1 == 0 {
+        return Err(Error::InvalidArgument(format!("cannot rescale from {}x{} to {}x{}", from.0, from.1, to.0, to.1)));
+    }
+    let horizontal = PgsAxisScale { from: from.0 as u32, to: to.0 as u32 };
+    let vertical = PgsAxisScale { from: from.1 as u32, to: to.1 as u32 };
+
+    let mut segments: Vec<PgsSegment> = Vec::with_capacity(stream.get_segments().len());
+    // ODS fragments of the object being collected
+    let mut fragments: Vec<PgsRc<PgsOdsSegment>> = Vec::new();
+    // Palettes of the current epoch and the palette used by the current composition
+    let mut palettes: HashMap<u8, PgsPalette> = HashMap::new();
+    let mut palette_id = 0;
+    for segment in stream.get_segments_mut().drain(..) {
+        match segment {
+            PgsSegment::Pcs(mut pcs) => {
+                if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+                    palettes.clear();
+                }
+                palette_id = pcs.palette_id;
+                let pcs_mut = PgsRc::make_mut(&mut pcs);
+                pcs_mut.width = to.0;
+                pcs_mut.height = to.1;
+                for obj in &mut pcs_mut.composition_objects {
+                    obj.object_horizontal_position = horizontal.floor(obj.object_horizontal_position);
+                    obj.object_vertical_position = vertical.floor(obj.object_vertical_position);
+                    obj.object_cropping_horizontal_position = horizontal.floor(obj.object_cropping_horizontal_position);
+                    obj.object_cropping_vertical_position = vertical.floor(obj.object_cropping_vertical_position);
+                    obj.object_cropping_width = horizontal.floor(obj.object_cropping_width);
+                    obj.object_cropping_height_position = vertical.floor(obj.object_cropping_height_position);
+                }
+                segments.push(PgsSegment::Pcs(pcs));
+            },
+            PgsSegment::Wds(mut wds) => {
+                for window in &mut PgsRc::make_mut(&mut wds).windows {
+                    let x = horizontal.floor(window.window_horizontal_position);
+                    let y = vertical.floor(window.window_vertical_position);
+                    let right = horizontal.ceil(window.window_horizontal_position.saturating_add(window.window_width)).min(to.0);
+                    let bottom = vertical.ceil(window.window_vertical_position.saturating_add(window.window_height)).min(to.1);
+                    window.window_horizontal_position = x;
+                    window.window_vertical_position = y;
+                    window.window_width = right.saturating_sub(x);
+                    window.window_height = bottom.saturating_sub(y);
+                }
+                segments.push(PgsSegment::Wds(wds));
+            },
+            PgsSegment::Ods(ods) => {
+                match ods.last_in_sequence_flag {
+                    PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both => {
+                        // An unfinished sequence is kept as it is
+                        segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+                        fragments.push(ods);
+                    },
+                    _ if fragments.is_empty() => segments.push(PgsSegment::Ods(ods)),
+                    _ => fragments.push(ods)
+                }
+                if matches!(fragments.last(), Some(ods) if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::Last | PgsOdsSequenceFlag::Both)) {
+                    segments.extend(rescale_object(&fragments, horizontal, vertical, filter, palettes.get(&palette_id))?);
+                    fragments.clear();
+                }
+            },
+            segment => {
+                if let PgsSegment::Pds(pds) = &segment {
+                    let palette = palettes.entry(pds.palette_id).or_default();
+                    palette.extend(pds.palette_entries.iter().map(|entry| (entry.palette_entry_id, entry.clone())));
+                }
+                segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+                segments.push(segment);
+            }
+        }
+    }
+    segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+
+    *stream.get_segments_mut() = segments;
+    Ok(())
+}
+
+/// Rebuilds the display set at `index` as an Epoch Start holding the windows, palettes and objects that the
+/// decoder has at that point of its epoch, so it can start a stream on its own.
+pub(crate) fn epoch_start_display_set(display_sets: &[&[PgsSegment]], index: usize) -> Vec<PgsSegment> {
+    let is_epoch_start = |segments: &[PgsSegment]| matches!(&segments[0], PgsSegment::Pcs(pcs) if pcs.composition_state == PgsPcsCompositionState::EpochStart);
+    let first = (0..=index).rev().find(|&index| is_epoch_start(display_sets[index])).unwrap_or(0);
+
+    let mut windows = None;
+    let mut palettes: BTreeMap<u8, (u8, BTreeMap<u8, PgsPdsSegmentPaletteEntry>)> = BTreeMap::new();
+    let mut objects: BTreeMap<u16, Vec<PgsRc<PgsOdsSegment>>> = BTreeMap::new();
+    for segment in display_sets[first..=index].iter().flat_map(|display_set| display_set.iter()) {
+        match segment {
+            PgsSegment::Wds(wds) => windows = Some(wds.clone()),
+            PgsSegment::Pds(pds) => {
+                let palette = palettes.entry(pds.palette_id).or_default();
+                palette.0 = pds.palette_version_number;
+                palette.1.extend(pds.palette_entries.iter().map(|entry| (entry.palette_entry_id, entry.clone())));
+            },
+            PgsSegment::Ods(ods) => {
+                let fragments = objects.entry(ods.object_id).or_default();
+                if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) {
+                    fragments.clear();
+                }
+                fragments.push(ods.clone());
+            },
+            _ => {}
+        }
+    }
+
+    let display_set = display_sets[index];
+    let (PgsSegment::Pcs(pcs), Some(end)) = (&display_set[0], display_set.last()) else {
+        return display_set.to_vec();
+    };
+    let header = pcs.header;
+    let mut segments = vec![PgsSegment::Pcs(PgsRc::new(PgsPcsSegment { composition_state: PgsPcsCompositionState::EpochStart, ..pcs.as_ref().clone() }))];
+    segments.extend(windows.map(PgsSegment::Wds));
+    for (palette_id, (palette_version_number, entries)) in palettes {
+        let pds = PgsPdsSegment { header: PgsSegmentHeader { segment_type: PgsSegmentType::PDS, ..header }, palette_id, palette_version_number, palette_entries: PgsRc::new(entries.into_values().collect()) };
+        segments.push(PgsSegment::Pds(PgsRc::new(pds)));
+    }
+    segments.extend(objects.into_values().flatten().map(PgsSegment::Ods));
+    segments.push(end.clone());
+    for segment in &mut segments {
+        let PgsSegmentHeader { segment_type, segment_length, .. } = *segment.header();
+        *segment.header_mut() = PgsSegmentHeader { segment_type, segment_length, ..header };
+    }
+    segments
+}
+
+/// Cuts a stream down to the display sets shown in the time range `start..end` (90 kHz ticks).
+///
+/// A display set is kept if its on-screen interval, up to the next display set, overlaps the range. The display
+/// set following the range is kept as well if it clears the screen, so the last subtitle does not stay visible.
+/// Timestamps of kept display sets are clamped to the range, preserving the DTS lead, and optionally rebased so
+/// the range starts at zero.
+///
+/// If the range starts within an epoch, the first kept display set is turned into an Epoch Start carrying the
+/// windows, palettes and objects defined earlier in that epoch, so the result is a standalone valid stream.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `start`: Start of the range, inclusive.
+/// - `end`: End of the range, exclusive.
+/// - `rebase`: Subtract `start` from every timestamp.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the range is empty.
+pub fn trim(stream: &mut PgsStream, start: u32, end: u32, rebase: bool) -> Result<()> {
+    if start >= end {
+        return Err(Error::InvalidArgument(format!("cannot trim to an empty range {start}..{end}")));
+    }
+
+    let display_sets: Vec<&[PgsSegment]> = stream.get_display_set_segments().collect();
+    let timestamp = |segments: &[PgsSegment]| segments[0].header().presentation_timestamp;
+    let clears_screen = |segments: &[PgsSegment]| matches!(&segments[0], PgsSegment::Pcs(pcs) if pcs.composition_objects.is_empty());
+
+    let mut segments: Vec<PgsSegment> = Vec::new();
+    for (index, display_set) in display_sets.iter().enumerate() {
+        let presentation_timestamp = timestamp(display_set);
+        let next_timestamp = display_sets.get(index + 1).map_or(u32::MAX, |next| timestamp(next));
+        let shown = presentation_timestamp < end && next_timestamp > start;
+        let closes_range = presentation_timestamp >= end && clears_screen(display_set);
+        if (shown || closes_range) && segments.is_empty() {
+            segments = epoch_start_display_set(&display_sets, index);
+        } else if shown || closes_range {
+            segments.extend(display_set.iter().cloned());
+        }
+        if presentation_timestamp >= end {
+            break;
+        }
+    }
+
+    let offset = if rebase { start } else { 0 };
+    for segment in &mut segments {
+        let header = segment.header_mut();
+        let timestamp = header.presentation_timestamp.clamp(start, end);
+        if header.decoding_timestamp != 0 {
+            let lead = header.presentation_timestamp.saturating_sub(header.decoding_timestamp);
+            header.decoding_timestamp = timestamp.saturating_sub(lead).saturating_sub(offset);
+        }
+        header.presentation_timestamp = timestamp - offset;
+    }
+
+    *stream.get_segments_mut() = segments;
+    Ok(())
+}
+
+/// Returns the complete objects defined by the ODS fragments of a display set, as the object id, the indices of
+/// its fragments in `display_set` and the joined object.
+pub(crate) fn complete_objects(display_set: &[PgsSegment]) -> Vec<(u16, Vec<usize>, PgsOdsSegment)> {
+    let mut objects = Vec::new();
+    let mut fragments: Vec<usize> = Vec::new();
+    for (index, segment) in display_set.iter().enumerate() {
+        let PgsSegment::Ods(ods) = segment else {
+            continue;
+        };
+        if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) {
+            fragments.clear();
+        }
+        fragments.push(index);
+        if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::Last | PgsOdsSequenceFlag::Both) {
+            let first = match &display_set[fragments[0]] {
+                PgsSegment::Ods(first) => first,
+                _ => unreachable!()
+            };
+            let object = PgsOdsSegment {
+                object_data: fragments.iter().flat_map(|&index| match &display_set[index] {
+                    PgsSegment::Ods(ods) => ods.object_data.clone(),
+                    _ => Vec::new()
+                }).collect(),
+                ..first.as_ref().clone()
+            };
+            objects.push((first.object_id, std::mem::take(&mut fragments), object));
+        }
+    }
+    objects
+}
+
+/// Removes objects whose bitmap is identical to another object already in the decoder buffer, and makes the
+/// compositions refer to that object instead, following the object reuse model of the specification.
+///
+/// An object is only replaced by an object that stays unchanged for the rest of the epoch, and only if no
+/// composition shows both objects at once, so every composition still shows the same bitmaps. Objects of
+/// different epochs are never shared, and objects are only removed from normal display sets, as epoch starts and
+/// acquisition points must define every object they show.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+///
+/// # Errors
+/// Returns any error from decoding the object bitmaps.
+///
+/// # Returns
+/// The number of removed objects.
+pub fn deduplicate_objects(stream: &mut PgsStream) -> Result<usize> {
+    let mut display_sets: Vec<Vec<PgsSegment>> = stream.get_display_set_segments().map(|display_set| display_set.to_vec()).collect();
+    let epoch_start = |display_set: &[PgsSegment]| matches!(display_set.first(),
+        Some(PgsSegment::Pcs(pcs)) if pcs.composition_state == PgsPcsCompositionState::EpochStart);
+    let composition = |display_set: &[PgsSegment]| match display_set.first() {
+        Some(PgsSegment::Pcs(pcs)) => pcs.composition_objects.iter().map(|obj| obj.object_id).collect(),
+        _ => Vec::new()
+    };
+
+    let mut removed = 0;
+    let mut epoch = 0;
+    while epoch < display_sets.len() {
+        let epoch_end = display_sets[epoch + 1..].iter().position(|display_set| epoch_start(display_set))
+            .map_or(display_sets.len(), |offset| epoch + 1 + offset);
+        // Object ids defined by every display set and shown by every composition of the epoch
+        let definitions: Vec<Vec<u16>> = display_sets[epoch..epoch_end].iter()
+            .map(|display_set| complete_objects(display_set).into_iter().map(|(object_id, _, _)| object_id).collect())
+            .collect();
+        let compositions: Vec<Vec<u16>> = display_sets[epoch..epoch_end].iter().map(|display_set| composition(display_set)).collect();
+        let redefined_after = |object_id: u16, position: usize, order: usize| definitions[position][order + 1..].contains(&object_id)
+            || definitions[position + 1..].iter().any(|ids| ids.contains(&object_id));
+
+        // Decoded bitmap of every object sent to the buffer, and the objects replaced by another one
+        let mut buffer: HashMap<u16, Vec<Vec<u8>>> = HashMap::new();
+        let mut aliases: HashMap<u16, u16> = HashMap::new();
+        for position in 0..epoch_end - epoch {
+            let display_set = &mut display_sets[epoch + position];
+            // Epoch starts and acquisition points must define every object they show, so only normal display sets
+            // can rely on objects already in the buffer
+            let normal = matches!(display_set.first(),
+                Some(PgsSegment::Pcs(pcs)) if pcs.composition_state == PgsPcsCompositionState::Normal);
+            let mut dropped: Vec<usize> = Vec::new();
+            for (order, (object_id, fragments, object)) in complete_objects(display_set).into_iter().enumerate() {
+                aliases.remove(&object_id);
+                let bitmap = decode_rle_indices(&object)?;
+                // Compositions showing this definition of the object, up to its next definition
+                let shown_until = definitions[position + 1..].iter().position(|ids| ids.contains(&object_id))
+                    .map_or(compositions.len(), |offset| position + 1 + offset);
+                let original = buffer.iter()
+                    .filter(|_| normal)
+                    .filter(|&(&id, existing)| id != object_id && *existing == bitmap && !aliases.contains_key(&id) && !redefined_after(id, position, order))
+                    .map(|(&id, _)| id)
+                    .filter(|&id| compositions[position..shown_until].iter().all(|shown| {
+                        let resolve = |shown_id: &u16| aliases.get(shown_id).copied().unwrap_or(*shown_id);
+                        !(shown.contains(&object_id) && shown.iter().any(|shown_id| resolve(shown_id) == id))
+                    }))
+                    .min();
+                match original {
+                    Some(original) => {
+                        aliases.insert(object_id, original);
+                        buffer.remove(&object_id);
+                        dropped.extend(fragments);
+                        removed += 1;
+                    },
+                    None => {
+                        buffer.insert(object_id, bitmap);
+                    }
+                }
+            }
+
+            let mut index = 0;
+            display_set.retain(|_| {
+                index += 1;
+                !dropped.contains(&(index - 1))
+            });
+            if let Some(PgsSegment::Pcs(pcs)) = display_set.first_mut() {
+                if pcs.composition_objects.iter().any(|obj| aliases.contains_key(&obj.object_id)) {
+                    for obj in &mut PgsRc::make_mut(pcs).composition_objects {
+                        obj.object_id = aliases.get(&obj.object_id).copied().unwrap_or(obj.object_id);
+                    }
+                }
+            }
+        }
+        epoch = epoch_end;
+    }
+
+    *stream.get_segments_mut() = display_sets.into_iter().flatten().collect();
+    Ok(removed)
+}
+
+/// Summary of the changes made by `recompress`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsRecompressReport {
+    /// Number of complete objects in the stream.
+    pub objects: usize,
+    /// Objects whose RLE data was replaced by a shorter encoding.
+    pub objects_recompressed: usize,
+    /// Total size of the object data before recompressing, in bytes.
+    pub size_before: usize,
+    /// Total size of the object data after recompressing, in bytes.
+    pub size_after: usize
+}
+
+impl PgsRecompressReport {
+    /// Returns the number of bytes saved.
+    pub fn saved(&self) -> usize {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// Re-encodes the RLE data of every object with the shortest run codes, for streams written by authoring tools
+/// that emit needlessly long encodings.
+///
+/// Every object is decoded and re-encoded, and the new encoding is used only if it is shorter, split into as many
+/// ODS fragments as needed. The bitmaps, and therefore the presentation, stay identical.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+///
+/// # Errors
+/// Returns any error from decoding the object bitmaps.
+///
+/// # Returns
+/// A `PgsRecompressReport` with the number of recompressed objects and the size savings.
+pub fn recompress(stream: &mut PgsStream) -> Result<PgsRecompressReport> {
+    let mut report = PgsRecompressReport::default();
+    let mut segments: Vec<PgsSegment> = Vec::with_capacity(stream.get_segments().len());
+    // ODS fragments of the object being collected
+    let mut fragments: Vec<PgsRc<PgsOdsSegment>> = Vec::new();
+    for segment in stream.get_segments_mut().drain(..) {
+        let PgsSegment::Ods(ods) = segment else {
+            segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+            segments.push(segment);
+            continue;
+        };
+        match ods.last_in_sequence_flag {
+            PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both => {
+                // An unfinished sequence is kept as it is
+                segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+                fragments.push(ods);
+            },
+            _ if fragments.is_empty() => segments.push(PgsSegment::Ods(ods)),
+            _ => fragments.push(ods)
+        }
+        if !matches!(fragments.last(), Some(ods) if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::Last | PgsOdsSequenceFlag::Both)) {
+            continue;
+        }
+
+        let first = &fragments[0];
+        let object = PgsOdsSegment {
+            object_data: fragments.iter().flat_map(|ods| ods.object_data.iter().copied()).collect(),
+            ..first.as_ref().clone()
+        };
+        let data = encode_rle(&decode_rle_indices(&object)?);
+        report.objects += 1;
+        report.size_before += object.object_data.len();
+        if data.len() < object.object_data.len() {
+            report.objects_recompressed += 1;
+            report.size_after += data.len();
+            segments.extend(PgsOdsSegment::from_object(first.header, first.object_id, first.object_version_number, object.width, object.height, &data)
+                .into_iter().map(|ods| PgsSegment::Ods(PgsRc::new(ods))));
+            fragments.clear();
+        } else {
+            report.size_after += object.object_data.len();
+            segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+        }
+    }
+    segments.extend(fragments.drain(..).map(PgsSegment::Ods));
+
+    *stream.get_segments_mut() = segments;
+    Ok(report)
+}
+
+/// Summary of the changes made by `optimize`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsOptimizeReport {
+    /// Display sets removed because they did not change what is shown on screen.
+    pub display_sets_removed: usize,
+    /// Unchanged `AcquisitionPoint` display sets among the removed display sets.
+    pub acquisition_points_removed: usize,
+    /// Objects (all of their ODS fragments) removed because the same object was already in the decoder buffer.
+    pub objects_removed: usize,
+    /// PDS segments removed because they did not change the palette.
+    pub palettes_removed: usize,
+    /// Encoded size of the stream before optimizing, in bytes.
+    pub size_before: usize,
+    /// Encoded size of the stream after optimizing, in bytes.
+    pub size_after: usize
+}
+
+impl PgsOptimizeReport {
+    /// Returns the number of bytes saved.
+    pub fn saved(&self) -> usize {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// Returns the encoded size of the segments in bytes.
+fn encoded_size(segments: &[PgsSegment]) -> Result<usize> {
+    segments.iter().try_fold(0, |size, segment| Ok(size + PGS_SEGMENT_HEADER_LENGTH + segment.to_data()?.len()))
+}
+
+/// Decoder state of the current epoch, as far as it is needed to recognize redundant data.
+#[derive(Default)]
+struct PgsEpochState {
+    windows: HashMap<u8, PgsWdsSegmentWindowDefinition>,
+    palettes: HashMap<u8, HashMap<u8, PgsPdsSegmentPaletteEntry>>,
+    /// Version and complete RLE data of every object in the buffer, by object id.
+    objects: HashMap<u16, (u8, Vec<u8>)>,
+    /// Incremented whenever the state changes, so unchanged display sets can be recognized.
+    generation: u64
+}
+
+/// Options of `optimize_with_options`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PgsOptimizeOptions {
+    /// Also remove `AcquisitionPoint` display sets that change nothing on screen. Players can then only start
+    /// decoding at the remaining refresh points, so seeking becomes less precise.
+    pub strip_acquisition_points: bool
+}
+
+/// Removes data that does not change the presentation of a stream:
+/// - PDS segments of `Normal` display sets that redefine palette entries with their current values,
+/// - objects of `Normal` display sets identical (version and data) to the object already in the buffer,
+/// - `Normal` display sets that change nothing and show the same composition as the previous display set,
+///   including clear frames following a clear frame.
+///
+/// `EpochStart` and `AcquisitionPoint` display sets are kept with all their data, so random access still works.
+/// Compositions are renumbered if display sets were removed.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+///
+/// # Returns
+/// A `PgsOptimizeReport` with the number of removed items and the size savings.
+pub fn optimize(stream: &mut PgsStream) -> Result<PgsOptimizeReport> {
+    optimize_with_options(stream, PgsOptimizeOptions::default())
+}
+
+/// Removes data that does not change the presentation of a stream like `optimize`, and with
+/// `strip_acquisition_points` also `AcquisitionPoint` display sets that change nothing and show the same
+/// composition as the previous display set.
+///
+/// # Returns
+/// A `PgsOptimizeReport` with the number of removed items and the size savings.
+pub fn optimize_with_options(stream: &mut PgsStream, options: PgsOptimizeOptions) -> Result<PgsOptimizeReport> {
+    let mut report = PgsOptimizeReport { size_before: encoded_size(stream.get_segments())?, ..Default::default() };
+
+    let mut state = PgsEpochState::default();
+    let mut previous: Option<(PgsRc<PgsPcsSegment>, u64)> = None;
+    let mut segments: Vec<PgsSegment> = Vec::new();
+    for display_set in stream.get_display_set_segments() {
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            segments.extend(display_set.iter().cloned());
+            continue;
+        };
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            state = PgsEpochState::default();
+        }
+        let normal = pcs.composition_state == PgsPcsCompositionState::Normal;
+        let generation = state.generation;
+
+        let mut kept: Vec<PgsSegment> = Vec::new();
+        let mut fragments: Vec<PgsRc<PgsOdsSegment>> = Vec::new();
+        for segment in display_set {
+            match segment {
+                PgsSegment::Wds(wds) => {
+                    for window in &wds.windows {
+                        if state.windows.insert(window.window_id, window.clone()).as_ref() != Some(window) {
+                            state.generation += 1;
+                        }
+                    }
+                    kept.push(segment.clone());
+                },
+                PgsSegment::Pds(pds) => {
+                    let palette = state.palettes.entry(pds.palette_id).or_default();
+                    let changed = pds.palette_entries.iter()
+                        .filter(|&entry| palette.insert(entry.palette_entry_id, entry.clone()).as_ref() != Some(entry))
+                        .count() > 0;
+                    if changed || !normal {
+                        state.generation += changed as u64;
+                        kept.push(segment.clone());
+                    } else {
+                        report.palettes_removed += 1;
+                    }
+                },
+                PgsSegment::Ods(ods) => {
+                    fragments.push(ods.clone());
+                    if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::Last | PgsOdsSequenceFlag::Both) {
+                        let object = (fragments[0].object_version_number,
+                            fragments.iter().flat_map(|ods| ods.object_data.iter().copied()).collect::<Vec<u8>>());
+                        let changed = state.objects.insert(fragments[0].object_id, object.clone()) != Some(object);
+                        if changed || !normal {
+                            state.generation += changed as u64;
+                            kept.extend(fragments.drain(..).map(PgsSegment::Ods));
+                        } else {
+                            report.objects_removed += 1;
+                            fragments.clear();
+                        }
+                    }
+                },
+                segment => {
+                    kept.extend(fragments.drain(..).map(PgsSegment::Ods));
+                    kept.push(segment.clone());
+                }
+            }
+        }
+        kept.extend(fragments.drain(..).map(PgsSegment::Ods));
+
+        // The palette of an empty composition does not matter
+        let unchanged = matches!(&previous, Some((previous_pcs, previous_generation))
+            if *previous_generation == state.generation && state.generation == generation
+                && (previous_pcs.palette_id == pcs.palette_id || pcs.composition_objects.is_empty())
+                && previous_pcs.composition_objects == pcs.composition_objects);
+        let acquisition_point = pcs.composition_state == PgsPcsCompositionState::AcquisitionPoint;
+        if unchanged && (normal || (acquisition_point && options.strip_acquisition_points)) {
+            report.display_sets_removed += 1;
+            report.acquisition_points_removed += acquisition_point as usize;
+            continue;
+        }
+        previous = Some((pcs.clone(), state.generation));
+        segments.extend(kept);
+    }
+
+    *stream.get_segments_mut() = segments;
+    if report.display_sets_removed > 0 {
+        stream.renumber_compositions();
+    }
+    report.size_after = encoded_size(stream.get_segments())?;
+    Ok(report)
+}
+
+/// Moves subtitles on screen, for example up and away from a broadcaster logo or out of black bars.
+///
+/// Only windows whose top edge is at or below the line `below` are moved, so `reposition(stream, 0, 0, -60)`
+/// moves every subtitle while `reposition(stream, 540, 0, -60)` moves only the ones in the lower half of a 1080p
+/// video. Windows are clamped to the video size taken from the PCS, and the composition objects of a window move
+/// by the same amount as the window, so they stay inside it.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `below`: The topmost line of the windows to move.
+/// - `dx`, `dy`: The offset in pixels, negative to move left or up.
+///
+/// # Returns
+/// The number of window definitions that were moved.
+pub fn reposition(stream: &mut PgsStream, below: u16, dx: i32, dy: i32) -> usize {
+    let mut moved = 0;
+    // Actual offset of every window of the current epoch, after clamping
+    let mut offsets: HashMap<u8, (i32, i32)> = HashMap::new();
+    let segments = stream.get_segments_mut();
+    for display_set in segments.split_inclusive_mut(|segment| matches!(segment, PgsSegment::End(_))) {
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            continue;
+        };
+        let (width, height) = (pcs.width as i32, pcs.height as i32);
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            offsets.clear();
+        }
+
+        for segment in display_set.iter_mut() {
+            let PgsSegment::Wds(wds) = segment else {
+                continue;
+            };
+            for window in &mut PgsRc::make_mut(wds).windows {
+                if window.window_vertical_position < below {
+                    offsets.remove(&window.window_id);
+                    continue;
+                }
+                let (x, y) = (window.window_horizontal_position as i32, window.window_vertical_position as i32);
+                let new_x = x.saturating_add(dx).min(width - window.window_width as i32).max(0);
+                let new_y = y.saturating_add(dy).min(height - window.window_height as i32).max(0);
+                window.window_horizontal_position = new_x as u16;
+                window.window_vertical_position = new_y as u16;
+                moved += (new_x != x || new_y != y) as usize;
+                offsets.insert(window.window_id, (new_x - x, new_y - y));
+            }
+        }
+
+        let PgsSegment::Pcs(pcs) = &mut display_set[0] else {
+            continue;
+        };
+        if pcs.composition_objects.iter().any(|obj| offsets.contains_key(&obj.window_id)) {
+            for obj in &mut PgsRc::make_mut(pcs).composition_objects {
+                if let Some(&(dx, dy)) = offsets.get(&obj.window_id) {
+                    obj.object_horizontal_position = (obj.object_horizontal_position as i32 + dx).clamp(0, u16::MAX as i32) as u16;
+                    obj.object_vertical_position = (obj.object_vertical_position as i32 + dy).clamp(0, u16::MAX as i32) as u16;
+                }
+            }
+        }
+    }
+    moved
+}
+
+/// Changes the declared video size of a stream without scaling its bitmaps, for example to remove letterboxing
+/// (1920x1080 to 1920x800) or to add pillarbox borders.
+///
+/// Windows and their composition objects are moved by `offset`, or recentred on the new canvas if no offset is
+/// given, and clamped to the new video size like with `reposition`. Windows larger than the new canvas are placed
+/// at its top left corner and still need to be cropped or rescaled to be valid.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `to`: The new video size (width, height).
+/// - `offset`: The horizontal and vertical offset of the old canvas on the new one, in pixels.
+///
+/// # Errors
+/// Returns `Error::InvalidArgument` if the new size has a zero dimension.
+///
+/// # Returns
+/// The number of window definitions that were moved.
+pub fn resize_canvas(stream: &mut PgsStream, to: (u16, u16), offset: Option<(i32, i32)>) -> Result<usize> {
+    if to.0 == 0 || to.1 == 0 {
+        return Err(Error::InvalidArgument(format!("invalid canvas size {}x{}", to.0, to.1)));
+    }
+    let Some(from) = stream.get_segments().iter().find_map(|segment| match segment {
+        PgsSegment::Pcs(pcs) => Some((pcs.width, pcs.height)),
+        _ => None
+    }) else {
+        return Ok(0);
+    };
+    let (dx, dy) = offset.unwrap_or(((to.0 as i32 - from.0 as i32) / 2, (to.1 as i32 - from.1 as i32) / 2));
+
+    for segment in stream.get_segments_mut() {
+        if let PgsSegment::Pcs(pcs) = segment {
+            let pcs = PgsRc::make_mut(pcs);
+            pcs.width = to.0;
+            pcs.height = to.1;
+        }
+    }
+    Ok(reposition(stream, 0, dx, dy))
+}
+
+/// Predicate for `filter` keeping only forced objects, e.g. to build a "foreign parts only" track.
+pub fn forced_only() -> fn(&PgsPcsSegmentCompositionObjects) -> bool {
+    |obj| obj.object_forced_flag
+}
+
+/// Predicate for `filter` keeping only objects that are not forced.
+pub fn non_forced_only() -> fn(&PgsPcsSegmentCompositionObjects) -> bool {
+    |obj| !obj.object_forced_flag
+}
+
+/// Keeps only the composition objects accepted by `predicate`, for example `forced_only()`.
+///
+/// Rejected objects are removed from every composition, together with the ODS segments of objects no kept
+/// composition object of the epoch refers to. A composition left without objects clears the screen; `Normal`
+/// display sets that would clear an already empty screen without defining any kept object are removed, and the
+/// compositions are renumbered if any were. `EpochStart` and `AcquisitionPoint` display sets are always kept.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+/// - `predicate`: Returns `true` for the composition objects to keep.
+///
+/// # Returns
+/// The number of removed display sets.
+pub fn filter<F: FnMut(&PgsPcsSegmentCompositionObjects) -> bool>(stream: &mut PgsStream, mut predicate: F) -> usize {
+    let display_sets: Vec<Vec<PgsSegment>> = stream.get_display_set_segments().map(|display_set| display_set.to_vec()).collect();
+    let epoch_start = |display_set: &[PgsSegment]| matches!(display_set.first(),
+        Some(PgsSegment::Pcs(pcs)) if pcs.composition_state == PgsPcsCompositionState::EpochStart);
+
+    let mut segments: Vec<PgsSegment> = Vec::new();
+    let mut removed = 0;
+    let mut screen_empty = true;
+    let mut index = 0;
+    while index < display_sets.len() {
+        let epoch_end = display_sets[index + 1..].iter().position(|display_set| epoch_start(display_set))
+            .map_or(display_sets.len(), |offset| index + 1 + offset);
+        let mut epoch: Vec<Vec<PgsSegment>> = display_sets[index..epoch_end].to_vec();
+        // Objects referred to by the kept composition objects of the epoch
+        let mut kept_objects: Vec<u16> = Vec::new();
+        for display_set in &mut epoch {
+            if let Some(PgsSegment::Pcs(pcs)) = display_set.first_mut() {
+                let pcs = PgsRc::make_mut(pcs);
+                pcs.composition_objects.retain(&mut predicate);
+                pcs.number_of_composition_objects = pcs.composition_objects.len() as u8;
+                kept_objects.extend(pcs.composition_objects.iter().map(|obj| obj.object_id));
+            }
+        }
+
+        for mut display_set in epoch {
+            display_set.retain(|segment| !matches!(segment, PgsSegment::Ods(ods) if !kept_objects.contains(&ods.object_id)));
+            let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+                segments.extend(display_set);
+                continue;
+            };
+            let empty = pcs.composition_objects.is_empty();
+            let defines_objects = display_set.iter().any(|segment| matches!(segment, PgsSegment::Ods(_)));
+            if empty && screen_empty && !defines_objects && pcs.composition_state == PgsPcsCompositionState::Normal {
+                removed += 1;
+                continue;
+            }
+            screen_empty = empty;
+            segments.extend(display_set);
+        }
+        index = epoch_end;
+    }
+
+    *stream.get_segments_mut() = segments;
+    if removed > 0 {
+        stream.renumber_compositions();
+    }
+    removed
+}
+
+/// Summary of the changes made by `normalize_compositions`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PgsNormalizeReport {
+    /// Compositions whose number changed.
+    pub compositions_renumbered: usize,
+    /// Display sets starting the stream that were turned into an `EpochStart`.
+    pub epoch_starts_added: usize,
+    /// `AcquisitionPoint` display sets turned into `Normal` ones because they do not redefine what they show.
+    pub acquisition_points_downgraded: usize,
+    /// Palette update flags cleared on display sets that are not `Normal`.
+    pub palette_update_flags_cleared: usize
+}
+
+/// Renumbers the compositions consecutively from 0 (wrapping at 65536) and repairs composition states that
+/// break the rules of the specification, for example after merging or cutting streams.
+///
+/// The first display set becomes an `EpochStart`, since decoding has to start with one. An `AcquisitionPoint`
+/// that does not define every window, object and the palette it shows cannot start decoding, so it becomes a
+/// `Normal` display set relying on the epoch before it. The palette update flag is only allowed on `Normal`
+/// display sets and is cleared elsewhere.
+///
+/// # Parameters
+/// - `stream`: The stream to modify.
+///
+/// # Returns
+/// The changes made.
+pub fn normalize_compositions(stream: &mut PgsStream) -> PgsNormalizeReport {
+    let mut report = PgsNormalizeReport::default();
+    let mut composition_number: u16 = 0;
+    for (index, display_set) in stream.get_segments_mut().split_inclusive_mut(|segment| matches!(segment, PgsSegment::End(_))).enumerate() {
+        let (first, rest) = display_set.split_at_mut(1);
+        let PgsSegment::Pcs(pcs) = &mut first[0] else {
+            continue;
+        };
+        let defines_all = pcs.composition_objects.is_empty() || (
+            rest.iter().any(|segment| matches!(segment, PgsSegment::Pds(pds) if pds.palette_id == pcs.palette_id))
+            && pcs.composition_objects.iter().all(|obj| {
+                rest.iter().any(|segment| matches!(segment, PgsSegment::Wds(wds) if wds.windows.iter().any(|window| window.window_id == obj.window_id)))
+                    && rest.iter().any(|segment| matches!(segment, PgsSegment::Ods(ods) if ods.object_id == obj.object_id))
+            }));
+
+        if index == 0 && pcs.composition_state != PgsPcsCompositionState::EpochStart {
+            PgsRc::make_mut(pcs).composition_state = PgsPcsCompositionState::EpochStart;
+            report.epoch_starts_added += 1;
+        } else if pcs.composition_state == PgsPcsCompositionState::AcquisitionPoint && !defines_all {
+            PgsRc::make_mut(pcs).composition_state = PgsPcsCompositionState::Normal;
+            report.acquisition_points_downgraded += 1;
+        }
+        if pcs.composition_state != PgsPcsCompositionState::Normal && pcs.palette_update_flag != 0 {
+            PgsRc::make_mut(pcs).palette_update_flag = 0;
+            report.palette_update_flags_cleared += 1;
+        }
+        if pcs.composition_number != composition_number {
+            PgsRc::make_mut(pcs).composition_number = composition_number;
+            report.compositions_renumbered += 1;
+        }
+        composition_number = composition_number.wrapping_add(1);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_composite::screens, pgs_test_support::{black_and_white, clear_display_set, composition_object, display_set_of, header, ods, palette_entry, pcs, pds, segments, wds, window,
+        window_display_set}};
+
+    use super::*;
+
+    #[test]
+    fn test_reposition() {
+        let mut stream = PgsStream::new([
+            segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (100, 900, 800, 100))),
+            // Top subtitle, left in place
+            segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (100, 50, 800, 100))),
+            // Clamped to the right edge of the video
+            segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (1100, 960, 800, 100)))
+        ].concat());
+        assert_eq!(reposition(&mut stream, 540, 40, -60), 2);
+
+        let positions: Vec<((u16, u16), (u16, u16))> = stream.get_display_sets().iter().map(|ds| {
+            let (window, obj) = (&ds.wds.as_ref().unwrap().windows[0], &ds.pcs.as_ref().unwrap().composition_objects[0]);
+            ((window.window_horizontal_position, window.window_vertical_position), (obj.object_horizontal_position, obj.object_vertical_position))
+        }).collect();
+        assert_eq!(positions, vec![((140, 840), (140, 840)), ((100, 50), (100, 50)), ((1120, 900), (1120, 900))]);
+
+        // Offsets beyond the screen are clamped to its edges
+        assert_eq!(reposition(&mut stream, 0, i32::MAX, i32::MIN), 3);
+        let display_sets = stream.get_display_sets();
+        let window = &display_sets[0].wds.as_ref().unwrap().windows[0];
+        assert_eq!((window.window_horizontal_position, window.window_vertical_position), (1120, 0));
+    }
+
+    #[test]
+    fn test_scale_bitmap_bilinear() {
+        let entry = |palette_entry_id, luminance| (palette_entry_id, PgsPdsSegmentPaletteEntry { palette_entry_id, luminance, color_difference_red: 128, color_difference_blue: 128, transparency: 255 });
+        let palette: PgsPalette = [entry(1, 235), entry(2, 16), entry(3, 150)].into_iter().collect();
+
+        assert_eq!(scale_bitmap(&[vec![1, 2]], 4, 1), vec![vec![1, 1, 2, 2]]);
+        assert_eq!(scale_bitmap_bilinear(&[vec![1, 2]], 4, 1, &palette), vec![vec![1, 3, 2, 2]]);
+        assert_eq!(scale_bitmap_bilinear(&[vec![1, 1], vec![1, 1]], 3, 3, &palette), vec![vec![1; 3]; 3]);
+    }
+
+    #[test]
+    fn test_rescale_empty_object() {
+        let scale = PgsAxisScale { from: 1, to: 2 };
+        for (width, height) in [(0, 0), (0, 5), (5, 0)] {
+            let fragments = vec![PgsRc::new(ods(0, 0, 0, width, height, &[]))];
+            let scaled = rescale_object(&fragments, scale, scale, PgsScaleFilter::Bilinear, None).unwrap();
+            assert!(matches!(&scaled[..], [PgsSegment::Ods(ods)] if (ods.width, ods.height) == (width, height)));
+        }
+    }
+
+    #[test]
+    fn test_resize_canvas() {
+        let mut stream = PgsStream::new([
+            segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (100, 950, 800, 100))),
+            segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (100, 500, 800, 100)))
+        ].concat());
+        assert_eq!(resize_canvas(&mut stream, (1920, 800), None).unwrap(), 2);
+
+        let display_sets = stream.get_display_sets();
+        assert_eq!((display_sets[0].pcs.as_ref().unwrap().width, display_sets[0].pcs.as_ref().unwrap().height), (1920, 800));
+        let positions: Vec<(u16, u16)> = display_sets.iter().map(|ds| {
+            let obj = &ds.pcs.as_ref().unwrap().composition_objects[0];
+            (obj.object_horizontal_position, obj.object_vertical_position)
+        }).collect();
+        assert_eq!(positions, vec![(100, 700), (100, 360)]);
+    }
+
+    #[test]
+    fn test_filter() {
+        let forced = |state, window, forced: bool| {
+            let mut display_set = segments(&window_display_set(0, state, (1920, 1080), window));
+            if let PgsSegment::Pcs(pcs) = &mut display_set[0] {
+                PgsRc::make_mut(pcs).composition_objects[0].object_forced_flag = forced;
+            }
+            display_set
+        };
+        let mut stream = PgsStream::new([
+            forced(PgsPcsCompositionState::EpochStart, (100, 900, 800, 100), false),
+            forced(PgsPcsCompositionState::Normal, (100, 900, 800, 100), false),
+            forced(PgsPcsCompositionState::Normal, (100, 900, 800, 100), true),
+            forced(PgsPcsCompositionState::Normal, (100, 900, 800, 100), false)
+        ].concat());
+        assert_eq!(filter(&mut stream, forced_only()), 1);
+
+        let objects: Vec<(u16, usize)> = stream.get_display_sets().iter()
+            .map(|ds| ds.pcs.as_ref().map(|pcs| (pcs.composition_number, pcs.composition_objects.len())).unwrap()).collect();
+        assert_eq!(objects, vec![(0, 0), (1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn test_optimize_with_options() {
+        let clear = |palette_id| {
+            let mut display_set = segments(&window_display_set(0, PgsPcsCompositionState::Normal, (1920, 1080), (100, 900, 800, 100)));
+            if let PgsSegment::Pcs(pcs) = &mut display_set[0] {
+                let pcs = PgsRc::make_mut(pcs);
+                pcs.palette_id = palette_id;
+                pcs.composition_objects.clear();
+            }
+            display_set
+        };
+        let stream = PgsStream::new([
+            segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (100, 900, 800, 100))),
+            segments(&window_display_set(0, PgsPcsCompositionState::AcquisitionPoint, (1920, 1080), (100, 900, 800, 100))),
+            clear(0),
+            clear(1)
+        ].concat());
+
+        let mut optimized = stream.clone();
+        let report = optimize(&mut optimized).unwrap();
+        assert_eq!((report.display_sets_removed, report.acquisition_points_removed), (1, 0));
+
+        let mut optimized = stream.clone();
+        let report = optimize_with_options(&mut optimized, PgsOptimizeOptions { strip_acquisition_points: true }).unwrap();
+        assert_eq!((report.display_sets_removed, report.acquisition_points_removed), (2, 1));
+        let states: Vec<PgsPcsCompositionState> = optimized.get_display_sets().iter().map(|ds| ds.pcs.as_ref().unwrap().composition_state).collect();
+        assert_eq!(states, vec![PgsPcsCompositionState::EpochStart, PgsPcsCompositionState::Normal]);
+    }
+
+    #[test]
+    fn test_optimize_keeps_screens() {
+        let subtitle = |pts, state, x| segments(&display_set_of(pcs(pts, state, vec![composition_object(0, 0, x, 900)]),
+            Some(wds(pts, vec![window(0, (100, 900, 800, 100))])), Some(pds(pts, 0, black_and_white())), Some(ods(pts, 0, 0, 4, 2, &[vec![1, 0, 0, 1], vec![0, 1, 1, 0]]))));
+        // The moved subtitle sends the palette and the object in the buffer again, and the second clear is redundant
+        let stream = PgsStream::new([
+            subtitle(90000, PgsPcsCompositionState::EpochStart, 100),
+            subtitle(180000, PgsPcsCompositionState::Normal, 200),
+            segments(&clear_display_set(270000)),
+            segments(&clear_display_set(360000))
+        ].concat());
+
+        let mut optimized = stream.clone();
+        let report = optimize(&mut optimized).unwrap();
+        assert_eq!((report.palettes_removed, report.objects_removed, report.display_sets_removed), (1, 1, 1));
+        assert!(report.size_after < report.size_before);
+        assert_eq!(report.size_after, encoded_size(optimized.get_segments()).unwrap());
+
+        // Every remaining display set shows the same pixels as before, and the removed one showed what was already
+        // on screen
+        let screens = |stream: &PgsStream| screens(stream).unwrap().into_iter().map(|screen| (screen.presentation_timestamp, screen.objects)).collect::<Vec<_>>();
+        let (before, after) = (screens(&stream), screens(&optimized));
+        assert!(before[..3] == after[..]);
+        assert!(before[3].1 == before[2].1);
+    }
+
+    #[test]
+    fn test_recompress() {
+        // Eight transparent pixels written as single pixel codes, then the same row encoded optimally
+        let wasteful = [[0x00, 0x01].repeat(8), vec![0x00, 0x00]].concat();
+        let mut stream = PgsStream::new(vec![
+            PgsSegment::Ods(PgsRc::new(PgsOdsSegment::from_object(header(PgsSegmentType::ODS, 0), 0, 0, 8, 1, &wasteful).remove(0))),
+            PgsSegment::Ods(PgsRc::new(ods(0, 1, 0, 8, 1, &[vec![0; 8]])))
+        ]);
+
+        let report = recompress(&mut stream).unwrap();
+        assert_eq!(report, PgsRecompressReport { objects: 2, objects_recompressed: 1, size_before: 22, size_after: 8 });
+        let PgsSegment::Ods(ods) = &stream.get_segments()[0] else {
+            unreachable!()
+        };
+        assert_eq!(ods.object_data, vec![0x00, 0x08, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_deduplicate_objects() {
+        let object = |object_id, color| PgsSegment::Ods(PgsRc::new(ods(0, object_id, 0, 4, 1, &[vec![color; 4]])));
+        // A display set showing the given objects and defining the given objects
+        let display_set = |state, shown: &[u16], defined: Vec<PgsSegment>| {
+            let mut display_set = segments(&window_display_set(0, state, (1920, 1080), (100, 900, 800, 100)));
+            if let PgsSegment::Pcs(pcs) = &mut display_set[0] {
+                let pcs = PgsRc::make_mut(pcs);
+                pcs.composition_objects = shown.iter().map(|&object_id| PgsPcsSegmentCompositionObjects { object_id, ..pcs.composition_objects[0].clone() }).collect();
+                pcs.number_of_composition_objects = shown.len() as u8;
+            }
+            display_set.splice(2..2, defined);
+            display_set
+        };
+        let mut stream = PgsStream::new([
+            // Object 3 is identical to object 0 until it is redefined, but an epoch start keeps its objects
+            display_set(PgsPcsCompositionState::EpochStart, &[0], vec![object(0, 1), object(3, 1)]),
+            // Object 1 is replaced by object 0, so object 2 is kept as both are shown at once
+            display_set(PgsPcsCompositionState::Normal, &[1, 2], vec![object(1, 1), object(2, 1)]),
+            display_set(PgsPcsCompositionState::Normal, &[3], vec![object(3, 2)]),
+            display_set(PgsPcsCompositionState::Normal, &[1], Vec::new())
+        ].concat());
+        assert_eq!(deduplicate_objects(&mut stream).unwrap(), 1);
+
+        let display_sets = stream.get_display_set_segments().map(|ds| {
+            let objects: Vec<u16> = ds.iter().filter_map(|segment| match segment {
+                PgsSegment::Ods(ods) => Some(ods.object_id),
+                _ => None
+            }).collect();
+            let PgsSegment::Pcs(pcs) = &ds[0] else {
+                unreachable!()
+            };
+            (pcs.composition_objects.iter().map(|obj| obj.object_id).collect::<Vec<u16>>(), objects)
+        }).collect::<Vec<_>>();
+        assert_eq!(display_sets, vec![(vec![0], vec![0, 3]), (vec![0, 2], vec![2]), (vec![3], vec![3]), (vec![0], vec![])]);
+
+        // An acquisition point defines its objects even if they are identical to objects in the buffer
+        let mut stream = PgsStream::new([
+            display_set(PgsPcsCompositionState::EpochStart, &[0], vec![object(0, 1)]),
+            display_set(PgsPcsCompositionState::Normal, &[], Vec::new()),
+            display_set(PgsPcsCompositionState::AcquisitionPoint, &[1], vec![object(1, 1)])
+        ].concat());
+        assert_eq!(deduplicate_objects(&mut stream).unwrap(), 0);
+        assert!(matches!(&stream.get_segments()[7..], [PgsSegment::Pcs(pcs), PgsSegment::Wds(_), PgsSegment::Ods(ods), PgsSegment::End(_)]
+            if pcs.composition_objects[0].object_id == 1 && ods.object_id == 1));
+    }
+
+    #[test]
+    fn test_trim() {
+        let palette = |palette_version_number, palette_entry_id| PgsSegment::Pds(PgsRc::new(pds(0, palette_version_number, vec![palette_entry(palette_entry_id, 235, 255)])));
+        let object = PgsSegment::Ods(PgsRc::new(ods(0, 0, 0, 2, 1, &[vec![1, 2]])));
+        let mut epoch_start = segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (100, 900, 2, 1)));
+        epoch_start.splice(2..2, [palette(0, 1), object]);
+        let mut palette_update = segments(&window_display_set(0, PgsPcsCompositionState::Normal, (1920, 1080), (100, 900, 2, 1)));
+        palette_update.splice(1..2, [palette(1, 2)]);
+        let mut stream = PgsStream::new([epoch_start, palette_update, segments(&window_display_set(0, PgsPcsCompositionState::Normal, (1920, 1080), (100, 900, 2, 1)))].concat());
+        for (index, display_set) in stream.get_segments_mut().split_inclusive_mut(|segment| matches!(segment, PgsSegment::End(_))).enumerate() {
+            for segment in display_set {
+                segment.header_mut().presentation_timestamp = index as u32 * 90000;
+            }
+        }
+
+        trim(&mut stream, 100000, 200000, true).unwrap();
+        let display_sets = stream.get_display_sets();
+        assert_eq!(display_sets.len(), 2);
+        let pcs = display_sets[0].pcs.as_ref().unwrap();
+        assert_eq!((pcs.composition_state, pcs.header.presentation_timestamp), (PgsPcsCompositionState::EpochStart, 0));
+        assert_eq!(display_sets[0].wds.as_ref().unwrap().windows[0].window_vertical_position, 900);
+        let pds = display_sets[0].pds.as_ref().unwrap();
+        assert_eq!((pds.header.segment_type, pds.palette_version_number, pds.palette_entries.len()), (PgsSegmentType::PDS, 1, 2));
+        assert_eq!(decode_rle_indices(display_sets[0].ods.as_ref().unwrap()).unwrap(), vec![vec![1, 2]]);
+        assert_eq!(display_sets[1].pcs.as_ref().unwrap().header.presentation_timestamp, 80000);
+    }
+
+    #[test]
+    fn test_trim_cut_points() {
+        // Two epochs showing a subtitle each, cleared 2 s later, decoded 0.1 s before they are presented
+        let subtitle = |pts| segments(&display_set_of(pcs(pts, PgsPcsCompositionState::EpochStart, vec![composition_object(0, 0, 100, 900)]),
+            Some(wds(pts, vec![window(0, (100, 900, 2, 1))])), Some(pds(pts, 0, black_and_white())), Some(ods(pts, 0, 0, 2, 1, &[vec![1, 1]]))));
+        let mut stream = PgsStream::new([subtitle(90000), segments(&clear_display_set(270000)), subtitle(450000), segments(&clear_display_set(630000))].concat());
+        for segment in stream.get_segments_mut() {
+            let header = segment.header_mut();
+            header.decoding_timestamp = header.presentation_timestamp - 9000;
+        }
+        let cut = |start, end, rebase| {
+            let mut stream = stream.clone();
+            trim(&mut stream, start, end, rebase).unwrap();
+            stream.get_display_sets().iter().map(|ds| {
+                let pcs = ds.pcs.as_ref().unwrap();
+                (pcs.header.presentation_timestamp, pcs.header.decoding_timestamp, pcs.composition_state, pcs.composition_objects.len(), ds.ods.is_some())
+            }).collect::<Vec<_>>()
+        };
+
+        // Cut between a subtitle and its clear: the clear is kept and moved to the end of the range
+        assert_eq!(cut(0, 180000, false), [
+            (90000, 81000, PgsPcsCompositionState::EpochStart, 1, true),
+            (180000, 171000, PgsPcsCompositionState::Normal, 0, false)
+        ]);
+        // Cut inside an epoch, after the subtitle is shown: it starts the cut stream, with its data, at the start of
+        // the range
+        assert_eq!(cut(180000, 500000, false), [
+            (180000, 171000, PgsPcsCompositionState::EpochStart, 1, true),
+            (270000, 261000, PgsPcsCompositionState::Normal, 0, false),
+            (450000, 441000, PgsPcsCompositionState::EpochStart, 1, true),
+            (500000, 491000, PgsPcsCompositionState::Normal, 0, false)
+        ]);
+        // Rebased timestamps keep their decoding lead, clamped to zero
+        assert_eq!(cut(180000, 500000, true), [
+            (0, 0, PgsPcsCompositionState::EpochStart, 1, true),
+            (90000, 81000, PgsPcsCompositionState::Normal, 0, false),
+            (270000, 261000, PgsPcsCompositionState::EpochStart, 1, true),
+            (320000, 311000, PgsPcsCompositionState::Normal, 0, false)
+        ]);
+        // Cut inside an epoch while the screen is clear: the clear starts the epoch and still defines the subtitle
+        assert_eq!(cut(300000, 400000, true), [(0, 0, PgsPcsCompositionState::EpochStart, 0, true)]);
+    }
+
+    #[test]
+    fn test_normalize_compositions() {
+        let mut stream = PgsStream::new([
+            segments(&window_display_set(0, PgsPcsCompositionState::AcquisitionPoint, (1920, 1080), (100, 900, 800, 100))),
+            segments(&window_display_set(0, PgsPcsCompositionState::AcquisitionPoint, (1920, 1080), (100, 900, 800, 100))),
+            segments(&window_display_set(0, PgsPcsCompositionState::EpochStart, (1920, 1080), (100, 900, 800, 100)))
+        ].concat());
+        for (index, segment) in stream.get_segments_mut().iter_mut().enumerate() {
+            if let PgsSegment::Pcs(pcs) = segment {
+                let pcs = PgsRc::make_mut(pcs);
+                pcs.composition_number = 7;
+                pcs.palette_update_flag = if index == 6 { 0x80 } else { 0 };
+            }
+        }
+
+        let report = normalize_compositions(&mut stream);
+        assert_eq!(report, PgsNormalizeReport { compositions_renumbered: 3, epoch_starts_added: 1, acquisition_points_downgraded: 1, palette_update_flags_cleared: 1 });
+        let compositions: Vec<(u16, PgsPcsCompositionState, u8)> = stream.get_display_sets().iter()
+            .map(|ds| ds.pcs.as_ref().map(|pcs| (pcs.composition_number, pcs.composition_state, pcs.palette_update_flag)).unwrap()).collect();
+        assert_eq!(compositions, vec![
+            (0, PgsPcsCompositionState::EpochStart, 0),
+            (1, PgsPcsCompositionState::Normal, 0),
+            (2, PgsPcsCompositionState::EpochStart, 0)
+        ]);
+        assert_eq!(normalize_compositions(&mut stream), PgsNormalizeReport::default());
+    }
+}
@@ -0,0 +1,51 @@
+//! # PGS Unknown Segment
+//!
+//! This module defines the `PgsUnknownSegment` struct, which keeps segments with a type byte not defined by the
+//! PGS specification (for example vendor specific extensions) so they can be skipped and written back unchanged.
+
+use crate::{Error, PgsRc, PgsSegmentHeader, Result};
+
+/// Struct representing a segment of unknown type in a PGS file.
+///
+/// The header's `segment_type` is `PgsSegmentType::ERR`; the raw type byte is kept in `segment_type`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsUnknownSegment {
+    pub header: PgsSegmentHeader,
+    pub segment_type: u8,
+    pub data: Vec<u8>
+}
+
+impl PgsUnknownSegment {
+    fn new(header: PgsSegmentHeader, segment_type: u8, data: Vec<u8>) -> Self {
+        PgsUnknownSegment {
+            header,
+            segment_type,
+            data
+        }
+    }
+
+    /// Constructs a `PgsUnknownSegment` from the given header, raw type byte and payload.
+    ///
+    /// # Parameters
+    /// - `header`: The segment header.
+    /// - `segment_type`: The type byte read from the segment header.
+    /// - `data`: A slice of raw data representing the contents of the segment.
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidSegmentDataLength` if the length of the provided data is less than the expected length.
+    ///
+    /// # Returns
+    /// A `PgsRc<PgsUnknownSegment>` holding a copy of the payload.
+    pub fn from_data(header: PgsSegmentHeader, segment_type: u8, data: &[u8]) -> Result<PgsRc<PgsUnknownSegment>> {
+        if data.len() < header.segment_length as usize {
+            return Err(Error::InvalidSegmentDataLength { length: data.len(), expected: header.segment_length as usize });
+        }
+
+        Ok(PgsRc::new(PgsUnknownSegment::new(header, segment_type, data[..header.segment_length as usize].to_vec())))
+    }
+
+    /// Serializes the segment payload (without the header), which is the payload as read.
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        Ok(self.data.clone())
+    }
+}
@@ -0,0 +1,502 @@
+//! # PGS Validation
+//!
+//! This module checks a parsed PGS stream against the constraints of the Blu-ray PGS specification and
+//! collects every violation into a `PgsValidationReport`. Validation never fails: problems are reported as
+//! issues with a severity, a category and the display set they were found in.
+
+use std::{collections::{HashMap, HashSet}, fmt::Display};
+
+use crate::{is_timestamp_wrap, Error, PgsOdsSequenceFlag, PgsParser, PgsPcsCompositionState, PgsPcsObjectCroppedFlag, PgsSegment, PgsWdsSegmentWindowDefinition, Result};
+
+/// Maximum width and height of a single object, in pixels.
+pub const PGS_MAX_OBJECT_SIZE: u16 = 4096;
+
+/// Size of the decoded object buffer of Blu-ray players, in bytes. Decoded objects take one byte per pixel and
+/// stay in the buffer until the epoch ends or they are redefined.
+pub const PGS_DECODED_OBJECT_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Severity of a validation issue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PgsValidationSeverity {
+    /// The stream is unusual but will normally still play.
+    Warning,
+    /// The stream violates the specification and may fail on hardware players.
+    Error
+}
+
+/// Category of the check that produced a validation issue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgsValidationCategory {
+    /// Segment or timestamp ordering.
+    Ordering,
+    /// References between segments (windows, objects, palettes).
+    Reference,
+    /// Decoder buffer and object size limits.
+    BufferLimit,
+    /// Positions and sizes of windows and objects.
+    Geometry,
+    /// Epoch structure, such as Acquisition Points that do not redefine everything they show.
+    Epoch
+}
+
+/// A single problem found during validation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgsValidationIssue {
+    pub severity: PgsValidationSeverity,
+    pub category: PgsValidationCategory,
+    /// Index of the display set the issue was found in.
+    pub display_set: usize,
+    /// Composition number of the display set (0 if it has no PCS).
+    pub composition_number: u16,
+    /// Presentation timestamp of the display set (90 kHz).
+    pub presentation_timestamp: u32,
+    pub message: String
+}
+
+impl Display for PgsValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} [{:?}] display set {} (composition {}, pts {}): {}", self.severity, self.category, self.display_set,
+            self.composition_number, self.presentation_timestamp, self.message)
+    }
+}
+
+/// The result of validating a stream.
+#[derive(Debug, Default, Clone)]
+pub struct PgsValidationReport {
+    pub issues: Vec<PgsValidationIssue>,
+    /// Peak decoded object buffer usage of every epoch, in bytes, in stream order.
+    pub object_buffer_peaks: Vec<usize>
+}
+
+impl PgsValidationReport {
+    /// Returns `true` if no issue with `Error` severity was found.
+    pub fn is_valid(&self) -> bool {
+        !self.issues.iter().any(|issue| issue.severity == PgsValidationSeverity::Error)
+    }
+
+    /// Returns an iterator over the issues with `Error` severity.
+    pub fn errors(&self) -> impl Iterator<Item = &PgsValidationIssue> {
+        self.issues.iter().filter(|issue| issue.severity == PgsValidationSeverity::Error)
+    }
+
+    /// Returns an iterator over the issues with `Warning` severity.
+    pub fn warnings(&self) -> impl Iterator<Item = &PgsValidationIssue> {
+        self.issues.iter().filter(|issue| issue.severity == PgsValidationSeverity::Warning)
+    }
+}
+
+/// Windows and objects referenced by a composition but not defined in the current epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgsDanglingReferences {
+    /// Index of the display set holding the composition.
+    pub display_set: usize,
+    pub composition_number: u16,
+    /// Window ids used by composition objects but not defined by any WDS of the epoch, in ascending order.
+    pub window_ids: Vec<u8>,
+    /// Object ids used by composition objects but not defined by any ODS of the epoch, in ascending order.
+    pub object_ids: Vec<u16>
+}
+
+/// State accumulated while walking the display sets of a stream.
+#[derive(Default)]
+struct PgsValidator {
+    report: PgsValidationReport,
+    display_set: usize,
+    composition_number: u16,
+    presentation_timestamp: u32,
+    previous_timestamp: Option<u32>,
+    /// Whether an Epoch Start display set has been seen.
+    epoch_started: bool,
+    /// Windows defined in the current epoch, by window id.
+    windows: HashMap<u8, PgsWdsSegmentWindowDefinition>,
+    /// Width and height of the objects defined in the current epoch, by object id.
+    objects: HashMap<u16, (u16, u16)>,
+    /// Palettes defined in the current epoch.
+    palettes: HashSet<u8>,
+    /// Whether the decoded object buffer overflow of the current epoch has been reported.
+    buffer_exceeded: bool
+}
+
+impl PgsValidator {
+    fn issue(&mut self, severity: PgsValidationSeverity, category: PgsValidationCategory, message: String) {
+        self.report.issues.push(PgsValidationIssue {
+            severity,
+            category,
+            display_set: self.display_set,
+            composition_number: self.composition_number,
+            presentation_timestamp: self.presentation_timestamp,
+            message
+        });
+    }
+
+    /// Updates the peak decoded object buffer usage of the current epoch and reports the first overflow.
+    fn check_object_buffer(&mut self) {
+        let usage: usize = self.objects.values().map(|&(width, height)| width as usize * height as usize).sum();
+        if let Some(peak) = self.report.object_buffer_peaks.last_mut() {
+            *peak = usage.max(*peak);
+        }
+        if usage > PGS_DECODED_OBJECT_BUFFER_SIZE && !self.buffer_exceeded {
+            self.buffer_exceeded = true;
+            self.issue(PgsValidationSeverity::Error, PgsValidationCategory::BufferLimit,
+                format!("decoded objects of the epoch need {usage} bytes, more than the {PGS_DECODED_OBJECT_BUFFER_SIZE} byte object buffer"));
+        }
+    }
+
+    fn validate_display_set(&mut self, segments: &[PgsSegment]) {
+        let Some(PgsSegment::Pcs(pcs)) = segments.first() else {
+            self.composition_number = 0;
+            self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Ordering,
+                "display set does not start with a PCS".to_string());
+            return;
+        };
+        self.presentation_timestamp = pcs.header.presentation_timestamp;
+        self.composition_number = pcs.composition_number;
+
+        if !matches!(segments.last(), Some(PgsSegment::End(_))) {
+            self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Ordering,
+                "display set is not terminated by an END segment".to_string());
+        }
+
+        if let Some(previous) = self.previous_timestamp {
+            if is_timestamp_wrap(previous, self.presentation_timestamp) {
+                self.issue(PgsValidationSeverity::Warning, PgsValidationCategory::Ordering,
+                    format!("presentation timestamp wraps around from {previous}"));
+            } else if self.presentation_timestamp < previous {
+                self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Ordering,
+                    format!("presentation timestamp goes backwards from {previous}"));
+            }
+        }
+        self.previous_timestamp = Some(self.presentation_timestamp);
+
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            self.windows.clear();
+            self.objects.clear();
+            self.palettes.clear();
+            self.buffer_exceeded = false;
+        } else if !self.epoch_started {
+            self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Ordering,
+                "stream does not start with an Epoch Start display set".to_string());
+        }
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart || !self.epoch_started {
+            self.report.object_buffer_peaks.push(0);
+        }
+        self.epoch_started = true;
+
+        for segment in segments {
+            let header = segment.header();
+            if header.decoding_timestamp > header.presentation_timestamp && !is_timestamp_wrap(header.decoding_timestamp, header.presentation_timestamp) {
+                self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Ordering,
+                    format!("{} has a decoding timestamp ({}) after its presentation timestamp ({})", header.segment_type,
+                        header.decoding_timestamp, header.presentation_timestamp));
+            }
+        }
+
+        // Windows, palettes and objects defined by this display set itself
+        let mut defined_windows: HashSet<u8> = HashSet::new();
+        let mut defined_palettes: HashSet<u8> = HashSet::new();
+        let mut defined_objects: HashSet<u16> = HashSet::new();
+        for segment in &segments[1..] {
+            match segment {
+                PgsSegment::Pcs(_) => {
+                    self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Ordering,
+                        "display set contains more than one PCS".to_string());
+                },
+                PgsSegment::Wds(wds) => {
+                    for window in &wds.windows {
+                        if window.window_horizontal_position as u32 + window.window_width as u32 > pcs.width as u32
+                            || window.window_vertical_position as u32 + window.window_height as u32 > pcs.height as u32 {
+                            self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Geometry,
+                                format!("window {} exceeds the {}x{} video frame", window.window_id, pcs.width, pcs.height));
+                        }
+                        self.windows.insert(window.window_id, window.clone());
+                        defined_windows.insert(window.window_id);
+                    }
+                },
+                PgsSegment::Pds(pds) => {
+                    self.palettes.insert(pds.palette_id);
+                    defined_palettes.insert(pds.palette_id);
+                },
+                // Only the first fragment of an object carries its size
+                PgsSegment::Ods(ods) if matches!(ods.last_in_sequence_flag, PgsOdsSequenceFlag::First | PgsOdsSequenceFlag::Both) => {
+                    if ods.width > PGS_MAX_OBJECT_SIZE || ods.height > PGS_MAX_OBJECT_SIZE {
+                        self.issue(PgsValidationSeverity::Error, PgsValidationCategory::BufferLimit,
+                            format!("object {} is {}x{}, larger than the {PGS_MAX_OBJECT_SIZE}x{PGS_MAX_OBJECT_SIZE} maximum",
+                                ods.object_id, ods.width, ods.height));
+                    }
+                    self.objects.insert(ods.object_id, (ods.width, ods.height));
+                    defined_objects.insert(ods.object_id);
+                    self.check_object_buffer();
+                },
+                _ => {}
+            }
+        }
+
+        // Decoding can start at an Acquisition Point, so it has to redefine everything it shows
+        if pcs.composition_state == PgsPcsCompositionState::AcquisitionPoint {
+            if !pcs.composition_objects.is_empty() && !defined_palettes.contains(&pcs.palette_id) {
+                self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Epoch,
+                    format!("acquisition point does not define its palette {}", pcs.palette_id));
+            }
+            for com_obj in &pcs.composition_objects {
+                if !defined_windows.contains(&com_obj.window_id) {
+                    self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Epoch,
+                        format!("acquisition point does not define window {}", com_obj.window_id));
+                }
+                if !defined_objects.contains(&com_obj.object_id) {
+                    self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Epoch,
+                        format!("acquisition point does not define object {}", com_obj.object_id));
+                }
+            }
+        }
+
+        if !pcs.composition_objects.is_empty() && !self.palettes.contains(&pcs.palette_id) {
+            self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Reference,
+                format!("composition references undefined palette {}", pcs.palette_id));
+        }
+
+        for com_obj in &pcs.composition_objects {
+            let window = self.windows.get(&com_obj.window_id).cloned();
+            if window.is_none() {
+                self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Reference,
+                    format!("object {} references undefined window {}", com_obj.object_id, com_obj.window_id));
+            }
+            let Some(&(width, height)) = self.objects.get(&com_obj.object_id) else {
+                self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Reference,
+                    format!("composition references undefined object {}", com_obj.object_id));
+                continue;
+            };
+            if !com_obj.cropping_fits(width, height) {
+                self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Geometry,
+                    format!("object {} cropping rectangle ({}x{} at {},{}) is empty or outside the {}x{} object", com_obj.object_id,
+                        com_obj.object_cropping_width, com_obj.object_cropping_height_position,
+                        com_obj.object_cropping_horizontal_position, com_obj.object_cropping_vertical_position, width, height));
+            }
+
+            let (width, height) = match com_obj.object_cropped_flag {
+                PgsPcsObjectCroppedFlag::ForceCroppedImage => (com_obj.object_cropping_width, com_obj.object_cropping_height_position),
+                PgsPcsObjectCroppedFlag::Off => (width, height)
+            };
+            let (x, y) = (com_obj.object_horizontal_position as u32, com_obj.object_vertical_position as u32);
+            if let Some(window) = window {
+                let (window_x, window_y) = (window.window_horizontal_position as u32, window.window_vertical_position as u32);
+                if x < window_x || y < window_y || x + width as u32 > window_x + window.window_width as u32
+                    || y + height as u32 > window_y + window.window_height as u32 {
+                    self.issue(PgsValidationSeverity::Error, PgsValidationCategory::Geometry,
+                        format!("object {} ({}x{} at {},{}) does not fit window {}", com_obj.object_id, width, height, x, y,
+                            window.window_id));
+                }
+            }
+        }
+    }
+}
+
+/// Validates a list of segments in stream order.
+///
+/// # Parameters
+/// - `segments`: The segments to validate, for example from `PgsParser::get_segments` or `PgsStream::get_segments`.
+///
+/// # Returns
+/// A `PgsValidationReport` listing every issue found.
+pub fn validate_segments(segments: &[PgsSegment]) -> PgsValidationReport {
+    let mut validator = PgsValidator::default();
+    for (index, display_set) in segments.split_inclusive(|segment| matches!(segment, PgsSegment::End(_))).enumerate() {
+        validator.display_set = index;
+        validator.validate_display_set(display_set);
+    }
+    validator.report
+}
+
+/// Validates a parsed PGS file.
+///
+/// The following checks are performed:
+/// - Ordering: the stream starts with an Epoch Start, every display set starts with a PCS, ends with an END
+///   segment, presentation timestamps never go backwards (a wrap around of the 32-bit timestamp is only a
+///   warning) and no segment is decoded after it is presented.
+/// - References: every composition object references a window and an object defined in the current epoch, and
+///   compositions showing objects use a palette defined in the current epoch.
+/// - Buffer limits: objects do not exceed the maximum object size and the objects of an epoch fit the decoded
+///   object buffer (`PGS_DECODED_OBJECT_BUFFER_SIZE`).
+/// - Geometry: windows fit inside the video frame, cropping rectangles are non-empty and inside their objects, and
+///   objects (or their cropped part) fit their windows.
+/// - Epoch: Acquisition Points are self-contained, i.e. they define the windows, palette and objects they show.
+///
+/// # Parameters
+/// - `parser`: The parser holding the parsed segments.
+///
+/// # Returns
+/// A `PgsValidationReport` listing every issue found.
+pub fn validate(parser: &PgsParser) -> PgsValidationReport {
+    validate_segments(parser.get_segments())
+}
+
+/// Checks that every composition object references a window and an object defined in the current epoch.
+///
+/// Unlike `validate_segments`, which reports all kinds of problems as text, this check fails with the dangling
+/// references of every display set, so callers can repair or reject a stream programmatically.
+///
+/// # Parameters
+/// - `segments`: The segments in stream order.
+///
+/// # Errors
+/// Returns `Error::DanglingReferences` listing every display set that references undefined windows or objects.
+pub fn check_references(segments: &[PgsSegment]) -> Result<()> {
+    let mut windows: HashSet<u8> = HashSet::new();
+    let mut objects: HashSet<u16> = HashSet::new();
+    let mut dangling: Vec<PgsDanglingReferences> = Vec::new();
+    for (index, display_set) in segments.split_inclusive(|segment| matches!(segment, PgsSegment::End(_))).enumerate() {
+        let Some(PgsSegment::Pcs(pcs)) = display_set.first() else {
+            continue;
+        };
+        if pcs.composition_state == PgsPcsCompositionState::EpochStart {
+            windows.clear();
+            objects.clear();
+        }
+        for segment in display_set {
+            match segment {
+                PgsSegment::Wds(wds) => windows.extend(wds.windows.iter().map(|window| window.window_id)),
+                PgsSegment::Ods(ods) => {
+                    objects.insert(ods.object_id);
+                },
+                _ => {}
+            }
+        }
+
+        let mut references = PgsDanglingReferences {
+            display_set: index,
+            composition_number: pcs.composition_number,
+            window_ids: Vec::new(),
+            object_ids: Vec::new()
+        };
+        for com_obj in &pcs.composition_objects {
+            if !windows.contains(&com_obj.window_id) && !references.window_ids.contains(&com_obj.window_id) {
+                references.window_ids.push(com_obj.window_id);
+            }
+            if !objects.contains(&com_obj.object_id) && !references.object_ids.contains(&com_obj.object_id) {
+                references.object_ids.push(com_obj.object_id);
+            }
+        }
+        if !references.window_ids.is_empty() || !references.object_ids.is_empty() {
+            references.window_ids.sort_unstable();
+            references.object_ids.sort_unstable();
+            dangling.push(references);
+        }
+    }
+
+    if dangling.is_empty() { Ok(()) } else { Err(Error::DanglingReferences(dangling)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_test_support::{composition_object, display_set_of, ods, pcs, pds, segments, wds, window}, PgsPcsSegment, PgsRc};
+
+    use super::*;
+
+    fn display_set(pts: u32, window_id: u8, window_width: u16) -> Vec<PgsSegment> {
+        segments(&display_set_of(
+            PgsPcsSegment { width: 1920, height: 1080, ..pcs(pts, PgsPcsCompositionState::EpochStart, vec![composition_object(0, window_id, 100, 900)]) },
+            Some(wds(pts, vec![window(0, (100, 900, window_width, 100))])),
+            Some(pds(pts, 0, Vec::new())),
+            Some(ods(pts, 0, 0, 200, 50, &[]))
+        ))
+    }
+
+    #[test]
+    fn test_valid_stream() {
+        let segments = [display_set(90000, 0, 500), display_set(180000, 0, 500)].concat();
+        let report = validate_segments(&segments);
+        assert!(report.is_valid());
+        assert!(report.issues.is_empty());
+        assert_eq!(report.object_buffer_peaks, vec![10000, 10000]);
+    }
+
+    #[test]
+    fn test_object_buffer() {
+        let mut segments = display_set(90000, 0, 500);
+        for object_id in 1..=2 {
+            let ods = ods(90000, object_id, 0, 4000, 1000, &[]);
+            segments.insert(4, PgsSegment::Ods(PgsRc::new(ods)));
+        }
+
+        let report = validate_segments(&segments);
+        assert_eq!(report.object_buffer_peaks, vec![8_010_000]);
+        let messages: Vec<String> = report.errors().map(|issue| issue.message.clone()).collect();
+        assert_eq!(messages, vec!["decoded objects of the epoch need 8010000 bytes, more than the 4194304 byte object buffer"]);
+    }
+
+    #[test]
+    fn test_invalid_stream() {
+        let segments = [display_set(180000, 0, 500), display_set(90000, 1, 1900)].concat();
+        let report = validate_segments(&segments);
+        assert!(!report.is_valid());
+
+        let categories: Vec<PgsValidationCategory> = report.errors().map(|issue| issue.category).collect();
+        assert_eq!(categories, vec![PgsValidationCategory::Ordering, PgsValidationCategory::Geometry, PgsValidationCategory::Reference]);
+        assert!(report.issues.iter().all(|issue| issue.display_set == 1));
+    }
+
+    #[test]
+    fn test_acquisition_point() {
+        let mut segments = [display_set(90000, 0, 500), display_set(180000, 0, 500)].concat();
+        let PgsSegment::Pcs(pcs) = &mut segments[5] else { panic!("Expected PCS segment") };
+        let pcs = PgsRc::make_mut(pcs);
+        pcs.composition_state = PgsPcsCompositionState::AcquisitionPoint;
+        pcs.composition_number = 3;
+        assert!(validate_segments(&segments).issues.is_empty());
+
+        segments.remove(8);
+        segments.remove(6);
+        let report = validate_segments(&segments);
+        let messages: Vec<String> = report.errors().map(|issue| issue.message.clone()).collect();
+        assert_eq!(messages, vec!["acquisition point does not define window 0", "acquisition point does not define object 0"]);
+        assert!(report.issues.iter().all(|issue| issue.category == PgsValidationCategory::Epoch && issue.composition_number == 3));
+    }
+
+    #[test]
+    fn test_decoding_after_presentation() {
+        let mut segments = display_set(90000, 0, 500);
+        segments[2].header_mut().decoding_timestamp = 90001;
+
+        let messages: Vec<String> = validate_segments(&segments).errors().map(|issue| issue.message.clone()).collect();
+        assert_eq!(messages, vec!["Palette Definition Segment has a decoding timestamp (90001) after its presentation timestamp (90000)"]);
+    }
+
+    #[test]
+    fn test_invalid_references() {
+        let mut segments = display_set(90000, 0, 150);
+        segments.remove(2);
+        let PgsSegment::Pcs(pcs) = &mut segments[0] else { panic!("Expected PCS segment") };
+        PgsRc::make_mut(pcs).composition_state = PgsPcsCompositionState::Normal;
+
+        let messages: Vec<String> = validate_segments(&segments).errors().map(|issue| issue.message.clone()).collect();
+        assert_eq!(messages, vec![
+            "stream does not start with an Epoch Start display set",
+            "composition references undefined palette 0",
+            "object 0 (200x50 at 100,900) does not fit window 0"
+        ]);
+    }
+
+    #[test]
+    fn test_check_references() {
+        assert!(check_references(&[display_set(90000, 0, 500), display_set(180000, 0, 500)].concat()).is_ok());
+
+        let mut segments = [display_set(90000, 0, 500), display_set(180000, 2, 500)].concat();
+        segments.remove(3);
+        let Err(Error::DanglingReferences(dangling)) = check_references(&segments) else { panic!("Expected dangling references") };
+        assert_eq!(dangling, vec![
+            PgsDanglingReferences { display_set: 0, composition_number: 0, window_ids: Vec::new(), object_ids: vec![0] },
+            PgsDanglingReferences { display_set: 1, composition_number: 0, window_ids: vec![2], object_ids: Vec::new() }
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_cropping() {
+        let mut segments = display_set(90000, 0, 500);
+        let PgsSegment::Pcs(pcs) = &mut segments[0] else { panic!("Expected PCS segment") };
+        let com_obj = &mut PgsRc::make_mut(pcs).composition_objects[0];
+        com_obj.object_cropped_flag = PgsPcsObjectCroppedFlag::ForceCroppedImage;
+        (com_obj.object_cropping_horizontal_position, com_obj.object_cropping_width, com_obj.object_cropping_height_position) = (150, 100, 50);
+
+        let messages: Vec<String> = validate_segments(&segments).errors().map(|issue| issue.message.clone()).collect();
+        assert_eq!(messages, vec!["object 0 cropping rectangle (100x50 at 150,0) is empty or outside the 200x50 object"]);
+    }
+}
@@ -0,0 +1,62 @@
+//! # PGS Parse Visitor
+//!
+//! This module defines the `PgsVisitor` callbacks of `PgsParser::parse_with_visitor`, for code that inspects a
+//! stream while it is parsed, such as telemetry, filters or searches that stop at the first match, without keeping
+//! the whole parse result in memory.
+
+use crate::{PgsDisplaySet, PgsParseIssue, PgsSegment};
+
+/// Whether a parse driven by a `PgsVisitor` goes on after a callback.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PgsVisitFlow {
+    /// Keep parsing.
+    #[default]
+    Continue,
+    /// Stop the parse; no further callbacks are made.
+    Stop
+}
+
+/// Callbacks made by `PgsParser::parse_with_visitor` in stream order.
+///
+/// Every method has a default implementation that ignores its argument and continues, so a visitor only
+/// implements the callbacks it needs.
+///
+/// # Example
+/// ```rust
+/// use pgs_parse::{PgsDisplaySet, PgsParser, PgsVisitFlow, PgsVisitor};
+///
+/// /// Finds the presentation timestamp of the first display set.
+/// struct FirstDisplaySet(Option<u32>);
+///
+/// impl PgsVisitor for FirstDisplaySet {
+///     fn on_display_set(&mut self, display_set: &PgsDisplaySet) -> PgsVisitFlow {
+///         self.0 = display_set.pcs.as_ref().map(|pcs| pcs.header.presentation_timestamp);
+///         PgsVisitFlow::Stop
+///     }
+/// }
+///
+/// let mut visitor = FirstDisplaySet(None);
+/// let result = PgsParser::parse_with_visitor("subtitle.sup", &mut visitor);
+/// ```
+pub trait PgsVisitor {
+    /// Called for every segment with the file offset of its header.
+    fn on_segment(&mut self, _offset: usize, _segment: &PgsSegment) -> PgsVisitFlow {
+        PgsVisitFlow::Continue
+    }
+
+    /// Called for every display set ended by an END segment, after the callbacks of its segments.
+    fn on_display_set(&mut self, _display_set: &PgsDisplaySet) -> PgsVisitFlow {
+        PgsVisitFlow::Continue
+    }
+
+    /// Called before `on_display_set` for a display set starting a new epoch, which resets all windows, palettes
+    /// and objects.
+    fn on_epoch_start(&mut self, _display_set: &PgsDisplaySet) -> PgsVisitFlow {
+        PgsVisitFlow::Continue
+    }
+
+    /// Called for every problem found while parsing.
+    fn on_issue(&mut self, _issue: &PgsParseIssue) -> PgsVisitFlow {
+        PgsVisitFlow::Continue
+    }
+}
@@ -1,12 +1,10 @@
-use std::rc::Rc;
-
-use crate::{pgs_memory_buffer::{BigEndian, ReadBytes}, Error, PgsMemoryBuffer, PgsSegmentHeader, Result};
+use crate::{pgs_memory_buffer::{BigEndian, ReadBytes, WriteBytes}, Error, PgsMemoryBuffer, PgsRc, PgsSegmentHeader, Result};
 
 /// Represents the definition of a display window within a Window Definition Segment (WDS).
 ///
 /// The `PgsWdsSegmentWindowDefinition` structure contains details about the position and size of
 /// a window where subtitles will be displayed on the screen.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PgsWdsSegmentWindowDefinition {
     pub window_id: u8,
     pub window_horizontal_position: u16,
@@ -35,7 +33,7 @@ impl PgsWdsSegmentWindowDefinition {
 ///
 /// The `PgsWdsSegment` structure contains information about multiple windows used for displaying subtitles.
 /// Each window is defined by its ID, position, and size.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PgsWdsSegment {
     pub header: PgsSegmentHeader,
     pub number_of_windows: u8,
@@ -58,11 +56,11 @@ impl PgsWdsSegment {
     /// - `data`: Raw byte data containing the segment information.
     ///
     /// # Returns
-    /// - `Ok(Rc<PgsWdsSegment>)`: A reference-counted pointer to the parsed `PgsWdsSegment`.
+    /// - `Ok(PgsRc<PgsWdsSegment>)`: A reference-counted pointer to the parsed `PgsWdsSegment`.
     /// - `Err(Error)`: An error if the data is invalid or cannot be parsed.
-    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<Rc<PgsWdsSegment>> {
+    pub fn from_data(header: PgsSegmentHeader, data: &[u8]) -> Result<PgsRc<PgsWdsSegment>> {
         if data.len() < header.segment_length as usize {
-            return Err(Error::InvalidSegmentDataLength);
+            return Err(Error::InvalidSegmentDataLength { length: data.len(), expected: header.segment_length as usize });
         }
 
         let mut buffer: PgsMemoryBuffer = PgsMemoryBuffer::from(data);
@@ -77,6 +75,25 @@ impl PgsWdsSegment {
             windows.push(PgsWdsSegmentWindowDefinition::new(window_id, window_horizontal_position, window_vertical_position, window_width, window_height))
         }
 
-        Ok(Rc::new(PgsWdsSegment::new(header, number_of_windows, windows)))
+        Ok(PgsRc::new(PgsWdsSegment::new(header, number_of_windows, windows)))
+    }
+
+    /// Serializes the segment payload (without the header).
+    ///
+    /// # Returns
+    /// - `Ok(Vec<u8>)`: The encoded payload bytes, with the window count taken from `windows`.
+    /// - `Err(Error)`: An error if the data cannot be written.
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = Vec::new();
+        data.write_u8(self.windows.len() as u8)?;
+        for window in &self.windows {
+            data.write_u8(window.window_id)?;
+            data.write_u16::<BigEndian>(window.window_horizontal_position)?;
+            data.write_u16::<BigEndian>(window.window_vertical_position)?;
+            data.write_u16::<BigEndian>(window.window_width)?;
+            data.write_u16::<BigEndian>(window.window_height)?;
+        }
+
+        Ok(data)
     }
 }
\ No newline at end of file
@@ -0,0 +1,196 @@
+//! # PGS Writer
+//!
+//! This module defines the `PgsWriter`, which serializes PGS segments back into the SUP file format.
+
+use std::{fs::File, io::{BufWriter, Write}};
+
+use crate::{pgs_dts::regenerate_decoding_timestamps, pgs_error::Result, pgs_transform::deduplicate_objects, Error, PgsSegment, PgsStream};
+
+/// A writer producing SUP data from PGS segments.
+///
+/// Segment lengths in the written headers are always recomputed from the serialized payload, so segments can be
+/// modified freely before they are written.
+#[derive(Debug)]
+pub struct PgsWriter<W: Write> {
+    writer: W,
+    regenerate_dts: bool,
+    deduplicate_objects: bool
+}
+
+impl PgsWriter<BufWriter<File>> {
+    /// Creates (or truncates) a SUP file and returns a `PgsWriter` writing to it.
+    ///
+    /// # Arguments
+    /// * `sup_file_path` - The path of the file to be written.
+    ///
+    /// # Returns
+    /// Returns a `Result` containing either a `PgsWriter` instance or an `Error` if the file cannot be created.
+    pub fn create(sup_file_path: &str) -> Result<Self> {
+        Ok(PgsWriter::new(BufWriter::new(File::create(sup_file_path)?)))
+    }
+}
+
+impl<W: Write> PgsWriter<W> {
+    /// Creates a new `PgsWriter` on top of any `Write` implementation.
+    pub fn new(writer: W) -> Self {
+        PgsWriter {
+            writer,
+            regenerate_dts: false,
+            deduplicate_objects: false
+        }
+    }
+
+    /// Makes `write_segments` regenerate the decoding timestamps of every display set from the decoder model
+    /// (see `transform::regenerate_decoding_timestamps`) before writing, for streams whose DTS were stripped.
+    pub fn set_regenerate_dts(&mut self, regenerate_dts: bool) {
+        self.regenerate_dts = regenerate_dts;
+    }
+
+    /// Makes `write_segments` send every object bitmap only once per epoch, replacing identical objects by the
+    /// object already in the decoder buffer (see `transform::deduplicate_objects`).
+    pub fn set_deduplicate_objects(&mut self, deduplicate_objects: bool) {
+        self.deduplicate_objects = deduplicate_objects;
+    }
+
+    /// Writes a single segment (header and payload).
+    ///
+    /// # Errors
+    /// Returns `Error::InvalidSegmentDataLength` if the serialized payload does not fit the 16-bit segment length,
+    /// or `Error::File` if writing fails.
+    pub fn write_segment(&mut self, segment: &PgsSegment) -> Result<()> {
+        let payload = segment.to_data()?;
+        if payload.len() > u16::MAX as usize {
+            return Err(Error::InvalidSegmentDataLength { length: payload.len(), expected: u16::MAX as usize });
+        }
+
+        let mut header = *segment.header();
+        header.segment_length = payload.len() as u16;
+        let mut header = header.to_data();
+        header[10] = segment.segment_type_byte();
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&payload)?;
+        Ok(())
+    }
+
+    /// Writes all segments in order, deduplicating objects if enabled with `set_deduplicate_objects` and
+    /// regenerating their decoding timestamps if enabled with `set_regenerate_dts`.
+    ///
+    /// # Errors
+    /// Returns any error from decoding the object bitmaps when deduplicating, or from writing a segment.
+    pub fn write_segments<'s>(&mut self, segments: impl IntoIterator<Item = &'s PgsSegment>) -> Result<()> {
+        if self.regenerate_dts || self.deduplicate_objects {
+            let mut stream = PgsStream::new(segments.into_iter().cloned().collect());
+            if self.deduplicate_objects {
+                deduplicate_objects(&mut stream)?;
+            }
+            if self.regenerate_dts {
+                regenerate_decoding_timestamps(&mut stream);
+            }
+            return stream.get_segments().iter().try_for_each(|segment| self.write_segment(segment));
+        }
+        for segment in segments {
+            self.write_segment(segment)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes any buffered data to the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Consumes the `PgsWriter` and returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pgs_pcs_segment::PgsPcsSegmentCompositionObjects, pgs_segment_header::PGS_SEGMENT_HEADER_LENGTH, pgs_test_support::timed_header, PgsEndSegment, PgsOdsSegment, PgsOdsSequenceFlag, PgsPcsCompositionState, PgsPcsObjectCroppedFlag, PgsPcsSegment, PgsPdsSegment, PgsPdsSegmentPaletteEntry, PgsRc, PgsSegmentHeader, PgsSegmentType, PgsWdsSegment, PgsWdsSegmentWindowDefinition};
+
+    use super::*;
+
+    fn round_trip(segment: &PgsSegment) -> PgsSegment {
+        let mut writer = PgsWriter::new(Vec::new());
+        writer.write_segment(segment).unwrap();
+        let data = writer.into_inner();
+
+        let header = PgsSegmentHeader::from_data(&data).unwrap();
+        assert_eq!(header.segment_length as usize, data.len() - PGS_SEGMENT_HEADER_LENGTH);
+        PgsSegment::from_data(header, &data[PGS_SEGMENT_HEADER_LENGTH..]).unwrap()
+    }
+
+    #[test]
+    fn test_pcs_round_trip() {
+        let pcs = PgsSegmentHeader { segment_length: 27, ..timed_header(PgsSegmentType::PCS, 90000, 89100) };
+        let pcs = PgsPcsSegment {
+            header: pcs,
+            width: 1920,
+            height: 1080,
+            composition_number: 7,
+            composition_state: PgsPcsCompositionState::EpochStart,
+            number_of_composition_objects: 1,
+            composition_objects: vec![PgsPcsSegmentCompositionObjects {
+                object_id: 1,
+                window_id: 0,
+                object_cropped_flag: PgsPcsObjectCroppedFlag::ForceCroppedImage,
+                object_forced_flag: true,
+                object_horizontal_position: 100,
+                object_vertical_position: 900,
+                object_cropping_horizontal_position: 2,
+                object_cropping_vertical_position: 4,
+                object_cropping_width: 50,
+                object_cropping_height_position: 20
+            }],
+            ..Default::default()
+        };
+        let PgsSegment::Pcs(read) = round_trip(&PgsSegment::Pcs(PgsRc::new(pcs.clone()))) else { panic!("Expected PCS segment") };
+        assert_eq!(*read, pcs);
+    }
+
+    #[test]
+    fn test_wds_pds_ods_end_round_trip() {
+        let wds = PgsSegmentHeader { segment_length: 10, ..timed_header(PgsSegmentType::WDS, 90000, 89100) };
+        let wds = PgsWdsSegment {
+            header: wds,
+            number_of_windows: 1,
+            windows: vec![PgsWdsSegmentWindowDefinition { window_id: 0, window_horizontal_position: 10, window_vertical_position: 20, window_width: 300, window_height: 40 }]
+        };
+        let PgsSegment::Wds(read) = round_trip(&PgsSegment::Wds(PgsRc::new(wds.clone()))) else { panic!("Expected WDS segment") };
+        assert_eq!(*read, wds);
+
+        let pds = PgsSegmentHeader { segment_length: 12, ..timed_header(PgsSegmentType::PDS, 90000, 89100) };
+        let pds = PgsPdsSegment {
+            header: pds,
+            palette_id: 1,
+            palette_version_number: 2,
+            palette_entries: PgsRc::new(vec![
+                PgsPdsSegmentPaletteEntry { palette_entry_id: 0, luminance: 16, color_difference_red: 128, color_difference_blue: 128, transparency: 0 },
+                PgsPdsSegmentPaletteEntry { palette_entry_id: 1, luminance: 235, color_difference_red: 128, color_difference_blue: 128, transparency: 255 }
+            ])
+        };
+        let PgsSegment::Pds(read) = round_trip(&PgsSegment::Pds(PgsRc::new(pds.clone()))) else { panic!("Expected PDS segment") };
+        assert_eq!(*read, pds);
+
+        let ods = PgsSegmentHeader { segment_length: 14, ..timed_header(PgsSegmentType::ODS, 90000, 89100) };
+        let ods = PgsOdsSegment {
+            header: ods,
+            object_id: 3,
+            object_version_number: 0,
+            last_in_sequence_flag: PgsOdsSequenceFlag::Both,
+            object_data_length: 3,
+            width: 2,
+            height: 1,
+            object_data: vec![0x01, 0x00, 0x00]
+        };
+        let PgsSegment::Ods(read) = round_trip(&PgsSegment::Ods(PgsRc::new(ods.clone()))) else { panic!("Expected ODS segment") };
+        assert_eq!(*read, ods);
+
+        let end = PgsSegmentHeader { segment_length: 1, ..timed_header(PgsSegmentType::END, 90000, 89100) };
+        let end = PgsEndSegment { header: end, data: vec![0xAB] };
+        let PgsSegment::End(read) = round_trip(&PgsSegment::End(PgsRc::new(end.clone()))) else { panic!("Expected END segment") };
+        assert_eq!(*read, end);
+    }
+}